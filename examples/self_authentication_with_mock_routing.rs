@@ -67,7 +67,15 @@ fn main() {
     }
 
     //TODO Not to be used if not using non_networking_test_framework.
-    let data_store = maidsafe_client::client::non_networking_test_framework::get_new_data_store();
+    // Given a directory the mock network is kept there, so accounts created by an earlier run
+    // can be logged in to.
+    let data_store = match std::env::args().nth(1) {
+        Some(dir) => match maidsafe_client::client::non_networking_test_framework::get_file_data_store(std::path::Path::new(&dir)) {
+            Ok(data_store) => data_store,
+            Err(io_error) => panic!("Could not open {}: {:?}", dir, io_error.description()),
+        },
+        None => maidsafe_client::client::non_networking_test_framework::get_new_data_store(),
+    };
 
     // Account Creation
     {
@@ -0,0 +1,85 @@
+/*
+ * C interface to maidsafe_client: account login and the Container and Blob API.
+ *
+ * Handles are opaque and owned by the caller; release each with its _free function.
+ * Functions returning int32_t return SAFE_OK or one of the SAFE_ERR_* codes, and
+ * safe_last_error() describes the last failure on the calling thread.
+ *
+ * Keep in step with src/ffi/mod.rs; its tests check every declaration against the Rust
+ * signature.
+ */
+
+#ifndef MAIDSAFE_CLIENT_H
+#define MAIDSAFE_CLIENT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SAFE_OK 0
+#define SAFE_ERR_NULL_POINTER -1
+#define SAFE_ERR_INVALID_UTF8 -2
+#define SAFE_ERR_NOT_FOUND -3
+#define SAFE_ERR_ALREADY_EXISTS -4
+#define SAFE_ERR_READ_ONLY -5
+#define SAFE_ERR_LOGIN_FAILED -6
+#define SAFE_ERR_OPERATION_FAILED -7
+/* An internal error stopped the call; functions not returning a code return NULL or 0 */
+#define SAFE_ERR_PANIC -8
+
+typedef struct SafeDataStore SafeDataStore;
+typedef struct SafeClient SafeClient;
+typedef struct SafeContainer SafeContainer;
+typedef struct SafeNameList SafeNameList;
+
+/* Message of the last failure on this thread, valid until the next call on the thread */
+const char* safe_last_error(void);
+
+/* Store of the mock network, shared by the clients created on it */
+SafeDataStore* safe_data_store_new(void);
+/* Store kept in the directory, created if need be, so the mock network survives restarts */
+int32_t safe_data_store_open(const char* path, SafeDataStore** data_store);
+void safe_data_store_free(SafeDataStore* data_store);
+
+int32_t safe_create_account(const char* keyword, uint32_t pin, const char* password,
+                            const SafeDataStore* data_store, SafeClient** client);
+int32_t safe_log_in(const char* keyword, uint32_t pin, const char* password,
+                    const SafeDataStore* data_store, SafeClient** client);
+/* Containers opened through the client stay usable after it is freed */
+void safe_client_free(SafeClient* client);
+
+/* Opens the root container of the user, creating it on first use */
+int32_t safe_container_user_root(const SafeClient* client, SafeContainer** container);
+void safe_container_free(SafeContainer* container);
+/* Copy of the name, freed with safe_string_free */
+char* safe_container_name(const SafeContainer* container);
+void safe_string_free(char* value);
+
+/* metadata may be NULL */
+int32_t safe_container_create(SafeContainer* container, const char* name, const char* metadata);
+int32_t safe_container_get(SafeContainer* container, const char* name, SafeContainer** found);
+int32_t safe_container_delete(SafeContainer* container, const char* name);
+int32_t safe_container_list_containers(const SafeContainer* container, SafeNameList** names);
+int32_t safe_container_list_blobs(const SafeContainer* container, SafeNameList** names);
+
+size_t safe_name_list_len(const SafeNameList* names);
+/* NULL past the end; valid until the list is freed */
+const char* safe_name_list_get(const SafeNameList* names, size_t index);
+void safe_name_list_free(SafeNameList* names);
+
+/* The content is returned in a new buffer, freed with safe_buffer_free */
+int32_t safe_blob_read(const SafeContainer* container, const char* name, uint8_t** data, size_t* length);
+void safe_buffer_free(uint8_t* data, size_t length);
+/* Creates the blob or replaces its content; a NULL metadata keeps that of an existing blob */
+int32_t safe_blob_write(SafeContainer* container, const char* name, const uint8_t* data, size_t length,
+                        const char* metadata);
+int32_t safe_blob_delete(SafeContainer* container, const char* name);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* MAIDSAFE_CLIENT_H */
@@ -0,0 +1,497 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Operates on the network from a shell.
+//!
+//! Log in once with `safe account login`; the credentials are then kept in a session cache
+//! so that later commands do not prompt again, until `safe account logout`. The cache is
+//! sealed, but the key sealing it is kept beside it in the session directory, so anyone who
+//! can read that directory can log in as the user: treat it like a plaintext password file.
+//! The directory and both files are created readable only by the user.
+//! Paths are relative to the root container of the user, e.g. `/photos/cat.jpg`.
+//!
+//! Only the mock network is available so far, so every command needs `--mock-store <dir>`,
+//! the directory in which the mock network is kept between commands.
+
+extern crate maidsafe_client;
+extern crate rustc_serialize;
+extern crate sodiumoxide;
+extern crate time;
+
+use std::error::Error;
+use std::io::{Read, Write};
+use rustc_serialize::hex::{FromHex, ToHex};
+use maidsafe_client::client;
+use maidsafe_client::nfs;
+
+const USAGE: &'static str = "Usage: safe [--mock-store <dir>] [--session-dir <dir>] <command>
+
+Commands:
+    account create|login|change-password|logout
+    ls [<path>]
+    mkdir <path> [--metadata <metadata>]
+    put <local file or -> <path> [--metadata <metadata>]
+    get <path> [<local file>] [--version <version>]
+    cat <path> [--version <version>]
+    rm <path>
+    mv <path> <new path>
+    versions <path>
+    stat <path>";
+
+const SESSION_FILE: &'static str = "session";
+const SESSION_KEY_FILE: &'static str = "session.key";
+
+struct Options {
+    mock_store:  Option<::std::path::PathBuf>,
+    session_dir: ::std::path::PathBuf,
+    metadata:    Option<String>,
+    version:     Option<[u8;64]>,
+    arguments:   Vec<String>,
+}
+
+impl Options {
+    fn parse(args: Vec<String>) -> Result<Options, String> {
+        let mut options = Options {
+            mock_store: None,
+            session_dir: match ::std::env::home_dir() {
+                Some(home) => home.join(".safe"),
+                None => ::std::path::PathBuf::from(".safe"),
+            },
+            metadata: None,
+            version: None,
+            arguments: Vec::new(),
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                options.arguments.push(arg);
+                continue;
+            }
+            let value = match args.next() {
+                Some(value) => value,
+                None => return Err(format!("{} needs a value", arg)),
+            };
+            match &arg[..] {
+                "--mock-store" => options.mock_store = Some(::std::path::PathBuf::from(value)),
+                "--session-dir" => options.session_dir = ::std::path::PathBuf::from(value),
+                "--metadata" => options.metadata = Some(value),
+                "--version" => options.version = Some(try!(parse_version(&value))),
+                _ => return Err(format!("Unknown option {}", arg)),
+            }
+        }
+        Ok(options)
+    }
+
+    fn argument(&self, index: usize) -> Result<String, String> {
+        match self.arguments.get(index) {
+            Some(argument) => Ok(argument.clone()),
+            None => Err(USAGE.to_string()),
+        }
+    }
+}
+
+fn parse_version(hex: &str) -> Result<[u8;64], String> {
+    match hex.from_hex() {
+        Ok(ref bytes) if bytes.len() == 64 => {
+            let mut version = [0u8;64];
+            for (to, from) in version.iter_mut().zip(bytes.iter()) {
+                *to = *from;
+            }
+            Ok(version)
+        },
+        _ => Err(format!("{} is not a version", hex)),
+    }
+}
+
+/// What is needed to log in again without prompting
+#[derive(RustcEncodable, RustcDecodable)]
+struct Session {
+    keyword:  String,
+    pin:      u32,
+    password: String,
+}
+
+impl Session {
+    fn prompt() -> Result<Session, String> {
+        let keyword = prompt("Keyword");
+        let password = prompt("Password");
+        match prompt("PIN").parse::<u32>() {
+            Ok(pin) => Ok(Session { keyword: keyword, pin: pin, password: password }),
+            Err(_) => Err("PIN must be a number".to_string()),
+        }
+    }
+
+    /// The session is sealed with a key kept in a separate file which only the user can read.
+    /// Sealing only keeps the password out of a copy of the session file taken without the key
+    /// file; it is no protection against anyone who can read the directory.
+    fn save(&self, dir: &::std::path::Path) -> Result<(), String> {
+        use sodiumoxide::crypto::secretbox;
+
+        try!(create_private_dir(dir));
+        let key = match try!(read_file(&dir.join(SESSION_KEY_FILE))) {
+            Some(ref bytes) if bytes.len() == secretbox::KEYBYTES => {
+                let mut key = [0u8; secretbox::KEYBYTES];
+                for (to, from) in key.iter_mut().zip(bytes.iter()) {
+                    *to = *from;
+                }
+                secretbox::Key(key)
+            },
+            _ => {
+                let key = secretbox::gen_key();
+                try!(write_private_file(&dir.join(SESSION_KEY_FILE), &key.0));
+                key
+            },
+        };
+        let encoded = try!(rustc_serialize::json::encode(self).map_err(|err| err.to_string()));
+        let nonce = secretbox::gen_nonce();
+        let mut sealed = nonce.0.to_vec();
+        sealed.extend(secretbox::seal(encoded.as_bytes(), &nonce, &key).into_iter());
+        write_private_file(&dir.join(SESSION_FILE), &sealed)
+    }
+
+    fn load(dir: &::std::path::Path) -> Result<Session, String> {
+        use sodiumoxide::crypto::secretbox;
+
+        let not_logged_in = "Not logged in: run safe account login".to_string();
+        let (key_bytes, sealed) = match (try!(read_file(&dir.join(SESSION_KEY_FILE))), try!(read_file(&dir.join(SESSION_FILE)))) {
+            (Some(key_bytes), Some(sealed)) => (key_bytes, sealed),
+            _ => return Err(not_logged_in),
+        };
+        if key_bytes.len() != secretbox::KEYBYTES || sealed.len() < secretbox::NONCEBYTES {
+            return Err("Session cache is corrupt: run safe account login".to_string());
+        }
+        let mut key = [0u8; secretbox::KEYBYTES];
+        for (to, from) in key.iter_mut().zip(key_bytes.iter()) {
+            *to = *from;
+        }
+        let mut nonce = [0u8; secretbox::NONCEBYTES];
+        for (to, from) in nonce.iter_mut().zip(sealed.iter()) {
+            *to = *from;
+        }
+        let opened = match secretbox::open(&sealed[secretbox::NONCEBYTES..], &secretbox::Nonce(nonce), &secretbox::Key(key)) {
+            Some(opened) => opened,
+            None => return Err("Session cache is corrupt: run safe account login".to_string()),
+        };
+        let encoded = try!(String::from_utf8(opened).map_err(|err| err.to_string()));
+        rustc_serialize::json::decode(&encoded).map_err(|err| err.to_string())
+    }
+
+    fn remove(dir: &::std::path::Path) -> Result<(), String> {
+        for file in [SESSION_FILE, SESSION_KEY_FILE].iter() {
+            match ::std::fs::remove_file(dir.join(file)) {
+                Ok(()) => {},
+                Err(ref err) if err.kind() == ::std::io::ErrorKind::NotFound => {},
+                Err(err) => return Err(err.to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    fn log_in(&self, data_store: client::non_networking_test_framework::DataStore)
+              -> Result<::std::sync::Arc<::std::sync::Mutex<client::Client>>, String> {
+        let client = match client::Client::log_in(&self.keyword, self.pin, self.password.as_bytes(), data_store) {
+            Ok(client) => ::std::sync::Arc::new(::std::sync::Mutex::new(client)),
+            Err(io_error) => return Err(format!("Failed to log in: {}", io_error.description())),
+        };
+        try!(nfs::rest::provision_well_known(client.clone()));
+        Ok(client)
+    }
+}
+
+fn prompt(message: &str) -> String {
+    print!("{}: ", message);
+    let _ = std::io::stdout().flush();
+    let mut line = String::new();
+    let _ = std::io::stdin().read_line(&mut line);
+    line.trim().to_string()
+}
+
+/// None if the file does not exist
+fn read_file(path: &::std::path::Path) -> Result<Option<Vec<u8>>, String> {
+    let mut file = match ::std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == ::std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.to_string()),
+    };
+    let mut contents = Vec::new();
+    try!(file.read_to_end(&mut contents).map_err(|err| err.to_string()));
+    Ok(Some(contents))
+}
+
+/// Creates the file readable only by the user, so the contents are never readable by others
+/// even for a moment. A file which already exists is made private before it is overwritten.
+#[cfg(unix)]
+fn write_private_file(path: &::std::path::Path, contents: &[u8]) -> Result<(), String> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mut file = try!(::std::fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600)
+                                                     .open(path).map_err(|err| err.to_string()));
+    try!(file.set_permissions(::std::fs::Permissions::from_mode(0o600)).map_err(|err| err.to_string()));
+    file.write_all(contents).map_err(|err| err.to_string())
+}
+
+#[cfg(not(unix))]
+fn write_private_file(path: &::std::path::Path, contents: &[u8]) -> Result<(), String> {
+    let mut file = try!(::std::fs::File::create(path).map_err(|err| err.to_string()));
+    file.write_all(contents).map_err(|err| err.to_string())
+}
+
+/// Creates the directory, and any missing parents, with only the user able to enter it
+#[cfg(unix)]
+fn create_private_dir(dir: &::std::path::Path) -> Result<(), String> {
+    use std::os::unix::fs::DirBuilderExt;
+    ::std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir).map_err(|err| err.to_string())
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &::std::path::Path) -> Result<(), String> {
+    ::std::fs::create_dir_all(dir).map_err(|err| err.to_string())
+}
+
+/// Components of a path below the root container
+fn split_path(path: &str) -> Result<Vec<String>, String> {
+    let mut components = Vec::new();
+    for component in path.split('/').filter(|component| !component.is_empty() && *component != ".") {
+        if component == ".." {
+            return Err(format!("{}: .. is not supported", path));
+        }
+        components.push(component.to_string());
+    }
+    Ok(components)
+}
+
+fn open_container(client: &::std::sync::Arc<::std::sync::Mutex<client::Client>>, components: &[String]) -> Result<nfs::rest::Container, String> {
+    let mut container = try!(nfs::rest::Container::user_root(client.clone()));
+    for component in components.iter() {
+        container = try!(container.get_container(component.clone(), None));
+    }
+    Ok(container)
+}
+
+/// The container holding the entry at the path, and the name of the entry
+fn open_parent(client: &::std::sync::Arc<::std::sync::Mutex<client::Client>>, path: &str) -> Result<(nfs::rest::Container, String), String> {
+    let mut components = try!(split_path(path));
+    let name = match components.pop() {
+        Some(name) => name,
+        None => return Err("The root container has no parent".to_string()),
+    };
+    Ok((try!(open_container(client, &components[..])), name))
+}
+
+fn read_blob(blob: &nfs::rest::Blob) -> Result<Vec<u8>, String> {
+    let mut reader = blob.reader();
+    reader.read(0, blob.get_size()).map_err(|err| err.to_string())
+}
+
+/// Creates the blob or replaces its content
+fn write_blob(container: &mut nfs::rest::Container, name: String, content: &[u8], metadata: Option<String>) -> Result<(), String> {
+    let exists = container.get_blob(name.clone(), None).is_ok();
+    container.transaction(|tx| {
+        if exists {
+            try!(tx.update_blob(name.clone(), content));
+            match metadata {
+                Some(_) => tx.update_blob_metadata(name.clone(), metadata.clone()),
+                None => Ok(()),
+            }
+        } else {
+            tx.create_blob(name.clone(), metadata.clone(), content)
+        }
+    })
+}
+
+fn account(options: &Options, data_store: client::non_networking_test_framework::DataStore) -> Result<(), String> {
+    match &try!(options.argument(1))[..] {
+        "create" => {
+            let session = try!(Session::prompt());
+            let client = match client::Client::create_account(&session.keyword, session.pin, session.password.as_bytes(), data_store) {
+                Ok(client) => ::std::sync::Arc::new(::std::sync::Mutex::new(client)),
+                Err(io_error) => return Err(format!("Failed to create account: {}", io_error.description())),
+            };
+            try!(nfs::rest::provision_well_known(client));
+            session.save(&options.session_dir)
+        },
+        "login" => {
+            let session = try!(Session::prompt());
+            let _ = try!(session.log_in(data_store));
+            session.save(&options.session_dir)
+        },
+        "change-password" => {
+            let mut session = try!(Session::load(&options.session_dir));
+            let client = try!(session.log_in(data_store));
+            let password = prompt("New password");
+            if prompt("Repeat new password") != password {
+                return Err("Passwords differ".to_string());
+            }
+            try!(client.lock().unwrap().change_password(password.as_bytes()).map_err(|err| err.description().to_string()));
+            session.password = password;
+            session.save(&options.session_dir)
+        },
+        "logout" => Session::remove(&options.session_dir),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn run(options: &Options, client: &::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> Result<(), String> {
+    match &try!(options.argument(0))[..] {
+        "ls" => {
+            let path = options.argument(1).unwrap_or("/".to_string());
+            let container = try!(open_container(client, &try!(split_path(&path))[..]));
+            for info in container.get_containers().iter() {
+                println!("{}/", info.get_name());
+            }
+            for blob in container.get_blobs().iter() {
+                println!("{:>12}  {}", blob.get_size(), blob.get_name());
+            }
+            Ok(())
+        },
+        "mkdir" => {
+            let (mut parent, name) = try!(open_parent(client, &try!(options.argument(1))));
+            parent.create(name, options.metadata.clone())
+        },
+        "put" => {
+            let local = try!(options.argument(1));
+            let mut content = Vec::new();
+            if local == "-" {
+                try!(::std::io::stdin().read_to_end(&mut content).map_err(|err| err.to_string()));
+            } else {
+                content = match try!(read_file(::std::path::Path::new(&local))) {
+                    Some(content) => content,
+                    None => return Err(format!("{} not found", local)),
+                };
+            }
+            let (mut parent, name) = try!(open_parent(client, &try!(options.argument(2))));
+            write_blob(&mut parent, name, &content, options.metadata.clone())
+        },
+        "get" | "cat" => {
+            let (parent, name) = try!(open_parent(client, &try!(options.argument(1))));
+            let content = try!(read_blob(&try!(parent.get_blob(name, options.version))));
+            match options.arguments.get(2) {
+                Some(local) if options.arguments[0] == "get" => {
+                    let mut file = try!(::std::fs::File::create(local).map_err(|err| err.to_string()));
+                    file.write_all(&content).map_err(|err| err.to_string())
+                },
+                _ => ::std::io::stdout().write_all(&content).map_err(|err| err.to_string()),
+            }
+        },
+        "rm" => {
+            let (mut parent, name) = try!(open_parent(client, &try!(options.argument(1))));
+            if parent.get_blob(name.clone(), None).is_ok() {
+                parent.delete_blob(name)
+            } else {
+                parent.delete_container(name)
+            }
+        },
+        "mv" => {
+            let (mut from, name) = try!(open_parent(client, &try!(options.argument(1))));
+            let (mut to, new_name) = try!(open_parent(client, &try!(options.argument(2))));
+            let blob = from.get_blob(name.clone(), None);
+            if from.get_id()[..] == to.get_id()[..] {
+                return from.transaction(|tx| {
+                    if blob.is_ok() {
+                        tx.rename_blob(name.clone(), new_name.clone())
+                    } else {
+                        tx.rename_container(name.clone(), new_name.clone())
+                    }
+                });
+            }
+            let blob = match blob {
+                Ok(blob) => blob,
+                Err(_) if from.has_container(&name) => return Err("Containers can only be renamed within their parent".to_string()),
+                Err(err) => return Err(err),
+            };
+            if to.get_blob(new_name.clone(), None).is_ok() {
+                return Err(format!("{} already exists", new_name));
+            }
+            let metadata = blob.get_user_metadata().and_then(|metadata| String::from_utf8(metadata).ok());
+            let content = try!(read_blob(&blob));
+            try!(to.transaction(|tx| tx.create_blob(new_name.clone(), metadata.clone(), &content)));
+            from.delete_blob(name)
+        },
+        "versions" => {
+            let path = try!(options.argument(1));
+            let components = try!(split_path(&path));
+            let versions = if components.is_empty() {
+                try!(try!(open_container(client, &[])).get_versions())
+            } else {
+                let (mut parent, name) = try!(open_parent(client, &path));
+                if parent.get_blob(name.clone(), None).is_ok() {
+                    try!(parent.get_blob_versions(name))
+                } else {
+                    try!(try!(parent.get_container(name, None)).get_versions())
+                }
+            };
+            for version in versions.iter() {
+                println!("{}", version.to_hex());
+            }
+            Ok(())
+        },
+        "stat" => {
+            let path = try!(options.argument(1));
+            let components = try!(split_path(&path));
+            let container = if components.is_empty() {
+                try!(open_container(client, &[]))
+            } else {
+                let (mut parent, name) = try!(open_parent(client, &path));
+                match parent.get_blob(name.clone(), options.version) {
+                    Ok(blob) => {
+                        println!("Blob:      {}", blob.get_name());
+                        println!("Size:      {}", blob.get_size());
+                        println!("Created:   {}", blob.get_created_time().rfc3339());
+                        println!("Modified:  {}", blob.get_modified_time().rfc3339());
+                        println!("ETag:      {}", blob.get_etag());
+                        if let Some(metadata) = blob.get_user_metadata() {
+                            println!("Metadata:  {}", String::from_utf8_lossy(&metadata));
+                        }
+                        return Ok(());
+                    },
+                    Err(_) => try!(parent.get_container(name, options.version)),
+                }
+            };
+            println!("Container: {}", container.get_name());
+            println!("Id:        {}", container.get_id().to_hex());
+            println!("Created:   {}", container.get_created_time().rfc3339());
+            println!("Modified:  {}", container.get_modified_time().rfc3339());
+            println!("Contains:  {} containers, {} blobs", container.get_containers().len(), container.get_blobs().len());
+            if let Some(metadata) = container.get_metadata() {
+                println!("Metadata:  {}", metadata);
+            }
+            Ok(())
+        },
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn main() {
+    let result = Options::parse(::std::env::args().skip(1).collect()).and_then(|options| {
+        let store_path = match options.mock_store {
+            Some(ref store_path) => store_path.clone(),
+            None => return Err("Only the mock network is available: pass --mock-store <dir>".to_string()),
+        };
+        let data_store = try!(client::non_networking_test_framework::get_file_data_store(&store_path).map_err(|err| err.to_string()));
+        if try!(options.argument(0)) == "account" {
+            account(&options, data_store)
+        } else {
+            Session::load(&options.session_dir).and_then(|session| session.log_in(data_store))
+                                               .and_then(|client| run(&options, &client))
+        }
+    });
+
+    if let Err(message) = result {
+        let _ = writeln!(::std::io::stderr(), "safe: {}", message);
+        ::std::process::exit(1);
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Logs in once and serves apps over a Unix domain socket, asking on the terminal before
+//! letting an app in.
+//!
+//! Usage: `safe_launcher [--create] [--mock-store <dir>] <socket path>`. With `--create` a new
+//! account is created rather than logged in to. `--mock-store` keeps the mock network in the
+//! directory, as the `safe` command does, so accounts and data outlive the launcher; without it
+//! they are lost when the launcher exits.
+
+extern crate maidsafe_client;
+
+#[cfg(unix)]
+use std::error::Error;
+#[cfg(unix)]
+use std::io::Write;
+
+#[cfg(unix)]
+fn prompt(message: &str) -> String {
+    print!("{}: ", message);
+    let _ = std::io::stdout().flush();
+    let mut line = String::new();
+    let _ = std::io::stdin().read_line(&mut line);
+    line.trim().to_string()
+}
+
+/// Reports the error on stderr and exits
+#[cfg(unix)]
+fn fail(message: &str) -> ! {
+    let _ = writeln!(std::io::stderr(), "safe_launcher: {}", message);
+    std::process::exit(1);
+}
+
+#[cfg(unix)]
+fn main() {
+    let usage = "Usage: safe_launcher [--create] [--mock-store <dir>] <socket path>";
+    let mut create = false;
+    let mut mock_store = None;
+    let mut path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--create" => create = true,
+            "--mock-store" => match args.next() {
+                Some(dir) => mock_store = Some(std::path::PathBuf::from(dir)),
+                None => fail("--mock-store needs a value"),
+            },
+            _ if arg.starts_with("--") => fail(&format!("Unknown option {}\n{}", arg, usage)),
+            _ => path = Some(arg),
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => fail(usage),
+    };
+
+    let keyword = prompt("Keyword");
+    let password = prompt("Password");
+    let pin = match prompt("PIN").parse::<u32>() {
+        Ok(pin) => pin,
+        Err(_) => fail("PIN must be a number"),
+    };
+
+    //TODO Not to be used if not using non_networking_test_framework.
+    let data_store = match mock_store {
+        Some(ref dir) => match maidsafe_client::client::non_networking_test_framework::get_file_data_store(dir) {
+            Ok(data_store) => data_store,
+            Err(io_error) => fail(&format!("Failed to open {}: {}", dir.display(), io_error.description())),
+        },
+        None => maidsafe_client::client::non_networking_test_framework::get_new_data_store(),
+    };
+    let result = if create {
+        maidsafe_client::client::Client::create_account(&keyword, pin, password.as_bytes(), data_store)
+    } else {
+        maidsafe_client::client::Client::log_in(&keyword, pin, password.as_bytes(), data_store)
+    };
+    let client = match result {
+        Ok(client) => std::sync::Arc::new(std::sync::Mutex::new(client)),
+        Err(io_error) => fail(&format!("Failed to log in: {}", io_error.description())),
+    };
+    if let Err(message) = maidsafe_client::nfs::rest::provision_well_known(client.clone()) {
+        fail(&format!("Failed to provision the well-known containers: {}", message));
+    }
+
+    let approver: maidsafe_client::launcher::Approver = Box::new(|app_id, grants| {
+        let grants: Vec<String> = grants.iter().map(|(well_known, access)| format!("{} ({:?})", well_known.get_name(), access)).collect();
+        let answer = prompt(&format!("Allow {} access to its own container and to [{}]? [y/N]", app_id, grants.join(", ")));
+        answer == "y" || answer == "Y"
+    });
+    let launcher = match maidsafe_client::launcher::Launcher::bind(client, &path, approver) {
+        Ok(launcher) => launcher,
+        Err(message) => fail(&message),
+    };
+    println!("Listening on {} (protocol version {})", path, maidsafe_client::launcher::PROTOCOL_VERSION);
+    launcher.serve();
+}
+
+#[cfg(not(unix))]
+fn main() {
+    use std::io::Write;
+    let _ = writeln!(std::io::stderr(), "safe_launcher: needs Unix domain sockets");
+    std::process::exit(1);
+}
@@ -31,15 +31,38 @@ mod user_account;
 mod response_getter;
 mod callback_interface;
 
+// What is needed to save a changed Account back to the session packet
+struct SessionPacketKeys {
+    id:       routing::NameType,
+    password: Vec<u8>,
+    pin:      u32,
+}
+
+/// Storage used by an account and what is left of its quota, in bytes
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountInfo {
+    pub used_space:      u64,
+    pub available_space: u64,
+    pub chunk_count:     u64,
+}
+
+/// Whether a PUT failed because the account has used up its quota
+//TODO: Toggle depending on if using actual routing or non_networking_test_framework
+pub fn is_out_of_space(error: &routing::error::ResponseError) -> bool {
+    match *error {
+        routing::error::ResponseError::FailedToStoreData(ref data) => &data[..] == non_networking_test_framework::OUT_OF_SPACE,
+        _ => false,
+    }
+}
+
 pub struct Client {
     account:             user_account::Account,
+    session_packet_keys: SessionPacketKeys,
     //TODO: Toggle depending on if using actual routing or non_networking_test_framework
     // routing:             ::std::sync::Arc<::std::sync::Mutex<routing::routing_client::RoutingClient<callback_interface::CallbackInterface>>>,
     routing:             ::std::sync::Arc<::std::sync::Mutex<non_networking_test_framework::RoutingClientMock>>,
     response_notifier:   ::ResponseNotifier,
     callback_interface:  ::std::sync::Arc<::std::sync::Mutex<callback_interface::CallbackInterface>>,
-    routing_stop_flag:   ::std::sync::Arc<::std::sync::Mutex<bool>>,
-    routing_join_handle: Option<::std::thread::JoinHandle<()>>,
 }
 
 impl Client {
@@ -53,23 +76,14 @@ impl Client {
 
         //TODO: Toggle depending on if using actual routing or non_networking_test_framework
         // let routing_client = ::std::sync::Arc::new(::std::sync::Mutex::new(routing::routing_client::RoutingClient::new(callback_interface.clone(), client_id_packet)));
-        let routing_client = ::std::sync::Arc::new(::std::sync::Mutex::new(non_networking_test_framework::RoutingClientMock::new(callback_interface.clone(), data_store)));
-        let cloned_routing_client = routing_client.clone();
-        let routing_stop_flag = ::std::sync::Arc::new(::std::sync::Mutex::new(false));
-        let routing_stop_flag_clone = routing_stop_flag.clone();
+        let routing_client = ::std::sync::Arc::new(::std::sync::Mutex::new(non_networking_test_framework::RoutingClientMock::new(callback_interface.clone(), account_packet.get_public_maid().name(), data_store)));
 
-        let client = Client {
+        let mut client = Client {
             account: account_packet,
+            session_packet_keys: SessionPacketKeys { id: user_account::Account::generate_network_id(&keyword, pin), password: password.to_vec(), pin: pin },
             routing: routing_client,
             callback_interface: callback_interface,
             response_notifier: notifier,
-            routing_stop_flag: routing_stop_flag,
-            routing_join_handle: Some(::std::thread::spawn(move || {
-                while !*routing_stop_flag_clone.lock().unwrap() {
-                    ::std::thread::sleep_ms(10);
-                    cloned_routing_client.lock().unwrap().run();
-                }
-            })),
         };
 
         {
@@ -79,10 +93,9 @@ impl Client {
         }
 
         let encrypted_account = maidsafe_types::ImmutableData::new(client.account.encrypt(&password, pin).ok().unwrap());
-        let put_res = client.routing.lock().unwrap().put(encrypted_account.clone());
+        let put_res = client.put(encrypted_account.clone());
         match put_res {
-            Ok(id) => {
-                let mut response_getter = response_getter::ResponseGetter::new(id, client.response_notifier.clone(), client.callback_interface.clone());
+            Ok(mut response_getter) => {
                 match response_getter.get() {
                     Ok(_) => {},
                     Err(_) => return Err(::IoError::new(::std::io::ErrorKind::Other, "Session-Packet PUT-Response Failure !!")),
@@ -91,11 +104,10 @@ impl Client {
                 let account_version = maidsafe_types::StructuredData::new(user_account::Account::generate_network_id(&keyword, pin),
                                                                           client.account.get_public_maid().name(),
                                                                           vec![encrypted_account.name()]);
-                let put_res = client.routing.lock().unwrap().put(account_version);
+                let put_res = client.put(account_version);
 
                 match put_res {
-                    Ok(id) => {
-                        let mut response_getter = response_getter::ResponseGetter::new(id, client.response_notifier.clone(), client.callback_interface.clone());
+                    Ok(mut response_getter) => {
                         match response_getter.get() {
                             Ok(_) => {},
                             Err(_) => return Err(::IoError::new(::std::io::ErrorKind::Other, "Version-Packet PUT-Response Failure !!")),
@@ -121,39 +133,13 @@ impl Client {
 
         //TODO: Toggle depending on if using actual routing or non_networking_test_framework
         // let fake_routing_client = ::std::sync::Arc::new(::std::sync::Mutex::new(routing::routing_client::RoutingClient::new(callback_interface.clone(), fake_client_id_packet)));
-        let fake_routing_client = ::std::sync::Arc::new(::std::sync::Mutex::new(non_networking_test_framework::RoutingClientMock::new(callback_interface.clone(), data_store.clone())));
-        let cloned_fake_routing_client = fake_routing_client.clone();
-        let fake_routing_stop_flag = ::std::sync::Arc::new(::std::sync::Mutex::new(false));
-        let fake_routing_stop_flag_clone = fake_routing_stop_flag.clone();
-
-        struct RAIIThreadExit {
-            routing_stop_flag: ::std::sync::Arc<::std::sync::Mutex<bool>>,
-            join_handle: Option<::std::thread::JoinHandle<()>>,
-        }
-
-        impl Drop for RAIIThreadExit {
-            fn drop(&mut self) {
-                *self.routing_stop_flag.lock().unwrap() = true;
-                self.join_handle.take().unwrap().join().unwrap();
-            }
-        }
-
-        let _managed_thread = RAIIThreadExit {
-            routing_stop_flag: fake_routing_stop_flag,
-            join_handle: Some(::std::thread::spawn(move || {
-                while !*fake_routing_stop_flag_clone.lock().unwrap() {
-                    ::std::thread::sleep_ms(10);
-                    cloned_fake_routing_client.lock().unwrap().run();
-                }
-            })),
-        };
+        let fake_routing_client = ::std::sync::Arc::new(::std::sync::Mutex::new(non_networking_test_framework::RoutingClientMock::new(callback_interface.clone(), fake_account_packet.get_public_maid().name(), data_store.clone())));
 
         let structured_data_type_id: maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
-        let get_result = fake_routing_client.lock().unwrap().get(structured_data_type_id.type_tag(), user_network_id);
+        let get_result = send_get(&fake_routing_client, &notifier, &callback_interface, structured_data_type_id.type_tag(), user_network_id.clone());
 
         match get_result {
-            Ok(id) => {
-                let mut response_getter = response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+            Ok(mut response_getter) => {
                 match response_getter.get() {
                     Ok(raw_data) => {
                         let mut decoder = cbor::Decoder::from_bytes(raw_data);
@@ -162,10 +148,9 @@ impl Client {
                         match account_version.value().pop() {
                             Some(latest_version) => {
                                 let immutable_data_type_id: maidsafe_types::data::ImmutableDataTypeTag = unsafe { ::std::mem::uninitialized() };
-                                let get_result = fake_routing_client.lock().unwrap().get(immutable_data_type_id.type_tag(), latest_version);
+                                let get_result = send_get(&fake_routing_client, &notifier, &callback_interface, immutable_data_type_id.type_tag(), latest_version);
                                 match get_result {
-                                    Ok(id) => {
-                                        let mut response_getter = response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                                    Ok(mut response_getter) => {
                                         match response_getter.get() {
                                             Ok(raw_data) => {
                                                 let mut decoder = cbor::Decoder::from_bytes(raw_data);
@@ -182,23 +167,14 @@ impl Client {
 
                                                 //TODO: Toggle depending on if using actual routing or non_networking_test_framework
                                                 // let routing_client = ::std::sync::Arc::new(::std::sync::Mutex::new(routing::routing_client::RoutingClient::new(callback_interface.clone(), client_id_packet)));
-                                                let routing_client = ::std::sync::Arc::new(::std::sync::Mutex::new(non_networking_test_framework::RoutingClientMock::new(callback_interface.clone(), data_store)));
-                                                let cloned_routing_client = routing_client.clone();
-                                                let routing_stop_flag = ::std::sync::Arc::new(::std::sync::Mutex::new(false));
-                                                let routing_stop_flag_clone = routing_stop_flag.clone();
+                                                let routing_client = ::std::sync::Arc::new(::std::sync::Mutex::new(non_networking_test_framework::RoutingClientMock::new(callback_interface.clone(), account_packet.get_public_maid().name(), data_store)));
 
                                                 let client = Client {
                                                     account: account_packet,
+                                                    session_packet_keys: SessionPacketKeys { id: user_network_id, password: password.to_vec(), pin: pin },
                                                     routing: routing_client,
                                                     callback_interface: callback_interface,
                                                     response_notifier: notifier,
-                                                    routing_stop_flag: routing_stop_flag,
-                                                    routing_join_handle: Some(::std::thread::spawn(move || {
-                                                        while !*routing_stop_flag_clone.lock().unwrap() {
-                                                            ::std::thread::sleep_ms(10);
-                                                            cloned_routing_client.lock().unwrap().run();
-                                                        }
-                                                    })),
                                                 };
 
                                                 Ok(client)
@@ -344,42 +320,166 @@ impl Client {
         }
     }
 
+    /// Space the account uses on the network and what remains of its quota
+    pub fn get_account_info(&self) -> Result<AccountInfo, ::IoError> {
+        self.routing.lock().unwrap().get_account_info()
+    }
+
     pub fn get_owner(&self) -> routing::NameType {
         self.account.get_public_maid().name()
     }
 
-    pub fn put<T>(&mut self, sendable: T) -> Result<response_getter::ResponseGetter, ::IoError> where T: Sendable {
-        match self.routing.lock().unwrap().put(sendable) {
-            Ok(id)      => Ok(response_getter::ResponseGetter::new(id, self.response_notifier.clone(), self.callback_interface.clone())),
-            Err(io_err) => Err(io_err),
+    /// Id of the root directory of the user, once one has been created
+    pub fn get_user_root_directory_id(&self) -> Option<routing::NameType> {
+        self.account.get_root_dir_id().clone()
+    }
+
+    /// Records the root directory of the user in the session packet
+    pub fn set_user_root_directory_id(&mut self, root_dir_id: routing::NameType) -> Result<(), ::IoError> {
+        self.account.set_root_dir_id(root_dir_id);
+        self.save_session_packet()
+    }
+
+    /// Id of the well-known container of that name, once it has been provisioned
+    pub fn get_well_known_directory_id(&self, name: &str) -> Option<routing::NameType> {
+        self.account.get_well_known_dir_id(name).cloned()
+    }
+
+    /// Records well-known containers, by name, in the session packet
+    pub fn set_well_known_directory_ids(&mut self, dir_ids: ::std::collections::BTreeMap<String, routing::NameType>) -> Result<(), ::IoError> {
+        for (name, dir_id) in dir_ids.into_iter() {
+            self.account.set_well_known_dir_id(name, dir_id);
         }
+        self.save_session_packet()
     }
 
-    pub fn get(&mut self, tag_id: u64, name: routing::NameType) -> Result<response_getter::ResponseGetter, ::IoError> {
-        match self.routing.lock().unwrap().get(tag_id, name) {
-            Ok(id)      => Ok(response_getter::ResponseGetter::new(id, self.response_notifier.clone(), self.callback_interface.clone())),
-            Err(io_err) => Err(io_err),
+    /// Re-encrypts the session packet with a new password. The keyword and PIN stay the same.
+    pub fn change_password(&mut self, new_password: &[u8]) -> Result<(), ::IoError> {
+        let old_password = ::std::mem::replace(&mut self.session_packet_keys.password, new_password.to_vec());
+        let result = self.save_session_packet();
+        if result.is_err() {
+            self.session_packet_keys.password = old_password;
+        }
+        result
+    }
+
+    /// Stores the Account as a new version of the session packet
+    fn save_session_packet(&mut self) -> Result<(), ::IoError> {
+        let encrypted = match self.account.encrypt(&self.session_packet_keys.password[..], self.session_packet_keys.pin) {
+            Ok(encrypted) => encrypted,
+            Err(_) => return Err(::IoError::new(::std::io::ErrorKind::Other, "Could Not Encrypt Session Packet !!")),
+        };
+        let encrypted_account = maidsafe_types::ImmutableData::new(encrypted);
+        if try!(self.put(encrypted_account.clone())).get().is_err() {
+            return Err(::IoError::new(::std::io::ErrorKind::Other, "Session-Packet PUT-Response Failure !!"));
         }
+
+        let structured_data_type_id: maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
+        let session_packet_id = self.session_packet_keys.id.clone();
+        let raw_data = match try!(self.get(structured_data_type_id.type_tag(), session_packet_id)).get() {
+            Ok(raw_data) => raw_data,
+            Err(_) => return Err(::IoError::new(::std::io::ErrorKind::Other, "Version-Packet GET-Response Failure !!")),
+        };
+        let mut decoder = cbor::Decoder::from_bytes(raw_data);
+        let mut account_version: maidsafe_types::StructuredData = match decoder.decode().next() {
+            Some(Ok(account_version)) => account_version,
+            _ => return Err(::IoError::new(::std::io::ErrorKind::Other, "Could Not Decode Version-Packet !!")),
+        };
+        let mut versions = account_version.value();
+        versions.push(encrypted_account.name());
+        account_version.set_value(versions);
+        if try!(self.put(account_version)).get().is_err() {
+            return Err(::IoError::new(::std::io::ErrorKind::Other, "Version-Packet PUT-Response Failure !!"));
+        }
+        Ok(())
+    }
+
+    pub fn put<T>(&mut self, sendable: T) -> Result<response_getter::ResponseGetter, ::IoError> where T: Sendable {
+        send_put(&self.routing, &self.response_notifier, &self.callback_interface, sendable)
+    }
+
+    pub fn get(&mut self, tag_id: u64, name: routing::NameType) -> Result<response_getter::ResponseGetter, ::IoError> {
+        send_get(&self.routing, &self.response_notifier, &self.callback_interface, tag_id, name)
     }
 }
 
-impl Drop for Client {
-    fn drop(&mut self) {
-        *self.routing_stop_flag.lock().unwrap() = true;
-        self.routing_join_handle.take().unwrap().join().unwrap();
+//TODO: Toggle depending on if using actual routing or non_networking_test_framework
+type Routing = ::std::sync::Arc<::std::sync::Mutex<non_networking_test_framework::RoutingClientMock>>;
+
+/// A copy of what was PUT, kept so that it can be sent again
+#[derive(Clone)]
+struct RawSendable {
+    name:     routing::NameType,
+    type_tag: u64,
+    contents: Vec<u8>,
+}
+
+impl Sendable for RawSendable {
+    fn name(&self) -> routing::NameType {
+        self.name.clone()
+    }
+
+    fn type_tag(&self) -> u64 {
+        self.type_tag
     }
+
+    fn serialised_contents(&self) -> Vec<u8> {
+        self.contents.clone()
+    }
+
+    fn refresh(&self) -> bool {
+        false
+    }
+
+    fn merge(&self, _: Vec<Box<Sendable>>) -> Option<Box<Sendable>> {
+        None
+    }
+}
+
+fn send_get(routing: &Routing,
+            notifier: &::ResponseNotifier,
+            cb_interface: &::std::sync::Arc<::std::sync::Mutex<callback_interface::CallbackInterface>>,
+            tag_id: u64,
+            name: routing::NameType) -> Result<response_getter::ResponseGetter, ::IoError> {
+    let id = try!(routing.lock().unwrap().get(tag_id, name.clone()));
+    let resending_routing = routing.clone();
+    let resend: response_getter::Sender = Box::new(move || resending_routing.lock().unwrap().get(tag_id, name.clone()));
+    Ok(response_getter::ResponseGetter::new(id, notifier.clone(), cb_interface.clone()).with_resend(response_getter::Resend::Get(resend))
+                                                                                       .with_driver(driver(routing)))
+}
+
+fn send_put<T>(routing: &Routing,
+               notifier: &::ResponseNotifier,
+               cb_interface: &::std::sync::Arc<::std::sync::Mutex<callback_interface::CallbackInterface>>,
+               sendable: T) -> Result<response_getter::ResponseGetter, ::IoError> where T: Sendable {
+    let structured_data_type_id: maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
+    let copy = RawSendable { name: sendable.name(), type_tag: sendable.type_tag(), contents: sendable.serialised_contents() };
+    let id = try!(routing.lock().unwrap().put(sendable));
+    let is_structured_data = copy.type_tag == structured_data_type_id.type_tag();
+    let resending_routing = routing.clone();
+    let resend: response_getter::Sender = Box::new(move || resending_routing.lock().unwrap().put(copy.clone()));
+    let resend = if is_structured_data { response_getter::Resend::Update(resend) } else { response_getter::Resend::Put(resend) };
+    Ok(response_getter::ResponseGetter::new(id, notifier.clone(), cb_interface.clone()).with_resend(resend)
+                                                                                       .with_driver(driver(routing)))
+}
+
+//TODO: Not needed with actual routing, which delivers responses in real time
+fn driver(routing: &Routing) -> response_getter::Driver {
+    let routing = routing.clone();
+    Box::new(move || routing.lock().unwrap().advance())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use routing::sendable::Sendable;
 
     #[test]
     fn account_creation() {
         let keyword = "Spandan".to_string();
         let password = "Sharma".as_bytes();
         let pin = 1234u32;
-        let data_store = ::std::sync::Arc::new(::std::sync::Mutex::new(::std::collections::BTreeMap::new()));
+        let data_store = ::client::non_networking_test_framework::get_new_data_store();
         let result = Client::create_account(&keyword, pin, &password, data_store);
         assert!(result.is_ok());
     }
@@ -419,13 +519,53 @@ mod test {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn password_change() {
+        let keyword = "Spandan".to_string();
+        let pin = 1234u32;
+        let data_store = ::client::non_networking_test_framework::get_new_data_store();
+
+        let mut client = Client::create_account(&keyword, pin, "Sharma".as_bytes(), data_store.clone()).ok().unwrap();
+        assert!(client.change_password("Shrama".as_bytes()).is_ok());
+
+        assert!(Client::log_in(&keyword, pin, "Sharma".as_bytes(), data_store.clone()).is_err());
+        assert!(Client::log_in(&keyword, pin, "Shrama".as_bytes(), data_store).is_ok());
+    }
+
+    #[test]
+    fn account_info_and_quota() {
+        let data_store = ::client::non_networking_test_framework::get_new_data_store();
+        let mut client = Client::create_account(&"Spandan".to_string(), 1234, "Sharma".as_bytes(), data_store.clone()).ok().unwrap();
+        let info = client.get_account_info().ok().unwrap();
+        // At least the session packet and its version
+        assert!(info.chunk_count >= 2);
+        assert_eq!(info.used_space + info.available_space, ::client::non_networking_test_framework::DEFAULT_STORAGE_QUOTA);
+
+        let immutable_data = ::maidsafe_types::ImmutableData::new(vec![7u8; 1000]);
+        assert!(client.put(immutable_data.clone()).ok().unwrap().get().is_ok());
+        let stored = client.get_account_info().ok().unwrap();
+        assert_eq!(stored.chunk_count, info.chunk_count + 1);
+        assert_eq!(stored.used_space, info.used_space + immutable_data.serialised_contents().len() as u64);
+
+        // Another account is charged separately
+        let _other = Client::create_account(&"Other".to_string(), 1234, "Sharma".as_bytes(), data_store.clone()).ok().unwrap();
+        assert_eq!(client.get_account_info().ok().unwrap(), stored);
+
+        ::client::non_networking_test_framework::set_storage_quota(&data_store, stored.used_space + 100).ok().unwrap();
+        assert_eq!(client.get_account_info().ok().unwrap().available_space, 100);
+        let result = client.put(::maidsafe_types::ImmutableData::new(vec![8u8; 1000])).ok().unwrap().get();
+        assert!(::client::is_out_of_space(&result.err().unwrap()));
+        assert_eq!(client.get_account_info().ok().unwrap(), ::client::AccountInfo { used_space: stored.used_space, available_space: 100, chunk_count: stored.chunk_count });
+        assert!(client.put(::maidsafe_types::ImmutableData::new(vec![9u8; 10])).ok().unwrap().get().is_ok());
+    }
+
     #[test]
     fn hybrid_encryption_decryption() {
         // Construct Client
         let keyword = "Spandan".to_string();
         let password = "Sharma".as_bytes();
         let pin = 1234u32;
-        let data_store = ::std::sync::Arc::new(::std::sync::Mutex::new(::std::collections::BTreeMap::new()));
+        let data_store = ::client::non_networking_test_framework::get_new_data_store();
 
         let result = Client::create_account(&keyword, pin, &password, data_store);
         assert!(result.is_ok());
@@ -0,0 +1,168 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use rand::{Rng, SeedableRng};
+
+/// Environment variable choosing the profile of every mock network in the process: unset for a
+/// reliable network, `hostile` or `hostile:<seed>` for FaultModel::hostile
+pub const PROFILE_VARIABLE: &'static str = "MAIDSAFE_CLIENT_MOCK_NETWORK";
+
+static PROCESS_SEED: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::ATOMIC_USIZE_INIT;
+
+/// How the mock network misbehaves. Each probability is drawn independently per operation from
+/// an RNG seeded by `seed`, so a run which fails can be repeated with the same faults as long as
+/// the operations are issued in the same order.
+#[derive(Clone, Debug)]
+pub struct FaultModel {
+    /// The operation fails without effect: a GET answers InvalidRequest, a PUT FailedToStoreData
+    /// without data
+    pub failure_probability:     f64,
+    /// The operation takes effect but its response never arrives
+    pub drop_probability:        f64,
+    /// A byte of the payload of a GET response is flipped
+    pub corruption_probability:  f64,
+    /// The response arrives twice
+    pub duplication_probability: f64,
+    /// The response is held back by up to max_reorder_delay_ms, letting later ones overtake it
+    pub reorder_probability:     f64,
+    pub max_reorder_delay_ms:    u32,
+    pub seed:                    u64,
+}
+
+impl FaultModel {
+    /// A network which always answers, in order
+    pub fn none() -> FaultModel {
+        FaultModel {
+            failure_probability: 0.0,
+            drop_probability: 0.0,
+            corruption_probability: 0.0,
+            duplication_probability: 0.0,
+            reorder_probability: 0.0,
+            max_reorder_delay_ms: 0,
+            seed: 0,
+        }
+    }
+
+    /// Faults a correct client is expected to ride out by retrying. Payloads are not corrupted
+    /// since nothing above routing could tell.
+    pub fn hostile(seed: u64) -> FaultModel {
+        FaultModel {
+            failure_probability: 0.05,
+            drop_probability: 0.02,
+            corruption_probability: 0.0,
+            duplication_probability: 0.1,
+            reorder_probability: 0.2,
+            max_reorder_delay_ms: 2000,
+            seed: seed,
+        }
+    }
+
+    /// The profile named by PROFILE_VARIABLE. Without a seed one is picked for the process and
+    /// printed, so that the run can be reproduced.
+    pub fn from_env() -> FaultModel {
+        let profile = match ::std::env::var(PROFILE_VARIABLE) {
+            Ok(profile) => profile,
+            Err(_) => return FaultModel::none(),
+        };
+        let mut parts = profile.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("hostile"), Some(seed)) => match seed.parse::<u64>() {
+                Ok(seed) => FaultModel::hostile(seed),
+                Err(_) => panic!("{}: {} is not a seed", PROFILE_VARIABLE, seed),
+            },
+            (Some("hostile"), None) => FaultModel::hostile(process_seed()),
+            (Some("reliable"), None) | (Some(""), None) => FaultModel::none(),
+            _ => panic!("{}: unknown profile {}", PROFILE_VARIABLE, profile),
+        }
+    }
+
+    pub fn is_faulty(&self) -> bool {
+        self.failure_probability > 0.0 || self.drop_probability > 0.0 || self.corruption_probability > 0.0 ||
+        self.duplication_probability > 0.0 || (self.reorder_probability > 0.0 && self.max_reorder_delay_ms > 0)
+    }
+
+    pub fn new_rng(&self) -> ::rand::XorShiftRng {
+        // XorShift must not be seeded with zeros alone
+        ::rand::XorShiftRng::from_seed([self.seed as u32, (self.seed >> 32) as u32, 0x9e3779b9, 0x7f4a7c15])
+    }
+}
+
+fn process_seed() -> u64 {
+    use std::io::Write;
+    use std::sync::atomic::Ordering;
+
+    let seed = ::rand::random::<u32>() as usize | 1;
+    let previous = PROCESS_SEED.compare_and_swap(0, seed, Ordering::SeqCst);
+    if previous != 0 {
+        return previous as u64;
+    }
+    let _ = writeln!(::std::io::stderr(), "Mock network is hostile with seed {0}; set {1}=hostile:{0} to repeat the run",
+                     seed, PROFILE_VARIABLE);
+    seed as u64
+}
+
+/// The faults drawn for one operation
+#[derive(Clone, Debug, PartialEq)]
+pub struct Faults {
+    pub fail:           bool,
+    pub drop:           bool,
+    /// Picks the byte to flip, modulo the payload length
+    pub corrupt:        Option<usize>,
+    pub duplicate:      bool,
+    pub extra_delay_ms: u32,
+}
+
+impl Faults {
+    pub fn draw<R: Rng>(rng: &mut R, model: &FaultModel) -> Faults {
+        // Always draw every value, so that the sequence does not depend on earlier outcomes
+        let fail = rng.next_f64() < model.failure_probability;
+        let drop = rng.next_f64() < model.drop_probability;
+        let corrupt = rng.next_f64() < model.corruption_probability;
+        let corrupt_at = rng.next_u32() as usize;
+        let duplicate = rng.next_f64() < model.duplication_probability;
+        let reorder = rng.next_f64() < model.reorder_probability;
+        let delay = rng.next_u32();
+        Faults {
+            fail: fail,
+            drop: drop,
+            corrupt: if corrupt { Some(corrupt_at) } else { None },
+            duplicate: duplicate,
+            extra_delay_ms: if reorder && model.max_reorder_delay_ms > 0 { delay % model.max_reorder_delay_ms } else { 0 },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_faults() {
+        let model = FaultModel::hostile(42);
+        let mut first = model.new_rng();
+        let mut second = model.new_rng();
+        let first_faults: Vec<Faults> = (0..100).map(|_| Faults::draw(&mut first, &model)).collect();
+        let second_faults: Vec<Faults> = (0..100).map(|_| Faults::draw(&mut second, &model)).collect();
+        assert_eq!(first_faults, second_faults);
+        assert!(first_faults.iter().any(|faults| faults.extra_delay_ms > 0));
+
+        let mut reliable = model.new_rng();
+        assert!((0..100).all(|_| Faults::draw(&mut reliable, &FaultModel::none()) == Faults {
+            fail: false, drop: false, corrupt: None, duplicate: false, extra_delay_ms: 0,
+        }));
+    }
+}
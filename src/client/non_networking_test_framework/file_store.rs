@@ -0,0 +1,174 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use routing;
+use rustc_serialize::hex::ToHex;
+use std::io::{Read, Write};
+
+const PENDING_DIR: &'static str = "pending";
+
+// A write takes moments, so a pending write this old was left by a crash rather than being made
+// by another process using the store
+const STALE_PENDING_SECS: u64 = 10 * 60;
+
+/// Keeps each chunk in a file of its own, named by the hex of its name and fanned out over
+/// subdirectories by the first byte. A chunk is written to the pending directory, synced and then
+/// renamed into place, so after a crash a chunk is either there in full or not there at all.
+/// Writes left pending by a crash are cleared when the store is next opened, while those other
+/// processes sharing the store are making are left alone.
+pub struct FileStore {
+    root: ::std::path::PathBuf,
+}
+
+impl FileStore {
+    pub fn open(root: &::std::path::Path) -> Result<FileStore, ::IoError> {
+        try!(::std::fs::create_dir_all(root.join(PENDING_DIR)));
+        try!(clear_pending(root, ::std::time::Duration::from_secs(STALE_PENDING_SECS)));
+        Ok(FileStore { root: root.to_path_buf() })
+    }
+
+    pub fn get_root(&self) -> &::std::path::Path {
+        &self.root
+    }
+
+    fn chunk_path(&self, name: &routing::NameType) -> ::std::path::PathBuf {
+        let hex = name.0.to_hex();
+        self.root.join(&hex[..2]).join(&hex)
+    }
+}
+
+impl super::Storage for FileStore {
+    fn get(&self, name: &routing::NameType) -> Result<Option<Vec<u8>>, ::IoError> {
+        let mut file = match ::std::fs::File::open(self.chunk_path(name)) {
+            Ok(file) => file,
+            Err(ref io_error) if io_error.kind() == ::std::io::ErrorKind::NotFound => return Ok(None),
+            Err(io_error) => return Err(io_error),
+        };
+        let mut data = Vec::new();
+        try!(file.read_to_end(&mut data));
+        Ok(Some(data))
+    }
+
+    fn contains(&self, name: &routing::NameType) -> Result<bool, ::IoError> {
+        match ::std::fs::metadata(self.chunk_path(name)) {
+            Ok(_) => Ok(true),
+            Err(ref io_error) if io_error.kind() == ::std::io::ErrorKind::NotFound => Ok(false),
+            Err(io_error) => Err(io_error),
+        }
+    }
+
+    fn put(&mut self, name: routing::NameType, data: Vec<u8>) -> Result<(), ::IoError> {
+        let path = self.chunk_path(&name);
+        let parent = path.parent().unwrap().to_path_buf();
+        try!(::std::fs::create_dir_all(&parent));
+
+        let pending_path = self.root.join(PENDING_DIR).join(format!("{}.{}", name.0.to_hex(), ::rand::random::<u64>()));
+        {
+            let mut file = try!(::std::fs::File::create(&pending_path));
+            try!(file.write_all(&data));
+            try!(file.sync_all());
+        }
+        try!(::std::fs::rename(&pending_path, &path));
+        sync_directory(&parent)
+    }
+}
+
+/// Removes the pending writes last modified at least `max_age` ago
+fn clear_pending(root: &::std::path::Path, max_age: ::std::time::Duration) -> Result<(), ::IoError> {
+    let now = ::std::time::SystemTime::now();
+    for entry in try!(::std::fs::read_dir(root.join(PENDING_DIR))) {
+        let entry = try!(entry);
+        let stale = match entry.metadata().and_then(|metadata| metadata.modified()) {
+            // A write which looks newer than now, from a clock set back, is left for a later opening
+            Ok(modified) => now.duration_since(modified).map(|age| age >= max_age).unwrap_or(false),
+            Err(ref io_error) if io_error.kind() == ::std::io::ErrorKind::NotFound => false,
+            Err(io_error) => return Err(io_error),
+        };
+        if !stale {
+            continue;
+        }
+        match ::std::fs::remove_file(entry.path()) {
+            Ok(()) => (),
+            Err(ref io_error) if io_error.kind() == ::std::io::ErrorKind::NotFound => (),
+            Err(io_error) => return Err(io_error),
+        }
+    }
+    Ok(())
+}
+
+/// Makes the rename durable
+#[cfg(unix)]
+fn sync_directory(path: &::std::path::Path) -> Result<(), ::IoError> {
+    try!(::std::fs::File::open(path)).sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_: &::std::path::Path) -> Result<(), ::IoError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use client::non_networking_test_framework::Storage;
+
+    fn temp_root() -> ::std::path::PathBuf {
+        ::std::env::temp_dir().join(format!("maidsafe_client_file_store_{}", ::rand::random::<u64>()))
+    }
+
+    #[test]
+    fn put_get_and_reopen() {
+        let root = temp_root();
+        let name = ::routing::NameType::new([3u8; 64]);
+        {
+            let mut store = FileStore::open(&root).ok().unwrap();
+            assert!(store.get(&name).ok().unwrap().is_none());
+            assert!(!store.contains(&name).ok().unwrap());
+            store.put(name.clone(), vec![1u8, 2, 3]).ok().unwrap();
+            store.put(name.clone(), vec![4u8, 5]).ok().unwrap();
+        }
+
+        // A write in progress in another process is left alone on opening
+        let pending = root.join(PENDING_DIR).join("in_progress");
+        ::std::fs::File::create(&pending).ok().unwrap();
+
+        let store = FileStore::open(&root).ok().unwrap();
+        assert!(store.contains(&name).ok().unwrap());
+        assert_eq!(store.get(&name).ok().unwrap(), Some(vec![4u8, 5]));
+        assert!(::std::fs::metadata(&pending).is_ok());
+
+        // One old enough to have been interrupted by a crash is dropped
+        clear_pending(&root, ::std::time::Duration::from_secs(0)).ok().unwrap();
+        assert!(::std::fs::metadata(&pending).is_err());
+        assert!(store.contains(&name).ok().unwrap());
+        let _ = ::std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn log_in_after_restart() {
+        let root = temp_root();
+        let keyword = "Spandan".to_string();
+        let password = "Sharma".as_bytes();
+        {
+            let data_store = ::client::non_networking_test_framework::get_file_data_store(&root).ok().unwrap();
+            assert!(::client::Client::create_account(&keyword, 1234, password, data_store).is_ok());
+        }
+        let data_store = ::client::non_networking_test_framework::get_file_data_store(&root).ok().unwrap();
+        assert!(::client::Client::log_in(&keyword, 1234, password, data_store).is_ok());
+        let _ = ::std::fs::remove_dir_all(&root);
+    }
+}
@@ -15,6 +15,7 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
+use cbor;
 use routing;
 use maidsafe_types::TypeTag;
 use routing::sendable::Sendable;
@@ -22,109 +23,345 @@ use routing::client_interface::Interface;
 
 use client::callback_interface;
 
-pub type DataStore = ::std::sync::Arc<::std::sync::Mutex<::std::collections::BTreeMap<routing::NameType, Vec<u8>>>>;
+mod file_store;
+mod fault_model;
 
+pub use self::file_store::FileStore;
+pub use self::fault_model::{FaultModel, Faults, PROFILE_VARIABLE};
+
+/// Bytes each account may store, unless set_storage_quota says otherwise
+pub const DEFAULT_STORAGE_QUOTA: u64 = 1 << 30;
+/// What a PUT taking its owner past the quota answers FailedToStoreData with
+pub const OUT_OF_SPACE: &'static [u8] = b"Out of space";
+
+/// Where the mock network keeps its data
+pub trait Storage: Send {
+    /// None if nothing is stored under the name
+    fn get(&self, name: &routing::NameType) -> Result<Option<Vec<u8>>, ::IoError>;
+    fn contains(&self, name: &routing::NameType) -> Result<bool, ::IoError>;
+    /// Stores the data under the name, replacing anything stored there before
+    fn put(&mut self, name: routing::NameType, data: Vec<u8>) -> Result<(), ::IoError>;
+}
+
+impl Storage for ::std::collections::BTreeMap<routing::NameType, Vec<u8>> {
+    fn get(&self, name: &routing::NameType) -> Result<Option<Vec<u8>>, ::IoError> {
+        Ok(::std::collections::BTreeMap::get(self, name).cloned())
+    }
+
+    fn contains(&self, name: &routing::NameType) -> Result<bool, ::IoError> {
+        Ok(self.contains_key(name))
+    }
+
+    fn put(&mut self, name: routing::NameType, data: Vec<u8>) -> Result<(), ::IoError> {
+        let _ = self.insert(name, data);
+        Ok(())
+    }
+}
+
+pub type DataStore = ::std::sync::Arc<::std::sync::Mutex<Box<Storage>>>;
+
+/// A store which lives as long as the process
 pub fn get_new_data_store() -> DataStore {
-    ::std::sync::Arc::new(::std::sync::Mutex::new(::std::collections::BTreeMap::new()))
+    ::std::sync::Arc::new(::std::sync::Mutex::new(Box::new(::std::collections::BTreeMap::<routing::NameType, Vec<u8>>::new())))
+}
+
+/// A store kept in the directory, so that the mock network survives restarts. The directory is
+/// created if need be.
+pub fn get_file_data_store(path: &::std::path::Path) -> Result<DataStore, ::IoError> {
+    let file_store = try!(FileStore::open(path));
+    Ok(::std::sync::Arc::new(::std::sync::Mutex::new(Box::new(file_store))))
+}
+
+/// Sets the bytes each account using the store may keep there. Accounts already past it can
+/// still store what shrinks or replaces their data, but nothing which adds to it.
+pub fn set_storage_quota(data_store: &DataStore, quota: u64) -> Result<(), ::IoError> {
+    data_store.lock().unwrap().put(storage_quota_name(), encode(&quota))
+}
+
+/// A response waiting for the virtual clock to reach its delivery time
+struct PendingResponse {
+    msg_id:   routing::types::MessageId,
+    response: Result<Vec<u8>, routing::error::ResponseError>,
+    get:      bool,
 }
 
+/// Answers from a DataStore in the same process. Time on this network is virtual: responses are
+/// queued for delivery network_delay_ms after their request, and are only delivered as whoever
+/// waits for them moves the clock on with advance or advance_by. No thread sleeps, so a test
+/// runs as fast as the client code allows and, with the same requests, the same way every time.
 pub struct RoutingClientMock {
     callback_interface: ::std::sync::Arc<::std::sync::Mutex<callback_interface::CallbackInterface>>,
+    client_id: routing::NameType,
     data_store: DataStore,
     msg_id: routing::types::MessageId,
     network_delay_ms: u32,
+    fault_model: FaultModel,
+    rng: ::rand::XorShiftRng,
+    now_ms: u64,
+    // Keyed by delivery time, then by order of sending
+    pending: ::std::collections::BTreeMap<(u64, u64), PendingResponse>,
+    sent_count: u64,
 }
 
 impl RoutingClientMock {
+    /// Requests are made as the client_id, which must own the StructuredData it updates. The
+    /// network misbehaves as the profile in the environment says, see FaultModel::from_env.
     pub fn new(cb_interface: ::std::sync::Arc<::std::sync::Mutex<callback_interface::CallbackInterface>>,
+               client_id: routing::NameType,
                data_store: DataStore) -> RoutingClientMock {
+        let fault_model = FaultModel::from_env();
         RoutingClientMock {
             callback_interface: cb_interface,
+            client_id: client_id,
             data_store: data_store,
             msg_id: 1,
             network_delay_ms: 1000,
+            rng: fault_model.new_rng(),
+            fault_model: fault_model,
+            now_ms: 0,
+            pending: ::std::collections::BTreeMap::new(),
+            sent_count: 0,
         }
     }
 
+    /// Virtual time since the mock was created
+    pub fn now_ms(&self) -> u64 {
+        self.now_ms
+    }
+
+    pub fn pending_response_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Moves the clock on to the next pending response and delivers it, along with any due at
+    /// the same time. False if no response is pending.
+    pub fn advance(&mut self) -> bool {
+        let next = match self.pending.keys().next() {
+            Some(&(delivery_time, _)) => delivery_time,
+            None => return false,
+        };
+        self.advance_to(next);
+        true
+    }
+
+    /// Moves the clock on by the duration, delivering the responses falling due
+    pub fn advance_by(&mut self, duration_ms: u64) {
+        let time = self.now_ms + duration_ms;
+        self.advance_to(time);
+    }
+
+    fn advance_to(&mut self, time: u64) {
+        loop {
+            let key = match self.pending.keys().next() {
+                Some(&key) if key.0 <= time => key,
+                _ => break,
+            };
+            let pending = self.pending.remove(&key).unwrap();
+            self.now_ms = key.0;
+            if pending.get {
+                self.callback_interface.lock().unwrap().handle_get_response(pending.msg_id, pending.response);
+            } else {
+                self.callback_interface.lock().unwrap().handle_put_response(pending.msg_id, pending.response);
+            }
+        }
+        self.now_ms = time;
+    }
+
     pub fn set_network_delay_for_delay_simulation(&mut self, delay_ms: u32) {
         self.network_delay_ms = delay_ms;
     }
 
+    /// Replaces the fault model, restarting its RNG from the seed
+    pub fn set_fault_model(&mut self, fault_model: FaultModel) {
+        self.rng = fault_model.new_rng();
+        self.fault_model = fault_model;
+    }
+
     pub fn get(&mut self, _type_id: u64, name: routing::NameType) -> Result<routing::types::MessageId, ::IoError> {
         self.msg_id += 1;
-        let msg_id = self.msg_id;
-        let delay_ms = self.network_delay_ms;
-        let cb_interface = self.callback_interface.clone();
-        let data_store = self.data_store.clone();
-
-        ::std::thread::spawn(move || {
-            ::std::thread::sleep_ms(delay_ms);
-            match data_store.lock().unwrap().get(&name) {
-                Some(data) => cb_interface.lock().unwrap().handle_get_response(msg_id, Ok(data.clone())),
-                None => cb_interface.lock().unwrap().handle_get_response(msg_id, Err(routing::error::ResponseError::NoData)),
-            };
-        });
+        let faults = Faults::draw(&mut self.rng, &self.fault_model);
 
+        let response = if faults.fail {
+            Err(routing::error::ResponseError::InvalidRequest)
+        } else {
+            match self.data_store.lock().unwrap().get(&name) {
+                Ok(Some(mut data)) => {
+                    if let Some(position) = faults.corrupt {
+                        if !data.is_empty() {
+                            let length = data.len();
+                            data[position % length] ^= 0xff;
+                        }
+                    }
+                    Ok(data)
+                },
+                _ => Err(routing::error::ResponseError::NoData),
+            }
+        };
+
+        self.respond(faults, response, true);
         Ok(self.msg_id)
     }
 
     pub fn put<T>(&mut self, sendable: T) -> Result<routing::types::MessageId, ::IoError> where T: routing::sendable::Sendable {
         self.msg_id += 1;
-        let msg_id = self.msg_id;
-        let delay_ms = self.network_delay_ms;
-        let cb_interface = self.callback_interface.clone();
-        let data_store = self.data_store.clone();
-
-        let structured_data_type_id: ::maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
-        let success: bool = if sendable.type_tag() != structured_data_type_id.type_tag() && data_store.lock().unwrap().contains_key(&sendable.name()) {
-            false
+        let faults = Faults::draw(&mut self.rng, &self.fault_model);
+        let response = if faults.fail {
+            Err(routing::error::ResponseError::FailedToStoreData(Vec::new()))
         } else {
-            data_store.lock().unwrap().insert(sendable.name(), sendable.serialised_contents());
-            true
+            try!(self.store(&sendable))
         };
-
-        ::std::thread::spawn(move || {
-            ::std::thread::sleep_ms(delay_ms);
-            if success {
-                cb_interface.lock().unwrap().handle_put_response(msg_id, Ok(Vec::<u8>::new()));
-            } else {
-                cb_interface.lock().unwrap().handle_put_response(msg_id, Err(routing::error::ResponseError::InvalidRequest));
-            }
-        });
-
+        self.respond(faults, response, false);
         Ok(self.msg_id)
     }
 
     pub fn unauthorised_put(&mut self, _: routing::NameType, sendable: Box<Sendable>) -> Result<routing::types::MessageId, ::IoError> {
         self.msg_id += 1;
-        let msg_id = self.msg_id;
-        let delay_ms = self.network_delay_ms;
-        let cb_interface = self.callback_interface.clone();
-        let data_store = self.data_store.clone();
+        let faults = Faults::draw(&mut self.rng, &self.fault_model);
+        let response = if faults.fail {
+            Err(routing::error::ResponseError::FailedToStoreData(Vec::new()))
+        } else {
+            try!(self.store(&*sendable))
+        };
+        self.respond(faults, response, false);
+        Ok(self.msg_id)
+    }
+
+    /// Space used by the client's account, as its MaidManagers would account for it
+    //TODO: Not yet something routing can be asked
+    pub fn get_account_info(&self) -> Result<::client::AccountInfo, ::IoError> {
+        let data_store = self.data_store.lock().unwrap();
+        let (used_space, chunk_count) = try!(get_usage(&**data_store, &self.client_id));
+        let quota = try!(get_quota(&**data_store));
+        Ok(::client::AccountInfo {
+            used_space: used_space,
+            available_space: quota.saturating_sub(used_space),
+            chunk_count: chunk_count,
+        })
+    }
 
+    /// ImmutableData is never overwritten. StructuredData is stored by its owner only, and updated
+    /// only with a value made from the stored one. What is stored is charged to the client's
+    /// account, StructuredData by how much it grows. Answers as vaults do:
+    ///
+    /// - InvalidRequest if the ImmutableData exists, or the StructuredData is not the client's
+    /// - FailedToStoreData with the stored StructuredData if the update is stale
+    /// - FailedToStoreData with OUT_OF_SPACE if the account would go past its quota
+    fn store(&self, sendable: &Sendable) -> Result<Result<Vec<u8>, routing::error::ResponseError>, ::IoError> {
         let structured_data_type_id: ::maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
-        let success: bool = if sendable.type_tag() != structured_data_type_id.type_tag() && data_store.lock().unwrap().contains_key(&sendable.name()) {
-            false
+        let name = sendable.name();
+        let contents = sendable.serialised_contents();
+        let mut data_store = self.data_store.lock().unwrap();
+        let mut replaced_size = None;
+        if sendable.type_tag() != structured_data_type_id.type_tag() {
+            if try!(data_store.contains(&name)) {
+                return Ok(Err(routing::error::ResponseError::InvalidRequest));
+            }
         } else {
-            data_store.lock().unwrap().insert(sendable.name(), sendable.serialised_contents());
-            true
+            let updated = try!(decode_structured_data(&contents));
+            if updated.owner() != Some(self.client_id.clone()) {
+                return Ok(Err(routing::error::ResponseError::InvalidRequest));
+            }
+            if let Some(stored_contents) = try!(data_store.get(&name)) {
+                let stored = try!(decode_structured_data(&stored_contents));
+                if stored.owner() != Some(self.client_id.clone()) {
+                    return Ok(Err(routing::error::ResponseError::InvalidRequest));
+                }
+                if !follows(&stored.value()[..], &updated.value()[..]) {
+                    return Ok(Err(routing::error::ResponseError::FailedToStoreData(stored_contents)));
+                }
+                replaced_size = Some(stored_contents.len() as u64);
+            }
+        }
+
+        let (used_space, chunk_count) = try!(get_usage(&**data_store, &self.client_id));
+        let size = contents.len() as u64;
+        let usage = match replaced_size {
+            Some(replaced_size) => ((used_space + size).saturating_sub(replaced_size), chunk_count),
+            None => (used_space + size, chunk_count + 1),
         };
+        if usage.0 > used_space && usage.0 > try!(get_quota(&**data_store)) {
+            return Ok(Err(routing::error::ResponseError::FailedToStoreData(OUT_OF_SPACE.to_vec())));
+        }
+        try!(data_store.put(name, contents));
+        try!(data_store.put(usage_name(&self.client_id), encode(&usage)));
+        Ok(Ok(Vec::<u8>::new()))
+    }
 
-        ::std::thread::spawn(move || {
-            ::std::thread::sleep_ms(delay_ms);
-            if success {
-                cb_interface.lock().unwrap().handle_put_response(msg_id, Ok(Vec::<u8>::new()));
-            } else {
-                cb_interface.lock().unwrap().handle_put_response(msg_id, Err(routing::error::ResponseError::InvalidRequest));
-            }
-        });
+    /// Queues the response to the current message for after the network delay, unless it is dropped
+    fn respond(&mut self, faults: Faults, response: Result<Vec<u8>, routing::error::ResponseError>, get: bool) {
+        if faults.drop {
+            return;
+        }
+        let delivery_time = self.now_ms + (self.network_delay_ms + faults.extra_delay_ms) as u64;
+        let copies = if faults.duplicate { 2 } else { 1 };
+        for _ in 0..copies {
+            self.sent_count += 1;
+            let _ = self.pending.insert((delivery_time, self.sent_count), PendingResponse {
+                msg_id: self.msg_id,
+                response: response.clone(),
+                get: get,
+            });
+        }
+    }
+}
 
-        Ok(self.msg_id)
+// Accounting is kept in the store itself, under names of its own, so that it lasts as long as the
+// data does and is shared by every client of the store
+fn usage_name(owner: &routing::NameType) -> routing::NameType {
+    let mut seed = owner.0.to_vec();
+    seed.extend(b"mock account usage".iter().cloned());
+    routing::NameType(::sodiumoxide::crypto::hash::sha512::hash(&seed[..]).0)
+}
+
+fn storage_quota_name() -> routing::NameType {
+    routing::NameType(::sodiumoxide::crypto::hash::sha512::hash(b"mock storage quota").0)
+}
+
+/// Bytes and chunks stored by the owner
+fn get_usage(data_store: &Storage, owner: &routing::NameType) -> Result<(u64, u64), ::IoError> {
+    Ok(try!(get_entry(data_store, &usage_name(owner))).unwrap_or((0, 0)))
+}
+
+fn get_quota(data_store: &Storage) -> Result<u64, ::IoError> {
+    Ok(try!(get_entry(data_store, &storage_quota_name())).unwrap_or(DEFAULT_STORAGE_QUOTA))
+}
+
+fn get_entry<T>(data_store: &Storage, name: &routing::NameType) -> Result<Option<T>, ::IoError> where T: ::rustc_serialize::Decodable {
+    match try!(data_store.get(name)) {
+        Some(data) => match cbor::Decoder::from_bytes(data).decode().next() {
+            Some(Ok(entry)) => Ok(Some(entry)),
+            _ => Err(::IoError::new(::std::io::ErrorKind::InvalidData, "Could Not Decode Account Entry !!")),
+        },
+        None => Ok(None),
+    }
+}
+
+fn encode<T>(entry: &T) -> Vec<u8> where T: ::rustc_serialize::Encodable {
+    let mut encoder = cbor::Encoder::from_memory();
+    encoder.encode(&[entry]).unwrap();
+    encoder.into_bytes()
+}
+
+fn decode_structured_data(contents: &[u8]) -> Result<::maidsafe_types::StructuredData, ::IoError> {
+    match cbor::Decoder::from_bytes(contents).decode().next() {
+        Some(Ok(structured_data)) => Ok(structured_data),
+        _ => Err(::IoError::new(::std::io::ErrorKind::InvalidData, "Could Not Decode StructuredData !!")),
     }
+}
 
-    pub fn run(&mut self) {
-        ;
+/// Whether an update of StructuredData from the stored value was made from that value, so either
+/// only appends to it or only removes from it. An update which does both, like one made from an
+/// older value and adding its own, is stale. Removals keep the latest version, so one made from an
+/// older value, missing the versions added since, is stale too.
+fn follows(stored: &[routing::NameType], updated: &[routing::NameType]) -> bool {
+    if updated.len() >= stored.len() && &updated[..stored.len()] == stored {
+        return true;
     }
+    if updated.last() != stored.last() {
+        return false;
+    }
+    let mut remaining = stored.iter();
+    updated.iter().all(|version| remaining.any(|stored_version| stored_version == version))
 }
 
 #[cfg(test)]
@@ -137,39 +374,24 @@ mod test {
 
     use super::*;
 
+    fn response_getter(mock_routing: &::std::sync::Arc<::std::sync::Mutex<RoutingClientMock>>,
+                       id: ::routing::types::MessageId,
+                       notifier: &::ResponseNotifier,
+                       callback_interface: &::std::sync::Arc<::std::sync::Mutex<::client::callback_interface::CallbackInterface>>) -> ::client::response_getter::ResponseGetter {
+        let mock_routing = mock_routing.clone();
+        ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone())
+            .with_driver(Box::new(move || mock_routing.lock().unwrap().advance()))
+    }
+
     #[test]
     fn check_unauthorised_put() {
         let notifier = ::std::sync::Arc::new((::std::sync::Mutex::new(0), ::std::sync::Condvar::new()));
         let account_packet = ::client::user_account::Account::new(None);
         let callback_interface = ::std::sync::Arc::new(::std::sync::Mutex::new(::client::callback_interface::CallbackInterface::new(notifier.clone())));
 
-        let mock_routing = ::std::sync::Arc::new(::std::sync::Mutex::new(RoutingClientMock::new(callback_interface.clone(), get_new_data_store())));
-        let mock_routing_clone = mock_routing.clone();
-
-        let mock_routing_stop_flag = ::std::sync::Arc::new(::std::sync::Mutex::new(false));
-        let mock_routing_stop_flag_clone = mock_routing_stop_flag.clone();
-
-        struct RAIIThreadExit {
-            routing_stop_flag: ::std::sync::Arc<::std::sync::Mutex<bool>>,
-            join_handle: Option<::std::thread::JoinHandle<()>>,
-        }
-
-        impl Drop for RAIIThreadExit {
-            fn drop(&mut self) {
-                *self.routing_stop_flag.lock().unwrap() = true;
-                self.join_handle.take().unwrap().join().unwrap();
-            }
-        }
-
-        let _managed_thread = RAIIThreadExit {
-            routing_stop_flag: mock_routing_stop_flag,
-            join_handle: Some(::std::thread::spawn(move || {
-                while !*mock_routing_stop_flag_clone.lock().unwrap() {
-                    ::std::thread::sleep_ms(10);
-                    mock_routing_clone.lock().unwrap().run();
-                }
-            })),
-        };
+        let mock_routing = ::std::sync::Arc::new(::std::sync::Mutex::new(RoutingClientMock::new(callback_interface.clone(), account_packet.get_public_maid().name(), get_new_data_store())));
+        // These check the mock itself, so not under the profile in the environment
+        mock_routing.lock().unwrap().set_fault_model(FaultModel::none());
 
         // First Unauthorised-PUT should succeed
         {
@@ -177,7 +399,7 @@ mod test {
             let boxed_public_maid = Box::new(account_packet.get_public_maid().clone());
             match mock_routing.lock().unwrap().unauthorised_put(destination, boxed_public_maid) {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(_) => (),
                         Err(error) => panic!("Unauthorised-PUT Response Failure :: {:?}", error.description()),
@@ -194,7 +416,7 @@ mod test {
             let unauthorised_put_result = mock_routing.lock().unwrap().unauthorised_put(destination, boxed_public_maid);
             match unauthorised_put_result {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(_) => panic!("Overwriting of Existing Data Should Not Be Allowed !!"),
                         Err(_) => (),
@@ -210,33 +432,9 @@ mod test {
         let notifier = ::std::sync::Arc::new((::std::sync::Mutex::new(0), ::std::sync::Condvar::new()));
         let callback_interface = ::std::sync::Arc::new(::std::sync::Mutex::new(::client::callback_interface::CallbackInterface::new(notifier.clone())));
 
-        let mock_routing = ::std::sync::Arc::new(::std::sync::Mutex::new(RoutingClientMock::new(callback_interface.clone(), get_new_data_store())));
-        let mock_routing_clone = mock_routing.clone();
-
-        let mock_routing_stop_flag = ::std::sync::Arc::new(::std::sync::Mutex::new(false));
-        let mock_routing_stop_flag_clone = mock_routing_stop_flag.clone();
-
-        struct RAIIThreadExit {
-            routing_stop_flag: ::std::sync::Arc<::std::sync::Mutex<bool>>,
-            join_handle: Option<::std::thread::JoinHandle<()>>,
-        }
-
-        impl Drop for RAIIThreadExit {
-            fn drop(&mut self) {
-                *self.routing_stop_flag.lock().unwrap() = true;
-                self.join_handle.take().unwrap().join().unwrap();
-            }
-        }
-
-        let _managed_thread = RAIIThreadExit {
-            routing_stop_flag: mock_routing_stop_flag,
-            join_handle: Some(::std::thread::spawn(move || {
-                while !*mock_routing_stop_flag_clone.lock().unwrap() {
-                    ::std::thread::sleep_ms(10);
-                    mock_routing_clone.lock().unwrap().run();
-                }
-            })),
-        };
+        let mock_routing = ::std::sync::Arc::new(::std::sync::Mutex::new(RoutingClientMock::new(callback_interface.clone(), ::routing::NameType::new([1u8; 64]), get_new_data_store())));
+        // These check the mock itself, so not under the profile in the environment
+        mock_routing.lock().unwrap().set_fault_model(FaultModel::none());
 
         // Construct ImmutableData
         let immutable_data_type_id: maidsafe_types::data::ImmutableDataTypeTag = unsafe { ::std::mem::uninitialized() };
@@ -247,7 +445,7 @@ mod test {
         {
             match mock_routing.lock().unwrap().get(immutable_data_type_id.type_tag(), orig_immutable_data.name()) {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(_) => panic!("Should not have found data before a PUT"),
                         Err(_) => (),
@@ -261,7 +459,7 @@ mod test {
         {
             match mock_routing.lock().unwrap().put(orig_immutable_data.clone()) {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(_) => (),
                         Err(error) => panic!("PUT Response Failure :: {:?}", error.description()),
@@ -275,7 +473,7 @@ mod test {
         {
             match mock_routing.lock().unwrap().get(immutable_data_type_id.type_tag(), orig_immutable_data.name()) {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(data) => {
                             let mut decoder = ::cbor::Decoder::from_bytes(&data[..]);
@@ -296,7 +494,7 @@ mod test {
             let put_result = mock_routing.lock().unwrap().put(orig_immutable_data.clone());
             match put_result {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(_) => panic!("Second PUT for same ImmutableData should fail !!"),
                         Err(_) => (),
@@ -313,33 +511,9 @@ mod test {
         let account_packet = ::client::user_account::Account::new(None);
         let callback_interface = ::std::sync::Arc::new(::std::sync::Mutex::new(::client::callback_interface::CallbackInterface::new(notifier.clone())));
 
-        let mock_routing = ::std::sync::Arc::new(::std::sync::Mutex::new(RoutingClientMock::new(callback_interface.clone(), get_new_data_store())));
-        let mock_routing_clone = mock_routing.clone();
-
-        let mock_routing_stop_flag = ::std::sync::Arc::new(::std::sync::Mutex::new(false));
-        let mock_routing_stop_flag_clone = mock_routing_stop_flag.clone();
-
-        struct RAIIThreadExit {
-            routing_stop_flag: ::std::sync::Arc<::std::sync::Mutex<bool>>,
-            join_handle: Option<::std::thread::JoinHandle<()>>,
-        }
-
-        impl Drop for RAIIThreadExit {
-            fn drop(&mut self) {
-                *self.routing_stop_flag.lock().unwrap() = true;
-                self.join_handle.take().unwrap().join().unwrap();
-            }
-        }
-
-        let _managed_thread = RAIIThreadExit {
-            routing_stop_flag: mock_routing_stop_flag,
-            join_handle: Some(::std::thread::spawn(move || {
-                while !*mock_routing_stop_flag_clone.lock().unwrap() {
-                    ::std::thread::sleep_ms(10);
-                    mock_routing_clone.lock().unwrap().run();
-                }
-            })),
-        };
+        let mock_routing = ::std::sync::Arc::new(::std::sync::Mutex::new(RoutingClientMock::new(callback_interface.clone(), account_packet.get_public_maid().name(), get_new_data_store())));
+        // These check the mock itself, so not under the profile in the environment
+        mock_routing.lock().unwrap().set_fault_model(FaultModel::none());
 
         // Construct ImmutableData
         let immutable_data_type_id: maidsafe_types::data::ImmutableDataTypeTag = unsafe { ::std::mem::uninitialized() };
@@ -359,7 +533,7 @@ mod test {
         {
             match mock_routing.lock().unwrap().get(structured_data_type_id.type_tag(), user_id.clone()) {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(_) => panic!("Should not have found data before a PUT"),
                         Err(_) => (),
@@ -373,7 +547,7 @@ mod test {
         {
             match mock_routing.lock().unwrap().put(account_version.clone()) {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(_) => (),
                         Err(error) => panic!("PUT Response Failure :: {:?}", error.description()),
@@ -387,7 +561,7 @@ mod test {
         {
             match mock_routing.lock().unwrap().put(orig_immutable_data.clone()) {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(_) => (),
                         Err(error) => panic!("PUT Response Failure :: {:?}", error.description()),
@@ -403,7 +577,7 @@ mod test {
         {
             match mock_routing.lock().unwrap().get(structured_data_type_id.type_tag(), user_id.clone()) {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(data) => {
                             let mut decoder = ::cbor::Decoder::from_bytes(&data[..]);
@@ -422,7 +596,7 @@ mod test {
         {
             match mock_routing.lock().unwrap().get(immutable_data_type_id.type_tag(), received_structured_data.value().pop().unwrap()) {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(data) => {
                             let mut decoder = ::cbor::Decoder::from_bytes(&data[..]);
@@ -445,7 +619,7 @@ mod test {
         {
             match mock_routing.lock().unwrap().put(new_immutable_data.clone()) {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(_) => (),
                         Err(error) => panic!("PUT Response Failure :: {:?}", error.description()),
@@ -465,7 +639,7 @@ mod test {
             let put_result = mock_routing.lock().unwrap().put(account_version.clone());
             match put_result {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(_) => (),
                         Err(_) => panic!("StructuredData should be allowed to be overwritten !!"),
@@ -479,7 +653,7 @@ mod test {
         {
             match mock_routing.lock().unwrap().get(structured_data_type_id.type_tag(), user_id.clone()) {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(data) => {
                             let mut decoder = ::cbor::Decoder::from_bytes(&data[..]);
@@ -501,7 +675,7 @@ mod test {
             let get_result = mock_routing.lock().unwrap().get(immutable_data_type_id.type_tag(), received_structured_data.value()[1].clone());
             match get_result {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(data) => {
                             let mut decoder = ::cbor::Decoder::from_bytes(&data[..]);
@@ -521,7 +695,7 @@ mod test {
             let get_result = mock_routing.lock().unwrap().get(immutable_data_type_id.type_tag(), received_structured_data.value()[0].clone());
             match get_result {
                 Ok(id) => {
-                    let mut response_getter = ::client::response_getter::ResponseGetter::new(id, notifier.clone(), callback_interface.clone());
+                    let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface);
                     match response_getter.get() {
                         Ok(data) => {
                             let mut decoder = ::cbor::Decoder::from_bytes(&data[..]);
@@ -536,4 +710,132 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn faults_and_retries() {
+        let notifier = ::std::sync::Arc::new((::std::sync::Mutex::new(0), ::std::sync::Condvar::new()));
+        let callback_interface = ::std::sync::Arc::new(::std::sync::Mutex::new(::client::callback_interface::CallbackInterface::new(notifier.clone())));
+        let mock_routing = ::std::sync::Arc::new(::std::sync::Mutex::new(RoutingClientMock::new(callback_interface.clone(), ::routing::NameType::new([1u8; 64]), get_new_data_store())));
+        mock_routing.lock().unwrap().set_fault_model(FaultModel::none());
+
+        let immutable_data = maidsafe_types::ImmutableData::new(vec![7u8; 100]);
+        let immutable_data_type_id: maidsafe_types::data::ImmutableDataTypeTag = unsafe { ::std::mem::uninitialized() };
+        let tag = immutable_data_type_id.type_tag();
+        let name = immutable_data.name();
+        let id = mock_routing.lock().unwrap().put(immutable_data.clone()).ok().unwrap();
+        assert!(response_getter(&mock_routing, id, &notifier, &callback_interface).get().is_ok());
+        let stored = {
+            let id = mock_routing.lock().unwrap().get(tag, name.clone()).ok().unwrap();
+            response_getter(&mock_routing, id, &notifier, &callback_interface).get().ok().unwrap()
+        };
+
+        // A dropped response times out
+        let mut model = FaultModel::none();
+        model.drop_probability = 1.0;
+        mock_routing.lock().unwrap().set_fault_model(model);
+        {
+            let id = mock_routing.lock().unwrap().get(tag, name.clone()).ok().unwrap();
+            assert!(response_getter(&mock_routing, id, &notifier, &callback_interface).get().is_err());
+        }
+
+        // A failed GET is sent again, and succeeds once the network recovers
+        let mut model = FaultModel::none();
+        model.failure_probability = 1.0;
+        mock_routing.lock().unwrap().set_fault_model(model);
+        {
+            let id = mock_routing.lock().unwrap().get(tag, name.clone()).ok().unwrap();
+            let mock_routing_clone = mock_routing.clone();
+            let name_clone = name.clone();
+            let resend: ::client::response_getter::Sender = Box::new(move || {
+                let mut mock_routing = mock_routing_clone.lock().unwrap();
+                mock_routing.set_fault_model(FaultModel::none());
+                mock_routing.get(tag, name_clone.clone())
+            });
+            let mut response_getter = response_getter(&mock_routing, id, &notifier, &callback_interface)
+                                          .with_resend(::client::response_getter::Resend::Get(resend));
+            assert_eq!(response_getter.get().ok().unwrap(), stored);
+        }
+
+        // Corrupted and duplicated responses
+        let mut model = FaultModel::none();
+        model.corruption_probability = 1.0;
+        model.duplication_probability = 1.0;
+        mock_routing.lock().unwrap().set_fault_model(model);
+        for _ in 0..2 {
+            let id = mock_routing.lock().unwrap().get(tag, name.clone()).ok().unwrap();
+            let received = response_getter(&mock_routing, id, &notifier, &callback_interface).get().ok().unwrap();
+            assert_eq!(received.len(), stored.len());
+            assert!(received != stored);
+        }
+    }
+
+    #[test]
+    fn hostile_scenario_repeats_exactly() {
+        fn run(seed: u64) -> (Vec<bool>, u64) {
+            let notifier = ::std::sync::Arc::new((::std::sync::Mutex::new(0), ::std::sync::Condvar::new()));
+            let callback_interface = ::std::sync::Arc::new(::std::sync::Mutex::new(::client::callback_interface::CallbackInterface::new(notifier.clone())));
+            let mock_routing = ::std::sync::Arc::new(::std::sync::Mutex::new(RoutingClientMock::new(callback_interface.clone(), ::routing::NameType::new([1u8; 64]), get_new_data_store())));
+            mock_routing.lock().unwrap().set_fault_model(FaultModel::hostile(seed));
+            let immutable_data_type_id: maidsafe_types::data::ImmutableDataTypeTag = unsafe { ::std::mem::uninitialized() };
+
+            let mut outcomes = Vec::new();
+            for i in 0..200 {
+                let immutable_data = maidsafe_types::ImmutableData::new(format!("chunk {}", i).into_bytes());
+                let put = ::client::send_put(&mock_routing, &notifier, &callback_interface, immutable_data.clone());
+                outcomes.push(put.ok().unwrap().get().is_ok());
+                let get = ::client::send_get(&mock_routing, &notifier, &callback_interface, immutable_data_type_id.type_tag(), immutable_data.name());
+                outcomes.push(get.ok().unwrap().get().ok() == Some(immutable_data.serialised_contents()));
+            }
+            let now_ms = mock_routing.lock().unwrap().now_ms();
+            (outcomes, now_ms)
+        }
+
+        let first = run(7);
+        assert_eq!(first, run(7));
+        // 400 operations of a second each went by on the virtual clock alone
+        assert!(first.1 >= 400 * 1000);
+    }
+
+    #[test]
+    fn structured_data_owner_and_staleness() {
+        let notifier = ::std::sync::Arc::new((::std::sync::Mutex::new(0), ::std::sync::Condvar::new()));
+        let callback_interface = ::std::sync::Arc::new(::std::sync::Mutex::new(::client::callback_interface::CallbackInterface::new(notifier.clone())));
+        let data_store = get_new_data_store();
+        let owner = ::routing::NameType::new([1u8; 64]);
+        let other = ::routing::NameType::new([2u8; 64]);
+        let mock_routing = ::std::sync::Arc::new(::std::sync::Mutex::new(RoutingClientMock::new(callback_interface.clone(), owner.clone(), data_store.clone())));
+        let other_routing = ::std::sync::Arc::new(::std::sync::Mutex::new(RoutingClientMock::new(callback_interface.clone(), other.clone(), data_store)));
+        mock_routing.lock().unwrap().set_fault_model(FaultModel::none());
+        other_routing.lock().unwrap().set_fault_model(FaultModel::none());
+
+        let put = |mock_routing: &::std::sync::Arc<::std::sync::Mutex<RoutingClientMock>>, owner: &::routing::NameType, versions: &[u8]| {
+            let value = versions.iter().map(|&version| ::routing::NameType::new([version; 64])).collect();
+            let structured_data = maidsafe_types::StructuredData::new(::routing::NameType::new([9u8; 64]), owner.clone(), value);
+            let id = mock_routing.lock().unwrap().put(structured_data).ok().unwrap();
+            response_getter(mock_routing, id, &notifier, &callback_interface).get()
+        };
+
+        // Only StructuredData of its own can be stored by a client
+        assert_eq!(put(&mock_routing, &other, &[1]), Err(::routing::error::ResponseError::InvalidRequest));
+        assert!(put(&mock_routing, &owner, &[1]).is_ok());
+        // ... or updated
+        assert_eq!(put(&other_routing, &other, &[1, 2]), Err(::routing::error::ResponseError::InvalidRequest));
+
+        assert!(put(&mock_routing, &owner, &[1, 2]).is_ok());
+        assert!(put(&mock_routing, &owner, &[1, 2]).is_ok());
+        // Made from [1] while [1, 2] is stored
+        match put(&mock_routing, &owner, &[1, 3]) {
+            Err(::routing::error::ResponseError::FailedToStoreData(stored)) => {
+                let stored: maidsafe_types::StructuredData = ::cbor::Decoder::from_bytes(&stored[..]).decode().next().unwrap().unwrap();
+                assert_eq!(stored.value(), vec![::routing::NameType::new([1u8; 64]), ::routing::NameType::new([2u8; 64])]);
+            },
+            result => panic!("Stale update answered {:?}", result),
+        }
+        // Removing versions, as pruning does
+        assert!(put(&mock_routing, &owner, &[2]).is_ok());
+        assert!(put(&mock_routing, &owner, &[2, 4]).is_ok());
+        // Removing made from [2] while [2, 4] is stored
+        assert!(put(&mock_routing, &owner, &[]).is_err());
+        assert!(put(&mock_routing, &owner, &[4]).is_ok());
+    }
 }
@@ -15,10 +15,38 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
+/// How long to wait for a response before taking it as lost
+pub const RESPONSE_TIMEOUT_MS: u32 = 10000;
+/// Sends in all, first one included, before giving up on a request
+pub const MAX_ATTEMPTS: usize = 4;
+
+/// Sends the request again, giving the id of the new message
+pub type Sender = Box<FnMut() -> Result<::routing::types::MessageId, ::IoError> + Send>;
+
+/// Moves the network on to its next response, giving false if none is pending
+pub type Driver = Box<FnMut() -> bool + Send>;
+
+/// How to repeat a request whose response went missing or reported a transient failure
+pub enum Resend {
+    /// A GET which answered InvalidRequest failed in transit, since a missing chunk answers NoData
+    Get(Sender),
+    /// A PUT of ImmutableData which answered FailedToStoreData without data had no effect. One
+    /// which answers InvalidRequest after an earlier response went missing was stored by that
+    /// earlier send.
+    Put(Sender),
+    /// A PUT of StructuredData, retried like Put. Sending it again after its response went
+    /// missing is harmless, so InvalidRequest is always a rejection, as is FailedToStoreData
+    /// carrying the stored StructuredData.
+    Update(Sender),
+}
+
 pub struct ResponseGetter {
     message_id:         ::routing::types::MessageId,
     response_notifier:  ::ResponseNotifier,
     callback_interface: ::std::sync::Arc<::std::sync::Mutex<::client::callback_interface::CallbackInterface>>,
+    resend:             Option<Resend>,
+    driver:             Option<Driver>,
+    timeout_ms:         u32,
 }
 
 impl ResponseGetter {
@@ -29,26 +57,98 @@ impl ResponseGetter {
             message_id: msg_id,
             response_notifier: notifier,
             callback_interface: cb_interface,
+            resend: None,
+            driver: None,
+            timeout_ms: RESPONSE_TIMEOUT_MS,
         }
     }
 
-    pub fn get(&mut self) -> Result<Vec<u8>, ::routing::error::ResponseError> {
-        let &(ref lock, ref condition_var) = &*self.response_notifier;
-        let mut mutex_guard: _;
+    /// Lets get repeat the request, up to MAX_ATTEMPTS sends in all
+    pub fn with_resend(mut self, resend: Resend) -> ResponseGetter {
+        self.resend = Some(resend);
+        self
+    }
+
+    /// For a network on a virtual clock: rather than waiting, get moves the network on until
+    /// the response arrives, and takes it as lost once nothing more is pending
+    pub fn with_driver(mut self, driver: Driver) -> ResponseGetter {
+        self.driver = Some(driver);
+        self
+    }
 
-        {
-            let mut cb_interface = self.callback_interface.lock().unwrap();
-            match cb_interface.get_response(self.message_id) {
-                Some(response_result) => return response_result,
-                None                  => mutex_guard = lock.lock().unwrap(),
+    /// How long to wait in real time, without a driver
+    pub fn set_timeout_ms(&mut self, timeout_ms: u32) {
+        self.timeout_ms = timeout_ms;
+    }
+
+    /// Waits for the response. A response which does not arrive in time is reported as
+    /// InvalidRequest, unless the request can be sent again.
+    pub fn get(&mut self) -> Result<Vec<u8>, ::routing::error::ResponseError> {
+        let mut attempts = 1;
+        let mut lost_response = false;
+        loop {
+            let response = self.wait();
+            let transient = match (&response, &self.resend) {
+                (&None, &Some(_)) => true,
+                (&Some(Err(::routing::error::ResponseError::InvalidRequest)), &Some(Resend::Get(_))) => true,
+                (&Some(Err(::routing::error::ResponseError::FailedToStoreData(ref data))), &Some(Resend::Put(_))) |
+                (&Some(Err(::routing::error::ResponseError::FailedToStoreData(ref data))), &Some(Resend::Update(_))) => data.is_empty(),
+                _ => false,
+            };
+            if transient && attempts < MAX_ATTEMPTS {
+                lost_response = lost_response || response.is_none();
+                attempts += 1;
+                let sent = match self.resend {
+                    Some(Resend::Get(ref mut send)) | Some(Resend::Put(ref mut send)) | Some(Resend::Update(ref mut send)) => send(),
+                    None => unreachable!(),
+                };
+                match sent {
+                    Ok(message_id) => self.message_id = message_id,
+                    Err(_) => return Err(::routing::error::ResponseError::InvalidRequest),
+                }
+                continue;
             }
+
+            return match (response, &self.resend) {
+                (Some(Err(::routing::error::ResponseError::InvalidRequest)), &Some(Resend::Put(_))) if lost_response => Ok(Vec::new()),
+                (Some(response), _) => response,
+                (None, _) => Err(::routing::error::ResponseError::InvalidRequest),
+            };
         }
+    }
 
-        while *mutex_guard != self.message_id {
-            mutex_guard = condition_var.wait(mutex_guard).unwrap();
+    /// None if the response does not arrive in time
+    fn wait(&mut self) -> Option<Result<Vec<u8>, ::routing::error::ResponseError>> {
+        if let Some(ref mut driver) = self.driver {
+            loop {
+                if let Some(response_result) = self.callback_interface.lock().unwrap().get_response(self.message_id) {
+                    return Some(response_result);
+                }
+                if !driver() {
+                    // Another thread may have moved the network on to the response meanwhile
+                    return self.callback_interface.lock().unwrap().get_response(self.message_id);
+                }
+            }
         }
 
-        let mut cb_interface = self.callback_interface.lock().unwrap();
-        cb_interface.get_response(self.message_id).unwrap()
+        let &(ref lock, ref condition_var) = &*self.response_notifier;
+        let deadline = ::time::precise_time_ns() + self.timeout_ms as u64 * 1_000_000;
+        loop {
+            // Take the notifier lock before letting go of the callback interface, so that a
+            // response arriving in between is not missed
+            let mutex_guard = {
+                let mut cb_interface = self.callback_interface.lock().unwrap();
+                match cb_interface.get_response(self.message_id) {
+                    Some(response_result) => return Some(response_result),
+                    None => lock.lock().unwrap(),
+                }
+            };
+
+            let now = ::time::precise_time_ns();
+            if now >= deadline {
+                return None;
+            }
+            let _ = condition_var.wait_timeout_ms(mutex_guard, ((deadline - now) / 1_000_000) as u32 + 1).unwrap();
+        }
     }
 }
@@ -34,6 +34,38 @@ pub struct Account {
     public_mpid: maidsafe_types::PublicIdType,
 
     root_dir_id: Option<routing::NameType>,
+    // Ids of the well-known containers under the root directory, by name
+    well_known_dir_ids: ::std::collections::BTreeMap<String, routing::NameType>,
+}
+
+// Session packets stored before the well-known containers were recorded in them. They are still
+// read, as an Account with no well-known containers.
+#[derive(RustcEncodable, RustcDecodable)]
+struct AccountWithoutWellKnown {
+    an_maid: maidsafe_types::RevocationIdType,
+    maid: maidsafe_types::IdType,
+    public_maid: maidsafe_types::PublicIdType,
+
+    an_mpid: maidsafe_types::RevocationIdType,
+    mpid: maidsafe_types::IdType,
+    public_mpid: maidsafe_types::PublicIdType,
+
+    root_dir_id: Option<routing::NameType>,
+}
+
+impl AccountWithoutWellKnown {
+    fn into_account(self) -> Account {
+        Account {
+            an_maid: self.an_maid,
+            maid: self.maid,
+            public_maid: self.public_maid,
+            an_mpid: self.an_mpid,
+            mpid: self.mpid,
+            public_mpid: self.public_mpid,
+            root_dir_id: self.root_dir_id,
+            well_known_dir_ids: ::std::collections::BTreeMap::new(),
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -55,6 +87,7 @@ impl Account {
             mpid: mpid,
             public_mpid: public_mpid,
             root_dir_id: root_dir_id,
+            well_known_dir_ids: ::std::collections::BTreeMap::new(),
         }
     }
 
@@ -86,6 +119,18 @@ impl Account {
         &self.root_dir_id
     }
 
+    pub fn set_root_dir_id(&mut self, root_dir_id: routing::NameType) {
+        self.root_dir_id = Some(root_dir_id);
+    }
+
+    pub fn get_well_known_dir_id(&self, name: &str) -> Option<&routing::NameType> {
+        self.well_known_dir_ids.get(name)
+    }
+
+    pub fn set_well_known_dir_id(&mut self, name: String, dir_id: routing::NameType) {
+        let _ = self.well_known_dir_ids.insert(name, dir_id);
+    }
+
     pub fn generate_network_id(keyword: &String, pin: u32) -> routing::NameType {
         use crypto::digest::Digest;
 
@@ -233,10 +278,17 @@ impl Account {
         return encoder.encode(&[&self]).map(|()| encoder.into_bytes());
     }
 
+    /// Reads packets of the current layout and, failing that, of the layout before the
+    /// well-known containers were recorded. Saving the Account writes the current layout.
     fn deserialise(source : &[u8]) -> cbor::CborResult<Account> {
-        match cbor::Decoder::from_bytes(source).decode::<Account>().next() {
-            Some(result) => return result,
+        let error = match cbor::Decoder::from_bytes(source).decode::<Account>().next() {
+            Some(Ok(account)) => return Ok(account),
+            Some(Err(error)) => error,
             None => return Err(cbor::CborError::UnexpectedEOF)
+        };
+        match cbor::Decoder::from_bytes(source).decode::<AccountWithoutWellKnown>().next() {
+            Some(Ok(account)) => Ok(account.into_account()),
+            _ => Err(error),
         }
     }
 }
@@ -327,6 +379,32 @@ mod test {
         }
     }
 
+    #[test]
+    fn deserialising_packet_without_well_known() {
+        let mut account = Account::new(Some(::routing::NameType::new([3u8; 64])));
+        let old = super::AccountWithoutWellKnown {
+            an_maid: account.an_maid.clone(),
+            maid: account.maid.clone(),
+            public_maid: account.public_maid.clone(),
+            an_mpid: account.an_mpid.clone(),
+            mpid: account.mpid.clone(),
+            public_mpid: account.public_mpid.clone(),
+            root_dir_id: account.root_dir_id.clone(),
+        };
+        let mut encoder = ::cbor::Encoder::from_memory();
+        assert!(encoder.encode(&[&old]).is_ok());
+        match Account::deserialise(&encoder.into_bytes()) {
+            Ok(account_again) => assert_eq!(account, account_again),
+            Err(_) => panic!("Packets without well-known containers should still be read !!"),
+        }
+
+        account.set_well_known_dir_id("Documents".to_string(), ::routing::NameType::new([4u8; 64]));
+        match account.serialise().map(|serialised| Account::deserialise(&serialised)) {
+            Ok(Ok(account_again)) => assert_eq!(account, account_again),
+            _ => panic!("Should have been equal !!"),
+        }
+    }
+
     #[test]
     fn encryption() {
         let account = Account::new(None);
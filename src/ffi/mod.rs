@@ -0,0 +1,595 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+//! C interface to account login and the Container and Blob API, declared in
+//! `ffi/maidsafe_client.h`. Handles are opaque pointers owned by the caller and released with
+//! their `_free` function. Functions return one of the `SAFE_*` codes, and the message of the
+//! last failure on the calling thread is available from `safe_last_error`. No panic unwinds
+//! into C: a call which panics fails with `SAFE_ERR_PANIC`, or returns null or 0.
+
+use client;
+use nfs;
+use libc::{c_char, int32_t, size_t, uint8_t, uint32_t};
+
+pub const SAFE_OK: int32_t = 0;
+pub const SAFE_ERR_NULL_POINTER: int32_t = -1;
+pub const SAFE_ERR_INVALID_UTF8: int32_t = -2;
+pub const SAFE_ERR_NOT_FOUND: int32_t = -3;
+pub const SAFE_ERR_ALREADY_EXISTS: int32_t = -4;
+pub const SAFE_ERR_READ_ONLY: int32_t = -5;
+pub const SAFE_ERR_LOGIN_FAILED: int32_t = -6;
+pub const SAFE_ERR_OPERATION_FAILED: int32_t = -7;
+pub const SAFE_ERR_PANIC: int32_t = -8;
+
+/// Store of the mock network, shared by the clients created on it
+pub struct SafeDataStore(client::non_networking_test_framework::DataStore);
+
+pub struct SafeClient(::std::sync::Arc<::std::sync::Mutex<client::Client>>);
+
+pub struct SafeContainer(nfs::rest::Container);
+
+/// Names of entries, in order
+pub struct SafeNameList(Vec<::std::ffi::CString>);
+
+thread_local!(static LAST_ERROR: ::std::cell::RefCell<::std::ffi::CString> = ::std::cell::RefCell::new(::std::ffi::CString::new("").unwrap()));
+
+fn set_last_error(message: &str) {
+    let message = ::std::ffi::CString::new(message.replace("\0", "")).unwrap();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = message);
+}
+
+/// Records the message and returns the code
+fn fail(code: int32_t, message: String) -> int32_t {
+    set_last_error(&message);
+    code
+}
+
+/// Code for an operation on the named entry of the container which failed. The code is taken
+/// from the state of the container, never from the message.
+fn entry_error(container: &nfs::rest::Container, exists: bool) -> int32_t {
+    if container.is_read_only() {
+        SAFE_ERR_READ_ONLY
+    } else if !exists {
+        SAFE_ERR_NOT_FOUND
+    } else {
+        SAFE_ERR_OPERATION_FAILED
+    }
+}
+
+/// Runs the body of an interface function, which must not unwind into C. A panic is recorded as
+/// the last error and `on_panic` returned instead.
+fn catch_panic<T, F>(on_panic: T, body: F) -> T where F: FnOnce() -> T {
+    match ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(body)) {
+        Ok(value) => value,
+        Err(payload) => {
+            let message = match payload.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => payload.downcast_ref::<String>().cloned().unwrap_or_else(|| "unknown cause".to_string()),
+            };
+            set_last_error(&format!("Internal error: {}", message));
+            on_panic
+        },
+    }
+}
+
+fn null_pointer() -> int32_t {
+    set_last_error("Null pointer");
+    SAFE_ERR_NULL_POINTER
+}
+
+unsafe fn to_string(value: *const c_char) -> Result<String, int32_t> {
+    if value.is_null() {
+        return Err(null_pointer());
+    }
+    match ::std::str::from_utf8(::std::ffi::CStr::from_ptr(value).to_bytes()) {
+        Ok(value) => Ok(value.to_string()),
+        Err(_) => {
+            set_last_error("Invalid UTF-8");
+            Err(SAFE_ERR_INVALID_UTF8)
+        },
+    }
+}
+
+/// Null is None
+unsafe fn to_optional_string(value: *const c_char) -> Result<Option<String>, int32_t> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    to_string(value).map(|value| Some(value))
+}
+
+macro_rules! try_code {
+    ($result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(code) => return code,
+        }
+    }
+}
+
+macro_rules! check_pointers {
+    ($($pointer:expr),+) => {
+        if $($pointer.is_null())||+ {
+            return null_pointer();
+        }
+    }
+}
+
+/// Message of the last failure on this thread. Valid until the next call on the thread.
+#[no_mangle]
+pub extern "C" fn safe_last_error() -> *const c_char {
+    catch_panic(::std::ptr::null(), || {
+        LAST_ERROR.with(|last_error| last_error.borrow().as_ptr())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn safe_data_store_new() -> *mut SafeDataStore {
+    catch_panic(::std::ptr::null_mut(), || {
+        Box::into_raw(Box::new(SafeDataStore(client::non_networking_test_framework::get_new_data_store())))
+    })
+}
+
+/// Opens a store kept in the directory, creating it if need be, so the mock network survives
+/// restarts
+#[no_mangle]
+pub unsafe extern "C" fn safe_data_store_open(path: *const c_char, data_store: *mut *mut SafeDataStore) -> int32_t {
+    catch_panic(SAFE_ERR_PANIC, || {
+        check_pointers!(data_store);
+        let path = try_code!(to_string(path));
+        match client::non_networking_test_framework::get_file_data_store(::std::path::Path::new(&path)) {
+            Ok(opened) => {
+                *data_store = Box::into_raw(Box::new(SafeDataStore(opened)));
+                SAFE_OK
+            },
+            Err(io_error) => fail(SAFE_ERR_OPERATION_FAILED, io_error.to_string()),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn safe_data_store_free(data_store: *mut SafeDataStore) {
+    catch_panic((), || {
+        if !data_store.is_null() {
+            let _ = Box::from_raw(data_store);
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn safe_create_account(keyword: *const c_char, pin: uint32_t, password: *const c_char,
+                                             data_store: *const SafeDataStore, client: *mut *mut SafeClient) -> int32_t {
+    catch_panic(SAFE_ERR_PANIC, || {
+        check_pointers!(data_store, client);
+        let keyword = try_code!(to_string(keyword));
+        let password = try_code!(to_string(password));
+        match client::Client::create_account(&keyword, pin, password.as_bytes(), (*data_store).0.clone()) {
+            Ok(created) => {
+                let created = ::std::sync::Arc::new(::std::sync::Mutex::new(created));
+                if let Err(error) = nfs::rest::provision_well_known(created.clone()) {
+                    set_last_error(&error);
+                    return SAFE_ERR_LOGIN_FAILED;
+                }
+                *client = Box::into_raw(Box::new(SafeClient(created)));
+                SAFE_OK
+            },
+            Err(io_error) => {
+                set_last_error(&io_error.to_string());
+                SAFE_ERR_LOGIN_FAILED
+            },
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn safe_log_in(keyword: *const c_char, pin: uint32_t, password: *const c_char,
+                                     data_store: *const SafeDataStore, client: *mut *mut SafeClient) -> int32_t {
+    catch_panic(SAFE_ERR_PANIC, || {
+        check_pointers!(data_store, client);
+        let keyword = try_code!(to_string(keyword));
+        let password = try_code!(to_string(password));
+        match client::Client::log_in(&keyword, pin, password.as_bytes(), (*data_store).0.clone()) {
+            Ok(logged_in) => {
+                let logged_in = ::std::sync::Arc::new(::std::sync::Mutex::new(logged_in));
+                if let Err(error) = nfs::rest::provision_well_known(logged_in.clone()) {
+                    set_last_error(&error);
+                    return SAFE_ERR_LOGIN_FAILED;
+                }
+                *client = Box::into_raw(Box::new(SafeClient(logged_in)));
+                SAFE_OK
+            },
+            Err(io_error) => {
+                set_last_error(&io_error.to_string());
+                SAFE_ERR_LOGIN_FAILED
+            },
+        }
+    })
+}
+
+/// Containers opened through the client stay usable after it is freed
+#[no_mangle]
+pub unsafe extern "C" fn safe_client_free(client: *mut SafeClient) {
+    catch_panic((), || {
+        if !client.is_null() {
+            let _ = Box::from_raw(client);
+        }
+    })
+}
+
+/// Opens the root container of the user, creating it on first use
+#[no_mangle]
+pub unsafe extern "C" fn safe_container_user_root(client: *const SafeClient, container: *mut *mut SafeContainer) -> int32_t {
+    catch_panic(SAFE_ERR_PANIC, || {
+        check_pointers!(client, container);
+        match nfs::rest::Container::user_root((*client).0.clone()) {
+            Ok(root) => {
+                *container = Box::into_raw(Box::new(SafeContainer(root)));
+                SAFE_OK
+            },
+            Err(message) => fail(SAFE_ERR_OPERATION_FAILED, message),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn safe_container_free(container: *mut SafeContainer) {
+    catch_panic((), || {
+        if !container.is_null() {
+            let _ = Box::from_raw(container);
+        }
+    })
+}
+
+/// Copies the name of the container. Free the copy with safe_string_free.
+#[no_mangle]
+pub unsafe extern "C" fn safe_container_name(container: *const SafeContainer) -> *mut c_char {
+    catch_panic(::std::ptr::null_mut(), || {
+        if container.is_null() {
+            return ::std::ptr::null_mut();
+        }
+        ::std::ffi::CString::new((*container).0.get_name().replace("\0", "")).unwrap().into_raw()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn safe_string_free(value: *mut c_char) {
+    catch_panic((), || {
+        if !value.is_null() {
+            let _ = ::std::ffi::CString::from_raw(value);
+        }
+    })
+}
+
+/// Creates a container inside the container. The metadata may be null.
+#[no_mangle]
+pub unsafe extern "C" fn safe_container_create(container: *mut SafeContainer, name: *const c_char, metadata: *const c_char) -> int32_t {
+    catch_panic(SAFE_ERR_PANIC, || {
+        check_pointers!(container);
+        let name = try_code!(to_string(name));
+        let metadata = try_code!(to_optional_string(metadata));
+        let container = &mut (*container).0;
+        match container.create(name.clone(), metadata) {
+            Ok(()) => SAFE_OK,
+            Err(message) => {
+                let code = if container.is_read_only() {
+                    SAFE_ERR_READ_ONLY
+                } else if container.has_container(&name) {
+                    SAFE_ERR_ALREADY_EXISTS
+                } else {
+                    SAFE_ERR_OPERATION_FAILED
+                };
+                fail(code, message)
+            },
+        }
+    })
+}
+
+/// Opens a container inside the container
+#[no_mangle]
+pub unsafe extern "C" fn safe_container_get(container: *mut SafeContainer, name: *const c_char, found: *mut *mut SafeContainer) -> int32_t {
+    catch_panic(SAFE_ERR_PANIC, || {
+        check_pointers!(container, found);
+        let name = try_code!(to_string(name));
+        let container = &mut (*container).0;
+        match container.get_container(name.clone(), None) {
+            Ok(opened) => {
+                *found = Box::into_raw(Box::new(SafeContainer(opened)));
+                SAFE_OK
+            },
+            Err(message) => fail(if container.has_container(&name) { SAFE_ERR_OPERATION_FAILED } else { SAFE_ERR_NOT_FOUND }, message),
+        }
+    })
+}
+
+/// Moves a container inside the container to the trash
+#[no_mangle]
+pub unsafe extern "C" fn safe_container_delete(container: *mut SafeContainer, name: *const c_char) -> int32_t {
+    catch_panic(SAFE_ERR_PANIC, || {
+        check_pointers!(container);
+        let name = try_code!(to_string(name));
+        let container = &mut (*container).0;
+        match container.delete_container(name.clone()) {
+            Ok(()) => SAFE_OK,
+            Err(message) => fail(entry_error(container, container.has_container(&name)), message),
+        }
+    })
+}
+
+/// Names of the containers inside the container
+#[no_mangle]
+pub unsafe extern "C" fn safe_container_list_containers(container: *const SafeContainer, names: *mut *mut SafeNameList) -> int32_t {
+    catch_panic(SAFE_ERR_PANIC, || {
+        check_pointers!(container, names);
+        let list = (*container).0.get_containers().iter().map(|info| ::std::ffi::CString::new(info.get_name().replace("\0", "")).unwrap()).collect();
+        *names = Box::into_raw(Box::new(SafeNameList(list)));
+        SAFE_OK
+    })
+}
+
+/// Names of the blobs in the container
+#[no_mangle]
+pub unsafe extern "C" fn safe_container_list_blobs(container: *const SafeContainer, names: *mut *mut SafeNameList) -> int32_t {
+    catch_panic(SAFE_ERR_PANIC, || {
+        check_pointers!(container, names);
+        let list = (*container).0.get_blobs().iter().map(|blob| ::std::ffi::CString::new(blob.get_name().replace("\0", "")).unwrap()).collect();
+        *names = Box::into_raw(Box::new(SafeNameList(list)));
+        SAFE_OK
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn safe_name_list_len(names: *const SafeNameList) -> size_t {
+    catch_panic(0, || {
+        if names.is_null() {
+            return 0;
+        }
+        (*names).0.len() as size_t
+    })
+}
+
+/// The name at the index, or null past the end. Valid until the list is freed.
+#[no_mangle]
+pub unsafe extern "C" fn safe_name_list_get(names: *const SafeNameList, index: size_t) -> *const c_char {
+    catch_panic(::std::ptr::null(), || {
+        if names.is_null() {
+            return ::std::ptr::null();
+        }
+        match (*names).0.get(index as usize) {
+            Some(name) => name.as_ptr(),
+            None => ::std::ptr::null(),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn safe_name_list_free(names: *mut SafeNameList) {
+    catch_panic((), || {
+        if !names.is_null() {
+            let _ = Box::from_raw(names);
+        }
+    })
+}
+
+/// Reads the whole content of a blob into a new buffer. Free it with safe_buffer_free.
+#[no_mangle]
+pub unsafe extern "C" fn safe_blob_read(container: *const SafeContainer, name: *const c_char,
+                                        data: *mut *mut uint8_t, length: *mut size_t) -> int32_t {
+    catch_panic(SAFE_ERR_PANIC, || {
+        check_pointers!(container, data, length);
+        let name = try_code!(to_string(name));
+        let container = &(*container).0;
+        let blob = match container.get_blob(name.clone(), None) {
+            Ok(blob) => blob,
+            Err(message) => return fail(if container.has_blob(&name) { SAFE_ERR_OPERATION_FAILED } else { SAFE_ERR_NOT_FOUND }, message),
+        };
+        let mut reader = blob.reader();
+        let content = match reader.read(0, blob.get_size()) {
+            Ok(content) => content,
+            Err(message) => return fail(SAFE_ERR_OPERATION_FAILED, message.to_string()),
+        };
+        let mut content = content.into_boxed_slice();
+        *length = content.len() as size_t;
+        *data = content.as_mut_ptr();
+        ::std::mem::forget(content);
+        SAFE_OK
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn safe_buffer_free(data: *mut uint8_t, length: size_t) {
+    catch_panic((), || {
+        if !data.is_null() {
+            let _ = Box::from_raw(::std::slice::from_raw_parts_mut(data, length as usize) as *mut [u8]);
+        }
+    })
+}
+
+/// Creates the blob or replaces its content. The metadata may be null, which leaves the
+/// metadata of an existing blob as it is.
+#[no_mangle]
+pub unsafe extern "C" fn safe_blob_write(container: *mut SafeContainer, name: *const c_char,
+                                         data: *const uint8_t, length: size_t, metadata: *const c_char) -> int32_t {
+    catch_panic(SAFE_ERR_PANIC, || {
+        check_pointers!(container);
+        if data.is_null() && length > 0 {
+            return null_pointer();
+        }
+        let name = try_code!(to_string(name));
+        let metadata = try_code!(to_optional_string(metadata));
+        let content = if length == 0 { &[][..] } else { ::std::slice::from_raw_parts(data, length as usize) };
+        let container = &mut (*container).0;
+        let exists = container.get_blob(name.clone(), None).is_ok();
+        let result = container.transaction(|tx| {
+            if exists {
+                try!(tx.update_blob(name.clone(), content));
+                match metadata {
+                    Some(_) => tx.update_blob_metadata(name.clone(), metadata.clone()),
+                    None => Ok(()),
+                }
+            } else {
+                tx.create_blob(name.clone(), metadata.clone(), content)
+            }
+        });
+        match result {
+            Ok(()) => SAFE_OK,
+            Err(message) => fail(if container.is_read_only() { SAFE_ERR_READ_ONLY } else { SAFE_ERR_OPERATION_FAILED }, message),
+        }
+    })
+}
+
+/// Moves a blob to the trash
+#[no_mangle]
+pub unsafe extern "C" fn safe_blob_delete(container: *mut SafeContainer, name: *const c_char) -> int32_t {
+    catch_panic(SAFE_ERR_PANIC, || {
+        check_pointers!(container);
+        let name = try_code!(to_string(name));
+        let container = &mut (*container).0;
+        match container.delete_blob(name.clone()) {
+            Ok(()) => SAFE_OK,
+            Err(message) => fail(entry_error(container, container.has_blob(&name)), message),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn c_string(value: &str) -> ::std::ffi::CString {
+        ::std::ffi::CString::new(value).unwrap()
+    }
+
+    #[test]
+    fn header_declares_every_function() {
+        let header = include_str!("../../ffi/maidsafe_client.h");
+        let source = include_str!("mod.rs");
+
+        // Declarations only, on one line each with single spaces
+        let mut declarations = String::new();
+        let mut rest = header;
+        while let Some(start) = rest.find("/*") {
+            declarations.push_str(&rest[..start]);
+            rest = &rest[start + rest[start..].find("*/").unwrap() + 2..];
+        }
+        declarations.push_str(rest);
+        let declarations: Vec<String> = declarations.lines().filter(|line| !line.trim().starts_with('#')).collect::<Vec<_>>().join(" ")
+                                                    .split(';').filter(|declaration| declaration.contains('('))
+                                                    .map(|declaration| declaration.split_whitespace().collect::<Vec<_>>().join(" ") + ";")
+                                                    .collect();
+
+        let mut exported = 0;
+        let mut lines = source.lines();
+        while let Some(line) = lines.next() {
+            if line.trim() != "#[no_mangle]" {
+                continue;
+            }
+            let mut signature = String::new();
+            while !signature.contains('{') {
+                signature.push_str(lines.next().unwrap());
+                signature.push(' ');
+            }
+            let signature = signature.split_whitespace().collect::<Vec<_>>().join(" ");
+            let after_fn = signature.split("fn ").nth(1).unwrap();
+            let name = after_fn.split('(').next().unwrap();
+            let params = after_fn.split('(').nth(1).unwrap().split(')').next().unwrap();
+            let returned = match after_fn.split("->").nth(1) {
+                Some(returned) => c_type(returned.split('{').next().unwrap()),
+                None => "void".to_string(),
+            };
+            let params: Vec<String> = params.split(',').map(|param| param.trim()).filter(|param| !param.is_empty())
+                                            .map(|param| format!("{} {}", c_type(param.split(':').nth(1).unwrap()), param.split(':').next().unwrap().trim()))
+                                            .collect();
+            let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+            let declaration = format!("{} {}({});", returned, name, params);
+            assert!(declarations.contains(&declaration), "{} missing from the header", declaration);
+            exported += 1;
+        }
+        assert_eq!(declarations.len(), exported);
+    }
+
+    /// The C spelling of a Rust type of the interface
+    fn c_type(rust: &str) -> String {
+        let rust = rust.trim();
+        if rust.starts_with("*mut ") {
+            format!("{}*", c_type(&rust[5..]))
+        } else if rust.starts_with("*const ") {
+            format!("const {}*", c_type(&rust[7..]))
+        } else if rust == "c_char" {
+            "char".to_string()
+        } else {
+            rust.to_string()
+        }
+    }
+
+    #[test]
+    fn panics_are_caught() {
+        assert_eq!(catch_panic(SAFE_ERR_PANIC, || -> int32_t { panic!("Boom") }), SAFE_ERR_PANIC);
+        let message = unsafe { ::std::ffi::CStr::from_ptr(safe_last_error()) };
+        assert_eq!(message.to_bytes(), b"Internal error: Boom");
+        assert_eq!(catch_panic(SAFE_ERR_PANIC, || SAFE_OK), SAFE_OK);
+    }
+
+    #[test]
+    fn containers_and_blobs() {
+        unsafe {
+            let data_store = safe_data_store_new();
+            let keyword = c_string("Spandan");
+            let password = c_string("Sharma");
+            let mut client = ::std::ptr::null_mut();
+            assert_eq!(safe_create_account(keyword.as_ptr(), 1234, password.as_ptr(), data_store, &mut client), SAFE_OK);
+            safe_client_free(client);
+            assert_eq!(safe_log_in(keyword.as_ptr(), 1234, c_string("Wrong").as_ptr(), data_store, &mut client), SAFE_ERR_LOGIN_FAILED);
+            assert_eq!(safe_log_in(keyword.as_ptr(), 1234, password.as_ptr(), data_store, &mut client), SAFE_OK);
+
+            let mut root = ::std::ptr::null_mut();
+            assert_eq!(safe_container_user_root(client, &mut root), SAFE_OK);
+            let name = c_string("photos");
+            assert_eq!(safe_container_create(root, name.as_ptr(), ::std::ptr::null()), SAFE_OK);
+            assert_eq!(safe_container_create(root, name.as_ptr(), ::std::ptr::null()), SAFE_ERR_ALREADY_EXISTS);
+
+            let mut names = ::std::ptr::null_mut();
+            assert_eq!(safe_container_list_containers(root, &mut names), SAFE_OK);
+            assert_eq!(safe_name_list_len(names), 1);
+            assert_eq!(::std::ffi::CStr::from_ptr(safe_name_list_get(names, 0)).to_bytes(), b"photos");
+            assert!(safe_name_list_get(names, 1).is_null());
+            safe_name_list_free(names);
+
+            let mut photos = ::std::ptr::null_mut();
+            assert_eq!(safe_container_get(root, name.as_ptr(), &mut photos), SAFE_OK);
+            let blob = c_string("cat.jpg");
+            let content = b"not really a cat";
+            assert_eq!(safe_blob_write(photos, blob.as_ptr(), content.as_ptr(), content.len() as size_t, ::std::ptr::null()), SAFE_OK);
+            let mut data = ::std::ptr::null_mut();
+            let mut length = 0;
+            assert_eq!(safe_blob_read(photos, blob.as_ptr(), &mut data, &mut length), SAFE_OK);
+            assert_eq!(::std::slice::from_raw_parts(data, length as usize), &content[..]);
+            safe_buffer_free(data, length);
+
+            assert_eq!(safe_blob_delete(photos, blob.as_ptr()), SAFE_OK);
+            assert_eq!(safe_blob_read(photos, blob.as_ptr(), &mut data, &mut length), SAFE_ERR_NOT_FOUND);
+            assert!(::std::ffi::CStr::from_ptr(safe_last_error()).to_bytes().len() > 0);
+            safe_container_free(photos);
+
+            assert_eq!(safe_container_delete(root, name.as_ptr()), SAFE_OK);
+            assert_eq!(safe_container_get(root, name.as_ptr(), &mut photos), SAFE_ERR_NOT_FOUND);
+            assert_eq!(safe_container_get(root, ::std::ptr::null(), &mut photos), SAFE_ERR_NULL_POINTER);
+
+            safe_container_free(root);
+            safe_client_free(client);
+            safe_data_store_free(data_store);
+        }
+    }
+}
@@ -0,0 +1,499 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+//! Local IPC service through which apps in other processes use one logged in Client. Apps
+//! connect to a Unix domain socket, ask the user for access and work through the session
+//! token they are handed, which only reaches what they were granted.
+
+use client;
+use nfs;
+use nfs::rest::{Access, AppClient, WellKnown};
+use rand::Rng;
+use rustc_serialize::json;
+use rustc_serialize::base64::{FromBase64, ToBase64, STANDARD};
+use rustc_serialize::hex::ToHex;
+use self::protocol::{Call, RpcError};
+
+pub mod protocol;
+
+pub use self::protocol::PROTOCOL_VERSION;
+
+/// Asked whether an app may have the access it requests; usually the user is prompted
+pub type Approver = Box<Fn(&str, &::std::collections::BTreeMap<WellKnown, Access>) -> bool + Send + Sync>;
+
+/// Longest request line read from an app, generous enough for blobs sent as base64. A connection
+/// sending a longer one is answered with an error and closed.
+pub const MAX_REQUEST_SIZE: u64 = 16 * 1024 * 1024;
+
+type Sessions = ::std::sync::Arc<::std::sync::Mutex<::std::collections::BTreeMap<String, ::std::sync::Arc<AppClient>>>>;
+
+/// Serves apps over a Unix domain socket readable only by the user. Methods, all taking the
+/// session `token` and, where they act on a container, `root` ("app" or the name of a
+/// well-known container) and `path` (names of the containers below it):
+///
+///  - `hello {protocol_version}`, required first on every connection
+///  - `authorise_app {app_id, grants}`, where grants maps well-known names to "read" or
+///    "read_write", returns `{token}` if approved
+///  - `revoke {token}`
+///  - `list_container`, `create_container {name, metadata}`, `delete_container {name}`
+///  - `read_blob {name, offset, length}` returns base64 `content`, `write_blob {name, content,
+///    metadata}` creates or replaces, `delete_blob {name}`
+pub struct Launcher {
+    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+    listener: ::unix_socket::UnixListener,
+    path: ::std::path::PathBuf,
+    approver: ::std::sync::Arc<Approver>,
+    sessions: Sessions,
+}
+
+impl Launcher {
+    /// Listens on the socket at the path. A socket left behind by a launcher which is no
+    /// longer running is replaced. The socket is bound inside a directory only the user can
+    /// enter and moved to the path once it is readable only by the user, so no other user can
+    /// connect while its permissions are still the defaults.
+    pub fn bind<P: AsRef<::std::path::Path>>(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+                                              path: P,
+                                              approver: Approver) -> Result<Launcher, String> {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+        let path = path.as_ref().to_path_buf();
+        if path.exists() {
+            if ::unix_socket::UnixStream::connect(&path).is_ok() {
+                return Err(format!("A launcher is already listening on {}", path.display()));
+            }
+            let _ = ::std::fs::remove_file(&path);
+        }
+        let file_name = match path.file_name() {
+            Some(file_name) => file_name.to_string_lossy().into_owned(),
+            None => return Err(format!("{} does not name a socket", path.display())),
+        };
+        let staging = path.with_file_name(format!(".{}.{:016x}", file_name, ::rand::random::<u64>()));
+        try!(::std::fs::DirBuilder::new().mode(0o700).create(&staging).map_err(|err| format!("Failed to create {}: {}", staging.display(), err)));
+        let staged = staging.join("socket");
+        let bound = ::unix_socket::UnixListener::bind(&staged).map_err(|err| format!("Failed to bind to {}: {}", path.display(), err))
+            .and_then(|listener| {
+                try!(::std::fs::set_permissions(&staged, ::std::fs::Permissions::from_mode(0o600)).map_err(|err| err.to_string()));
+                try!(::std::fs::rename(&staged, &path).map_err(|err| format!("Failed to move the socket to {}: {}", path.display(), err)));
+                Ok(listener)
+            });
+        let _ = ::std::fs::remove_dir_all(&staging);
+        let listener = try!(bound);
+        Ok(Launcher {
+            client: client,
+            listener: listener,
+            path: path,
+            approver: ::std::sync::Arc::new(approver),
+            sessions: ::std::sync::Arc::new(::std::sync::Mutex::new(::std::collections::BTreeMap::new())),
+        })
+    }
+
+    pub fn get_path(&self) -> &::std::path::Path {
+        &self.path
+    }
+
+    /// Accepts connections until the listener fails, serving each on its own thread
+    pub fn serve(&self) {
+        for stream in self.listener.incoming() {
+            if let Ok(stream) = stream {
+                let mut connection = Connection {
+                    client: self.client.clone(),
+                    approver: self.approver.clone(),
+                    sessions: self.sessions.clone(),
+                    greeted: false,
+                };
+                let _ = ::std::thread::spawn(move || connection.serve(stream));
+            }
+        }
+    }
+}
+
+impl Drop for Launcher {
+    fn drop(&mut self) {
+        let _ = ::std::fs::remove_file(&self.path);
+    }
+}
+
+struct Connection {
+    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+    approver: ::std::sync::Arc<Approver>,
+    sessions: Sessions,
+    greeted: bool,
+}
+
+impl Connection {
+    fn serve(&mut self, stream: ::unix_socket::UnixStream) {
+        use std::io::{BufRead, Write};
+
+        let mut writer = match stream.try_clone() {
+            Ok(writer) => writer,
+            Err(_) => return,
+        };
+        let mut reader = ::std::io::BufReader::new(stream);
+        loop {
+            let mut line = Vec::new();
+            match ::std::io::Read::take(&mut reader, MAX_REQUEST_SIZE + 1).read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => (),
+            }
+            if line.len() as u64 > MAX_REQUEST_SIZE {
+                let error = RpcError::new(protocol::INVALID_REQUEST, format!("Requests are limited to {} bytes", MAX_REQUEST_SIZE));
+                let _ = writer.write_all(format!("{}\n", protocol::failure(json::Json::Null, error)).as_bytes());
+                return;
+            }
+            let line = match String::from_utf8(line) {
+                Ok(line) => line,
+                Err(_) => return,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match Call::parse(&line) {
+                Ok(call) => {
+                    let id = call.id.clone();
+                    match self.dispatch(&call) {
+                        Ok(result) => protocol::success(id, result),
+                        Err(error) => protocol::failure(id, error),
+                    }
+                },
+                Err((id, error)) => protocol::failure(id, error),
+            };
+            if writer.write_all(format!("{}\n", response).as_bytes()).is_err() {
+                return;
+            }
+        }
+    }
+
+    fn dispatch(&mut self, call: &Call) -> Result<json::Json, RpcError> {
+        if call.method == "hello" {
+            return self.hello(call);
+        }
+        if !self.greeted {
+            return Err(RpcError::new(protocol::HANDSHAKE_REQUIRED, "Call hello first".to_string()));
+        }
+        match &call.method[..] {
+            "authorise_app" => self.authorise_app(call),
+            "revoke" => self.revoke(call),
+            "list_container" => self.list_container(call),
+            "create_container" => self.create_container(call),
+            "delete_container" => self.delete_container(call),
+            "read_blob" => self.read_blob(call),
+            "write_blob" => self.write_blob(call),
+            "delete_blob" => self.delete_blob(call),
+            _ => Err(RpcError::new(protocol::METHOD_NOT_FOUND, format!("Unknown method {}", call.method))),
+        }
+    }
+
+    fn hello(&mut self, call: &Call) -> Result<json::Json, RpcError> {
+        match try!(call.get_optional_u64("protocol_version")) {
+            Some(PROTOCOL_VERSION) => (),
+            Some(version) => return Err(RpcError::new(protocol::UNSUPPORTED_VERSION,
+                                                      format!("Protocol version {} is not supported, this launcher speaks {}", version, PROTOCOL_VERSION))),
+            None => return Err(RpcError::invalid_params("protocol_version is required".to_string())),
+        }
+        self.greeted = true;
+        let mut result = json::Object::new();
+        let _ = result.insert("protocol_version".to_string(), json::Json::U64(PROTOCOL_VERSION));
+        Ok(json::Json::Object(result))
+    }
+
+    fn authorise_app(&mut self, call: &Call) -> Result<json::Json, RpcError> {
+        let app_id = try!(call.get_string("app_id"));
+        let mut grants = ::std::collections::BTreeMap::new();
+        for (name, access) in try!(call.get_string_map("grants")).into_iter() {
+            let well_known = try!(WellKnown::from_name(&name).ok_or_else(|| RpcError::invalid_params(format!("No well-known container {}", name))));
+            let access = match &access[..] {
+                "read" => Access::Read,
+                "read_write" => Access::ReadWrite,
+                _ => return Err(RpcError::invalid_params(format!("Access must be read or read_write, not {}", access))),
+            };
+            let _ = grants.insert(well_known, access);
+        }
+        if !(self.approver)(&app_id, &grants) {
+            return Err(RpcError::new(protocol::ACCESS_DENIED, format!("{} was not authorised", app_id)));
+        }
+
+        let app = try!(AppClient::register(self.client.clone(), app_id, grants).map_err(Connection::failed));
+        let mut token = [0u8; 32];
+        match ::rand::OsRng::new() {
+            Ok(mut generator) => generator.fill_bytes(&mut token),
+            Err(_) => return Err(Connection::failed("No source of randomness for the token".to_string())),
+        }
+        let token = (&token[..]).to_hex();
+        let _ = self.sessions.lock().unwrap().insert(token.clone(), ::std::sync::Arc::new(app));
+
+        let mut result = json::Object::new();
+        let _ = result.insert("token".to_string(), json::Json::String(token));
+        Ok(json::Json::Object(result))
+    }
+
+    fn revoke(&mut self, call: &Call) -> Result<json::Json, RpcError> {
+        let token = try!(call.get_string("token"));
+        match self.sessions.lock().unwrap().remove(&token) {
+            Some(_) => Ok(json::Json::Null),
+            None => Err(RpcError::new(protocol::INVALID_TOKEN, "Invalid token".to_string())),
+        }
+    }
+
+    fn list_container(&mut self, call: &Call) -> Result<json::Json, RpcError> {
+        let container = try!(self.resolve(call));
+        let containers = container.get_containers().iter().map(|info| {
+            let mut entry = json::Object::new();
+            let _ = entry.insert("name".to_string(), json::Json::String(info.get_name()));
+            let _ = entry.insert("metadata".to_string(), Connection::optional_string(info.get_metadata()));
+            let _ = entry.insert("created".to_string(), json::Json::String(format!("{}", info.get_created_time().rfc3339())));
+            json::Json::Object(entry)
+        }).collect();
+        let blobs = container.get_blobs().iter().map(|blob| {
+            let mut entry = json::Object::new();
+            let _ = entry.insert("name".to_string(), json::Json::String(blob.get_name()));
+            let _ = entry.insert("size".to_string(), json::Json::U64(blob.get_size()));
+            let _ = entry.insert("metadata".to_string(),
+                                 Connection::optional_string(blob.get_user_metadata().map(|data| String::from_utf8_lossy(&data[..]).into_owned())));
+            let _ = entry.insert("created".to_string(), json::Json::String(format!("{}", blob.get_created_time().rfc3339())));
+            let _ = entry.insert("modified".to_string(), json::Json::String(format!("{}", blob.get_modified_time().rfc3339())));
+            json::Json::Object(entry)
+        }).collect();
+
+        let mut result = json::Object::new();
+        let _ = result.insert("name".to_string(), json::Json::String(container.get_name()));
+        let _ = result.insert("read_only".to_string(), json::Json::Boolean(container.is_read_only()));
+        let _ = result.insert("containers".to_string(), json::Json::Array(containers));
+        let _ = result.insert("blobs".to_string(), json::Json::Array(blobs));
+        Ok(json::Json::Object(result))
+    }
+
+    fn create_container(&mut self, call: &Call) -> Result<json::Json, RpcError> {
+        let mut container = try!(self.resolve_writable(call));
+        let name = try!(call.get_string("name"));
+        try!(container.create(name, try!(call.get_optional_string("metadata"))).map_err(Connection::failed));
+        Ok(json::Json::Null)
+    }
+
+    fn delete_container(&mut self, call: &Call) -> Result<json::Json, RpcError> {
+        let mut container = try!(self.resolve_writable(call));
+        try!(container.delete_container(try!(call.get_string("name"))).map_err(Connection::failed));
+        Ok(json::Json::Null)
+    }
+
+    fn read_blob(&mut self, call: &Call) -> Result<json::Json, RpcError> {
+        let container = try!(self.resolve(call));
+        let blob = try!(container.get_blob(try!(call.get_string("name")), None).map_err(Connection::failed));
+        let size = blob.get_size();
+        let offset = try!(call.get_optional_u64("offset")).unwrap_or(0);
+        if offset > size {
+            return Err(RpcError::invalid_params("offset is past the end of the blob".to_string()));
+        }
+        let length = ::std::cmp::min(try!(call.get_optional_u64("length")).unwrap_or(size - offset), size - offset);
+        let content = try!(blob.reader().read(offset, length).map_err(|err| Connection::failed(err.to_string())));
+
+        let mut result = json::Object::new();
+        let _ = result.insert("size".to_string(), json::Json::U64(size));
+        let _ = result.insert("content".to_string(), json::Json::String(content.to_base64(STANDARD)));
+        Ok(json::Json::Object(result))
+    }
+
+    fn write_blob(&mut self, call: &Call) -> Result<json::Json, RpcError> {
+        let mut container = try!(self.resolve_writable(call));
+        let name = try!(call.get_string("name"));
+        let metadata = try!(call.get_optional_string("metadata"));
+        let content = try!(try!(call.get_string("content")).from_base64().map_err(|_| RpcError::invalid_params("content must be base64".to_string())));
+        let exists = container.get_blob(name.clone(), None).is_ok();
+        try!(container.transaction(|tx| {
+            if exists {
+                try!(tx.update_blob(name.clone(), &content[..]));
+                match metadata {
+                    Some(_) => tx.update_blob_metadata(name.clone(), metadata.clone()),
+                    None => Ok(()),
+                }
+            } else {
+                tx.create_blob(name.clone(), metadata.clone(), &content[..])
+            }
+        }).map_err(Connection::failed));
+        Ok(json::Json::Null)
+    }
+
+    fn delete_blob(&mut self, call: &Call) -> Result<json::Json, RpcError> {
+        let mut container = try!(self.resolve_writable(call));
+        try!(container.delete_blob(try!(call.get_string("name"))).map_err(Connection::failed));
+        Ok(json::Json::Null)
+    }
+
+    /// The container a call acts on, reached through the session of its token
+    fn resolve(&self, call: &Call) -> Result<nfs::rest::Container, RpcError> {
+        let token = try!(call.get_string("token"));
+        let app = match self.sessions.lock().unwrap().get(&token) {
+            Some(app) => app.clone(),
+            None => return Err(RpcError::new(protocol::INVALID_TOKEN, "Invalid token".to_string())),
+        };
+        let root = try!(call.get_string("root"));
+        let mut container = if root == "app" {
+            try!(app.app_root().map_err(Connection::failed))
+        } else {
+            let well_known = try!(WellKnown::from_name(&root).ok_or_else(|| RpcError::invalid_params(format!("No well-known container {}", root))));
+            try!(app.well_known(well_known).map_err(|err| RpcError::new(protocol::ACCESS_DENIED, err)))
+        };
+        for name in try!(call.get_string_array("path")).into_iter() {
+            container = try!(container.get_container(name, None).map_err(Connection::failed));
+        }
+        Ok(container)
+    }
+
+    /// As resolve, for a call changing the container, which is denied on a read-only one
+    fn resolve_writable(&self, call: &Call) -> Result<nfs::rest::Container, RpcError> {
+        let container = try!(self.resolve(call));
+        if container.is_read_only() {
+            return Err(RpcError::new(protocol::ACCESS_DENIED, "Container is read-only".to_string()));
+        }
+        Ok(container)
+    }
+
+    fn failed(message: String) -> RpcError {
+        RpcError::new(protocol::OPERATION_FAILED, message)
+    }
+
+    fn optional_string(value: Option<String>) -> json::Json {
+        match value {
+            Some(value) => json::Json::String(value),
+            None => json::Json::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufRead, Write};
+    use rustc_serialize::json;
+    use rustc_serialize::base64::{FromBase64, ToBase64, STANDARD};
+
+    fn get_dummy_client() -> ::client::Client {
+        let keyword = "Spandan".to_string();
+        let password = "Sharma".as_bytes();
+        let pin = 1234u32;
+
+        ::client::Client::create_account(&keyword,
+                                         pin,
+                                         &password,
+                                         ::client::non_networking_test_framework::get_new_data_store()).ok().unwrap()
+    }
+
+    struct App {
+        reader: ::std::io::BufReader<::unix_socket::UnixStream>,
+        writer: ::unix_socket::UnixStream,
+        next_id: u64,
+    }
+
+    impl App {
+        fn connect(path: &::std::path::Path) -> App {
+            let stream = ::unix_socket::UnixStream::connect(path).unwrap();
+            App { writer: stream.try_clone().unwrap(), reader: ::std::io::BufReader::new(stream), next_id: 0 }
+        }
+
+        fn call(&mut self, method: &str, params: &str) -> json::Json {
+            self.next_id += 1;
+            let request = format!("{{\"jsonrpc\":\"2.0\",\"id\":{},\"method\":\"{}\",\"params\":{}}}\n", self.next_id, method, params);
+            self.writer.write_all(request.as_bytes()).unwrap();
+            let mut line = String::new();
+            let _ = self.reader.read_line(&mut line).unwrap();
+            let response = json::Json::from_str(&line).unwrap();
+            assert_eq!(response.find("id").unwrap().as_u64(), Some(self.next_id));
+            response
+        }
+
+        fn error_code(response: &json::Json) -> Option<i64> {
+            response.find_path(&["error", "code"]).and_then(|code| code.as_i64())
+        }
+    }
+
+    #[test]
+    fn apps_over_unix_socket() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        assert!(::nfs::rest::provision_well_known(client.clone()).is_ok());
+        let path = ::std::env::temp_dir().join(format!("safe_launcher_test_{}.sock", ::rand::random::<u64>()));
+        let approver: Approver = Box::new(|app_id, _| app_id != "malware");
+        let launcher = Launcher::bind(client.clone(), &path, approver).ok().unwrap();
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(::std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        let _ = ::std::thread::spawn(move || launcher.serve());
+
+        let mut app = App::connect(&path);
+        assert_eq!(App::error_code(&app.call("authorise_app", r#"{"app_id":"editor"}"#)), Some(protocol::HANDSHAKE_REQUIRED));
+        assert_eq!(App::error_code(&app.call("hello", r#"{"protocol_version":99}"#)), Some(protocol::UNSUPPORTED_VERSION));
+        let hello = app.call("hello", &format!(r#"{{"protocol_version":{}}}"#, PROTOCOL_VERSION));
+        assert_eq!(hello.find_path(&["result", "protocol_version"]).unwrap().as_u64(), Some(PROTOCOL_VERSION));
+        assert_eq!(App::error_code(&app.call("explode", "{}")), Some(protocol::METHOD_NOT_FOUND));
+
+        assert_eq!(App::error_code(&app.call("authorise_app", r#"{"app_id":"malware"}"#)), Some(protocol::ACCESS_DENIED));
+        let authorised = app.call("authorise_app", r#"{"app_id":"editor","grants":{"Documents":"read_write","Pictures":"read"}}"#);
+        let token = authorised.find_path(&["result", "token"]).unwrap().as_string().unwrap().to_string();
+
+        let content = b"hello launcher".to_base64(STANDARD);
+        let write = app.call("write_blob", &format!(r#"{{"token":"{}","root":"app","name":"notes.txt","content":"{}","metadata":"text"}}"#, token, content));
+        assert!(write.find("error").is_none());
+        assert!(app.call("create_container", &format!(r#"{{"token":"{}","root":"Documents","name":"Drafts"}}"#, token)).find("error").is_none());
+        assert!(app.call("write_blob", &format!(r#"{{"token":"{}","root":"Documents","path":["Drafts"],"name":"a.txt","content":"{}"}}"#, token, content)).find("error").is_none());
+
+        let listing = app.call("list_container", &format!(r#"{{"token":"{}","root":"app"}}"#, token));
+        let blobs = listing.find_path(&["result", "blobs"]).unwrap().as_array().unwrap();
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs[0].find("metadata").unwrap().as_string(), Some("text"));
+
+        // A second connection works through the same session
+        let mut other = App::connect(&path);
+        let _ = other.call("hello", r#"{"protocol_version":1}"#);
+        let read = other.call("read_blob", &format!(r#"{{"token":"{}","root":"Documents","path":["Drafts"],"name":"a.txt","offset":6}}"#, token));
+        let read_content = read.find_path(&["result", "content"]).unwrap().as_string().unwrap().from_base64().unwrap();
+        assert_eq!(read_content, b"launcher".to_vec());
+
+        assert_eq!(App::error_code(&app.call("write_blob", &format!(r#"{{"token":"{}","root":"Pictures","name":"a.jpg","content":""}}"#, token))),
+                   Some(protocol::ACCESS_DENIED));
+        assert_eq!(App::error_code(&app.call("create_container", &format!(r#"{{"token":"{}","root":"Pictures","name":"Albums"}}"#, token))),
+                   Some(protocol::ACCESS_DENIED));
+        assert_eq!(App::error_code(&app.call("list_container", &format!(r#"{{"token":"{}","root":"Music"}}"#, token))), Some(protocol::ACCESS_DENIED));
+        assert_eq!(App::error_code(&app.call("list_container", r#"{"token":"forged","root":"app"}"#)), Some(protocol::INVALID_TOKEN));
+
+        assert!(app.call("revoke", &format!(r#"{{"token":"{}"}}"#, token)).find("error").is_none());
+        assert_eq!(App::error_code(&other.call("list_container", &format!(r#"{{"token":"{}","root":"app"}}"#, token))), Some(protocol::INVALID_TOKEN));
+
+        // Another launcher cannot take over a live socket
+        let approver: Approver = Box::new(|_, _| true);
+        assert!(Launcher::bind(client, &path, approver).is_err());
+        let _ = ::std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn oversized_requests_are_refused() {
+        use std::io::Read;
+
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let path = ::std::env::temp_dir().join(format!("safe_launcher_test_{}.sock", ::rand::random::<u64>()));
+        let approver: Approver = Box::new(|_, _| true);
+        let launcher = Launcher::bind(client, &path, approver).ok().unwrap();
+        let _ = ::std::thread::spawn(move || launcher.serve());
+
+        let mut app = App::connect(&path);
+        app.writer.write_all(&vec![b' '; MAX_REQUEST_SIZE as usize + 1][..]).unwrap();
+        let mut line = String::new();
+        let _ = app.reader.read_line(&mut line).unwrap();
+        let response = json::Json::from_str(&line).unwrap();
+        assert_eq!(App::error_code(&response), Some(protocol::INVALID_REQUEST));
+        // The connection is closed
+        assert_eq!(app.reader.read(&mut [0u8; 1]).unwrap(), 0);
+        let _ = ::std::fs::remove_file(&path);
+    }
+}
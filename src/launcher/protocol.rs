@@ -0,0 +1,187 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+//! The launcher speaks JSON-RPC 2.0, one request or response object per line. A connection
+//! starts with `hello`, in which the app states the protocol version it speaks.
+
+use rustc_serialize::json;
+
+/// Version of the protocol this launcher speaks. It serves no other.
+pub const PROTOCOL_VERSION: u64 = 1;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// The app speaks a protocol version the launcher does not
+pub const UNSUPPORTED_VERSION: i64 = 1;
+/// A method other than `hello` was called before `hello`
+pub const HANDSHAKE_REQUIRED: i64 = 2;
+/// The session token is unknown or was revoked
+pub const INVALID_TOKEN: i64 = 3;
+/// The user refused the app, or the app asked for something outside its grants
+pub const ACCESS_DENIED: i64 = 4;
+/// The operation was allowed but failed
+pub const OPERATION_FAILED: i64 = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: String) -> RpcError {
+        RpcError { code: code, message: message }
+    }
+
+    pub fn invalid_params(message: String) -> RpcError {
+        RpcError::new(INVALID_PARAMS, message)
+    }
+}
+
+/// A request read off a connection
+pub struct Call {
+    pub id: json::Json,
+    pub method: String,
+    params: json::Object,
+}
+
+impl Call {
+    /// Parses a line. On failure returns the id of the request, when it could be read, along
+    /// with the error to answer with.
+    pub fn parse(line: &str) -> Result<Call, (json::Json, RpcError)> {
+        let value = match json::Json::from_str(line) {
+            Ok(value) => value,
+            Err(_) => return Err((json::Json::Null, RpcError::new(PARSE_ERROR, "Parse error".to_string()))),
+        };
+        let mut object = match value {
+            json::Json::Object(object) => object,
+            _ => return Err((json::Json::Null, RpcError::new(INVALID_REQUEST, "Request must be an object".to_string()))),
+        };
+        let id = object.remove("id").unwrap_or(json::Json::Null);
+        if object.get("jsonrpc").and_then(|version| version.as_string()) != Some("2.0") {
+            return Err((id, RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"".to_string())));
+        }
+        let method = match object.get("method").and_then(|method| method.as_string()) {
+            Some(method) => method.to_string(),
+            None => return Err((id, RpcError::new(INVALID_REQUEST, "method must be a string".to_string()))),
+        };
+        let params = match object.remove("params") {
+            Some(json::Json::Object(params)) => params,
+            None | Some(json::Json::Null) => json::Object::new(),
+            Some(_) => return Err((id, RpcError::new(INVALID_REQUEST, "params must be an object".to_string()))),
+        };
+        Ok(Call { id: id, method: method, params: params })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&json::Json> {
+        match self.params.get(key) {
+            Some(&json::Json::Null) | None => None,
+            Some(value) => Some(value),
+        }
+    }
+
+    pub fn get_string(&self, key: &str) -> Result<String, RpcError> {
+        match try!(self.get_optional_string(key)) {
+            Some(value) => Ok(value),
+            None => Err(RpcError::invalid_params(format!("{} is required", key))),
+        }
+    }
+
+    pub fn get_optional_string(&self, key: &str) -> Result<Option<String>, RpcError> {
+        match self.get(key) {
+            Some(&json::Json::String(ref value)) => Ok(Some(value.clone())),
+            Some(_) => Err(RpcError::invalid_params(format!("{} must be a string", key))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_optional_u64(&self, key: &str) -> Result<Option<u64>, RpcError> {
+        match self.get(key) {
+            Some(value) => match value.as_u64() {
+                Some(value) => Ok(Some(value)),
+                None => Err(RpcError::invalid_params(format!("{} must be a non-negative integer", key))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// An array of strings, empty when absent
+    pub fn get_string_array(&self, key: &str) -> Result<Vec<String>, RpcError> {
+        let invalid = || RpcError::invalid_params(format!("{} must be an array of strings", key));
+        match self.get(key) {
+            Some(&json::Json::Array(ref values)) => values.iter().map(|value| value.as_string().map(|value| value.to_string()).ok_or_else(|| invalid())).collect(),
+            Some(_) => Err(invalid()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// An object of string values, empty when absent
+    pub fn get_string_map(&self, key: &str) -> Result<::std::collections::BTreeMap<String, String>, RpcError> {
+        let invalid = || RpcError::invalid_params(format!("{} must be an object of strings", key));
+        match self.get(key) {
+            Some(&json::Json::Object(ref values)) => values.iter().map(|(name, value)| value.as_string().map(|value| (name.clone(), value.to_string())).ok_or_else(|| invalid())).collect(),
+            Some(_) => Err(invalid()),
+            None => Ok(::std::collections::BTreeMap::new()),
+        }
+    }
+}
+
+pub fn success(id: json::Json, result: json::Json) -> String {
+    let mut response = json::Object::new();
+    let _ = response.insert("jsonrpc".to_string(), json::Json::String("2.0".to_string()));
+    let _ = response.insert("id".to_string(), id);
+    let _ = response.insert("result".to_string(), result);
+    json::Json::Object(response).to_string()
+}
+
+pub fn failure(id: json::Json, error: RpcError) -> String {
+    let mut error_object = json::Object::new();
+    let _ = error_object.insert("code".to_string(), json::Json::I64(error.code));
+    let _ = error_object.insert("message".to_string(), json::Json::String(error.message));
+    let mut response = json::Object::new();
+    let _ = response.insert("jsonrpc".to_string(), json::Json::String("2.0".to_string()));
+    let _ = response.insert("id".to_string(), id);
+    let _ = response.insert("error".to_string(), json::Json::Object(error_object));
+    json::Json::Object(response).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rustc_serialize::json;
+
+    #[test]
+    fn parse_requests() {
+        let call = Call::parse(r#"{"jsonrpc":"2.0","id":7,"method":"list_container","params":{"root":"app","path":["a","b"]}}"#).ok().unwrap();
+        assert_eq!(call.id, json::Json::U64(7));
+        assert_eq!(call.method, "list_container".to_string());
+        assert_eq!(call.get_string("root"), Ok("app".to_string()));
+        assert_eq!(call.get_string_array("path"), Ok(vec!["a".to_string(), "b".to_string()]));
+        assert!(call.get_string("token").is_err());
+        assert!(call.get_string_array("root").is_err());
+
+        assert_eq!(Call::parse("{").err().unwrap().1.code, PARSE_ERROR);
+        assert_eq!(Call::parse("[]").err().unwrap().1.code, INVALID_REQUEST);
+        let (id, error) = Call::parse(r#"{"jsonrpc":"1.0","id":"x","method":"hello"}"#).err().unwrap();
+        assert_eq!(id, json::Json::String("x".to_string()));
+        assert_eq!(error.code, INVALID_REQUEST);
+
+        let response = json::Json::from_str(&failure(json::Json::U64(1), RpcError::new(INVALID_TOKEN, "gone".to_string()))).unwrap();
+        assert_eq!(response.find_path(&["error", "code"]).unwrap().as_i64(), Some(INVALID_TOKEN));
+    }
+}
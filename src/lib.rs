@@ -20,6 +20,7 @@
 #![doc(html_logo_url = "http://maidsafe.net/img/Resources/branding/maidsafe_logo.fab2.png",
        html_favicon_url = "http://maidsafe.net/img/favicon.ico",
               html_root_url = "http://dirvine.github.io/dirvine/maidsafe_client/")]
+#![cfg_attr(feature = "bench", feature(test))]
 
 extern crate cbor;
 extern crate rand;
@@ -28,13 +29,22 @@ extern crate crypto;
 extern crate routing;
 extern crate sodiumoxide;
 extern crate rustc_serialize;
+extern crate flate2;
+extern crate libc;
 
 extern crate maidsafe_types;
 extern crate lru_time_cache;
 extern crate self_encryption;
+#[cfg(unix)]
+extern crate unix_socket;
+#[cfg(feature = "bench")]
+extern crate test;
 
 pub mod nfs;
 pub mod client;
+pub mod ffi;
+#[cfg(unix)]
+pub mod launcher;
 
 pub type IoError = std::io::Error;
 pub type ResponseNotifier = std::sync::Arc<(std::sync::Mutex<routing::types::MessageId>, std::sync::Condvar)>;
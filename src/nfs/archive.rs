@@ -0,0 +1,605 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+//! Export of a container tree to a tar stream and import of one. Archives are POSIX pax:
+//! ustar headers, each preceded by an extended header carrying the full path, the times to
+//! the nanosecond and the user metadata, so tools which know nothing of SAFE can read them.
+
+use nfs;
+use time;
+use client;
+use rustc_serialize::base64::{FromBase64, ToBase64, STANDARD};
+
+const BLOCK_SIZE: usize = 512;
+// Content is streamed through the Reader and Writer in pieces of this size
+const COPY_SIZE: u64 = 64 * 1024;
+// Largest value the 11 octal digits of the ustar size field can hold
+const MAX_USTAR_SIZE: u64 = 0o77777777777;
+// Largest pax extended header read, as it is held in memory whole
+const MAX_EXTENDED_SIZE: u64 = 1024 * 1024;
+
+const TYPE_FILE: u8 = b'0';
+const TYPE_DIRECTORY: u8 = b'5';
+const TYPE_PAX: u8 = b'x';
+const TYPE_PAX_GLOBAL: u8 = b'g';
+
+/// Pax keyword for the creation time, which tar itself has no field for
+pub const PAX_CREATED: &'static str = "SAFE.created";
+/// Pax keyword for the user metadata, base64 encoded
+pub const PAX_USER_METADATA: &'static str = "SAFE.user_metadata";
+
+/// Writes the tree below the directory to the output as a tar archive. Paths in the archive
+/// are relative to the directory.
+pub fn export<W: ::std::io::Write>(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+                                   directory: &nfs::directory_listing::DirectoryListing,
+                                   output: &mut W) -> Result<(), String> {
+    try!(export_directory(&client, directory, "", output));
+    write_all(output, &[0u8; BLOCK_SIZE * 2])
+}
+
+/// Reads a tar archive into the directory, creating the directories and blobs it holds and
+/// replacing blobs of the same name. Links and other special entries are skipped. The listing
+/// is updated to the saved state of the directory. Returns the number of blobs imported.
+///
+/// No directory is created or changed until the whole archive has been read, so an archive
+/// which fails to import leaves the tree as it was. Content of blobs read before the failure
+/// is stored all the same, referenced by nothing.
+pub fn import<R: ::std::io::Read>(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+                                  directory: &mut nfs::directory_listing::DirectoryListing,
+                                  input: &mut R) -> Result<usize, String> {
+    let mut importer = Importer {
+        client: client,
+        directories: ::std::collections::BTreeMap::new(),
+        dirty: ::std::collections::BTreeSet::new(),
+        created: ::std::collections::BTreeSet::new(),
+    };
+    let _ = importer.directories.insert(String::new(), directory.clone());
+
+    let mut count = 0;
+    let mut extended = ::std::collections::BTreeMap::<String, String>::new();
+    loop {
+        let mut block = [0u8; BLOCK_SIZE];
+        if !try!(read_block(input, &mut block)) || block.iter().all(|byte| *byte == 0) {
+            break;
+        }
+        try!(verify_checksum(&block));
+        let type_flag = block[156];
+        let mut size = try!(parse_octal(&block[124..136]));
+
+        if type_flag == TYPE_PAX || type_flag == TYPE_PAX_GLOBAL {
+            if size > MAX_EXTENDED_SIZE {
+                return Err(format!("Extended header of {} bytes is larger than the {} allowed", size, MAX_EXTENDED_SIZE));
+            }
+            let data = try!(read_data(input, size));
+            // Global headers set defaults which none of the keywords used here take from
+            if type_flag == TYPE_PAX {
+                extended = try!(parse_pax(&data[..]));
+            }
+            continue;
+        }
+
+        if let Some(value) = extended.get("size") {
+            size = try!(value.parse::<u64>().map_err(|_| "Invalid size in archive".to_string()));
+        }
+        let path = match extended.remove("path") {
+            Some(path) => path,
+            None => ustar_path(&block),
+        };
+        let components = try!(split_path(&path));
+        let mut metadata = nfs::metadata::Metadata::new(String::new(), Vec::new());
+        let modified_time = match extended.get("mtime") {
+            Some(value) => try!(parse_time(value)),
+            None => time::at_utc(time::Timespec::new(try!(parse_octal(&block[136..148])) as i64, 0)),
+        };
+        metadata.set_modified_time(modified_time);
+        metadata.set_created_time(match extended.get(PAX_CREATED) {
+            Some(value) => try!(parse_time(value)),
+            None => modified_time,
+        });
+        if let Some(value) = extended.get(PAX_USER_METADATA) {
+            metadata.set_user_metadata(try!(value.from_base64().map_err(|_| "Invalid user metadata in archive".to_string())));
+        }
+        extended.clear();
+
+        match type_flag {
+            TYPE_FILE | 0 if !components.is_empty() => {
+                try!(importer.import_file(&components[..], metadata, size, input));
+                count += 1;
+            },
+            TYPE_DIRECTORY => {
+                try!(importer.import_directory(&components[..], metadata));
+                try!(skip_data(input, size));
+            },
+            _ => try!(skip_data(input, size)),
+        }
+    }
+
+    *directory = try!(importer.save());
+    Ok(count)
+}
+
+fn export_directory<W: ::std::io::Write>(client: &::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+                                         directory: &nfs::directory_listing::DirectoryListing,
+                                         prefix: &str,
+                                         output: &mut W) -> Result<(), String> {
+    for file in directory.get_files() {
+        let mut reader = nfs::io::Reader::new(file.clone(), client.clone());
+        let size = reader.size();
+        try!(write_entry(output, &format!("{}{}", prefix, file.get_name()), &file.get_metadata(), TYPE_FILE, size));
+
+        let mut position = 0;
+        while position < size {
+            let length = ::std::cmp::min(COPY_SIZE, size - position);
+            let data = try!(reader.read(position, length).map_err(|err| err.to_string()));
+            try!(write_all(output, &data[..]));
+            position += length;
+        }
+        try!(write_all(output, &padding(size)[..]));
+    }
+
+    for info in directory.get_sub_directories() {
+        let path = format!("{}{}/", prefix, info.get_name());
+        try!(write_entry(output, &path, info.get_metadata(), TYPE_DIRECTORY, 0));
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(client.clone());
+        let sub_directory = try!(directory_helper.get(info.get_id(), directory.get_id()));
+        try!(export_directory(client, &sub_directory, &path, output));
+    }
+    Ok(())
+}
+
+/// Writes the extended header and the ustar header of an entry
+fn write_entry<W: ::std::io::Write>(output: &mut W, path: &str, metadata: &nfs::metadata::Metadata, type_flag: u8, size: u64) -> Result<(), String> {
+    let modified_time = metadata.get_modified_time().to_timespec();
+    let mut records = Vec::new();
+    pax_record(&mut records, "path", path);
+    pax_record(&mut records, "mtime", &format_time(metadata.get_modified_time()));
+    pax_record(&mut records, PAX_CREATED, &format_time(metadata.get_created_time()));
+    if let Some(user_metadata) = metadata.get_user_metadata() {
+        pax_record(&mut records, PAX_USER_METADATA, &user_metadata.to_base64(STANDARD));
+    }
+    if size > MAX_USTAR_SIZE {
+        pax_record(&mut records, "size", &size.to_string());
+    }
+
+    let pax_path = format!("PaxHeaders/{}", path.trim_right_matches('/'));
+    try!(write_all(output, &header(&pax_path, TYPE_PAX, 0o644, records.len() as u64, modified_time.sec)[..]));
+    try!(write_all(output, &records[..]));
+    try!(write_all(output, &padding(records.len() as u64)[..]));
+    let mode = if type_flag == TYPE_DIRECTORY { 0o755 } else { 0o644 };
+    write_all(output, &header(path, type_flag, mode, ::std::cmp::min(size, MAX_USTAR_SIZE), modified_time.sec)[..])
+}
+
+/// A ustar header. The name is cut to fit, readers take the full path from the extended header.
+fn header(path: &str, type_flag: u8, mode: u64, size: u64, modified_sec: i64) -> [u8; BLOCK_SIZE] {
+    let mut block = [0u8; BLOCK_SIZE];
+    for (destination, source) in block[..100].iter_mut().zip(path.as_bytes().iter()) {
+        *destination = *source;
+    }
+    set_octal(&mut block[100..108], mode);
+    set_octal(&mut block[108..116], 0);
+    set_octal(&mut block[116..124], 0);
+    set_octal(&mut block[124..136], size);
+    set_octal(&mut block[136..148], ::std::cmp::max(modified_sec, 0) as u64);
+    block[156] = type_flag;
+    for (destination, source) in block[257..265].iter_mut().zip(b"ustar\000".iter()) {
+        *destination = *source;
+    }
+    set_checksum(&mut block);
+    block
+}
+
+fn set_octal(field: &mut [u8], value: u64) {
+    let width = field.len() - 1;
+    let digits = format!("{:01$o}", value, width);
+    for (destination, source) in field.iter_mut().zip(digits.as_bytes().iter()) {
+        *destination = *source;
+    }
+    field[width] = 0;
+}
+
+fn checksum(block: &[u8; BLOCK_SIZE]) -> u64 {
+    block.iter().enumerate().map(|(index, byte)| if index >= 148 && index < 156 { b' ' as u64 } else { *byte as u64 }).fold(0, |sum, byte| sum + byte)
+}
+
+fn set_checksum(block: &mut [u8; BLOCK_SIZE]) {
+    let digits = format!("{:06o}\0 ", checksum(block));
+    for (destination, source) in block[148..156].iter_mut().zip(digits.as_bytes().iter()) {
+        *destination = *source;
+    }
+}
+
+fn verify_checksum(block: &[u8; BLOCK_SIZE]) -> Result<(), String> {
+    if try!(parse_octal(&block[148..156])) != checksum(block) {
+        return Err("Invalid checksum in archive header".to_string());
+    }
+    Ok(())
+}
+
+fn parse_octal(field: &[u8]) -> Result<u64, String> {
+    if field.len() > 0 && field[0] & 0x80 != 0 {
+        return Err("Binary numeric fields in archive headers are not supported".to_string());
+    }
+    let digits: String = field.iter().take_while(|byte| **byte != 0).map(|byte| *byte as char).collect();
+    let digits = digits.trim();
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| "Invalid number in archive header".to_string())
+}
+
+fn ustar_path(block: &[u8; BLOCK_SIZE]) -> String {
+    let field = |range: &[u8]| String::from_utf8_lossy(&range[..range.iter().position(|byte| *byte == 0).unwrap_or(range.len())]).into_owned();
+    let name = field(&block[..100]);
+    if &block[257..262] == b"ustar" {
+        let prefix = field(&block[345..500]);
+        if !prefix.is_empty() {
+            return format!("{}/{}", prefix, name);
+        }
+    }
+    name
+}
+
+/// Splits an archive path into its components, rejecting paths which climb out of the
+/// directory being imported into
+fn split_path(path: &str) -> Result<Vec<String>, String> {
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => continue,
+            ".." => return Err(format!("Archive path {} leaves the container", path)),
+            _ => components.push(component.to_string()),
+        }
+    }
+    Ok(components)
+}
+
+/// Appends a pax record, `<length> <keyword>=<value>\n`, where the length counts itself
+fn pax_record(records: &mut Vec<u8>, keyword: &str, value: &str) {
+    let content_length = keyword.len() + value.len() + 3;
+    let mut length = content_length + 1;
+    while length.to_string().len() + content_length != length {
+        length = length.to_string().len() + content_length;
+    }
+    records.extend(format!("{} {}={}\n", length, keyword, value).as_bytes().iter().cloned());
+}
+
+fn parse_pax(data: &[u8]) -> Result<::std::collections::BTreeMap<String, String>, String> {
+    let invalid = || "Invalid extended header in archive".to_string();
+    let mut records = ::std::collections::BTreeMap::new();
+    let mut rest = data;
+    while !rest.is_empty() && rest[0] != 0 {
+        let space = try!(rest.iter().position(|byte| *byte == b' ').ok_or_else(|| invalid()));
+        let length = try!(String::from_utf8_lossy(&rest[..space]).parse::<usize>().map_err(|_| invalid()));
+        if length <= space + 1 || length > rest.len() || rest[length - 1] != b'\n' {
+            return Err(invalid());
+        }
+        let record = try!(String::from_utf8(rest[space + 1..length - 1].to_vec()).map_err(|_| invalid()));
+        let equals = try!(record.find('=').ok_or_else(|| invalid()));
+        let _ = records.insert(record[..equals].to_string(), record[equals + 1..].to_string());
+        rest = &rest[length..];
+    }
+    Ok(records)
+}
+
+fn format_time(tm: time::Tm) -> String {
+    let timespec = tm.to_timespec();
+    format!("{}.{:09}", timespec.sec, timespec.nsec)
+}
+
+fn parse_time(value: &str) -> Result<time::Tm, String> {
+    let invalid = || format!("Invalid time {} in archive", value);
+    let mut parts = value.splitn(2, '.');
+    let sec = try!(parts.next().unwrap_or("").parse::<i64>().map_err(|_| invalid()));
+    let nsec = match parts.next() {
+        Some(fraction) => {
+            let digits: String = fraction.chars().chain(::std::iter::repeat('0')).take(9).collect();
+            try!(digits.parse::<i32>().map_err(|_| invalid()))
+        },
+        None => 0,
+    };
+    Ok(time::at_utc(time::Timespec::new(sec, nsec)))
+}
+
+fn padding(size: u64) -> Vec<u8> {
+    let remainder = (size % BLOCK_SIZE as u64) as usize;
+    if remainder == 0 { Vec::new() } else { vec![0u8; BLOCK_SIZE - remainder] }
+}
+
+fn write_all<W: ::std::io::Write>(output: &mut W, data: &[u8]) -> Result<(), String> {
+    output.write_all(data).map_err(|err| format!("Failed to write archive: {}", err))
+}
+
+/// Reads a whole block. Returns false at the end of the input.
+fn read_block<R: ::std::io::Read>(input: &mut R, block: &mut [u8; BLOCK_SIZE]) -> Result<bool, String> {
+    let mut filled = 0;
+    while filled < BLOCK_SIZE {
+        match input.read(&mut block[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err("Archive ends inside a header".to_string()),
+            Ok(count) => filled += count,
+            Err(ref err) if err.kind() == ::std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(format!("Failed to read archive: {}", err)),
+        }
+    }
+    Ok(true)
+}
+
+fn read_exact<R: ::std::io::Read>(input: &mut R, buffer: &mut [u8]) -> Result<(), String> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match input.read(&mut buffer[filled..]) {
+            Ok(0) => return Err("Archive ends inside an entry".to_string()),
+            Ok(count) => filled += count,
+            Err(ref err) if err.kind() == ::std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(format!("Failed to read archive: {}", err)),
+        }
+    }
+    Ok(())
+}
+
+/// Reads the data of an entry along with its padding
+fn read_data<R: ::std::io::Read>(input: &mut R, size: u64) -> Result<Vec<u8>, String> {
+    let mut data = vec![0u8; size as usize];
+    try!(read_exact(input, &mut data[..]));
+    try!(read_exact(input, &mut padding(size)[..]));
+    Ok(data)
+}
+
+/// Skips the data of an entry along with its padding
+fn skip_data<R: ::std::io::Read>(input: &mut R, size: u64) -> Result<(), String> {
+    let mut remaining = size + padding(size).len() as u64;
+    let mut buffer = [0u8; BLOCK_SIZE];
+    while remaining > 0 {
+        let length = ::std::cmp::min(remaining, BLOCK_SIZE as u64) as usize;
+        try!(read_exact(input, &mut buffer[..length]));
+        remaining -= length as u64;
+    }
+    Ok(())
+}
+
+/// Directories touched by an import, by path, saved once the whole archive has been read
+struct Importer {
+    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+    directories: ::std::collections::BTreeMap<String, nfs::directory_listing::DirectoryListing>,
+    dirty: ::std::collections::BTreeSet<String>,
+    // Directories the archive adds, which are only stored by save
+    created: ::std::collections::BTreeSet<String>,
+}
+
+impl Importer {
+    /// Opens the directory at the path, creating any missing directories on the way.
+    /// Returns its key in directories.
+    fn open_directory(&mut self, components: &[String]) -> Result<String, String> {
+        let key = components.join("/");
+        if self.directories.contains_key(&key) {
+            return Ok(key);
+        }
+        let parent_key = try!(self.open_directory(&components[..components.len() - 1]));
+        let name = components[components.len() - 1].clone();
+        let (parent_id, existing) = {
+            let parent = self.directories.get(&parent_key).unwrap();
+            if parent.find_file(&name).is_some() {
+                return Err(format!("{} is a blob, not a container", key));
+            }
+            (parent.get_id(), parent.find_sub_directory(&name).map(|info| info.get_id()))
+        };
+
+        let directory = match existing {
+            Some(id) => try!(nfs::helper::DirectoryHelper::new(self.client.clone()).get(id, parent_id)),
+            None => {
+                let info = nfs::directory_info::DirectoryInfo::new(parent_id, nfs::metadata::Metadata::new(name, Vec::new()));
+                let _ = self.directories.get_mut(&parent_key).unwrap().upsert_sub_directory(info.clone());
+                let _ = self.dirty.insert(parent_key);
+                let _ = self.created.insert(key.clone());
+                nfs::directory_listing::DirectoryListing::from_info(info)
+            },
+        };
+        let _ = self.directories.insert(key.clone(), directory);
+        Ok(key)
+    }
+
+    fn import_directory(&mut self, components: &[String], metadata: nfs::metadata::Metadata) -> Result<(), String> {
+        // The directory being imported into keeps its own metadata
+        if components.is_empty() {
+            return Ok(());
+        }
+        // Containers give their user metadata as text
+        if let Some(user_metadata) = metadata.get_user_metadata() {
+            if ::std::str::from_utf8(&user_metadata[..]).is_err() {
+                return Err(format!("Invalid user metadata for {} in archive", components.join("/")));
+            }
+        }
+        let key = try!(self.open_directory(components));
+        let info = {
+            let directory = self.directories.get_mut(&key).unwrap();
+            {
+                let directory_metadata = directory.get_mut_metadata();
+                directory_metadata.set_created_time(metadata.get_created_time());
+                directory_metadata.set_modified_time(metadata.get_modified_time());
+                // An entry without user metadata leaves that of an existing container as it is
+                if let Some(user_metadata) = metadata.get_user_metadata() {
+                    directory_metadata.set_user_metadata(user_metadata);
+                }
+            }
+            directory.get_info()
+        };
+        let parent_key = components[..components.len() - 1].join("/");
+        let _ = self.directories.get_mut(&parent_key).unwrap().upsert_sub_directory(info);
+        let _ = self.dirty.insert(parent_key);
+        let _ = self.dirty.insert(key);
+        Ok(())
+    }
+
+    fn import_file<R: ::std::io::Read>(&mut self, components: &[String], metadata: nfs::metadata::Metadata,
+                                      size: u64, input: &mut R) -> Result<(), String> {
+        let name = components[components.len() - 1].clone();
+        let key = try!(self.open_directory(&components[..components.len() - 1]));
+        let mut writer = {
+            let directory = self.directories.get(&key).unwrap();
+            if directory.find_sub_directory(&name).is_some() {
+                return Err(format!("{} is a container, not a blob", components.join("/")));
+            }
+            // The Writer is only finished, never closed, so it is handed the entries of the
+            // directory rather than a copy of the whole listing. An existing blob is replaced
+            // when the file is put into the listing.
+            let entries = nfs::directory_listing::DirectoryListing::from_info(directory.get_info());
+            let mut file_helper = nfs::helper::FileHelper::new(self.client.clone());
+            try!(file_helper.create(name, size, metadata.get_user_metadata().unwrap_or(Vec::new()), entries))
+        };
+
+        let mut position = 0;
+        let mut buffer = vec![0u8; COPY_SIZE as usize];
+        while position < size {
+            let length = ::std::cmp::min(COPY_SIZE, size - position) as usize;
+            try!(read_exact(input, &mut buffer[..length]));
+            writer.write(&buffer[..length], position);
+            position += length as u64;
+        }
+        try!(read_exact(input, &mut padding(size)[..]));
+
+        let mut file = try!(writer.finish());
+        let mut file_metadata = file.get_metadata();
+        file_metadata.set_created_time(metadata.get_created_time());
+        file_metadata.set_modified_time(metadata.get_modified_time());
+        file.set_metadata(file_metadata);
+        let _ = self.directories.get_mut(&key).unwrap().upsert_file(file);
+        let _ = self.dirty.insert(key);
+        Ok(())
+    }
+
+    /// Creates the new directories and saves every directory which changed. Returns the
+    /// directory imported into.
+    fn save(mut self) -> Result<nfs::directory_listing::DirectoryListing, String> {
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
+        for key in self.created.iter() {
+            let _ = try!(directory_helper.create_from_info(self.directories.get(key).unwrap().get_info()));
+        }
+        for key in self.dirty.iter() {
+            try!(directory_helper.update(self.directories.get(key).unwrap().clone()));
+        }
+        Ok(self.directories.remove("").unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nfs::traits::DirectoryListingWrapper;
+
+    fn get_dummy_client() -> ::client::Client {
+        let keyword = "Spandan".to_string();
+        let password = "Sharma".as_bytes();
+        let pin = 1234u32;
+
+        ::client::Client::create_account(&keyword,
+                                         pin,
+                                         &password,
+                                         ::client::non_networking_test_framework::get_new_data_store()).ok().unwrap()
+    }
+
+    #[test]
+    fn pax_records_count_their_own_length() {
+        let mut records = Vec::new();
+        super::pax_record(&mut records, "path", "a");
+        assert_eq!(records, b"9 path=a\n".to_vec());
+        records.clear();
+        // Eleven bytes of record push the length to two digits
+        super::pax_record(&mut records, "path", "abcd");
+        assert_eq!(records, b"14 path=abcd\n".to_vec());
+        let parsed = super::parse_pax(&records[..]).ok().unwrap();
+        assert_eq!(parsed.get("path"), Some(&"abcd".to_string()));
+        assert!(super::split_path("a/../../b").is_err());
+        assert_eq!(super::split_path("./a//b/").ok().unwrap(), vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn export_and_import() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let mut directory_helper = ::nfs::helper::DirectoryHelper::new(client.clone());
+        let source_id = directory_helper.create(parent_id.clone(), "Source".to_string(), Vec::new()).ok().unwrap();
+        let target_id = directory_helper.create(parent_id.clone(), "Target".to_string(), Vec::new()).ok().unwrap();
+
+        let large: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        let mut source = ::nfs::rest::Container::authorise(client.clone(), source_id.0, parent_id.0).ok().unwrap();
+        assert!(source.transaction(|tx| {
+            try!(tx.create_blob("a.txt".to_string(), Some("note".to_string()), b"hello"));
+            tx.create_container("Docs".to_string(), Some("papers".to_string()))
+        }).is_ok());
+        let mut docs = source.get_container("Docs".to_string(), None).ok().unwrap();
+        assert!(docs.transaction(|tx| tx.create_blob("b.bin".to_string(), None, &large[..])).is_ok());
+
+        let mut archive = Vec::new();
+        assert!(source.export_archive(&mut archive).is_ok());
+        assert_eq!(archive.len() % BLOCK_SIZE, 0);
+        assert_eq!(&archive[257..262], b"ustar");
+        assert_eq!(archive[156], TYPE_PAX);
+
+        let mut target = ::nfs::rest::Container::authorise(client.clone(), target_id.0, parent_id.0).ok().unwrap();
+        assert_eq!(target.import_archive(&mut &archive[..]).ok().unwrap(), 2);
+
+        let mut target = ::nfs::rest::Container::authorise(client.clone(), target_id.0, parent_id.0).ok().unwrap();
+        let original = source.get_blob("a.txt".to_string(), None).ok().unwrap();
+        let imported = target.get_blob("a.txt".to_string(), None).ok().unwrap();
+        assert_eq!(imported.get_user_metadata(), Some("note".to_string().into_bytes()));
+        assert_eq!(imported.get_modified_time(), original.get_modified_time());
+        assert_eq!(imported.get_created_time(), original.get_created_time());
+        assert_eq!(imported.reader().read(0, 5).ok().unwrap(), b"hello".to_vec());
+
+        assert_eq!(target.get_containers().len(), 1);
+        let imported_docs = target.get_container("Docs".to_string(), None).ok().unwrap();
+        assert_eq!(imported_docs.get_metadata(), Some("papers".to_string()));
+        assert_eq!(imported_docs.convert_to_directory_listing().get_files().len(), 1);
+        let blob = imported_docs.get_blob("b.bin".to_string(), None).ok().unwrap();
+        assert_eq!(blob.reader().read(0, 5000).ok().unwrap(), large);
+
+        // A container entry without user metadata leaves that of an existing container as it is
+        let mut bare = Vec::new();
+        assert!(super::write_entry(&mut bare, "Docs/", &::nfs::metadata::Metadata::new("Docs".to_string(), Vec::new()), TYPE_DIRECTORY, 0).is_ok());
+        bare.extend([0u8; BLOCK_SIZE * 2].iter().cloned());
+        assert_eq!(target.import_archive(&mut &bare[..]).ok().unwrap(), 0);
+        let imported_docs = target.get_container("Docs".to_string(), None).ok().unwrap();
+        assert_eq!(imported_docs.get_metadata(), Some("papers".to_string()));
+
+        // Containers only take user metadata which is text
+        let mut binary = Vec::new();
+        assert!(super::write_entry(&mut binary, "Binary/", &::nfs::metadata::Metadata::new("Binary".to_string(), vec![0xffu8, 0xfe]), TYPE_DIRECTORY, 0).is_ok());
+        binary.extend([0u8; BLOCK_SIZE * 2].iter().cloned());
+        assert!(target.import_archive(&mut &binary[..]).is_err());
+        assert!(target.get_container("Binary".to_string(), None).is_err());
+
+        // Nothing outside the container can be written to
+        let mut hostile = Vec::new();
+        assert!(super::write_entry(&mut hostile, "../escape.txt", &::nfs::metadata::Metadata::new("escape.txt".to_string(), Vec::new()), TYPE_FILE, 0).is_ok());
+        hostile.extend([0u8; BLOCK_SIZE * 2].iter().cloned());
+        assert!(target.import_archive(&mut &hostile[..]).is_err());
+
+        // An extended header too large to hold is refused before it is read
+        let mut oversized = Vec::new();
+        assert!(super::write_entry(&mut oversized, "huge", &::nfs::metadata::Metadata::new("huge".to_string(), Vec::new()), TYPE_PAX, MAX_EXTENDED_SIZE + 1).is_ok());
+        assert!(target.import_archive(&mut &oversized[..]).is_err());
+
+        // An archive ending inside an entry changes nothing, not even the directories before it
+        let partial_id = directory_helper.create(parent_id.clone(), "Partial".to_string(), Vec::new()).ok().unwrap();
+        let mut partial = ::nfs::rest::Container::authorise(client.clone(), partial_id.0, parent_id.0).ok().unwrap();
+        let versions = partial.get_versions().ok().unwrap().len();
+        assert!(partial.import_archive(&mut &archive[..archive.len() - BLOCK_SIZE * 4]).is_err());
+        let partial = ::nfs::rest::Container::authorise(client.clone(), partial_id.0, parent_id.0).ok().unwrap();
+        assert_eq!(partial.get_versions().ok().unwrap().len(), versions);
+        assert!(partial.get_containers().is_empty());
+        assert!(partial.get_blobs().is_empty());
+    }
+}
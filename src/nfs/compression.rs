@@ -0,0 +1,201 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+use std::io::{Read, Write};
+
+/// Size of the uncompressed blocks content is compressed in, so that any span can be read back
+/// by inflating only the blocks covering it
+pub const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Compression applied to content before it is self-encrypted
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Compression {
+    None,
+    Deflate,
+}
+
+impl Compression {
+    fn to_tag(&self) -> u8 {
+        match *self {
+            Compression::None => 0,
+            Compression::Deflate => 1,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<Compression, String> {
+        match tag {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            _ => Err(format!("Unknown compression {}", tag)),
+        }
+    }
+}
+
+/// Compresses a whole buffer, prefixing it with the compression used
+pub fn compress(compression: Compression, data: &[u8]) -> Vec<u8> {
+    let mut output = vec![compression.to_tag()];
+    output.extend(compress_block(compression, data));
+    output
+}
+
+/// Reverses compress
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.is_empty() {
+        return Err("Missing compression header".to_string());
+    }
+    let compression = try!(Compression::from_tag(data[0]));
+    decompress_block(compression, &data[1..])
+}
+
+fn compress_block(compression: Compression, data: &[u8]) -> Vec<u8> {
+    match compression {
+        Compression::None => data.to_vec(),
+        Compression::Deflate => {
+            let mut encoder = ::flate2::write::DeflateEncoder::new(Vec::new(), ::flate2::Compression::Default);
+            // Writing to a Vec can not fail
+            let _ = encoder.write_all(data);
+            encoder.finish().unwrap_or(Vec::new())
+        },
+    }
+}
+
+/// Inflates a single block
+pub fn decompress_block(compression: Compression, data: &[u8]) -> Result<Vec<u8>, String> {
+    match compression {
+        Compression::None => Ok(data.to_vec()),
+        Compression::Deflate => {
+            let mut output = Vec::new();
+            match ::flate2::read::DeflateDecoder::new(data).read_to_end(&mut output) {
+                Ok(_) => Ok(output),
+                Err(error) => Err(format!("Failed to decompress: {}", error)),
+            }
+        },
+    }
+}
+
+/// Compresses content in BLOCK_SIZE blocks. The output starts with the uncompressed size, the
+/// block count and the compressed length of every block, followed by the blocks themselves.
+pub fn compress_blocks(compression: Compression, data: &[u8]) -> Vec<u8> {
+    let blocks: Vec<Vec<u8>> = data.chunks(BLOCK_SIZE).map(|block| compress_block(compression, block)).collect();
+    let mut output = Vec::new();
+    write_u64(&mut output, data.len() as u64);
+    write_u64(&mut output, blocks.len() as u64);
+    for block in blocks.iter() {
+        write_u64(&mut output, block.len() as u64);
+    }
+    for block in blocks {
+        output.extend(block);
+    }
+    output
+}
+
+/// Locates the blocks of content written by compress_blocks
+#[derive(Clone, Debug)]
+pub struct BlockIndex {
+    size: u64,
+    // (offset, length) of every compressed block in the stream
+    blocks: Vec<(u64, u64)>,
+}
+
+impl BlockIndex {
+    /// Length of the fixed part of the header, holding the size and the block count
+    pub fn fixed_header_len() -> u64 {
+        16
+    }
+
+    /// Reads the block count from the fixed part of the header
+    pub fn block_count(fixed_header: &[u8]) -> Result<u64, String> {
+        if fixed_header.len() < BlockIndex::fixed_header_len() as usize {
+            return Err("Truncated compression header".to_string());
+        }
+        Ok(read_u64(&fixed_header[8..16]))
+    }
+
+    /// Parses the whole header: the fixed part followed by the block lengths
+    pub fn parse(header: &[u8]) -> Result<BlockIndex, String> {
+        let block_count = try!(BlockIndex::block_count(header));
+        let header_len = BlockIndex::fixed_header_len() + 8 * block_count;
+        if (header.len() as u64) < header_len {
+            return Err("Truncated compression header".to_string());
+        }
+
+        let mut offset = header_len;
+        let mut blocks = Vec::with_capacity(block_count as usize);
+        for index in 0..block_count as usize {
+            let start = BlockIndex::fixed_header_len() as usize + 8 * index;
+            let length = read_u64(&header[start..start + 8]);
+            blocks.push((offset, length));
+            offset += length;
+        }
+        Ok(BlockIndex { size: read_u64(&header[0..8]), blocks: blocks })
+    }
+
+    /// Uncompressed size of the content
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// (offset, length) of a compressed block in the stream
+    pub fn locate(&self, index: usize) -> Option<(u64, u64)> {
+        self.blocks.get(index).cloned()
+    }
+
+    /// Index of the block holding the given uncompressed position
+    pub fn block_of(position: u64) -> usize {
+        (position / BLOCK_SIZE as u64) as usize
+    }
+}
+
+fn write_u64(output: &mut Vec<u8>, value: u64) {
+    for shift in 0..8 {
+        output.push((value >> (8 * shift)) as u8);
+    }
+}
+
+fn read_u64(input: &[u8]) -> u64 {
+    input.iter().take(8).enumerate().fold(0u64, |value, (shift, byte)| value | (*byte as u64) << (8 * shift))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn whole_buffer() {
+        let data: String = (0..100).map(|_| "{\"name\": \"value\"}").collect();
+        for compression in vec![Compression::None, Compression::Deflate] {
+            let compressed = compress(compression, data.as_bytes());
+            assert_eq!(decompress(&compressed[..]).ok().unwrap(), data.as_bytes().to_vec());
+        }
+        assert!(compress(Compression::Deflate, data.as_bytes()).len() < data.len());
+        assert!(decompress(&[7u8, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn blocks() {
+        let data: Vec<u8> = (0..3 * BLOCK_SIZE + 100).map(|index| (index % 251) as u8).collect();
+        let compressed = compress_blocks(Compression::Deflate, &data[..]);
+
+        let index = BlockIndex::parse(&compressed[..]).ok().unwrap();
+        assert_eq!(index.size(), data.len() as u64);
+        assert!(index.locate(4).is_none());
+
+        let position = 2 * BLOCK_SIZE as u64 + 10;
+        let (offset, length) = index.locate(BlockIndex::block_of(position)).unwrap();
+        let block = decompress_block(Compression::Deflate, &compressed[offset as usize..(offset + length) as usize]).ok().unwrap();
+        assert_eq!(&block[..], &data[2 * BLOCK_SIZE..3 * BLOCK_SIZE]);
+    }
+}
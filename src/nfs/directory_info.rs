@@ -37,6 +37,15 @@ impl DirectoryInfo {
         }
     }
 
+    /// DirectoryInfo for a directory whose id is derived rather than random
+    pub fn with_id(id: routing::NameType, parent_dir_id: routing::NameType, metadata: Metadata) -> DirectoryInfo {
+        DirectoryInfo {
+            id: id,
+            parent_dir_id: parent_dir_id,
+            metadata: metadata,
+        }
+    }
+
     pub fn get_id(&self) -> routing::NameType {
         self.id.clone()
     }
@@ -18,15 +18,28 @@
 use super::file::File;
 use super::directory_info::DirectoryInfo;
 use nfs::metadata::Metadata;
+use nfs::query::{Page, Query};
+use nfs::retention::RetentionPolicy;
 use routing;
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
 use std::fmt;
 
+/// Borrowed iterator over the files of a DirectoryListing, in name order
+pub type Files<'a> = ::std::collections::btree_map::Values<'a, String, File>;
+
+/// Borrowed iterator over the sub-directories of a DirectoryListing, in name order
+pub type SubDirectories<'a> = ::std::collections::btree_map::Values<'a, String, DirectoryInfo>;
+
+/// A listing compares and serialises by its content only: the version it was read from is not
+/// part of it.
 #[allow(dead_code)]
-#[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Clone)]
 pub struct DirectoryListing {
     info: DirectoryInfo,
-    sub_directories: Vec<DirectoryInfo>,
-    files: Vec<File>
+    sub_directories: ::std::collections::BTreeMap<String, DirectoryInfo>,
+    files: ::std::collections::BTreeMap<String, File>,
+    retention_policy: Option<RetentionPolicy>,
+    version: Option<routing::NameType>
 }
 
 #[allow(dead_code)]
@@ -34,11 +47,71 @@ impl DirectoryListing {
     pub fn new(parent_dir_id: routing::NameType, name: String, user_metadata: Vec<u8>) -> DirectoryListing {
         DirectoryListing {
             info: DirectoryInfo::new(parent_dir_id, Metadata::new(name, user_metadata)),
-            sub_directories: Vec::new(),
-            files: Vec::new()
+            sub_directories: ::std::collections::BTreeMap::new(),
+            files: ::std::collections::BTreeMap::new(),
+            retention_policy: None,
+            version: None
         }
     }
 
+    /// An empty listing for an existing directory, used when a listing is rebuilt from storage
+    pub fn from_info(info: DirectoryInfo) -> DirectoryListing {
+        DirectoryListing {
+            info: info,
+            sub_directories: ::std::collections::BTreeMap::new(),
+            files: ::std::collections::BTreeMap::new(),
+            retention_policy: None,
+            version: None
+        }
+    }
+
+    /// The listing without its entries
+    pub fn header(&self) -> DirectoryListing {
+        DirectoryListing {
+            info: self.info.clone(),
+            sub_directories: ::std::collections::BTreeMap::new(),
+            files: ::std::collections::BTreeMap::new(),
+            retention_policy: self.retention_policy.clone(),
+            version: None
+        }
+    }
+
+    /// The stored version of the directory this listing was read from, None for a listing not
+    /// read as the latest version. Changes made to the listing are reapplied onto the versions
+    /// other clients stored since.
+    pub fn get_version(&self) -> Option<routing::NameType> {
+        self.version.clone()
+    }
+
+    pub fn set_version(&mut self, version: Option<routing::NameType>) {
+        self.version = version;
+    }
+
+    /// Reapplies the changes made to this listing since `base` onto `latest`, a later version of
+    /// the same directory. An entry changed both here and in `latest` takes the change made here.
+    /// The result is based on the version of `latest`.
+    pub fn rebase(self, base: &DirectoryListing, latest: DirectoryListing) -> DirectoryListing {
+        let mut rebased = latest;
+        if self.info != base.info {
+            rebased.info = self.info;
+        }
+        if self.retention_policy != base.retention_policy {
+            rebased.retention_policy = self.retention_policy;
+        }
+        reapply(&self.files, &base.files, &mut rebased.files);
+        reapply(&self.sub_directories, &base.sub_directories, &mut rebased.sub_directories);
+        rebased
+    }
+
+    /// Which versions of the directory prune_versions keeps
+    pub fn get_retention_policy(&self) -> Option<RetentionPolicy> {
+        self.retention_policy.clone()
+    }
+
+    pub fn set_retention_policy(&mut self, retention_policy: Option<RetentionPolicy>) {
+        self.retention_policy = retention_policy;
+    }
+
     pub fn get_info(&self) -> DirectoryInfo {
         self.info.clone()
     }
@@ -67,24 +140,52 @@ impl DirectoryListing {
         self.info.get_parent_dir_id()
     }
 
-    pub fn add_file(&mut self, file: File) {
-        self.files.push(file);
+    /// Inserts the file, replacing and returning any file of the same name
+    pub fn upsert_file(&mut self, file: File) -> Option<File> {
+        self.files.insert(file.get_name(), file)
+    }
+
+    pub fn find_file(&self, name: &str) -> Option<&File> {
+        self.files.get(name)
+    }
+
+    pub fn find_file_mut(&mut self, name: &str) -> Option<&mut File> {
+        self.files.get_mut(name)
+    }
+
+    pub fn remove_file(&mut self, name: &str) -> Option<File> {
+        self.files.remove(name)
+    }
+
+    pub fn get_files(&self) -> Files {
+        self.files.values()
+    }
+
+    /// Files satisfying the query, sorted and paged as it asks
+    pub fn query_files(&self, query: &Query) -> Result<Page<File>, String> {
+        query.apply(self.files.values().cloned(), |file| file.get_metadata())
     }
 
-    pub fn get_files(&self) -> Vec<File> {
-        self.files.clone()
+    /// Sub-directories satisfying the query, sorted and paged as it asks
+    pub fn query_sub_directories(&self, query: &Query) -> Result<Page<DirectoryInfo>, String> {
+        query.apply(self.sub_directories.values().cloned(), |info| info.get_metadata().clone())
     }
 
-    pub fn set_files(&mut self, files: Vec<File>) {
-        self.files = files;
+    /// Inserts the sub-directory, replacing and returning any sub-directory of the same name
+    pub fn upsert_sub_directory(&mut self, directory_info: DirectoryInfo) -> Option<DirectoryInfo> {
+        self.sub_directories.insert(directory_info.get_name(), directory_info)
     }
 
-    pub fn get_sub_directories(&self) -> Vec<DirectoryInfo> {
-        self.sub_directories.clone()
+    pub fn find_sub_directory(&self, name: &str) -> Option<&DirectoryInfo> {
+        self.sub_directories.get(name)
     }
 
-    pub fn set_sub_directories(&mut self, dirs: Vec<DirectoryInfo>) {
-        self.sub_directories = dirs;
+    pub fn remove_sub_directory(&mut self, name: &str) -> Option<DirectoryInfo> {
+        self.sub_directories.remove(name)
+    }
+
+    pub fn get_sub_directories(&self) -> SubDirectories {
+        self.sub_directories.values()
     }
 
     pub fn set_name(&mut self, name: String) {
@@ -100,6 +201,74 @@ impl DirectoryListing {
     }
 }
 
+/// Makes in `target` the additions, replacements and removals `changed` has against `base`
+fn reapply<T>(changed: &::std::collections::BTreeMap<String, T>,
+              base: &::std::collections::BTreeMap<String, T>,
+              target: &mut ::std::collections::BTreeMap<String, T>) where T: Clone + PartialEq {
+    for (name, entry) in changed.iter() {
+        if base.get(name) != Some(entry) {
+            let _ = target.insert(name.clone(), entry.clone());
+        }
+    }
+    for name in base.keys() {
+        if !changed.contains_key(name) {
+            let _ = target.remove(name);
+        }
+    }
+}
+
+impl DirectoryListing {
+    fn content(&self) -> (&DirectoryInfo, &::std::collections::BTreeMap<String, DirectoryInfo>,
+                          &::std::collections::BTreeMap<String, File>, &Option<RetentionPolicy>) {
+        (&self.info, &self.sub_directories, &self.files, &self.retention_policy)
+    }
+}
+
+impl PartialEq for DirectoryListing {
+    fn eq(&self, other: &DirectoryListing) -> bool {
+        self.content() == other.content()
+    }
+}
+
+impl Eq for DirectoryListing {}
+
+impl PartialOrd for DirectoryListing {
+    fn partial_cmp(&self, other: &DirectoryListing) -> Option<::std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DirectoryListing {
+    fn cmp(&self, other: &DirectoryListing) -> ::std::cmp::Ordering {
+        self.content().cmp(&other.content())
+    }
+}
+
+impl Encodable for DirectoryListing {
+    fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        e.emit_struct("DirectoryListing", 4, |e| {
+            try!(e.emit_struct_field("info", 0, |e| self.info.encode(e)));
+            try!(e.emit_struct_field("sub_directories", 1, |e| self.sub_directories.encode(e)));
+            try!(e.emit_struct_field("files", 2, |e| self.files.encode(e)));
+            e.emit_struct_field("retention_policy", 3, |e| self.retention_policy.encode(e))
+        })
+    }
+}
+
+impl Decodable for DirectoryListing {
+    fn decode<D: Decoder>(d: &mut D) -> Result<DirectoryListing, D::Error> {
+        d.read_struct("DirectoryListing", 4, |d| {
+            Ok(DirectoryListing {
+                info: try!(d.read_struct_field("info", 0, Decodable::decode)),
+                sub_directories: try!(d.read_struct_field("sub_directories", 1, Decodable::decode)),
+                files: try!(d.read_struct_field("files", 2, Decodable::decode)),
+                retention_policy: try!(d.read_struct_field("retention_policy", 3, Decodable::decode)),
+                version: None
+            })
+        })
+    }
+}
+
 impl fmt::Debug for DirectoryListing {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "id: {}, metadata: {}", self.info.get_id(), self.info.get_metadata())
@@ -131,4 +300,137 @@ mod test {
 
         assert_eq!(obj_before, obj_after);
     }
+
+    #[test]
+    fn query() {
+        let mut listing = DirectoryListing::new(routing::NameType([1u8; 64]), "Home".to_string(), Vec::new());
+        for name in vec!["c.txt", "a.txt", "b.jpg"] {
+            let _ = listing.upsert_file(::nfs::file::File::new(::nfs::metadata::Metadata::new(name.to_string(), Vec::new()),
+                                                    ::self_encryption::datamap::DataMap::None));
+        }
+
+        let mut query = ::nfs::query::Query::new();
+        query.add_filter(::nfs::query::Filter::NameGlob("*.txt".to_string()));
+        let page = listing.query_files(&query).ok().unwrap();
+        assert_eq!(page.items.iter().map(|file| file.get_name()).collect::<Vec<String>>(), vec!["a.txt".to_string(), "c.txt".to_string()]);
+        assert!(listing.query_sub_directories(&query).ok().unwrap().items.is_empty());
+    }
+
+    #[test]
+    fn indexed_entries() {
+        let mut listing = DirectoryListing::new(routing::NameType([1u8; 64]), "Home".to_string(), Vec::new());
+        for name in vec!["c.txt", "a.txt", "b.jpg"] {
+            assert!(listing.upsert_file(::nfs::file::File::new(::nfs::metadata::Metadata::new(name.to_string(), Vec::new()),
+                                                               ::self_encryption::datamap::DataMap::None)).is_none());
+        }
+        assert_eq!(listing.get_files().map(|file| file.get_name()).collect::<Vec<String>>(),
+                   vec!["a.txt".to_string(), "b.jpg".to_string(), "c.txt".to_string()]);
+
+        let replaced = listing.upsert_file(::nfs::file::File::new(::nfs::metadata::Metadata::new("a.txt".to_string(), vec![1u8]),
+                                                                  ::self_encryption::datamap::DataMap::None));
+        assert!(replaced.unwrap().get_metadata().get_user_metadata().is_none());
+        assert_eq!(listing.get_files().len(), 3);
+        assert_eq!(listing.find_file("a.txt").unwrap().get_metadata().get_user_metadata(), Some(vec![1u8]));
+
+        assert!(listing.remove_file("b.jpg").is_some());
+        assert!(listing.find_file("b.jpg").is_none());
+        assert!(listing.remove_file("b.jpg").is_none());
+
+        let sub_directory = DirectoryListing::new(listing.get_id(), "Sub".to_string(), Vec::new());
+        assert!(listing.upsert_sub_directory(sub_directory.get_info()).is_none());
+        assert_eq!(listing.find_sub_directory("Sub").unwrap().get_id(), sub_directory.get_id());
+        assert_eq!(listing.remove_sub_directory("Sub").unwrap().get_id(), sub_directory.get_id());
+        assert_eq!(listing.get_sub_directories().len(), 0);
+    }
+
+    #[test]
+    fn rebase() {
+        let file = |name: &str, content: u8| ::nfs::file::File::new_inline(::nfs::metadata::Metadata::new(name.to_string(), Vec::new()), vec![content]);
+        let mut base = DirectoryListing::new(routing::NameType([1u8; 64]), "Home".to_string(), Vec::new());
+        for name in vec!["kept.txt", "changed.txt", "removed.txt"] {
+            let _ = base.upsert_file(file(name, 0));
+        }
+
+        let their_change = file("kept.txt", 1);
+        let our_change = file("changed.txt", 2);
+        let mut latest = base.clone();
+        let _ = latest.upsert_file(file("theirs.txt", 0));
+        let _ = latest.upsert_file(their_change.clone());
+        latest.set_version(Some(routing::NameType([2u8; 64])));
+
+        let mut ours = base.clone();
+        let _ = ours.upsert_file(file("ours.txt", 0));
+        let _ = ours.upsert_file(our_change.clone());
+        let _ = ours.remove_file("removed.txt");
+        ours.set_name("Renamed".to_string());
+
+        let rebased = ours.rebase(&base, latest);
+        assert_eq!(rebased.get_files().map(|file| file.get_name()).collect::<Vec<String>>(),
+                   vec!["changed.txt".to_string(), "kept.txt".to_string(), "ours.txt".to_string(), "theirs.txt".to_string()]);
+        assert_eq!(rebased.find_file("kept.txt"), Some(&their_change));
+        assert_eq!(rebased.find_file("changed.txt"), Some(&our_change));
+        assert_eq!(rebased.get_name(), "Renamed".to_string());
+        assert_eq!(rebased.get_version(), Some(routing::NameType([2u8; 64])));
+    }
+}
+
+#[cfg(all(test, feature = "bench"))]
+mod bench {
+    use super::*;
+    use routing;
+    use test::Bencher;
+
+    const ENTRIES: usize = 100_000;
+
+    fn file(index: usize) -> ::nfs::file::File {
+        ::nfs::file::File::new(::nfs::metadata::Metadata::new(format!("file_{:06}", index), Vec::new()),
+                               ::self_encryption::datamap::DataMap::None)
+    }
+
+    fn populated_listing() -> DirectoryListing {
+        let mut listing = DirectoryListing::new(routing::NameType([1u8; 64]), "Home".to_string(), Vec::new());
+        // Insert in reverse so the benchmarks do not depend on the insertion order
+        for index in (0..ENTRIES).rev() {
+            let _ = listing.upsert_file(file(index));
+        }
+        listing
+    }
+
+    #[bench]
+    fn find_file(b: &mut Bencher) {
+        let listing = populated_listing();
+        let name = format!("file_{:06}", ENTRIES / 2);
+        b.iter(|| listing.find_file(&name).is_some());
+    }
+
+    #[bench]
+    fn upsert_file(b: &mut Bencher) {
+        let mut listing = populated_listing();
+        let replacement = file(ENTRIES / 2);
+        b.iter(|| listing.upsert_file(replacement.clone()).is_some());
+    }
+
+    #[bench]
+    fn remove_and_reinsert_file(b: &mut Bencher) {
+        let mut listing = populated_listing();
+        let name = format!("file_{:06}", ENTRIES / 2);
+        b.iter(|| {
+            let removed = listing.remove_file(&name).unwrap();
+            listing.upsert_file(removed)
+        });
+    }
+
+    #[bench]
+    fn iterate_files(b: &mut Bencher) {
+        let listing = populated_listing();
+        b.iter(|| listing.get_files().fold(0u64, |total, file| total + file.get_metadata().get_size()));
+    }
+
+    #[bench]
+    fn query_first_page(b: &mut Bencher) {
+        let listing = populated_listing();
+        let mut query = ::nfs::query::Query::new();
+        query.set_limit(Some(100));
+        b.iter(|| listing.query_files(&query).ok().unwrap().items.len());
+    }
 }
@@ -19,11 +19,20 @@ use self_encryption;
 use std::fmt;
 use super::metadata::Metadata;
 
+/// Where the content of a File lives
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum FileContent {
+    /// Self-encrypted into chunks stored on the network
+    DataMap(self_encryption::datamap::DataMap),
+    /// Small content kept inside the (encrypted) DirectoryListing itself
+    Inline(Vec<u8>),
+}
+
 #[allow(dead_code)]
 #[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct File {
     metadata: Metadata,
-    datamap: self_encryption::datamap::DataMap
+    content: FileContent
 }
 
 #[allow(dead_code)]
@@ -31,7 +40,15 @@ impl File {
     pub fn new(metadata: Metadata, datamap: self_encryption::datamap::DataMap) -> File {
         File {
             metadata: metadata,
-            datamap: datamap
+            content: FileContent::DataMap(datamap)
+        }
+    }
+
+    /// A File whose content is stored inline
+    pub fn new_inline(metadata: Metadata, data: Vec<u8>) -> File {
+        File {
+            metadata: metadata,
+            content: FileContent::Inline(data)
         }
     }
 
@@ -47,16 +64,25 @@ impl File {
         self.metadata.clone()
     }
 
-    pub fn set_datamap(&mut self, datamap: self_encryption::datamap::DataMap) {
-        self.datamap = datamap;
+    pub fn set_content(&mut self, content: FileContent) {
+        self.content = content;
     }
 
-    pub fn get_datamap(&self) -> self_encryption::datamap::DataMap {
-        self.datamap.clone()
+    pub fn get_content(&self) -> FileContent {
+        self.content.clone()
     }
 
 }
 
+impl fmt::Debug for FileContent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FileContent::DataMap(_) => write!(f, "DataMap"),
+            FileContent::Inline(ref data) => write!(f, "Inline({} bytes)", data.len()),
+        }
+    }
+}
+
 impl fmt::Debug for File {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "metadata: {}", self.get_metadata())
@@ -90,5 +116,15 @@ mod test {
         let obj_after: File = d.decode().next().unwrap().unwrap();
 
         assert_eq!(obj_before, obj_after);
+
+        let inline_before = File::new_inline(Metadata::new("a.txt".to_string(), Vec::new()), vec![1u8, 2, 3]);
+
+        let mut e = cbor::Encoder::from_memory();
+        e.encode(&[&inline_before]).unwrap();
+
+        let mut d = cbor::Decoder::from_bytes(e.as_bytes());
+        let inline_after: File = d.decode().next().unwrap().unwrap();
+
+        assert_eq!(inline_before, inline_after);
     }
 }
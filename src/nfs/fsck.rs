@@ -0,0 +1,370 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+use nfs;
+use routing;
+use maidsafe_types;
+use maidsafe_types::TypeTag;
+use client;
+use self_encryption;
+
+/// Something found wrong with the tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// The StructuredData of a directory does not exist
+    MissingDirectory(routing::NameType),
+    /// The StructuredData of a directory could not be fetched for a reason other than it not
+    /// existing. Never repaired, as the directory may well be intact.
+    UnreachableDirectory(routing::NameType, String),
+    /// A sub-directory entry points back at a directory above it
+    Cycle(routing::NameType),
+    /// The StructuredData of a directory does not deserialise
+    UndecodableDirectory(routing::NameType),
+    /// The StructuredData of a directory records no versions
+    NoVersions(routing::NameType),
+    /// The ImmutableData holding a version of a listing or a shard could not be fetched
+    MissingVersion(routing::NameType),
+    /// A version does not decrypt with the nonce of its directory
+    DecryptionFailed(routing::NameType),
+    /// A version decrypts but its DataMap, content or listing does not deserialise
+    UndecodableVersion(routing::NameType),
+    /// A shard does not hash to the value its listing recorded for it
+    ShardMismatch(routing::NameType),
+    /// A chunk named in a DataMap could not be fetched
+    MissingChunk(Vec<u8>),
+    /// A sub-directory entry points at a directory which does not exist
+    DanglingSubDirectory(routing::NameType),
+}
+
+/// A problem and the path of the directory or file it was found at
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Issue {
+    pub path: String,
+    pub problem: Problem,
+}
+
+/// Outcome of a check
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub directories_checked: usize,
+    pub files_checked: usize,
+    pub chunks_checked: usize,
+    pub issues: Vec<Issue>,
+    /// Paths of the dangling entries dropped in repair mode
+    pub repaired: Vec<String>,
+}
+
+impl Report {
+    /// Whether the tree was found intact
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Walks a tree of directories checking that everything it references can be fetched,
+/// decrypted and deserialised. Repair mode drops sub-directory entries which point at
+/// directories the network reports as not existing; nothing else is changed. Each directory
+/// is checked once, however many entries point at it.
+pub struct Fsck {
+    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+    repair: bool,
+    all_versions: bool,
+}
+
+impl Fsck {
+    pub fn new(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> Fsck {
+        Fsck {
+            client: client,
+            repair: false,
+            all_versions: false,
+        }
+    }
+
+    /// Drop dangling sub-directory entries, saving a new version of each directory holding any
+    pub fn set_repair(&mut self, repair: bool) {
+        self.repair = repair;
+    }
+
+    /// Check every stored version of each directory rather than only the latest. Entries are
+    /// always taken from the latest version.
+    pub fn set_all_versions(&mut self, all_versions: bool) {
+        self.all_versions = all_versions;
+    }
+
+    /// Checks the tree below the directory, the directory included. Errors only if a repair
+    /// could not be saved; everything found wrong is in the report.
+    pub fn check(&self, directory_id: routing::NameType, parent_directory_id: routing::NameType) -> Result<Report, String> {
+        let mut report = Report {
+            directories_checked: 0,
+            files_checked: 0,
+            chunks_checked: 0,
+            issues: Vec::new(),
+            repaired: Vec::new(),
+        };
+        let mut visited = ::std::collections::HashSet::new();
+        let mut ancestors = Vec::new();
+        if let Err(problem) = try!(self.check_directory(&mut report, &mut visited, &mut ancestors, "/".to_string(),
+                                                        directory_id, parent_directory_id)) {
+            report.issues.push(Issue { path: "/".to_string(), problem: problem });
+        }
+        Ok(report)
+    }
+
+    /// Checks a directory and everything below it. The inner result is Err when the directory
+    /// itself is missing, so the caller can treat the entry pointing at it as dangling.
+    /// `ancestors` holds the directories on the path down to this one.
+    fn check_directory(&self, report: &mut Report, visited: &mut ::std::collections::HashSet<routing::NameType>,
+                       ancestors: &mut Vec<routing::NameType>, path: String, directory_id: routing::NameType,
+                       parent_directory_id: routing::NameType) -> Result<Result<(), Problem>, String> {
+        if ancestors.contains(&directory_id) {
+            return Ok(Err(Problem::Cycle(directory_id)));
+        }
+        if !visited.insert(directory_id.clone()) {
+            return Ok(Ok(()));
+        }
+        let structured_data_type_id: maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
+        let sdv: maidsafe_types::StructuredData = match nfs::utils::network_get(&self.client, structured_data_type_id.type_tag(), directory_id.clone()) {
+            Ok(Some(data)) => match nfs::utils::try_deserialise(data) {
+                Ok(sdv) => sdv,
+                Err(_) => return Ok(Err(Problem::UndecodableDirectory(directory_id))),
+            },
+            Ok(None) => return Ok(Err(Problem::MissingDirectory(directory_id))),
+            Err(error) => return Ok(Err(Problem::UnreachableDirectory(directory_id, error))),
+        };
+        report.directories_checked += 1;
+
+        let versions = sdv.value();
+        let latest = match versions.last() {
+            Some(latest) => latest.clone(),
+            None => {
+                report.issues.push(Issue { path: path, problem: Problem::NoVersions(directory_id) });
+                return Ok(Ok(()));
+            },
+        };
+        if self.all_versions {
+            for version in versions[..versions.len() - 1].iter() {
+                let _ = self.check_version(report, &path, version.clone(), &directory_id, &parent_directory_id);
+            }
+        }
+        let mut directory = match self.check_version(report, &path, latest, &directory_id, &parent_directory_id) {
+            Some(directory) => directory,
+            None => return Ok(Ok(())),
+        };
+
+        for file in directory.get_files() {
+            report.files_checked += 1;
+            if let nfs::file::FileContent::DataMap(datamap) = file.get_content() {
+                let _ = self.check_chunks(report, &format!("{}{}", path, file.get_name()), &datamap);
+            }
+        }
+
+        let sub_directories: Vec<nfs::directory_info::DirectoryInfo> = directory.get_sub_directories().cloned().collect();
+        let mut dangling = Vec::new();
+        ancestors.push(directory_id.clone());
+        for info in sub_directories.into_iter() {
+            let sub_path = format!("{}{}/", path, info.get_name());
+            match try!(self.check_directory(report, visited, ancestors, sub_path.clone(), info.get_id(), directory_id.clone())) {
+                Ok(()) => (),
+                Err(Problem::MissingDirectory(id)) => {
+                    report.issues.push(Issue { path: sub_path.clone(), problem: Problem::DanglingSubDirectory(id) });
+                    dangling.push((sub_path, info.get_name()));
+                },
+                Err(problem) => report.issues.push(Issue { path: sub_path, problem: problem }),
+            }
+        }
+        let _ = ancestors.pop();
+
+        if self.repair && !dangling.is_empty() {
+            for &(_, ref name) in dangling.iter() {
+                let _ = directory.remove_sub_directory(name);
+            }
+            let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
+            try!(directory_helper.update(directory));
+            report.repaired.extend(dangling.into_iter().map(|(sub_path, _)| sub_path));
+        }
+        Ok(Ok(()))
+    }
+
+    /// Checks a stored version of a listing and its shards, returning the assembled listing
+    /// when it is whole
+    fn check_version(&self, report: &mut Report, path: &String, version: routing::NameType,
+                     directory_id: &routing::NameType, parent_directory_id: &routing::NameType) -> Option<nfs::directory_listing::DirectoryListing> {
+        let stored: nfs::helper::StoredVersion = match self.load(report, path, version.clone(), directory_id, parent_directory_id) {
+            Some(stored) => stored,
+            None => return None,
+        };
+        match stored.listing {
+            nfs::helper::StoredListing::Whole(directory) => Some(directory),
+            nfs::helper::StoredListing::Sharded(header, shard_refs) => {
+                let mut directory = Some(header);
+                for shard_ref in shard_refs.iter() {
+                    let shard_id = nfs::helper::shard_id(directory_id, shard_ref.index);
+                    let shard: nfs::helper::Shard = match self.load(report, path, shard_ref.version.clone(), &shard_id, directory_id) {
+                        Some(shard) => shard,
+                        None => {
+                            directory = None;
+                            continue;
+                        },
+                    };
+                    if shard.hash() != shard_ref.hash {
+                        report.issues.push(Issue { path: path.clone(), problem: Problem::ShardMismatch(shard_ref.version.clone()) });
+                    }
+                    if let Some(ref mut directory) = directory {
+                        for (_, sub_directory) in shard.sub_directories {
+                            let _ = directory.upsert_sub_directory(sub_directory);
+                        }
+                        for (_, file) in shard.files {
+                            let _ = directory.upsert_file(file);
+                        }
+                    }
+                }
+                directory
+            },
+        }
+    }
+
+    /// Fetches, decrypts and deserialises what DirectoryHelper stored under the name, recording
+    /// the first step which fails
+    fn load<T>(&self, report: &mut Report, path: &String, name: routing::NameType,
+               id: &routing::NameType, parent_id: &routing::NameType) -> Option<T> where T: ::rustc_serialize::Decodable {
+        let immutable_data_type_id: maidsafe_types::data::ImmutableDataTypeTag = unsafe { ::std::mem::uninitialized() };
+        let problem = match nfs::utils::network_get(&self.client, immutable_data_type_id.type_tag(), name.clone()) {
+            Ok(None) | Err(_) => Problem::MissingVersion(name),
+            Ok(Some(data)) => match nfs::utils::try_deserialise::<maidsafe_types::ImmutableData>(data) {
+                Err(_) => Problem::UndecodableVersion(name),
+                Ok(immutable_data) => {
+                    let nonce = nfs::helper::DirectoryHelper::new(self.client.clone()).get_nonce(id.clone(), parent_id.clone());
+                    let decrypted = self.client.lock().unwrap().hybrid_decrypt(&immutable_data.value()[..], nonce);
+                    match decrypted {
+                        None => Problem::DecryptionFailed(name),
+                        Some(serialised_datamap) => match nfs::utils::try_deserialise::<self_encryption::datamap::DataMap>(serialised_datamap) {
+                            Err(_) => Problem::UndecodableVersion(name),
+                            Ok(datamap) => {
+                                if !self.check_chunks(report, path, &datamap) {
+                                    return None;
+                                }
+                                let storage = ::std::sync::Arc::new(nfs::io::NetworkStorage::new(self.client.clone()));
+                                let mut self_encryptor = self_encryption::SelfEncryptor::new(storage, datamap);
+                                let size = self_encryptor.len();
+                                match nfs::compression::decompress(&self_encryptor.read(0, size)[..]).and_then(|content| nfs::utils::try_deserialise(content)) {
+                                    Ok(value) => return Some(value),
+                                    Err(_) => Problem::UndecodableVersion(name),
+                                }
+                            },
+                        },
+                    }
+                },
+            },
+        };
+        report.issues.push(Issue { path: path.clone(), problem: problem });
+        None
+    }
+
+    /// Checks that every chunk of the DataMap can be fetched. Returns whether they all could.
+    fn check_chunks(&self, report: &mut Report, path: &String, datamap: &self_encryption::datamap::DataMap) -> bool {
+        let chunks = match *datamap {
+            self_encryption::datamap::DataMap::Chunks(ref chunks) => chunks,
+            _ => return true,
+        };
+        let immutable_data_type_id: maidsafe_types::data::ImmutableDataTypeTag = unsafe { ::std::mem::uninitialized() };
+        let mut intact = true;
+        for chunk in chunks.iter() {
+            report.chunks_checked += 1;
+            let mut name = [0u8; 64];
+            for (destination, source) in name.iter_mut().zip(chunk.hash.iter()) {
+                *destination = *source;
+            }
+            match nfs::utils::network_get(&self.client, immutable_data_type_id.type_tag(), routing::NameType(name)) {
+                Ok(Some(ref data)) if !data.is_empty() => (),
+                _ => {
+                    report.issues.push(Issue { path: path.clone(), problem: Problem::MissingChunk(chunk.hash.clone()) });
+                    intact = false;
+                },
+            }
+        }
+        intact
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_dummy_client() -> ::client::Client {
+        let keyword = "Spandan".to_string();
+        let password = "Sharma".as_bytes();
+        let pin = 1234u32;
+
+        ::client::Client::create_account(&keyword,
+                                         pin,
+                                         &password,
+                                         ::client::non_networking_test_framework::get_new_data_store()).ok().unwrap()
+    }
+
+    #[test]
+    fn check_and_repair() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let mut directory_helper = ::nfs::helper::DirectoryHelper::new(client.clone());
+        let root_id = directory_helper.create(parent_id.clone(), "Root".to_string(), Vec::new()).ok().unwrap();
+
+        let large: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        let mut root = ::nfs::rest::Container::authorise(client.clone(), root_id.0, parent_id.0).ok().unwrap();
+        assert!(root.transaction(|tx| {
+            try!(tx.create_blob("large.bin".to_string(), None, &large[..]));
+            try!(tx.create_blob("small.txt".to_string(), None, b"small"));
+            tx.create_container("Docs".to_string(), None)
+        }).is_ok());
+
+        let mut fsck = Fsck::new(client.clone());
+        fsck.set_all_versions(true);
+        let report = fsck.check(root_id.clone(), parent_id.clone()).ok().unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.directories_checked, 2);
+        assert_eq!(report.files_checked, 2);
+        assert!(report.chunks_checked > 0);
+
+        // An entry for a directory which was never stored
+        let mut listing = directory_helper.get(root_id.clone(), parent_id.clone()).ok().unwrap();
+        let ghost = ::nfs::directory_info::DirectoryInfo::new(root_id.clone(), ::nfs::metadata::Metadata::new("Ghost".to_string(), Vec::new()));
+        let ghost_id = ghost.get_id();
+        let _ = listing.upsert_sub_directory(ghost);
+        assert!(directory_helper.update(listing).is_ok());
+
+        let report = fsck.check(root_id.clone(), parent_id.clone()).ok().unwrap();
+        assert_eq!(report.issues, vec![Issue { path: "/Ghost/".to_string(), problem: Problem::DanglingSubDirectory(ghost_id) }]);
+        assert!(report.repaired.is_empty());
+
+        fsck.set_repair(true);
+        let report = fsck.check(root_id.clone(), parent_id.clone()).ok().unwrap();
+        assert_eq!(report.repaired, vec!["/Ghost/".to_string()]);
+        assert!(directory_helper.get(root_id.clone(), parent_id.clone()).ok().unwrap().find_sub_directory("Ghost").is_none());
+        assert!(fsck.check(root_id, parent_id.clone()).ok().unwrap().is_clean());
+
+        let missing = fsck.check(::routing::NameType::new([9u8; 64]), parent_id.clone()).ok().unwrap();
+        assert_eq!(missing.issues[0].problem, Problem::MissingDirectory(::routing::NameType::new([9u8; 64])));
+
+        // An entry pointing back at the root is reported, not followed and not repaired
+        let mut listing = directory_helper.get(root_id.clone(), parent_id.clone()).ok().unwrap();
+        let back = ::nfs::directory_info::DirectoryInfo::with_id(root_id.clone(), root_id.clone(),
+                                                                 ::nfs::metadata::Metadata::new("Loop".to_string(), Vec::new()));
+        let _ = listing.upsert_sub_directory(back);
+        assert!(directory_helper.update(listing).is_ok());
+        let report = fsck.check(root_id.clone(), parent_id).ok().unwrap();
+        assert_eq!(report.issues, vec![Issue { path: "/Loop/".to_string(), problem: Problem::Cycle(root_id) }]);
+        assert!(report.repaired.is_empty());
+    }
+}
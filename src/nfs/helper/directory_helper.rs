@@ -22,10 +22,15 @@ use client;
 use maidsafe_types::TypeTag;
 use self_encryption;
 
+// Times an update is made again from newer versions before giving up
+const STALE_UPDATE_ATTEMPTS: usize = 5;
+
 /// DirectoryHelper provides helper functions to perform Operations on Directory
 #[allow(dead_code)]
 pub struct DirectoryHelper {
-    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>
+    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+    shard_threshold: usize,
+    compression: nfs::compression::Compression
 }
 
 #[allow(dead_code)]
@@ -33,78 +38,139 @@ impl DirectoryHelper {
     /// Create a new DirectoryHelper instance
     pub fn new(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> DirectoryHelper {
         DirectoryHelper {
-            client: client
+            client: client,
+            shard_threshold: nfs::helper::DEFAULT_SHARD_THRESHOLD,
+            compression: nfs::compression::Compression::None
         }
     }
 
+    /// Sets the compression applied to serialised listings before they are self-encrypted.
+    /// Listings stored with any compression can be read back regardless of this setting.
+    pub fn set_compression(&mut self, compression: nfs::compression::Compression) {
+        self.compression = compression;
+    }
+
+    /// Sets the number of entries above which listings are stored in shards
+    pub fn set_shard_threshold(&mut self, shard_threshold: usize) {
+        self.shard_threshold = shard_threshold;
+    }
+
     /// Creates a Directory in the network.
     pub fn create(&mut self, parent_dir_id: routing::NameType, directory_name: String, user_metadata: Vec<u8>) -> Result<::routing::NameType, String> {
         let directory = nfs::directory_listing::DirectoryListing::new(parent_dir_id, directory_name, user_metadata);
-        let mut se = self_encryption::SelfEncryptor::new(::std::sync::Arc::new(nfs::io::NetworkStorage::new(self.client.clone())), self_encryption::datamap::DataMap::None);
-        se.write(&nfs::utils::serialise(directory.clone())[..], 0);
-        let datamap = se.close();
-
-        let encrypt_result: _;
+        self.create_listing(directory)
+    }
 
-        {
-            let client = self.client.lock().unwrap();
-            encrypt_result = client.hybrid_encrypt(&nfs::utils::serialise(datamap)[..], self.get_nonce(directory.get_id().clone(), directory.get_parent_dir_id().clone()));
-        }
+    /// Creates a Directory with a known id in the network, for directories which have to be
+    /// found again without a parent listing pointing at them
+    pub fn create_with_id(&mut self, id: routing::NameType, parent_dir_id: routing::NameType, directory_name: String, user_metadata: Vec<u8>) -> Result<::routing::NameType, String> {
+        let info = nfs::directory_info::DirectoryInfo::with_id(id, parent_dir_id, nfs::metadata::Metadata::new(directory_name, user_metadata));
+        self.create_listing(nfs::directory_listing::DirectoryListing::from_info(info))
+    }
 
-        if encrypt_result.is_err() {
-            return Err("Encryption failed".to_string());
-        }
+    /// Creates the Directory an entry of a parent listing describes, with the entry's id and
+    /// metadata
+    pub fn create_from_info(&mut self, info: nfs::directory_info::DirectoryInfo) -> Result<::routing::NameType, String> {
+        self.create_listing(nfs::directory_listing::DirectoryListing::from_info(info))
+    }
 
-        let immutable_data = maidsafe_types::ImmutableData::new(encrypt_result.unwrap());
-        let save_res = self.network_put(immutable_data.clone());
-        if save_res.is_err() {
-            return Err("Save Failed".to_string());
-        }
+    fn create_listing(&mut self, directory: nfs::directory_listing::DirectoryListing) -> Result<::routing::NameType, String> {
+        let stored = nfs::helper::StoredVersion::new(nfs::helper::StoredListing::Whole(directory.clone()));
+        let version = match self.save_encrypted(nfs::utils::serialise(stored), directory.get_id(), directory.get_parent_dir_id()) {
+            Ok(version) => version,
+            Err(error) => return Err(failure(error, "Save Failed")),
+        };
         let sdv: maidsafe_types::StructuredData = maidsafe_types::StructuredData::new(directory.get_id(), self.client.lock().unwrap().get_owner(),
-            vec![immutable_data.name()]);
-        let save_sdv_res = self.network_put(sdv);
-        if save_sdv_res.is_err() {
-            return Err("Failed to create directory".to_string());
+            vec![version]);
+        if let Err(error) = self.network_put(sdv) {
+            return Err(failure(error, "Failed to create directory"));
         }
         Ok(directory.get_id())
     }
 
-    /// Updates an existing DirectoryListing in the network.
+    /// Updates an existing DirectoryListing in the network. Large listings are stored in shards,
+    /// and only the shards whose entries changed since the latest version are stored again.
+    /// Fails with nfs::OUT_OF_SPACE if the account has no space left for the new version.
+    /// The changes made to a listing read as the latest version are reapplied onto the versions
+    /// other clients stored since; any other listing is stored as the latest version as it is.
     pub fn update(&mut self, directory: nfs::directory_listing::DirectoryListing) -> Result<(), String> {
+        let mut directory = directory;
+        self.update_listing(&mut directory)
+    }
+
+    /// As update, leaving the listing as it was stored: with the changes of other clients, and
+    /// based on the new version so that it can be changed and updated again
+    pub fn update_listing(&mut self, directory: &mut nfs::directory_listing::DirectoryListing) -> Result<(), String> {
         let structured_data_type_id: maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
         let result = self.network_get(structured_data_type_id.type_tag(), directory.get_id());
         if result.is_err() {
             return Err("Network IO Error".to_string());
         }
-        let mut sdv: maidsafe_types::StructuredData = nfs::utils::deserialise(result.unwrap());
-
-        let mut se = self_encryption::SelfEncryptor::new(::std::sync::Arc::new(nfs::io::NetworkStorage::new(self.client.clone())), self_encryption::datamap::DataMap::None);
-        se.write(&nfs::utils::serialise(directory.clone())[..], 0);
-        let datamap = se.close();
+        let mut sdv: maidsafe_types::StructuredData = try!(nfs::utils::try_deserialise(result.unwrap()));
+
+        for _ in 0..STALE_UPDATE_ATTEMPTS {
+            let latest = sdv.value().last().cloned();
+            if let (Some(base), Some(latest)) = (directory.get_version(), latest.clone()) {
+                if base != latest {
+                    let base_listing = try!(self.get_listing(directory.get_id(), directory.get_parent_dir_id(), base));
+                    let latest_listing = try!(self.get_listing(directory.get_id(), directory.get_parent_dir_id(), latest));
+                    *directory = directory.clone().rebase(&base_listing, latest_listing);
+                }
+            }
 
-        let encrypt_result: _;
-        {
-            let client = self.client.lock().unwrap();
-            encrypt_result = client.hybrid_encrypt(&nfs::utils::serialise(datamap)[..], self.get_nonce(directory.get_id().clone(), directory.get_parent_dir_id().clone()));
+            let version = try!(self.save_version(directory, latest));
+            match try!(self.append_version(sdv, version.clone())) {
+                None => {
+                    directory.set_version(Some(version));
+                    return Ok(());
+                },
+                // Another client stored a version meanwhile
+                Some(stored) => sdv = stored,
+            }
         }
+        Err(nfs::STALE_UPDATE.to_string())
+    }
 
-        if encrypt_result.is_err() {
-            return Err("Encryption failed".to_string());
+    /// Stores the listing as a version made after the latest one, returning the new version
+    fn save_version(&self, directory: &nfs::directory_listing::DirectoryListing, latest: Option<routing::NameType>) -> Result<routing::NameType, String> {
+        let entry_count = directory.get_files().len() + directory.get_sub_directories().len();
+        let stored = if entry_count > self.shard_threshold {
+            let previous = match latest {
+                Some(version) => try!(self.get_stored(directory.get_id(), directory.get_parent_dir_id(), version)).listing,
+                None => nfs::helper::StoredListing::Whole(directory.clone()),
+            };
+            nfs::helper::StoredListing::Sharded(directory.header(), try!(self.save_shards(directory, previous)))
+        } else {
+            nfs::helper::StoredListing::Whole(directory.clone())
+        };
+
+        let stored = nfs::helper::StoredVersion::new(stored);
+        match self.save_encrypted(nfs::utils::serialise(stored), directory.get_id(), directory.get_parent_dir_id()) {
+            Ok(version) => Ok(version),
+            Err(error) => Err(failure(error, "Failed to save directory")),
         }
+    }
 
-        let immutable_data = maidsafe_types::ImmutableData::new(encrypt_result.unwrap());
-        let immutable_data_put_result = self.network_put(immutable_data.clone());
-        if immutable_data_put_result.is_err() {
-            return Err("Failed to save directory".to_string());
-        };
+    /// Adds the version to the versions in the StructuredData. Returns the StructuredData the
+    /// network holds if it refuses the update as stale, because another client added a version
+    /// since the StructuredData was read.
+    fn append_version(&self, mut sdv: maidsafe_types::StructuredData, version: routing::NameType) -> Result<Option<maidsafe_types::StructuredData>, String> {
         let mut versions = sdv.value();
-        versions.push(immutable_data.name());
+        versions.push(version);
         sdv.set_value(versions);
-        let sdv_put_result = self.network_put(sdv);
-        if sdv_put_result.is_err() {
-            return Err("Failed to update directory version".to_string());
-        };
-        Ok(())
+        self.put_versions(sdv)
+    }
+
+    /// Stores the StructuredData, returning the one the network holds if it refuses it as stale
+    fn put_versions(&self, sdv: maidsafe_types::StructuredData) -> Result<Option<maidsafe_types::StructuredData>, String> {
+        let put_result = self.client.lock().unwrap().put(sdv);
+        let mut response_getter = try!(put_result.map_err(|_| "Network IO Error".to_string()));
+        match response_getter.get() {
+            Ok(_) => Ok(None),
+            Err(ref error) if client::is_out_of_space(error) => Err(nfs::OUT_OF_SPACE.to_string()),
+            Err(routing::error::ResponseError::FailedToStoreData(stored)) => Ok(Some(try!(nfs::utils::try_deserialise(stored)))),
+            Err(_) => Err("Failed to update directory version".to_string()),
+        }
     }
 
     /// Return the versions of the directory
@@ -118,52 +184,302 @@ impl DirectoryHelper {
         Ok(sdv.value())
     }
 
-    /// Return the DirectoryListing for the specified version
+    /// Return the DirectoryListing for the specified version. Updating it stores it as the latest
+    /// version as it is.
     pub fn get_by_version(&mut self, directory_id: routing::NameType, parent_directory_id: routing::NameType, version: routing::NameType) -> Result<nfs::directory_listing::DirectoryListing, String> {
-        let structured_data_type_id: maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
-        let data_res = self.network_get(structured_data_type_id.type_tag(), directory_id.clone());
-        if data_res.is_err() {
-            return Err("Network IO Error".to_string());
-        }
-        let sdv: maidsafe_types::StructuredData = nfs::utils::deserialise(data_res.unwrap());
-        if !sdv.value().contains(&version) {
+        let versions = try!(self.get_versions(directory_id.clone()));
+        if !versions.contains(&version) {
             return Err("Version not found".to_string());
         };
-        let immutable_data_type_id: maidsafe_types::data::ImmutableDataTypeTag = unsafe { ::std::mem::uninitialized() };
-        let get_data = self.network_get(immutable_data_type_id.type_tag(), version);
-        if get_data.is_err() {
+        let stored = try!(self.get_stored(directory_id, parent_directory_id, version));
+        self.assemble(stored.listing)
+    }
+
+    /// Return the DirectoryListing for the latest version
+    pub fn get(&mut self, directory_id: routing::NameType, parent_directory_id: routing::NameType) -> Result<nfs::directory_listing::DirectoryListing, String> {
+        let latest = try!(self.get_latest_version(directory_id.clone()));
+        self.get_listing(directory_id, parent_directory_id, latest)
+    }
+
+    /// Return the DirectoryListing for the latest version, or None if no directory has the id.
+    /// Failing to fetch it is an error, never taken for the directory not existing.
+    pub fn find(&mut self, directory_id: routing::NameType, parent_directory_id: routing::NameType) -> Result<Option<nfs::directory_listing::DirectoryListing>, String> {
+        let structured_data_type_id: maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
+        if try!(nfs::utils::network_get(&self.client, structured_data_type_id.type_tag(), directory_id.clone())).is_none() {
+            return Ok(None);
+        }
+        self.get(directory_id, parent_directory_id).map(Some)
+    }
+
+    /// Streams the entries of the latest version of a directory, fetching its shards one at a time
+    /// rather than assembling the whole listing in memory
+    pub fn stream(&mut self, directory_id: routing::NameType, parent_directory_id: routing::NameType) -> Result<nfs::helper::ListingStream, String> {
+        let latest = try!(self.get_latest_version(directory_id.clone()));
+        let stored = try!(self.get_stored(directory_id, parent_directory_id, latest));
+        Ok(nfs::helper::ListingStream::new(DirectoryHelper::new(self.client.clone()), stored.listing))
+    }
+
+    /// Drops the versions of the directory which its retention policy does not keep, rewriting
+    /// the version list. Versions of listing shards only referenced by the dropped versions are
+    /// dropped too. A directory without a retention policy keeps all its versions, and versions
+    /// recorded by a snapshot are always kept. Versions stored by other clients while pruning are
+    /// kept too, and so is the latest version.
+    pub fn prune_versions(&mut self, directory_id: routing::NameType, parent_directory_id: routing::NameType) -> Result<nfs::retention::PruneReport, String> {
+        let structured_data_type_id: maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
+        let result = self.network_get(structured_data_type_id.type_tag(), directory_id.clone());
+        if result.is_err() {
             return Err("Network IO Error".to_string());
         }
-        let imm: maidsafe_types::ImmutableData = nfs::utils::deserialise(get_data.unwrap());
+        let sdv: maidsafe_types::StructuredData = try!(nfs::utils::try_deserialise(result.unwrap()));
+        let versions = sdv.value();
 
-        let decrypt_result: _;
-        {
-            let client = self.client.lock().unwrap();
-            decrypt_result = client.hybrid_decrypt(&imm.value()[..], self.get_nonce(directory_id.clone(), parent_directory_id.clone()));
+        let mut stored_versions = Vec::with_capacity(versions.len());
+        for version in versions.iter() {
+            stored_versions.push(try!(self.get_stored(directory_id.clone(), parent_directory_id.clone(), version.clone())));
+        }
+        let policy = match stored_versions.last() {
+            Some(latest) => match latest.listing {
+                nfs::helper::StoredListing::Whole(ref directory) => directory.get_retention_policy(),
+                nfs::helper::StoredListing::Sharded(ref header, _) => header.get_retention_policy(),
+            },
+            None => None,
+        };
+        let policy = policy.unwrap_or(nfs::retention::RetentionPolicy::new());
+
+        let saved_times: Vec<::time::Tm> = stored_versions.iter().map(|stored| stored.get_saved_time()).collect();
+        let mut keep = policy.select(&saved_times[..], ::time::now_utc());
+        let pinned = try!(nfs::snapshot::pinned_versions(&self.client, &directory_id));
+        keep.extend((0..versions.len()).filter(|&index| pinned.contains(&versions[index])));
+
+        let mut report = nfs::retention::PruneReport { kept: Vec::new(), removed: Vec::new(), unreferenced: Vec::new() };
+        let mut kept_shards = ::std::collections::BTreeSet::new();
+        let mut removed_shards = ::std::collections::BTreeMap::new();
+        for (index, (version, stored)) in versions.into_iter().zip(stored_versions.into_iter()).enumerate() {
+            let shard_refs = match stored.listing {
+                nfs::helper::StoredListing::Sharded(_, shard_refs) => shard_refs,
+                nfs::helper::StoredListing::Whole(_) => Vec::new(),
+            };
+            if keep.contains(&index) {
+                kept_shards.extend(shard_refs.into_iter().map(|shard_ref| shard_ref.version));
+                report.kept.push(version);
+            } else {
+                for shard_ref in shard_refs {
+                    let _ = removed_shards.insert(shard_ref.version, shard_ref.index);
+                }
+                report.removed.push(version);
+            }
+        }
+        if report.removed.is_empty() {
+            return Ok(report);
         }
 
-        if decrypt_result.is_none() {
-            return Err("Failed to decrypt".to_string());
+        report.kept = try!(self.remove_versions(sdv, &report.removed[..]));
+        let kept = report.kept.clone();
+        report.removed.retain(|version| !kept.contains(version));
+
+        report.unreferenced = report.removed.iter().filter(|version| !report.kept.contains(version)).cloned().collect();
+        let mut pruned_shards = ::std::collections::BTreeMap::<usize, Vec<routing::NameType>>::new();
+        for (version, index) in removed_shards.into_iter().filter(|&(ref version, _)| !kept_shards.contains(version)) {
+            pruned_shards.entry(index).or_insert(Vec::new()).push(version);
         }
-        let datamap = nfs::utils::deserialise(decrypt_result.unwrap());
+        for (index, shard_versions) in pruned_shards.into_iter() {
+            let removed = try!(self.remove_shard_versions(nfs::helper::shard_id(&directory_id, index), &shard_versions[..]));
+            report.unreferenced.extend(removed);
+        }
+        Ok(report)
+    }
 
-        let mut se = self_encryption::SelfEncryptor::new(::std::sync::Arc::new(nfs::io::NetworkStorage::new(self.client.clone())), datamap);
-        let size = se.len();
-        Ok(nfs::utils::deserialise(se.read(0, size)))
+    /// Return a shard referenced by a version of the directory
+    pub fn get_shard(&self, directory_id: &routing::NameType, shard_ref: &nfs::helper::ShardRef) -> Result<nfs::helper::Shard, String> {
+        let shard_id = nfs::helper::shard_id(directory_id, shard_ref.index);
+        let content = try!(self.load_encrypted(shard_ref.version.clone(), shard_id, directory_id.clone()));
+        Ok(nfs::utils::deserialise(content))
     }
 
-    /// Return the DirectoryListing for the latest version
-    pub fn get(&mut self, directory_id: routing::NameType, parent_directory_id: routing::NameType) -> Result<nfs::directory_listing::DirectoryListing, String> {
+    fn get_latest_version(&self, directory_id: routing::NameType) -> Result<routing::NameType, String> {
         let structured_data_type_id: maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
-        let sdv_res = self.network_get(structured_data_type_id.type_tag(), directory_id.clone());
+        let sdv_res = self.network_get(structured_data_type_id.type_tag(), directory_id);
         if sdv_res.is_err() {
             return Err("Network IO Error".to_string());
         }
         let sdv: maidsafe_types::StructuredData = nfs::utils::deserialise(sdv_res.unwrap());
-        let name = match sdv.value().last() {
-            Some(data) => routing::NameType(data.0),
-            None => return Err("Could not find data".to_string())
+        match sdv.value().last() {
+            Some(data) => Ok(routing::NameType(data.0)),
+            None => Err("Could not find data".to_string())
+        }
+    }
+
+    /// The DirectoryListing of a version, based on that version
+    fn get_listing(&self, directory_id: routing::NameType, parent_directory_id: routing::NameType, version: routing::NameType) -> Result<nfs::directory_listing::DirectoryListing, String> {
+        let stored = try!(self.get_stored(directory_id, parent_directory_id, version.clone()));
+        let mut directory = try!(self.assemble(stored.listing));
+        directory.set_version(Some(version));
+        Ok(directory)
+    }
+
+    /// Return a version as stored. Versions saved in the layout used before versions had a header
+    /// are read as a whole listing.
+    fn get_stored(&self, directory_id: routing::NameType, parent_directory_id: routing::NameType, version: routing::NameType) -> Result<nfs::helper::StoredVersion, String> {
+        let content = try!(self.load_uncompressed(version, directory_id, parent_directory_id));
+        if let Ok(stored) = nfs::compression::decompress(&content[..]).and_then(nfs::utils::try_deserialise) {
+            return Ok(stored);
+        }
+        match nfs::utils::try_deserialise::<super::legacy::LegacyListing>(content) {
+            Ok(legacy) => Ok(legacy.into_stored_version()),
+            Err(_) => Err("Failed to decode directory".to_string()),
+        }
+    }
+
+    /// Rebuilds the full DirectoryListing from a stored version
+    fn assemble(&self, stored: nfs::helper::StoredListing) -> Result<nfs::directory_listing::DirectoryListing, String> {
+        match stored {
+            nfs::helper::StoredListing::Whole(directory) => Ok(directory),
+            nfs::helper::StoredListing::Sharded(header, shards) => {
+                let directory_id = header.get_id();
+                let mut directory = header;
+                for shard_ref in shards.iter() {
+                    let shard = try!(self.get_shard(&directory_id, shard_ref));
+                    for (_, sub_directory) in shard.sub_directories {
+                        let _ = directory.upsert_sub_directory(sub_directory);
+                    }
+                    for (_, file) in shard.files {
+                        let _ = directory.upsert_file(file);
+                    }
+                }
+                Ok(directory)
+            },
+        }
+    }
+
+    /// Stores the shards of the listing which differ from the previous version and returns the
+    /// references making up the new version
+    fn save_shards(&self, directory: &nfs::directory_listing::DirectoryListing,
+                   previous: nfs::helper::StoredListing) -> Result<Vec<nfs::helper::ShardRef>, String> {
+        let previous_refs: ::std::collections::BTreeMap<usize, nfs::helper::ShardRef> = match previous {
+            nfs::helper::StoredListing::Sharded(_, shards) => shards.into_iter().map(|shard_ref| (shard_ref.index, shard_ref)).collect(),
+            nfs::helper::StoredListing::Whole(_) => ::std::collections::BTreeMap::new(),
+        };
+
+        let directory_id = directory.get_id();
+        let mut shard_refs = Vec::new();
+        for (index, shard) in nfs::helper::split(directory).into_iter().enumerate() {
+            if shard.is_empty() {
+                continue;
+            }
+            let hash = shard.hash();
+            if let Some(previous_ref) = previous_refs.get(&index) {
+                if previous_ref.hash == hash {
+                    shard_refs.push(previous_ref.clone());
+                    continue;
+                }
+            }
+
+            let shard_id = nfs::helper::shard_id(&directory_id, index);
+            let version = match self.save_encrypted(nfs::utils::serialise(shard), shard_id.clone(), directory_id.clone()) {
+                Ok(version) => version,
+                Err(error) => return Err(failure(error, "Failed to save directory shard")),
+            };
+            try!(self.add_shard_version(shard_id, version.clone()));
+            shard_refs.push(nfs::helper::ShardRef { index: index, version: version, hash: hash });
+        }
+        Ok(shard_refs)
+    }
+
+    /// Records a new version in the StructuredData of a shard, creating it for a new shard. The
+    /// version is added after any other client added meanwhile, since the listing versions refer
+    /// to shard versions by name rather than by position.
+    fn add_shard_version(&self, shard_id: routing::NameType, version: routing::NameType) -> Result<(), String> {
+        let structured_data_type_id: maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
+        let mut sdv: maidsafe_types::StructuredData = match try!(nfs::utils::network_get(&self.client, structured_data_type_id.type_tag(), shard_id.clone())) {
+            Some(data) => try!(nfs::utils::try_deserialise(data)),
+            None => {
+                let sdv = maidsafe_types::StructuredData::new(shard_id, self.client.lock().unwrap().get_owner(), vec![version]);
+                return match self.network_put(sdv) {
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(failure(error, "Failed to update directory shard version")),
+                };
+            },
+        };
+        for _ in 0..STALE_UPDATE_ATTEMPTS {
+            sdv = match try!(self.append_version(sdv, version.clone())) {
+                None => return Ok(()),
+                Some(stored) => stored,
+            };
+        }
+        Err(nfs::STALE_UPDATE.to_string())
+    }
+
+    /// Removes the versions from the StructuredData of a shard, returning the versions actually
+    /// removed
+    fn remove_shard_versions(&self, shard_id: routing::NameType, removed: &[routing::NameType]) -> Result<Vec<routing::NameType>, String> {
+        let structured_data_type_id: maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
+        let sdv: maidsafe_types::StructuredData = match self.network_get(structured_data_type_id.type_tag(), shard_id) {
+            Ok(data) => try!(nfs::utils::try_deserialise(data)),
+            Err(_) => return Err("Network IO Error".to_string()),
         };
+        let kept = try!(self.remove_versions(sdv, removed));
+        Ok(removed.iter().filter(|version| !kept.contains(version)).cloned().collect())
+    }
+
+    /// Removes the versions from the versions in the StructuredData, returning the versions kept.
+    /// The latest version is never removed. If the network refuses the update as stale, the
+    /// versions are removed from the StructuredData it holds instead, so that versions added by
+    /// other clients meanwhile are kept.
+    fn remove_versions(&self, mut sdv: maidsafe_types::StructuredData, removed: &[routing::NameType]) -> Result<Vec<routing::NameType>, String> {
+        for _ in 0..STALE_UPDATE_ATTEMPTS {
+            let versions = sdv.value();
+            let latest = versions.last().cloned();
+            let kept: Vec<routing::NameType> = versions.into_iter()
+                                                       .filter(|version| !removed.contains(version) || Some(version) == latest.as_ref())
+                                                       .collect();
+            sdv.set_value(kept.clone());
+            sdv = match try!(self.put_versions(sdv)) {
+                None => return Ok(kept),
+                Some(stored) => stored,
+            };
+        }
+        Err(nfs::STALE_UPDATE.to_string())
+    }
+
+    /// Compresses and self-encrypts the content and stores its encrypted datamap as ImmutableData,
+    /// returning the name of the ImmutableData
+    fn save_encrypted(&self, content: Vec<u8>, id: routing::NameType, parent_id: routing::NameType) -> Result<routing::NameType, String> {
+        self.save_uncompressed(nfs::compression::compress(self.compression, &content[..]), id, parent_id)
+    }
+
+    /// As save_encrypted, storing the content as it is
+    fn save_uncompressed(&self, content: Vec<u8>, id: routing::NameType, parent_id: routing::NameType) -> Result<routing::NameType, String> {
+        let storage = ::std::sync::Arc::new(nfs::io::NetworkStorage::new(self.client.clone()));
+        let mut se = self_encryption::SelfEncryptor::new(storage.clone(), self_encryption::datamap::DataMap::None);
+        se.write(&content[..], 0);
+        let datamap = se.close();
+        try!(storage.check_stored());
+
+        let encrypt_result: _;
+        {
+            let client = self.client.lock().unwrap();
+            encrypt_result = client.hybrid_encrypt(&nfs::utils::serialise(datamap)[..], self.get_nonce(id, parent_id));
+        }
+
+        if encrypt_result.is_err() {
+            return Err("Encryption failed".to_string());
+        }
+
+        let immutable_data = maidsafe_types::ImmutableData::new(encrypt_result.unwrap());
+        if let Err(error) = self.network_put(immutable_data.clone()) {
+            return Err(failure(error, "Failed to save directory"));
+        };
+        Ok(immutable_data.name())
+    }
+
+    /// Reverses save_encrypted
+    fn load_encrypted(&self, name: routing::NameType, id: routing::NameType, parent_id: routing::NameType) -> Result<Vec<u8>, String> {
+        let content = try!(self.load_uncompressed(name, id, parent_id));
+        nfs::compression::decompress(&content[..])
+    }
+
+    /// Reverses save_uncompressed
+    fn load_uncompressed(&self, name: routing::NameType, id: routing::NameType, parent_id: routing::NameType) -> Result<Vec<u8>, String> {
         let immutable_data_type_id: maidsafe_types::data::ImmutableDataTypeTag = unsafe { ::std::mem::uninitialized() };
         let imm_data_res = self.network_get(immutable_data_type_id.type_tag(), name);
         if imm_data_res.is_err() {
@@ -171,9 +487,11 @@ impl DirectoryHelper {
         }
         let imm: maidsafe_types::ImmutableData = nfs::utils::deserialise(imm_data_res.unwrap());
 
-        let client_mutex = self.client.clone();
-        let client = client_mutex.lock().unwrap();
-        let decrypt_result = client.hybrid_decrypt(&imm.value()[..], self.get_nonce(directory_id.clone(), parent_directory_id.clone()));
+        let decrypt_result: _;
+        {
+            let client = self.client.lock().unwrap();
+            decrypt_result = client.hybrid_decrypt(&imm.value()[..], self.get_nonce(id, parent_id));
+        }
         if decrypt_result.is_none() {
             return Err("Failed to decrypt".to_string());
         }
@@ -181,34 +499,22 @@ impl DirectoryHelper {
 
         let mut se = self_encryption::SelfEncryptor::new(::std::sync::Arc::new(nfs::io::NetworkStorage::new(self.client.clone())), datamap);
         let size = se.len();
-        Ok(nfs::utils::deserialise(se.read(0, size)))
+        Ok(se.read(0, size))
     }
 
     fn network_get(&self, tag_id: u64, name: routing::NameType) -> Result<Vec<u8>, String> {
-        let get_result = self.client.lock().unwrap().get(tag_id, name);
-        if get_result.is_err() {
-            return Err("Network IO Error".to_string());
-        }
-
-        match get_result.ok().unwrap().get() {
-            Ok(data) => Ok(data),
-            Err(_) => Err("Failed to fetch data".to_string()),
+        match try!(nfs::utils::network_get(&self.client, tag_id, name)) {
+            Some(data) => Ok(data),
+            None => Err("Data not found".to_string()),
         }
     }
 
     fn network_put<T>(&self, sendable: T) -> Result<Vec<u8>, String> where T: Sendable {
-        let get_result = self.client.lock().unwrap().put(sendable);
-        if get_result.is_err() {
-            return Err("Network IO Error".to_string());
-        }
-
-        match get_result.ok().unwrap().get() {
-            Ok(data) => Ok(data),
-            Err(_) => Err("Failed to fetch data".to_string()),
-        }
+        nfs::utils::network_put(&self.client, sendable)
     }
 
-    fn get_nonce(&self, id: routing::NameType, parent_id: routing::NameType) -> Option<::sodiumoxide::crypto::asymmetricbox::Nonce> {
+    /// Nonce the listings of a directory are encrypted with
+    pub fn get_nonce(&self, id: routing::NameType, parent_id: routing::NameType) -> Option<::sodiumoxide::crypto::asymmetricbox::Nonce> {
         let mut nonce = [0u8;24];
         for i in 0..24 {
             if i % 2 == 0 {
@@ -222,6 +528,15 @@ impl DirectoryHelper {
 }
 
 
+/// The message for a failed step, unless it failed for lack of space, which callers are told as is
+fn failure(error: String, message: &str) -> String {
+    if error == nfs::OUT_OF_SPACE {
+        error
+    } else {
+        message.to_string()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -234,7 +549,7 @@ mod test {
         ::client::Client::create_account(&keyword,
                                          pin,
                                          &password,
-                                         ::std::sync::Arc::new(::std::sync::Mutex::new(::std::collections::BTreeMap::new()))).ok().unwrap()
+                                         ::client::non_networking_test_framework::get_new_data_store()).ok().unwrap()
     }
 
     #[test]
@@ -343,4 +658,254 @@ mod test {
             assert_eq!(rxd_dir_listing.get_name(), "DirName".to_string());
         }
     }
+
+    #[test]
+    fn sharded_update_and_stream() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let mut dir_helper = DirectoryHelper::new(client.clone());
+        dir_helper.set_shard_threshold(2);
+
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let created_dir_id = dir_helper.create(parent_id.clone(), "DirName".to_string(), Vec::new()).ok().unwrap();
+        let mut dir_listing = dir_helper.get(created_dir_id.clone(), parent_id.clone()).ok().unwrap();
+
+        // Pick names landing in distinct shards so that an update can be seen to touch only one
+        let mut names = Vec::<String>::new();
+        let mut index = 0;
+        while names.len() < 3 {
+            let name = format!("file_{}", index);
+            if names.iter().all(|existing| ::nfs::helper::shard_index(existing) != ::nfs::helper::shard_index(&name)) {
+                names.push(name);
+            }
+            index += 1;
+        }
+        for name in names.iter() {
+            let _ = dir_listing.upsert_file(::nfs::file::File::new(::nfs::metadata::Metadata::new(name.clone(), Vec::new()),
+                                                                   ::self_encryption::datamap::DataMap::None));
+        }
+        assert!(dir_helper.update(dir_listing.clone()).is_ok());
+        assert_eq!(dir_helper.get(created_dir_id.clone(), parent_id.clone()).ok().unwrap(), dir_listing);
+
+        let _ = dir_listing.upsert_file(::nfs::file::File::new(::nfs::metadata::Metadata::new(names[0].clone(), vec![1u8]),
+                                                               ::self_encryption::datamap::DataMap::None));
+        assert!(dir_helper.update(dir_listing.clone()).is_ok());
+        assert_eq!(dir_helper.get(created_dir_id.clone(), parent_id.clone()).ok().unwrap(), dir_listing);
+
+        let shard_versions = |name: &String| {
+            let shard_id = ::nfs::helper::shard_id(&created_dir_id, ::nfs::helper::shard_index(name));
+            DirectoryHelper::new(client.clone()).get_versions(shard_id).ok().unwrap().len()
+        };
+        assert_eq!(shard_versions(&names[0]), 2);
+        assert_eq!(shard_versions(&names[1]), 1);
+        assert_eq!(shard_versions(&names[2]), 1);
+
+        let mut streamed: Vec<String> = dir_helper.stream(created_dir_id.clone(), parent_id.clone()).ok().unwrap().map(|entry| {
+            match entry.ok().unwrap() {
+                ::nfs::helper::ListingEntry::File(file) => file.get_name(),
+                ::nfs::helper::ListingEntry::SubDirectory(info) => info.get_name(),
+            }
+        }).collect();
+        streamed.sort();
+        let mut expected = names.clone();
+        expected.sort();
+        assert_eq!(streamed, expected);
+
+        let versions = dir_helper.get_versions(created_dir_id.clone()).ok().unwrap();
+        let first_sharded = dir_helper.get_by_version(created_dir_id.clone(), parent_id.clone(), versions[1].clone()).ok().unwrap();
+        assert!(first_sharded.find_file(&names[0]).unwrap().get_metadata().get_user_metadata().is_none());
+    }
+
+    #[test]
+    fn compressed_listing() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let mut dir_helper = DirectoryHelper::new(client.clone());
+        dir_helper.set_compression(::nfs::compression::Compression::Deflate);
+
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let created_dir_id = dir_helper.create(parent_id.clone(), "DirName".to_string(), vec![7u8; 100]).ok().unwrap();
+        let mut dir_listing = dir_helper.get(created_dir_id.clone(), parent_id.clone()).ok().unwrap();
+        let _ = dir_listing.upsert_file(::nfs::file::File::new_inline(::nfs::metadata::Metadata::new("a.json".to_string(), Vec::new()),
+                                                                      vec![b'{'; 200]));
+        assert!(dir_helper.update(dir_listing.clone()).is_ok());
+
+        // Reading does not depend on the compression configured on the helper
+        let mut plain_helper = DirectoryHelper::new(client.clone());
+        assert_eq!(plain_helper.get(created_dir_id.clone(), parent_id.clone()).ok().unwrap(), dir_listing);
+    }
+
+    #[test]
+    fn prune_versions() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let mut dir_helper = DirectoryHelper::new(client.clone());
+        dir_helper.set_shard_threshold(1);
+
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let created_dir_id = dir_helper.create(parent_id.clone(), "DirName".to_string(), Vec::new()).ok().unwrap();
+        let mut dir_listing = dir_helper.get(created_dir_id.clone(), parent_id.clone()).ok().unwrap();
+
+        // Without a policy every version is kept
+        assert!(dir_helper.prune_versions(created_dir_id.clone(), parent_id.clone()).ok().unwrap().removed.is_empty());
+
+        for content in vec![vec![1u8], vec![2u8]] {
+            for name in vec!["a.txt", "b.txt"] {
+                let _ = dir_listing.upsert_file(::nfs::file::File::new_inline(::nfs::metadata::Metadata::new(name.to_string(), Vec::new()),
+                                                                              content.clone()));
+            }
+            assert!(dir_helper.update(dir_listing.clone()).is_ok());
+        }
+        let mut policy = ::nfs::retention::RetentionPolicy::new();
+        policy.set_keep_last(Some(2));
+        dir_listing.set_retention_policy(Some(policy));
+        assert!(dir_helper.update(dir_listing.clone()).is_ok());
+
+        let versions = dir_helper.get_versions(created_dir_id.clone()).ok().unwrap();
+        assert_eq!(versions.len(), 4);
+
+        let report = dir_helper.prune_versions(created_dir_id.clone(), parent_id.clone()).ok().unwrap();
+        assert_eq!(report.kept, versions[2..].to_vec());
+        assert_eq!(report.removed, versions[..2].to_vec());
+        // The shards written by the first update were replaced by the second one
+        let distinct_shards = ::nfs::helper::shard_index("a.txt") != ::nfs::helper::shard_index("b.txt");
+        assert_eq!(report.unreferenced.len(), 2 + if distinct_shards { 2 } else { 1 });
+
+        assert_eq!(dir_helper.get_versions(created_dir_id.clone()).ok().unwrap(), versions[2..].to_vec());
+        assert_eq!(dir_helper.get(created_dir_id.clone(), parent_id.clone()).ok().unwrap(), dir_listing);
+        let shard_id = ::nfs::helper::shard_id(&created_dir_id, ::nfs::helper::shard_index("a.txt"));
+        assert_eq!(dir_helper.get_versions(shard_id).ok().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn legacy_listing_is_read() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let mut dir_helper = DirectoryHelper::new(client.clone());
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let id = ::routing::NameType::new([9u8; 64]);
+
+        // Stored as before: the listing itself, uncompressed
+        let legacy = ::nfs::helper::legacy::LegacyListing::sample(id.clone(), parent_id.clone());
+        let version = dir_helper.save_uncompressed(::nfs::utils::serialise(legacy), id.clone(), parent_id.clone()).ok().unwrap();
+        let owner = client.lock().unwrap().get_owner();
+        assert!(dir_helper.network_put(::maidsafe_types::StructuredData::new(id.clone(), owner, vec![version])).is_ok());
+
+        let mut listing = dir_helper.get(id.clone(), parent_id.clone()).ok().unwrap();
+        assert_eq!(listing.get_name(), "Old".to_string());
+        assert_eq!(listing.get_sub_directories().map(|info| info.get_name()).collect::<Vec<_>>(), vec!["Sub".to_string()]);
+        let file = listing.find_file("a.txt").cloned().unwrap();
+        assert_eq!(file.get_metadata().get_size(), 3);
+        assert_eq!(file.get_metadata().get_user_metadata(), Some(b"meta".to_vec()));
+        assert_eq!(file.get_metadata().get_modified_time().to_timespec(), ::time::Timespec { sec: 1_450_000_000, nsec: 500 });
+
+        // Updating saves the current layout
+        let _ = listing.upsert_file(::nfs::file::File::new_inline(::nfs::metadata::Metadata::new("b.txt".to_string(), Vec::new()), vec![1u8]));
+        assert!(dir_helper.update_listing(&mut listing).is_ok());
+        assert_eq!(dir_helper.get(id.clone(), parent_id).ok().unwrap(), listing);
+        assert!(dir_helper.prune_versions(id, ::routing::NameType::new([8u8; 64])).is_ok());
+    }
+
+    #[test]
+    fn stale_versions_are_returned() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let mut dir_helper = DirectoryHelper::new(client.clone());
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let id = dir_helper.create(parent_id.clone(), "DirName".to_string(), vec![7u8; 100]).ok().unwrap();
+        let listing = dir_helper.get(id.clone(), parent_id.clone()).ok().unwrap();
+
+        // Read before another version is added
+        let structured_data_type_id: ::maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
+        let stale: ::maidsafe_types::StructuredData = ::nfs::utils::deserialise(dir_helper.network_get(structured_data_type_id.type_tag(), id.clone()).ok().unwrap());
+        assert!(dir_helper.update(listing).is_ok());
+        let versions = dir_helper.get_versions(id.clone()).ok().unwrap();
+        assert_eq!(versions.len(), 2);
+
+        let stored = dir_helper.append_version(stale, ::routing::NameType::new([5u8; 64])).ok().unwrap();
+        assert_eq!(stored.unwrap().value(), versions);
+        assert_eq!(dir_helper.get_versions(id).ok().unwrap(), versions);
+    }
+
+    #[test]
+    fn removing_versions_keeps_newer_ones() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let mut dir_helper = DirectoryHelper::new(client.clone());
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let id = dir_helper.create(parent_id.clone(), "DirName".to_string(), Vec::new()).ok().unwrap();
+        let listing = dir_helper.get(id.clone(), parent_id.clone()).ok().unwrap();
+        assert!(dir_helper.update(listing.clone()).is_ok());
+
+        // Read before another version is added, as pruning does
+        let structured_data_type_id: ::maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
+        let stale: ::maidsafe_types::StructuredData = ::nfs::utils::deserialise(dir_helper.network_get(structured_data_type_id.type_tag(), id.clone()).ok().unwrap());
+        let read_versions = stale.value();
+        assert!(dir_helper.update(listing).is_ok());
+        let versions = dir_helper.get_versions(id.clone()).ok().unwrap();
+        assert_eq!(versions.len(), 3);
+
+        // Every version read is removed but the one added since, now the latest, is kept
+        let kept = dir_helper.remove_versions(stale, &read_versions[..]).ok().unwrap();
+        assert_eq!(kept, versions[2..].to_vec());
+        assert_eq!(dir_helper.get_versions(id.clone()).ok().unwrap(), kept);
+
+        // The latest version is never removed
+        let sdv: ::maidsafe_types::StructuredData = ::nfs::utils::deserialise(dir_helper.network_get(structured_data_type_id.type_tag(), id.clone()).ok().unwrap());
+        assert_eq!(dir_helper.remove_versions(sdv, &kept[..]).ok().unwrap(), kept);
+    }
+
+    #[test]
+    fn concurrent_updates_keep_each_others_changes() {
+        let data_store = ::client::non_networking_test_framework::get_new_data_store();
+        let first = ::client::Client::create_account(&"Spandan".to_string(), 1234, "Sharma".as_bytes(), data_store.clone()).ok().unwrap();
+        let second = ::client::Client::log_in(&"Spandan".to_string(), 1234, "Sharma".as_bytes(), data_store.clone()).ok().unwrap();
+        let mut first_helper = DirectoryHelper::new(::std::sync::Arc::new(::std::sync::Mutex::new(first)));
+        let mut second_helper = DirectoryHelper::new(::std::sync::Arc::new(::std::sync::Mutex::new(second)));
+        let file = |name: &str| ::nfs::file::File::new_inline(::nfs::metadata::Metadata::new(name.to_string(), Vec::new()), vec![1u8]);
+
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let id = first_helper.create(parent_id.clone(), "DirName".to_string(), Vec::new()).ok().unwrap();
+        let mut listing = first_helper.get(id.clone(), parent_id.clone()).ok().unwrap();
+        let _ = listing.upsert_file(file("old.txt"));
+        assert!(first_helper.update(listing).is_ok());
+
+        // Both sessions read the same version and each add a different file
+        let mut first_listing = first_helper.get(id.clone(), parent_id.clone()).ok().unwrap();
+        let mut second_listing = second_helper.get(id.clone(), parent_id.clone()).ok().unwrap();
+        let _ = first_listing.upsert_file(file("a.txt"));
+        assert!(first_helper.update_listing(&mut first_listing).is_ok());
+        let _ = second_listing.upsert_file(file("b.txt"));
+        let _ = second_listing.remove_file("old.txt");
+        assert!(second_helper.update(second_listing).is_ok());
+
+        let latest = first_helper.get(id.clone(), parent_id.clone()).ok().unwrap();
+        assert!(latest.find_file("a.txt").is_some());
+        assert!(latest.find_file("b.txt").is_some());
+        assert!(latest.find_file("old.txt").is_none());
+        assert_eq!(first_helper.get_versions(id.clone()).ok().unwrap().len(), 4);
+
+        // A listing kept after its update goes on from the version stored
+        let _ = first_listing.upsert_file(file("c.txt"));
+        assert!(first_helper.update_listing(&mut first_listing).is_ok());
+        assert_eq!(first_listing.get_files().map(|file| file.get_name()).collect::<Vec<String>>(),
+                   vec!["a.txt".to_string(), "b.txt".to_string(), "c.txt".to_string()]);
+        assert_eq!(first_helper.get(id, parent_id).ok().unwrap(), first_listing);
+    }
+
+    #[test]
+    fn out_of_space() {
+        let data_store = ::client::non_networking_test_framework::get_new_data_store();
+        let client = ::client::Client::create_account(&"Spandan".to_string(), 1234, "Sharma".as_bytes(), data_store.clone()).ok().unwrap();
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(client));
+        let mut dir_helper = DirectoryHelper::new(client.clone());
+
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let created_dir_id = dir_helper.create(parent_id.clone(), "DirName".to_string(), Vec::new()).ok().unwrap();
+        let dir_listing = dir_helper.get(created_dir_id, parent_id.clone()).ok().unwrap();
+
+        let used_space = client.lock().unwrap().get_account_info().ok().unwrap().used_space;
+        ::client::non_networking_test_framework::set_storage_quota(&data_store, used_space + 20000).ok().unwrap();
+        let mut writer = ::nfs::helper::FileHelper::new(client.clone()).create("file.dat".to_string(), 0, Vec::new(), dir_listing).ok().unwrap();
+        writer.write(&vec![7u8; 100000][..], 0);
+        assert_eq!(writer.close(), Err(::nfs::OUT_OF_SPACE.to_string()));
+
+        let used_space = client.lock().unwrap().get_account_info().ok().unwrap().used_space;
+        ::client::non_networking_test_framework::set_storage_quota(&data_store, used_space).ok().unwrap();
+        assert_eq!(dir_helper.create(parent_id, "Another".to_string(), Vec::new()), Err(::nfs::OUT_OF_SPACE.to_string()));
+    }
 }
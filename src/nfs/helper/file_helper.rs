@@ -38,33 +38,31 @@ impl FileHelper {
 
     pub fn create(&mut self, name: String, size: u64, user_metatdata: Vec<u8>,
             directory: nfs::directory_listing::DirectoryListing) -> Result<nfs::io::Writer, String> {
-        if self.file_exists(directory.clone(), name.clone()) {
+        if self.file_exists(&directory, &name) {
             return Err("File already exists".to_string());
         }
         let mut metadata = nfs::metadata::Metadata::new(name, user_metatdata);
         metadata.set_size(size);
         let file = nfs::file::File::new(metadata, self_encryption::datamap::DataMap::None);
-        Ok(nfs::io::Writer::new(directory, file, self.client.clone()))
+        nfs::io::Writer::new(directory, file, self.client.clone())
     }
 
     pub fn update(&mut self, file: nfs::file::File, directory: nfs::directory_listing::DirectoryListing) -> Result<nfs::io::Writer, String> {
-        if !self.file_exists(directory.clone(), file.get_name()) {
+        if !self.file_exists(&directory, &file.get_name()) {
             return Err("File not present in the directory".to_string());
         }
-        Ok(nfs::io::Writer::new(directory, file, self.client.clone()))
+        nfs::io::Writer::new(directory, file, self.client.clone())
     }
 
     /// Updates the file metadata. Returns the updated DirectoryListing
-    pub fn update_metadata(&mut self, file: nfs::file::File, directory: &mut nfs::directory_listing::DirectoryListing, user_metadata: Vec<u8>) -> Result<(), String> {
-        if !self.file_exists(directory.clone(), file.get_name()) {
+    pub fn update_metadata(&mut self, mut file: nfs::file::File, directory: &mut nfs::directory_listing::DirectoryListing, user_metadata: Vec<u8>) -> Result<(), String> {
+        if directory.find_file(&file.get_name()).is_none() {
             return Err("File not present in the directory".to_string());
         }
-        file.get_metadata().set_user_metadata(user_metadata);
-        let pos = directory.get_files().binary_search_by(|p| p.get_name().cmp(&file.get_name())).unwrap();
-        let mut files = directory.get_files();
-        files.remove(pos);
-        files.insert(pos, file);
-        directory.set_files(files);
+        let mut metadata = file.get_metadata();
+        metadata.set_user_metadata(user_metadata);
+        file.set_metadata(metadata);
+        let _ = directory.upsert_file(file);
         let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
         if directory_helper.update(directory.clone()).is_err() {
             return Err("Failed to update".to_string());
@@ -84,7 +82,7 @@ impl FileHelper {
                 for version_id in sdv_versions {
                     match directory_helper.get_by_version(directory_id.clone(), parent_id.clone(), version_id.clone()) {
                         Ok(directory_listing) => {
-                            match directory_listing.get_files().iter().find(|&entry| entry.get_name() == file.get_name()) {
+                            match directory_listing.find_file(&file.get_name()) {
                                 Some(file) => {
                                    if file.get_metadata().get_modified_time() != modified_time {
                                         modified_time = file.get_metadata().get_modified_time();
@@ -108,10 +106,8 @@ impl FileHelper {
         nfs::io::Reader::new(file, self.client.clone())
     }
 
-    pub fn file_exists(&self, directory: nfs::directory_listing::DirectoryListing, file_name: String) -> bool {
-        directory.get_files().iter().find(|file| {
-                file.get_name() == file_name
-            }).is_some()
+    pub fn file_exists(&self, directory: &nfs::directory_listing::DirectoryListing, file_name: &str) -> bool {
+        directory.find_file(file_name).is_some()
     }
 
 }
@@ -128,7 +124,7 @@ mod test {
         ::client::Client::create_account(&keyword,
                                          pin,
                                          &password,
-                                         ::std::sync::Arc::new(::std::sync::Mutex::new(::std::collections::BTreeMap::new()))).ok().unwrap()
+                                         ::client::non_networking_test_framework::get_new_data_store()).ok().unwrap()
     }
 
 
@@ -175,7 +171,7 @@ mod test {
         }
 
         {
-            let result = dir_listing.get_files();
+            let result: Vec<::nfs::file::File> = dir_listing.get_files().cloned().collect();
             assert_eq!(result.len(), 1);
 
             let file = result[0].clone();
@@ -202,7 +198,7 @@ mod test {
                 assert!(get_result.is_ok());
                 dir_listing = get_result.ok().unwrap();
 
-                let result = dir_listing.get_files();
+                let result: Vec<::nfs::file::File> = dir_listing.get_files().cloned().collect();
                 assert_eq!(result.len(), 1);
 
                 let file = result[0].clone();
@@ -219,4 +215,83 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn inline_content_promotion() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let mut dir_helper = ::nfs::helper::directory_helper::DirectoryHelper::new(client.clone());
+
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let created_dir_id = dir_helper.create(parent_id.clone(), "DirName".to_string(), Vec::new()).ok().unwrap();
+        let dir_listing = dir_helper.get(created_dir_id.clone(), parent_id.clone()).ok().unwrap();
+
+        let mut file_helper = FileHelper::new(client);
+        let mut writer = file_helper.create("Small".to_string(), 0, Vec::new(), dir_listing).ok().unwrap();
+        writer.set_inline_threshold(16);
+        writer.write(&[1u8; 10], 0);
+        assert!(writer.close().is_ok());
+
+        let dir_listing = dir_helper.get(created_dir_id.clone(), parent_id.clone()).ok().unwrap();
+        let file = dir_listing.find_file("Small").unwrap().clone();
+        assert_eq!(file.get_content(), ::nfs::file::FileContent::Inline(vec![1u8; 10]));
+        assert_eq!(file_helper.read(file.clone()).read(0, 10).ok().unwrap(), vec![1u8; 10]);
+
+        // Growing past the threshold moves the content to self-encryption
+        let mut writer = file_helper.update(file, dir_listing).ok().unwrap();
+        writer.set_inline_threshold(16);
+        writer.write(&[2u8; 40], 10);
+        assert!(writer.close().is_ok());
+
+        let dir_listing = dir_helper.get(created_dir_id.clone(), parent_id.clone()).ok().unwrap();
+        let file = dir_listing.find_file("Small").unwrap().clone();
+        match file.get_content() {
+            ::nfs::file::FileContent::DataMap(_) => (),
+            ::nfs::file::FileContent::Inline(_) => panic!("Content should have been self-encrypted"),
+        }
+        let mut expected = vec![1u8; 10];
+        expected.extend(vec![2u8; 40]);
+        let mut reader = file_helper.read(file);
+        assert_eq!(reader.size(), 50);
+        assert_eq!(reader.read(0, 50).ok().unwrap(), expected);
+    }
+
+    #[test]
+    fn compressed_content() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let mut dir_helper = ::nfs::helper::directory_helper::DirectoryHelper::new(client.clone());
+
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let created_dir_id = dir_helper.create(parent_id.clone(), "DirName".to_string(), Vec::new()).ok().unwrap();
+        let dir_listing = dir_helper.get(created_dir_id.clone(), parent_id.clone()).ok().unwrap();
+
+        let data: Vec<u8> = (0..3 * ::nfs::compression::BLOCK_SIZE + 500).map(|index| b"{\"key\": \"value\"}\n"[index % 17]).collect();
+        let mut file_helper = FileHelper::new(client);
+        let mut writer = file_helper.create("data.json".to_string(), data.len() as u64, Vec::new(), dir_listing).ok().unwrap();
+        writer.set_compression(::nfs::compression::Compression::Deflate);
+        writer.write(&data[..], 0);
+        assert!(writer.close().is_ok());
+
+        let dir_listing = dir_helper.get(created_dir_id.clone(), parent_id.clone()).ok().unwrap();
+        let file = dir_listing.find_file("data.json").unwrap().clone();
+        assert_eq!(file.get_metadata().get_compression(), ::nfs::compression::Compression::Deflate);
+
+        // A span crossing a block boundary
+        let start = ::nfs::compression::BLOCK_SIZE - 10;
+        let mut reader = file_helper.read(file.clone());
+        assert_eq!(reader.size(), data.len() as u64);
+        assert_eq!(reader.read(start as u64, 20).ok().unwrap(), data[start..start + 20].to_vec());
+
+        // Rewriting part of compressed content keeps the rest
+        let mut writer = file_helper.update(file, dir_listing).ok().unwrap();
+        writer.write(&[b'x'; 5], 100);
+        assert!(writer.close().is_ok());
+
+        let dir_listing = dir_helper.get(created_dir_id.clone(), parent_id.clone()).ok().unwrap();
+        let mut reader = file_helper.read(dir_listing.find_file("data.json").unwrap().clone());
+        let mut expected = data.clone();
+        for byte in expected[100..105].iter_mut() {
+            *byte = b'x';
+        }
+        assert_eq!(reader.read(0, data.len() as u64).ok().unwrap(), expected);
+    }
 }
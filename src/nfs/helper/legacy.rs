@@ -0,0 +1,135 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Directory listings as stored before versions were saved with a header and their content
+//! compressed. They are still read, as a whole listing saved when the directory was last
+//! modified. Updating the directory saves the current layout.
+
+use cbor::CborTagEncode;
+use nfs;
+use routing;
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+use self_encryption;
+
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct LegacyListing {
+    info: LegacyDirectoryInfo,
+    sub_directories: Vec<LegacyDirectoryInfo>,
+    files: Vec<LegacyFile>,
+}
+
+impl LegacyListing {
+    pub fn into_stored_version(self) -> nfs::helper::StoredVersion {
+        let saved_time = self.info.metadata.modified_time;
+        let mut listing = nfs::directory_listing::DirectoryListing::from_info(self.info.into_info());
+        for info in self.sub_directories.into_iter() {
+            let _ = listing.upsert_sub_directory(info.into_info());
+        }
+        for file in self.files.into_iter() {
+            let _ = listing.upsert_file(file.into_file());
+        }
+        nfs::helper::StoredVersion {
+            saved_sec: saved_time.0,
+            saved_nsec: saved_time.1,
+            listing: nfs::helper::StoredListing::Whole(listing),
+        }
+    }
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
+struct LegacyDirectoryInfo {
+    id: routing::NameType,
+    parent_dir_id: routing::NameType,
+    metadata: LegacyMetadata,
+}
+
+impl LegacyDirectoryInfo {
+    fn into_info(self) -> nfs::directory_info::DirectoryInfo {
+        nfs::directory_info::DirectoryInfo::with_id(self.id, self.parent_dir_id, self.metadata.into_metadata())
+    }
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
+struct LegacyFile {
+    metadata: LegacyMetadata,
+    datamap: self_encryption::datamap::DataMap,
+}
+
+impl LegacyFile {
+    fn into_file(self) -> nfs::file::File {
+        nfs::file::File::new(self.metadata.into_metadata(), self.datamap)
+    }
+}
+
+// Metadata without compression, which content was never compressed with
+struct LegacyMetadata {
+    name: String,
+    size: u64,
+    user_metadata: Vec<u8>,
+    created_time: (i64, i32),
+    modified_time: (i64, i32),
+}
+
+impl LegacyMetadata {
+    fn into_metadata(self) -> nfs::metadata::Metadata {
+        let mut metadata = nfs::metadata::Metadata::new(self.name, self.user_metadata);
+        metadata.set_size(self.size);
+        metadata.set_created_time(::time::at_utc(::time::Timespec { sec: self.created_time.0, nsec: self.created_time.1 }));
+        metadata.set_modified_time(::time::at_utc(::time::Timespec { sec: self.modified_time.0, nsec: self.modified_time.1 }));
+        metadata
+    }
+}
+
+impl Encodable for LegacyMetadata {
+    fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        CborTagEncode::new(5483_000, &(self.name.clone(), self.size as usize, self.user_metadata.clone(),
+                                       self.created_time.0, self.created_time.1, self.modified_time.0, self.modified_time.1)).encode(e)
+    }
+}
+
+impl Decodable for LegacyMetadata {
+    fn decode<D: Decoder>(d: &mut D) -> Result<LegacyMetadata, D::Error> {
+        try!(d.read_u64());
+        let (name, size, user_metadata, created_sec, created_nsec, modified_sec, modified_nsec) = try!(Decodable::decode(d));
+        Ok(LegacyMetadata {
+            name: name,
+            size: size,
+            user_metadata: user_metadata,
+            created_time: (created_sec, created_nsec),
+            modified_time: (modified_sec, modified_nsec),
+        })
+    }
+}
+
+#[cfg(test)]
+impl LegacyListing {
+    /// A listing as it was stored, holding one sub-directory and one file
+    pub fn sample(id: routing::NameType, parent_dir_id: routing::NameType) -> LegacyListing {
+        let metadata = |name: &str| LegacyMetadata {
+            name: name.to_string(),
+            size: 3,
+            user_metadata: b"meta".to_vec(),
+            created_time: (1_400_000_000, 0),
+            modified_time: (1_450_000_000, 500),
+        };
+        LegacyListing {
+            info: LegacyDirectoryInfo { id: id.clone(), parent_dir_id: parent_dir_id, metadata: metadata("Old") },
+            sub_directories: vec![LegacyDirectoryInfo { id: routing::NameType::new([3u8; 64]), parent_dir_id: id, metadata: metadata("Sub") }],
+            files: vec![LegacyFile { metadata: metadata("a.txt"), datamap: self_encryption::datamap::DataMap::None }],
+        }
+    }
+}
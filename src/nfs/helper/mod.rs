@@ -17,6 +17,9 @@
 
 mod directory_helper;
 mod file_helper;
+mod legacy;
+mod shard;
 
 pub use self::directory_helper::*;
 pub use self::file_helper::*;
+pub use self::shard::*;
@@ -0,0 +1,199 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+use nfs;
+use routing;
+
+/// Listings with more entries than this are stored in shards
+pub const DEFAULT_SHARD_THRESHOLD: usize = 1000;
+/// Number of hash-prefix buckets a sharded listing is split into
+pub const SHARD_COUNT: usize = 64;
+
+/// What a version of a directory points to: either the whole listing or the listing without
+/// its entries together with a manifest of the shards holding them
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, Clone, Debug)]
+pub enum StoredListing {
+    Whole(nfs::directory_listing::DirectoryListing),
+    Sharded(nfs::directory_listing::DirectoryListing, Vec<ShardRef>),
+}
+
+/// A version of a directory as stored, with the time it was saved
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, Clone, Debug)]
+pub struct StoredVersion {
+    pub saved_sec: i64,
+    pub saved_nsec: i32,
+    pub listing: StoredListing,
+}
+
+impl StoredVersion {
+    pub fn new(listing: StoredListing) -> StoredVersion {
+        let saved_time = ::time::now_utc().to_timespec();
+        StoredVersion {
+            saved_sec: saved_time.sec,
+            saved_nsec: saved_time.nsec,
+            listing: listing,
+        }
+    }
+
+    pub fn get_saved_time(&self) -> ::time::Tm {
+        ::time::at_utc(::time::Timespec { sec: self.saved_sec, nsec: self.saved_nsec })
+    }
+}
+
+/// Points at the version of a shard making up a particular version of a directory
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, Clone, Debug)]
+pub struct ShardRef {
+    pub index: usize,
+    pub version: routing::NameType,
+    pub hash: Vec<u8>,
+}
+
+/// The entries of a listing whose names fall in one bucket
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, Clone, Debug)]
+pub struct Shard {
+    pub sub_directories: ::std::collections::BTreeMap<String, nfs::directory_info::DirectoryInfo>,
+    pub files: ::std::collections::BTreeMap<String, nfs::file::File>,
+}
+
+impl Shard {
+    pub fn new() -> Shard {
+        Shard {
+            sub_directories: ::std::collections::BTreeMap::new(),
+            files: ::std::collections::BTreeMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sub_directories.is_empty() && self.files.is_empty()
+    }
+
+    /// Content hash used to tell whether a shard changed since the previous version
+    pub fn hash(&self) -> Vec<u8> {
+        ::sodiumoxide::crypto::hash::sha512::hash(&nfs::utils::serialise(self.clone())[..]).0.to_vec()
+    }
+}
+
+/// Bucket an entry name belongs to
+pub fn shard_index(name: &str) -> usize {
+    ::sodiumoxide::crypto::hash::sha512::hash(name.as_bytes()).0[0] as usize % SHARD_COUNT
+}
+
+/// Id of the StructuredData holding the versions of a shard of a directory
+pub fn shard_id(directory_id: &routing::NameType, index: usize) -> routing::NameType {
+    let mut seed = directory_id.0.to_vec();
+    seed.extend(format!("shard:{}", index).into_bytes());
+    routing::NameType(::sodiumoxide::crypto::hash::sha512::hash(&seed[..]).0)
+}
+
+/// Splits the entries of a listing into its buckets
+pub fn split(directory: &nfs::directory_listing::DirectoryListing) -> Vec<Shard> {
+    let mut shards: Vec<Shard> = (0..SHARD_COUNT).map(|_| Shard::new()).collect();
+    for info in directory.get_sub_directories() {
+        let _ = shards[shard_index(&info.get_name())].sub_directories.insert(info.get_name(), info.clone());
+    }
+    for file in directory.get_files() {
+        let _ = shards[shard_index(&file.get_name())].files.insert(file.get_name(), file.clone());
+    }
+    shards
+}
+
+/// An entry yielded while streaming a listing
+#[derive(Clone, Debug)]
+pub enum ListingEntry {
+    SubDirectory(nfs::directory_info::DirectoryInfo),
+    File(nfs::file::File),
+}
+
+/// Streams the entries of a directory, fetching one shard at a time. Entries are in name
+/// order within a shard, but not across shards.
+pub struct ListingStream {
+    helper: nfs::helper::DirectoryHelper,
+    directory_id: routing::NameType,
+    pending: ::std::collections::VecDeque<ShardRef>,
+    current: ::std::vec::IntoIter<ListingEntry>,
+}
+
+impl ListingStream {
+    pub fn new(helper: nfs::helper::DirectoryHelper, stored: StoredListing) -> ListingStream {
+        match stored {
+            StoredListing::Whole(directory) => {
+                let mut shard = Shard::new();
+                shard.sub_directories = directory.get_sub_directories().map(|info| (info.get_name(), info.clone())).collect();
+                shard.files = directory.get_files().map(|file| (file.get_name(), file.clone())).collect();
+                ListingStream {
+                    helper: helper,
+                    directory_id: directory.get_id(),
+                    pending: ::std::collections::VecDeque::new(),
+                    current: ListingStream::entries(shard).into_iter(),
+                }
+            },
+            StoredListing::Sharded(header, shards) => ListingStream {
+                helper: helper,
+                directory_id: header.get_id(),
+                pending: shards.into_iter().collect(),
+                current: Vec::new().into_iter(),
+            },
+        }
+    }
+
+    fn entries(shard: Shard) -> Vec<ListingEntry> {
+        let mut entries: Vec<ListingEntry> = shard.sub_directories.into_iter().map(|(_, info)| ListingEntry::SubDirectory(info)).collect();
+        entries.extend(shard.files.into_iter().map(|(_, file)| ListingEntry::File(file)));
+        entries
+    }
+}
+
+impl Iterator for ListingStream {
+    type Item = Result<ListingEntry, String>;
+
+    fn next(&mut self) -> Option<Result<ListingEntry, String>> {
+        loop {
+            if let Some(entry) = self.current.next() {
+                return Some(Ok(entry));
+            }
+            let shard_ref = match self.pending.pop_front() {
+                Some(shard_ref) => shard_ref,
+                None => return None,
+            };
+            match self.helper.get_shard(&self.directory_id, &shard_ref) {
+                Ok(shard) => self.current = ListingStream::entries(shard).into_iter(),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_by_name() {
+        let mut listing = ::nfs::directory_listing::DirectoryListing::new(::routing::NameType::new([1u8; 64]), "Home".to_string(), Vec::new());
+        for index in 0..200 {
+            let _ = listing.upsert_file(::nfs::file::File::new(::nfs::metadata::Metadata::new(format!("file_{}", index), Vec::new()),
+                                                               ::self_encryption::datamap::DataMap::None));
+        }
+
+        let shards = split(&listing);
+        assert_eq!(shards.len(), SHARD_COUNT);
+        assert_eq!(shards.iter().fold(0, |total, shard| total + shard.files.len()), 200);
+        for (index, shard) in shards.iter().enumerate() {
+            assert!(shard.files.keys().all(|name| shard_index(name) == index));
+        }
+        assert!(shard_id(&listing.get_id(), 0) != shard_id(&listing.get_id(), 1));
+    }
+}
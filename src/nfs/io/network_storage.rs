@@ -23,13 +23,34 @@ use maidsafe_types::TypeTag;
 
 #[allow(dead_code)]
 pub struct NetworkStorage {
-    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>
+    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+    // The first chunk which could not be stored
+    failure: ::std::sync::Mutex<Option<String>>,
 }
 
 impl NetworkStorage {
     pub fn new(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> NetworkStorage {
         NetworkStorage {
-            client: client
+            client: client,
+            failure: ::std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Storage cannot report failures to self_encryption, so a chunk which could not be stored
+    /// is remembered here. Check this once the SelfEncryptor is closed: if it fails the content
+    /// is incomplete and must not be referenced. Fails with nfs::OUT_OF_SPACE if the account had
+    /// no space for a chunk.
+    pub fn check_stored(&self) -> Result<(), String> {
+        match *self.failure.lock().unwrap() {
+            Some(ref failure) => Err(failure.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn record_failure(&self, failure: String) {
+        let mut recorded = self.failure.lock().unwrap();
+        if recorded.is_none() {
+            *recorded = Some(failure);
         }
     }
 }
@@ -57,14 +78,23 @@ impl self_encryption::Storage for NetworkStorage {
         }
     }
 
-    #[allow(unused_must_use)]
     fn put(&self, _: Vec<u8>, data: Vec<u8>) {
         let sendable = maidsafe_types::ImmutableData::new(data);
         let client_mutex = self.client.clone();
         let mut client = client_mutex.lock().unwrap();
         let put_result = client.put(sendable);
-        if put_result.is_ok() {
-            put_result.ok().unwrap().get();
+        let error = match put_result {
+            Ok(mut response_getter) => match response_getter.get() {
+                Ok(_) => return,
+                Err(error) => error,
+            },
+            Err(_) => return self.record_failure("Network IO Error".to_string()),
+        };
+        match error {
+            // The chunk is stored already, as identical content always makes the same chunks
+            routing::error::ResponseError::InvalidRequest => (),
+            ref error if client::is_out_of_space(error) => self.record_failure(::nfs::OUT_OF_SPACE.to_string()),
+            _ => self.record_failure("Failed to store content".to_string()),
         }
     }
 
@@ -20,11 +20,76 @@ use super::network_storage::NetworkStorage;
 use self_encryption;
 use client;
 
+/// Content being read: held in memory when inline, fetched through self-encryption otherwise
+enum Content {
+    Inline(Vec<u8>),
+    Encrypted(self_encryption::SelfEncryptor<NetworkStorage>),
+    Compressed(CompressedContent),
+    /// Compressed content whose block index could not be read, with the reason
+    Unreadable(String),
+}
+
+/// Self-encrypted content compressed in blocks. The most recently inflated block is kept, as
+/// sequential reads usually ask for it again.
+struct CompressedContent {
+    self_encryptor: self_encryption::SelfEncryptor<NetworkStorage>,
+    compression: nfs::compression::Compression,
+    index: nfs::compression::BlockIndex,
+    cached_block: Option<(usize, Vec<u8>)>,
+}
+
+impl CompressedContent {
+    fn new(mut self_encryptor: self_encryption::SelfEncryptor<NetworkStorage>,
+           compression: nfs::compression::Compression) -> Result<CompressedContent, String> {
+        let fixed_header = self_encryptor.read(0, nfs::compression::BlockIndex::fixed_header_len());
+        let block_count = try!(nfs::compression::BlockIndex::block_count(&fixed_header[..]));
+        let header = self_encryptor.read(0, nfs::compression::BlockIndex::fixed_header_len() + 8 * block_count);
+        let index = try!(nfs::compression::BlockIndex::parse(&header[..]));
+        Ok(CompressedContent {
+            self_encryptor: self_encryptor,
+            compression: compression,
+            index: index,
+            cached_block: None,
+        })
+    }
+
+    fn read(&mut self, position: u64, length: u64) -> Result<Vec<u8>, String> {
+        let block_size = nfs::compression::BLOCK_SIZE as u64;
+        let mut data = Vec::with_capacity(length as usize);
+        let mut current = position;
+        while current < position + length {
+            let block_index = nfs::compression::BlockIndex::block_of(current);
+            let cached = self.cached_block.as_ref().map_or(false, |&(index, _)| index == block_index);
+            if !cached {
+                let (offset, compressed_length) = match self.index.locate(block_index) {
+                    Some(location) => location,
+                    None => return Err("Read past the end of the content".to_string()),
+                };
+                let compressed = self.self_encryptor.read(offset, compressed_length);
+                let block = try!(nfs::compression::decompress_block(self.compression, &compressed[..]));
+                self.cached_block = Some((block_index, block));
+            }
+
+            let block_start = block_index as u64 * block_size;
+            let end = ::std::cmp::min(position + length, block_start + block_size);
+            if let Some((_, ref block)) = self.cached_block {
+                if end - block_start > block.len() as u64 {
+                    return Err("Corrupt compressed block".to_string());
+                }
+                data.extend(block[(current - block_start) as usize..(end - block_start) as usize].iter().cloned());
+            }
+            current = end;
+        }
+        Ok(data)
+    }
+}
+
 #[allow(dead_code)]
 pub struct Reader {
     file: nfs::file::File,
-    self_encryptor: self_encryption::SelfEncryptor<NetworkStorage>,
-    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>
+    content: Content,
+    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+    position: u64,
 }
 
 #[allow(dead_code)]
@@ -32,23 +97,96 @@ impl Reader {
 
     pub fn new(file: nfs::file::File,
         client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> Reader {
-        let storage = sync::Arc::new(NetworkStorage::new(client.clone()));
+        let compression = file.get_metadata().get_compression();
+        let content = match file.get_content() {
+            nfs::file::FileContent::Inline(data) => Content::Inline(data),
+            nfs::file::FileContent::DataMap(self_encryption::datamap::DataMap::None) => Content::Inline(Vec::new()),
+            nfs::file::FileContent::DataMap(datamap) => {
+                let storage = sync::Arc::new(NetworkStorage::new(client.clone()));
+                let self_encryptor = self_encryption::SelfEncryptor::new(storage, datamap);
+                if compression == nfs::compression::Compression::None {
+                    Content::Encrypted(self_encryptor)
+                } else {
+                    match CompressedContent::new(self_encryptor, compression) {
+                        Ok(compressed) => Content::Compressed(compressed),
+                        // Reported by read, rather than reading as empty
+                        Err(error) => Content::Unreadable(error),
+                    }
+                }
+            },
+        };
         Reader {
-            file: file.clone(),
-            self_encryptor: self_encryption::SelfEncryptor::new(storage.clone(), file.get_datamap()),
-            client: client
+            file: file,
+            content: content,
+            client: client,
+            position: 0,
         }
     }
 
     pub fn size(&self) -> u64 {
-        self.self_encryptor.len()
+        match self.content {
+            Content::Inline(ref data) => data.len() as u64,
+            Content::Encrypted(ref self_encryptor) => self_encryptor.len(),
+            Content::Compressed(ref compressed) => compressed.index.size(),
+            Content::Unreadable(_) => self.file.get_metadata().get_size(),
+        }
     }
 
     pub fn read(&mut self,  position: u64, length: u64) -> Result<Vec<u8>, &str> {
-        if position > self.size() || length > self.size() {
+        if position > self.size() || length > self.size() - position {
             return Err("Invalid range specified");
         }
-        Ok(self.self_encryptor.read(position, length))
+        match self.read_range(position, length) {
+            Ok(data) => Ok(data),
+            Err(_) => Err("Failed to read content"),
+        }
+    }
+
+    fn read_range(&mut self, position: u64, length: u64) -> Result<Vec<u8>, String> {
+        match self.content {
+            Content::Inline(ref data) => Ok(data[position as usize..(position + length) as usize].to_vec()),
+            Content::Encrypted(ref mut self_encryptor) => Ok(self_encryptor.read(position, length)),
+            Content::Compressed(ref mut compressed) => compressed.read(position, length),
+            Content::Unreadable(ref error) => Err(error.clone()),
+        }
+    }
+
+}
+
+/// Sequential reads from the current position. Only the chunks covering the requested span are fetched,
+/// so wrap the Reader in a `BufReader` with a large capacity rather than issuing many small reads.
+impl ::std::io::Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+        let size = self.size();
+        if self.position >= size {
+            return Ok(0);
+        }
+
+        let length = ::std::cmp::min(buf.len() as u64, size - self.position);
+        let position = self.position;
+        let data = match self.read_range(position, length) {
+            Ok(data) => data,
+            Err(error) => return Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData, error)),
+        };
+        for (destination, source) in buf.iter_mut().zip(data.iter()) {
+            *destination = *source;
+        }
+        self.position += data.len() as u64;
+        Ok(data.len())
     }
+}
 
+impl ::std::io::Seek for Reader {
+    fn seek(&mut self, position: ::std::io::SeekFrom) -> ::std::io::Result<u64> {
+        let new_position = match position {
+            ::std::io::SeekFrom::Start(offset) => offset as i64,
+            ::std::io::SeekFrom::End(offset) => self.size() as i64 + offset,
+            ::std::io::SeekFrom::Current(offset) => self.position as i64 + offset,
+        };
+        if new_position < 0 {
+            return Err(::std::io::Error::new(::std::io::ErrorKind::InvalidInput, "Seek before the start of the file"));
+        }
+        self.position = new_position as u64;
+        Ok(self.position)
+    }
 }
@@ -21,59 +21,179 @@ use super::network_storage::NetworkStorage;
 use self_encryption;
 use client;
 
+/// Content up to this many bytes is stored inline in the DirectoryListing by default
+pub const DEFAULT_INLINE_THRESHOLD: u64 = 4096;
+
+/// Content being written: buffered while small enough to be stored inline, held uncompressed
+/// until close when it is to be compressed, and self-encrypted directly otherwise
+enum Content {
+    Inline(Vec<u8>),
+    Buffered(Vec<u8>),
+    Encrypted(self_encryption::SelfEncryptor<NetworkStorage>),
+}
+
 #[allow(dead_code)]
 pub struct Writer {
     file: nfs::file::File,
     directory: nfs::directory_listing::DirectoryListing,
-    self_encryptor: self_encryption::SelfEncryptor<NetworkStorage>,
+    content: Content,
+    inline_threshold: u64,
+    // Shared by every SelfEncryptor of the Writer, so that close can tell whether all their
+    // chunks were stored
+    storage: sync::Arc<NetworkStorage>,
     client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>
 }
 
 #[allow(dead_code)]
 impl Writer {
 
+    /// Fails if the file is compressed and its content cannot be read back to be added to
     pub fn new(directory: nfs::directory_listing::DirectoryListing, file: nfs::file::File,
-        client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> Writer {
+        client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> Result<Writer, String> {
+        let compression = file.get_metadata().get_compression();
         let storage = sync::Arc::new(NetworkStorage::new(client.clone()));
-        Writer {
-            file: file.clone(),
+        let content = match file.get_content() {
+            nfs::file::FileContent::Inline(data) => Content::Inline(data),
+            nfs::file::FileContent::DataMap(self_encryption::datamap::DataMap::None) => Content::Inline(Vec::new()),
+            nfs::file::FileContent::DataMap(datamap) => {
+                if compression == nfs::compression::Compression::None {
+                    Content::Encrypted(self_encryption::SelfEncryptor::new(storage.clone(), datamap))
+                } else {
+                    Content::Buffered(try!(Writer::read_all(file.clone(), client.clone())))
+                }
+            },
+        };
+        Ok(Writer {
+            file: file,
             directory: directory,
-            self_encryptor: self_encryption::SelfEncryptor::new(storage.clone(), file.get_datamap()),
+            content: content,
+            inline_threshold: DEFAULT_INLINE_THRESHOLD,
+            storage: storage,
             client: client
-        }
+        })
+    }
+
+    /// Sets the size up to which content is kept inline. Content already past it on the next
+    /// write is moved to self-encryption.
+    pub fn set_inline_threshold(&mut self, inline_threshold: u64) {
+        self.inline_threshold = inline_threshold;
+    }
+
+    /// Sets the compression applied to the content once it is self-encrypted. Compressed content
+    /// is held in memory until the Writer is closed.
+    pub fn set_compression(&mut self, compression: nfs::compression::Compression) {
+        let mut metadata = self.file.get_metadata();
+        metadata.set_compression(compression);
+        self.file.set_metadata(metadata);
+
+        let content = ::std::mem::replace(&mut self.content, Content::Inline(Vec::new()));
+        self.content = match (content, compression) {
+            (Content::Encrypted(mut self_encryptor), nfs::compression::Compression::Deflate) => {
+                let size = self_encryptor.len();
+                Content::Buffered(self_encryptor.read(0, size))
+            },
+            (Content::Buffered(buffer), nfs::compression::Compression::None) => Content::Encrypted(Writer::encrypt(&self.storage, &buffer[..])),
+            (content, _) => content,
+        };
     }
 
     pub fn write(&mut self, data: &[u8], position: u64) {
-        // let se = self.self_encryptor.clone().lock().unwrap();
-        self.self_encryptor.write(data, position);
+        let promoted = match self.content {
+            Content::Inline(ref mut buffer) => {
+                let end = ::std::cmp::max(buffer.len() as u64, position + data.len() as u64);
+                if end <= self.inline_threshold {
+                    Writer::write_to_buffer(buffer, data, position);
+                    return;
+                }
+                ::std::mem::replace(buffer, Vec::new())
+            },
+            Content::Buffered(ref mut buffer) => {
+                Writer::write_to_buffer(buffer, data, position);
+                return;
+            },
+            Content::Encrypted(ref mut self_encryptor) => {
+                self_encryptor.write(data, position);
+                return;
+            },
+        };
+
+        let mut buffer = promoted;
+        if self.file.get_metadata().get_compression() == nfs::compression::Compression::None {
+            let mut self_encryptor = Writer::encrypt(&self.storage, &buffer[..]);
+            self_encryptor.write(data, position);
+            self.content = Content::Encrypted(self_encryptor);
+        } else {
+            Writer::write_to_buffer(&mut buffer, data, position);
+            self.content = Content::Buffered(buffer);
+        }
+    }
+
+    /// Stores the content and saves the directory with the file in it. Fails with
+    /// nfs::OUT_OF_SPACE if the account had no space left for the content or the directory.
+    pub fn close(self) -> Result<(), String> {
+        let client = self.client.clone();
+        let (file, mut directory) = try!(self.finish_parts());
+        let _ = directory.upsert_file(file);
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(client.clone());
+        match directory_helper.update(directory) {
+            Ok(()) => Ok(()),
+            Err(ref error) if error == nfs::OUT_OF_SPACE => Err(error.clone()),
+            Err(_) => Err("Failed to save".to_string()),
+        }
     }
 
-    pub fn close(mut self) -> Result<(), String> {
-        let mut directory = self.directory.clone();
-        let ref mut file = self.file;
-        file.set_datamap(self.self_encryptor.close());
+    /// Stores the content and returns the finished File without adding it to the directory,
+    /// for callers which commit several changes to the directory at once
+    pub fn finish(self) -> Result<nfs::file::File, String> {
+        Ok(try!(self.finish_parts()).0)
+    }
+
+    fn finish_parts(self) -> Result<(nfs::file::File, nfs::directory_listing::DirectoryListing), String> {
+        let Writer { mut file, directory, content, storage, .. } = self;
+        let size = match content {
+            Content::Inline(ref buffer) | Content::Buffered(ref buffer) => buffer.len() as u64,
+            Content::Encrypted(ref self_encryptor) => self_encryptor.len(),
+        };
+        let file_content = match content {
+            Content::Inline(buffer) => nfs::file::FileContent::Inline(buffer),
+            Content::Buffered(buffer) => {
+                let compressed = nfs::compression::compress_blocks(file.get_metadata().get_compression(), &buffer[..]);
+                let self_encryptor = Writer::encrypt(&storage, &compressed[..]);
+                nfs::file::FileContent::DataMap(self_encryptor.close())
+            },
+            Content::Encrypted(self_encryptor) => nfs::file::FileContent::DataMap(self_encryptor.close()),
+        };
+        try!(storage.check_stored());
+        file.set_content(file_content);
 
         let mut metadata = file.get_metadata();
         metadata.set_modified_time(::time::now_utc());
+        metadata.set_size(size);
         file.set_metadata(metadata);
 
-        match directory.get_files().iter().find(|file| file.get_name() == file.get_name()) {
-            Some(old_file) => {
-                let pos = directory.get_files().binary_search_by(|p| p.get_name().cmp(&old_file.get_name())).unwrap();
-                let mut files = directory.get_files();
-                files.remove(pos);
-                files.insert(pos, file.clone());
-                directory.set_files(files);
-            },
-            None => {
-                directory.add_file(file.clone());
-            }
+        Ok((file, directory))
+    }
+
+    fn encrypt(storage: &sync::Arc<NetworkStorage>, data: &[u8]) -> self_encryption::SelfEncryptor<NetworkStorage> {
+        let mut self_encryptor = self_encryption::SelfEncryptor::new(storage.clone(), self_encryption::datamap::DataMap::None);
+        self_encryptor.write(data, 0);
+        self_encryptor
+    }
+
+    fn write_to_buffer(buffer: &mut Vec<u8>, data: &[u8], position: u64) {
+        let end = position as usize + data.len();
+        if buffer.len() < end {
+            buffer.resize(end, 0u8);
         }
-        let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
-        if directory_helper.update(directory).is_err() {
-            return Err("Failed to save".to_string());
+        for (destination, source) in buffer[position as usize..].iter_mut().zip(data.iter()) {
+            *destination = *source;
         }
-        Ok(())
+    }
+
+    fn read_all(file: nfs::file::File, client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> Result<Vec<u8>, String> {
+        let mut reader = nfs::io::Reader::new(file, client);
+        let size = reader.size();
+        reader.read(0, size).map_err(|error| error.to_string())
     }
 
 }
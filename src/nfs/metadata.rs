@@ -16,6 +16,7 @@
 // relating to use of the SAFE Network Software.
 
 use time;
+use nfs::compression::Compression;
 use cbor::CborTagEncode;
 use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
 use std::fmt;
@@ -27,7 +28,8 @@ pub struct Metadata {
     size: u64,
     created_time:  time::Tm,
     modified_time: time::Tm,
-    user_metadata: Vec<u8>
+    user_metadata: Vec<u8>,
+    compression: Compression
 }
 
 #[allow(dead_code)]
@@ -38,7 +40,8 @@ impl Metadata {
             size: 0,
             created_time:  time::now_utc(),
             modified_time: time::now_utc(),
-            user_metadata: user_metadata
+            user_metadata: user_metadata,
+            compression: Compression::None
         }
     }
 
@@ -86,6 +89,15 @@ impl Metadata {
         self.size = size;
     }
 
+    /// Compression applied to the content before self-encryption
+    pub fn get_compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
 }
 
 impl Encodable for Metadata {
@@ -93,7 +105,7 @@ impl Encodable for Metadata {
         let created_time = self.created_time.to_timespec();
         let modified_time = self.modified_time.to_timespec();
         CborTagEncode::new(5483_000, &(self.name.clone(), self.size as usize, self.user_metadata.clone(),
-        created_time.sec, created_time.nsec, modified_time.sec, modified_time.nsec)).encode(e)
+        created_time.sec, created_time.nsec, modified_time.sec, modified_time.nsec, self.compression)).encode(e)
     }
 }
 
@@ -101,11 +113,12 @@ impl Decodable for Metadata {
     fn decode<D: Decoder>(d: &mut D)->Result<Metadata, D::Error> {
         try!(d.read_u64());
         let (name, size, meta, created_sec, created_nsec, modified_sec,
-            modified_nsec) = try!(Decodable::decode(d));
+            modified_nsec, compression) = try!(Decodable::decode(d));
 
         Ok(Metadata {
                 name: name,
                 user_metadata: meta,
+                compression: compression,
                 size: size,
                 created_time:  time::at_utc(time::Timespec {
                         sec: created_sec,
@@ -148,4 +161,18 @@ mod test {
 
         assert_eq!(obj_before, obj_after);
     }
+
+    #[test]
+    fn serialise_compression() {
+        let mut obj_before = Metadata::new("hello.txt".to_string(), Vec::new());
+        obj_before.set_compression(::nfs::compression::Compression::Deflate);
+
+        let mut e = cbor::Encoder::from_memory();
+        e.encode(&[&obj_before]).unwrap();
+
+        let mut d = cbor::Decoder::from_bytes(e.as_bytes());
+        let obj_after: Metadata = d.decode().next().unwrap().unwrap();
+
+        assert_eq!(obj_after.get_compression(), ::nfs::compression::Compression::Deflate);
+    }
 }
@@ -24,4 +24,18 @@ mod directory_info;
 mod directory_listing;
 mod utils;
 
+pub mod archive;
+pub mod compression;
+pub mod fsck;
 pub mod rest;
+pub mod retention;
+pub mod snapshot;
+pub mod query;
+pub mod sync;
+
+/// Error given by operations which fail because the account has used up its storage quota
+pub const OUT_OF_SPACE: &'static str = "Out of space: the account has used up its storage quota";
+
+/// Error given by updates of a directory which other clients keep changing, so that its changes
+/// could not be reapplied onto the latest version in a few attempts
+pub const STALE_UPDATE: &'static str = "Stale update: the directory kept changing while it was updated";
@@ -0,0 +1,318 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use time;
+use nfs::metadata::Metadata;
+use rustc_serialize::hex::{FromHex, ToHex};
+
+/// Field listings are ordered by. Ties are broken by name.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortBy {
+    Name,
+    Size,
+    CreatedTime,
+    ModifiedTime,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// Condition an entry has to satisfy to be listed
+#[derive(Clone, PartialEq, Debug)]
+pub enum Filter {
+    /// Name starts with the given text
+    NamePrefix(String),
+    /// Name matches a glob pattern where `*` matches any run of characters and `?` any single one
+    NameGlob(String),
+    /// Modified strictly after the given time
+    ModifiedAfter(time::Tm),
+    /// Size lies within the inclusive range
+    SizeRange(u64, u64),
+    /// User metadata contains the given bytes
+    UserMetadataContains(Vec<u8>),
+}
+
+/// One page of a listing
+#[derive(Clone, PartialEq, Debug)]
+pub struct Page<T> {
+    /// Entries of this page
+    pub items: Vec<T>,
+    /// Number of entries satisfying the filters, over all pages
+    pub total: usize,
+    /// Cursor to pass to `Query::set_cursor` for the following page, None on the last page
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub fn map<U, F>(self, f: F) -> Page<U> where F: FnMut(T) -> U {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+/// Selects, orders and pages the entries of a listing. The default Query lists everything by name.
+///
+/// Pages can be walked either by offset and limit, or by passing the cursor of the previous page.
+/// A cursor remembers the position rather than the index, so entries added or removed in the
+/// meantime do not cause entries to be skipped or repeated.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Query {
+    offset: usize,
+    limit: Option<usize>,
+    cursor: Option<String>,
+    sort_by: SortBy,
+    sort_order: SortOrder,
+    filters: Vec<Filter>,
+}
+
+type SortKey = (u64, u32, String);
+
+impl Query {
+    pub fn new() -> Query {
+        Query {
+            offset: 0,
+            limit: None,
+            cursor: None,
+            sort_by: SortBy::Name,
+            sort_order: SortOrder::Ascending,
+            filters: Vec::new(),
+        }
+    }
+
+    pub fn set_offset(&mut self, offset: usize) {
+        self.offset = offset;
+    }
+
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    pub fn set_cursor(&mut self, cursor: Option<String>) {
+        self.cursor = cursor;
+    }
+
+    pub fn set_sort(&mut self, sort_by: SortBy, sort_order: SortOrder) {
+        self.sort_by = sort_by;
+        self.sort_order = sort_order;
+    }
+
+    pub fn add_filter(&mut self, filter: Filter) {
+        self.filters.push(filter);
+    }
+
+    /// True if the metadata satisfies every filter
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        self.filters.iter().all(|filter| match *filter {
+            Filter::NamePrefix(ref prefix) => metadata.get_name().starts_with(&prefix[..]),
+            Filter::NameGlob(ref pattern) => glob_match(pattern, &metadata.get_name()),
+            Filter::ModifiedAfter(ref tm) => metadata.get_modified_time().to_timespec() > tm.to_timespec(),
+            Filter::SizeRange(min, max) => metadata.get_size() >= min && metadata.get_size() <= max,
+            Filter::UserMetadataContains(ref needle) => match metadata.get_user_metadata() {
+                Some(user_metadata) => needle.is_empty() || user_metadata.windows(needle.len()).any(|window| window == &needle[..]),
+                None => needle.is_empty(),
+            },
+        })
+    }
+
+    /// Filters, sorts and pages the entries. `metadata_of` gives the Metadata of an entry.
+    pub fn apply<T, I, F>(&self, entries: I, metadata_of: F) -> Result<Page<T>, String>
+            where I: Iterator<Item=T>, F: Fn(&T) -> Metadata {
+        let mut keyed: Vec<(SortKey, T)> = entries.filter_map(|entry| {
+            let metadata = metadata_of(&entry);
+            if self.matches(&metadata) {
+                Some((self.sort_key(&metadata), entry))
+            } else {
+                None
+            }
+        }).collect();
+
+        let total = keyed.len();
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        if self.sort_order == SortOrder::Descending {
+            keyed.reverse();
+        }
+
+        let start = match self.cursor {
+            Some(ref cursor) => {
+                let after = try!(Query::decode_cursor(cursor));
+                let position = match self.sort_order {
+                    SortOrder::Ascending => keyed.iter().position(|entry| entry.0 > after),
+                    SortOrder::Descending => keyed.iter().position(|entry| entry.0 < after),
+                };
+                position.unwrap_or(keyed.len())
+            },
+            None => 0,
+        } + self.offset;
+
+        let end = match self.limit {
+            Some(limit) => ::std::cmp::min(start.saturating_add(limit), keyed.len()),
+            None => keyed.len(),
+        };
+        if start >= end {
+            return Ok(Page { items: Vec::new(), total: total, next_cursor: None });
+        }
+
+        let next_cursor = if end < keyed.len() {
+            Some(Query::encode_cursor(&keyed[end - 1].0))
+        } else {
+            None
+        };
+
+        Ok(Page {
+            items: keyed.into_iter().skip(start).take(end - start).map(|entry| entry.1).collect(),
+            total: total,
+            next_cursor: next_cursor,
+        })
+    }
+
+    fn sort_key(&self, metadata: &Metadata) -> SortKey {
+        // Signed seconds are shifted so that they keep their order as unsigned numbers
+        let time_key = |tm: time::Tm| {
+            let timespec = tm.to_timespec();
+            ((timespec.sec as u64) ^ (1u64 << 63), timespec.nsec as u32)
+        };
+        let (primary, secondary) = match self.sort_by {
+            SortBy::Name => (0, 0),
+            SortBy::Size => (metadata.get_size(), 0),
+            SortBy::CreatedTime => time_key(metadata.get_created_time()),
+            SortBy::ModifiedTime => time_key(metadata.get_modified_time()),
+        };
+        (primary, secondary, metadata.get_name())
+    }
+
+    fn encode_cursor(key: &SortKey) -> String {
+        format!("{:x}.{:x}.{}", key.0, key.1, key.2.as_bytes().to_hex())
+    }
+
+    fn decode_cursor(cursor: &String) -> Result<SortKey, String> {
+        let parts: Vec<&str> = cursor.split('.').collect();
+        if parts.len() != 3 {
+            return Err("Invalid cursor".to_string());
+        }
+        let primary = u64::from_str_radix(parts[0], 16);
+        let secondary = u32::from_str_radix(parts[1], 16);
+        let name = parts[2].from_hex().ok().and_then(|bytes| String::from_utf8(bytes).ok());
+        match (primary, secondary, name) {
+            (Ok(primary), Ok(secondary), Some(name)) => Ok((primary, secondary, name)),
+            _ => Err("Invalid cursor".to_string()),
+        }
+    }
+}
+
+/// Matches `name` against a pattern in which `*` stands for any run of characters and `?` for any single one
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else {
+            match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                },
+                None => return false,
+            }
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nfs::metadata::Metadata;
+
+    fn metadata(name: &str, size: u64, user_metadata: &str) -> Metadata {
+        let mut metadata = Metadata::new(name.to_string(), user_metadata.to_string().into_bytes());
+        metadata.set_size(size);
+        metadata
+    }
+
+    fn names(page: &Page<Metadata>) -> Vec<String> {
+        page.items.iter().map(|metadata| metadata.get_name()).collect()
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("*.txt", "notes.txt"));
+        assert!(glob_match("n?tes.*", "notes.txt"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("*.txt", "notes.txt.bak"));
+        assert!(!glob_match("a?", "a"));
+    }
+
+    #[test]
+    fn filter_sort_and_page() {
+        let entries = vec![metadata("d.txt", 40, "tag:red"),
+                           metadata("a.txt", 10, ""),
+                           metadata("c.jpg", 30, "tag:blue"),
+                           metadata("b.txt", 20, "tag:red")];
+
+        let mut query = Query::new();
+        let page = query.apply(entries.clone().into_iter(), |metadata| metadata.clone()).ok().unwrap();
+        assert_eq!(names(&page), vec!["a.txt".to_string(), "b.txt".to_string(), "c.jpg".to_string(), "d.txt".to_string()]);
+        assert_eq!(page.total, 4);
+        assert!(page.next_cursor.is_none());
+
+        query.add_filter(Filter::NameGlob("*.txt".to_string()));
+        query.set_sort(SortBy::Size, SortOrder::Descending);
+        query.set_limit(Some(2));
+        let page = query.apply(entries.clone().into_iter(), |metadata| metadata.clone()).ok().unwrap();
+        assert_eq!(names(&page), vec!["d.txt".to_string(), "b.txt".to_string()]);
+        assert_eq!(page.total, 3);
+
+        query.set_cursor(page.next_cursor.clone());
+        let page = query.apply(entries.clone().into_iter(), |metadata| metadata.clone()).ok().unwrap();
+        assert_eq!(names(&page), vec!["a.txt".to_string()]);
+        assert!(page.next_cursor.is_none());
+
+        let mut query = Query::new();
+        query.add_filter(Filter::UserMetadataContains("red".to_string().into_bytes()));
+        query.add_filter(Filter::SizeRange(15, 100));
+        query.set_offset(1);
+        let page = query.apply(entries.clone().into_iter(), |metadata| metadata.clone()).ok().unwrap();
+        assert_eq!(names(&page), vec!["d.txt".to_string()]);
+        assert_eq!(page.total, 2);
+
+        let mut query = Query::new();
+        query.add_filter(Filter::NamePrefix("c".to_string()));
+        query.add_filter(Filter::ModifiedAfter(::time::at_utc(::time::Timespec::new(0, 0))));
+        let page = query.apply(entries.clone().into_iter(), |metadata| metadata.clone()).ok().unwrap();
+        assert_eq!(names(&page), vec!["c.jpg".to_string()]);
+
+        let mut query = Query::new();
+        query.set_cursor(Some("garbage".to_string()));
+        assert!(query.apply(entries.into_iter(), |metadata| metadata.clone()).is_err());
+    }
+}
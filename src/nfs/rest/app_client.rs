@@ -0,0 +1,207 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+use nfs;
+use routing;
+use client;
+
+/// What an app may do with a well-known container
+#[derive(Clone, Copy, Debug, PartialEq, Eq, RustcEncodable, RustcDecodable)]
+pub enum Access {
+    Read,
+    ReadWrite,
+}
+
+/// Grants of every registered app, by app id. Kept in the user metadata of the registry
+/// directory, which apps cannot reach.
+type Registry = ::std::collections::BTreeMap<String, ::std::collections::BTreeMap<nfs::rest::WellKnown, Access>>;
+
+/// A handle for an app, which only reaches its own app-root container and the well-known
+/// containers it was granted. Containers it opens are sandboxed, so the Client it wraps is
+/// never handed out.
+pub struct AppClient {
+    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+    app_id: String,
+    app_root_id: routing::NameType,
+    registry_id: routing::NameType,
+    grants: ::std::collections::BTreeMap<nfs::rest::WellKnown, Access>,
+}
+
+impl AppClient {
+    /// Registers the app, or replaces the grants of a registered one, creating its app-root
+    /// container on first registration. This takes the unrestricted Client, so it is for the
+    /// user's own tools rather than for apps.
+    pub fn register(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>, app_id: String,
+                    grants: ::std::collections::BTreeMap<nfs::rest::WellKnown, Access>) -> Result<AppClient, String> {
+        if app_id.is_empty() {
+            return Err("App id cannot be empty".to_string());
+        }
+        let (mut registry_listing, mut registry) = try!(AppClient::load_registry(&client));
+        let owner = client.lock().unwrap().get_owner();
+        let app_root_id = AppClient::derive_id(&owner, &format!("apps/{}", app_id));
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(client.clone());
+        if !registry.contains_key(&app_id) {
+            let _ = try!(directory_helper.create_with_id(app_root_id.clone(), registry_listing.get_id(), app_id.clone(), Vec::new()));
+        }
+
+        let _ = registry.insert(app_id.clone(), grants.clone());
+        registry_listing.set_user_metadata(nfs::utils::serialise(registry));
+        try!(directory_helper.update(registry_listing.clone()));
+        Ok(AppClient {
+            client: client,
+            app_id: app_id,
+            app_root_id: app_root_id,
+            registry_id: registry_listing.get_id(),
+            grants: grants,
+        })
+    }
+
+    /// Opens a registered app with the grants recorded for it
+    pub fn open(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>, app_id: String) -> Result<AppClient, String> {
+        let (registry_listing, registry) = try!(AppClient::load_registry(&client));
+        let grants = match registry.get(&app_id) {
+            Some(grants) => grants.clone(),
+            None => return Err(format!("App {} is not registered", app_id)),
+        };
+        let owner = client.lock().unwrap().get_owner();
+        Ok(AppClient {
+            client: client,
+            app_root_id: AppClient::derive_id(&owner, &format!("apps/{}", app_id)),
+            app_id: app_id,
+            registry_id: registry_listing.get_id(),
+            grants: grants,
+        })
+    }
+
+    pub fn get_app_id(&self) -> String {
+        self.app_id.clone()
+    }
+
+    /// Access granted to the well-known container, if any
+    pub fn get_access(&self, well_known: nfs::rest::WellKnown) -> Option<Access> {
+        self.grants.get(&well_known).cloned()
+    }
+
+    /// The private container of the app
+    pub fn app_root(&self) -> Result<nfs::rest::Container, String> {
+        let container = try!(nfs::rest::Container::authorise(self.client.clone(), self.app_root_id.0, self.registry_id.0));
+        Ok(container.into_sandboxed(false))
+    }
+
+    /// A well-known container the app was granted, read-only unless granted read-write
+    pub fn well_known(&self, well_known: nfs::rest::WellKnown) -> Result<nfs::rest::Container, String> {
+        let access = match self.get_access(well_known) {
+            Some(access) => access,
+            None => return Err(format!("{} has no access to {}", self.app_id, well_known.get_name())),
+        };
+        let container = try!(nfs::rest::Container::well_known(self.client.clone(), well_known));
+        Ok(container.into_sandboxed(access == Access::Read))
+    }
+
+    /// Container::authorise for apps: only the app-root container and the granted well-known
+    /// containers can be opened directly, everything else is reached by walking down from them
+    pub fn authorise(&self, dir_id: [u8; 64], parent_dir_id: [u8; 64]) -> Result<nfs::rest::Container, String> {
+        if dir_id == self.app_root_id.0 && parent_dir_id == self.registry_id.0 {
+            return self.app_root();
+        }
+        for well_known in self.grants.keys() {
+            let granted = self.client.lock().unwrap().get_well_known_directory_id(well_known.get_name());
+            if granted.map(|id| id.0.to_vec()) == Some(dir_id.to_vec()) {
+                return self.well_known(*well_known);
+            }
+        }
+        Err("Access denied".to_string())
+    }
+
+    fn load_registry(client: &::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> Result<(nfs::directory_listing::DirectoryListing, Registry), String> {
+        let owner = client.lock().unwrap().get_owner();
+        let registry_id = AppClient::derive_id(&owner, "apps");
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(client.clone());
+        let registry_listing = match try!(directory_helper.find(registry_id.clone(), owner.clone())) {
+            Some(registry_listing) => registry_listing,
+            None => {
+                let _ = try!(directory_helper.create_with_id(registry_id.clone(), owner.clone(), "Apps".to_string(), nfs::utils::serialise(Registry::new())));
+                try!(directory_helper.get(registry_id, owner))
+            },
+        };
+        let registry = match registry_listing.get_user_metadata() {
+            Some(data) => try!(nfs::utils::try_deserialise(data)),
+            None => return Err("App registry missing".to_string()),
+        };
+        Ok((registry_listing, registry))
+    }
+
+    fn derive_id(owner: &routing::NameType, purpose: &str) -> routing::NameType {
+        let mut seed = owner.0.to_vec();
+        seed.extend(purpose.as_bytes().iter().cloned());
+        routing::NameType(::sodiumoxide::crypto::hash::sha512::hash(&seed[..]).0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nfs::rest::WellKnown;
+
+    fn get_dummy_client() -> ::client::Client {
+        let keyword = "Spandan".to_string();
+        let password = "Sharma".as_bytes();
+        let pin = 1234u32;
+
+        ::client::Client::create_account(&keyword,
+                                         pin,
+                                         &password,
+                                         ::client::non_networking_test_framework::get_new_data_store()).ok().unwrap()
+    }
+
+    #[test]
+    fn grants_are_enforced() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        assert!(::nfs::rest::provision_well_known(client.clone()).is_ok());
+        let mut grants = ::std::collections::BTreeMap::new();
+        let _ = grants.insert(WellKnown::Documents, Access::ReadWrite);
+        let _ = grants.insert(WellKnown::Pictures, Access::Read);
+        let app = AppClient::register(client.clone(), "editor".to_string(), grants).ok().unwrap();
+        assert!(AppClient::register(client.clone(), "".to_string(), ::std::collections::BTreeMap::new()).is_err());
+
+        let mut app_root = app.app_root().ok().unwrap();
+        assert!(app_root.transaction(|tx| tx.create_blob("settings".to_string(), None, b"{}")).is_ok());
+        assert!(app_root.get_client().is_err());
+
+        let mut documents = app.well_known(WellKnown::Documents).ok().unwrap();
+        assert!(documents.create("Drafts".to_string(), None).is_ok());
+        let mut pictures = app.well_known(WellKnown::Pictures).ok().unwrap();
+        assert!(pictures.is_read_only());
+        assert!(pictures.create("Holiday".to_string(), None).is_err());
+        assert!(app.well_known(WellKnown::Music).is_err());
+
+        // Containers below a granted one keep its restrictions
+        let drafts = documents.get_container("Drafts".to_string(), None).ok().unwrap();
+        assert!(!drafts.is_read_only());
+        assert!(drafts.get_client().is_err());
+
+        let root = ::nfs::rest::Container::user_root(client.clone()).ok().unwrap();
+        assert!(app.authorise(root.get_id(), client.lock().unwrap().get_owner().0).is_err());
+        assert!(app.authorise(documents.get_id(), root.get_id()).is_ok());
+        assert!(app.authorise(app_root.get_id(), [0u8; 64]).is_err());
+
+        let reopened = AppClient::open(client.clone(), "editor".to_string()).ok().unwrap();
+        assert_eq!(reopened.get_access(WellKnown::Pictures), Some(Access::Read));
+        assert_eq!(reopened.get_access(WellKnown::Music), None);
+        assert_eq!(reopened.app_root().ok().unwrap().get_blobs().len(), 1);
+        assert!(AppClient::open(client, "viewer".to_string()).is_err());
+    }
+}
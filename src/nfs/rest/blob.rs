@@ -15,16 +15,37 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 use nfs;
+use routing;
 use time;
 use client;
 
-#[allow(dead_code)]
+/// How a writer opened on a blob treats the existing content
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Start from empty content
+    Overwrite,
+    /// Start from the current content, writing over it in place
+    Modify,
+}
+
 pub struct Blob {
     client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
     file: nfs::file::File,
+    // (id, parent id) of the container the blob is in, None for blobs which cannot be changed
+    container: Option<(routing::NameType, routing::NameType)>,
 }
 
 impl Blob {
+    /// A Blob which changes are saved back into the container it was listed from
+    pub fn from_container(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+                          file: nfs::file::File,
+                          directory: &nfs::directory_listing::DirectoryListing) -> Blob {
+        Blob {
+            client: client,
+            file: file,
+            container: Some((directory.get_id(), directory.get_parent_dir_id())),
+        }
+    }
 
     pub fn get_name(&self) -> String {
         self.file.get_metadata().get_name()
@@ -46,6 +67,68 @@ impl Blob {
         self.file.get_metadata().get_size()
     }
 
+    /// Strong validator for the content, derived from the stored content and the metadata of the blob
+    pub fn get_etag(&self) -> String {
+        use crypto::digest::Digest;
+
+        let metadata = self.file.get_metadata();
+        let modified_time = metadata.get_modified_time().to_timespec();
+        let mut hasher = ::crypto::sha2::Sha256::new();
+        hasher.input(&nfs::utils::serialise(self.file.get_content())[..]);
+        hasher.input_str(&format!("{}:{}:{}.{}", metadata.get_name(), metadata.get_size(), modified_time.sec, modified_time.nsec));
+        format!("\"{}\"", hasher.result_str())
+    }
+
+    /// Returns a Reader over the content. The Reader implements `Read` and `Seek`, so any span
+    /// can be streamed without loading the whole blob into memory.
+    pub fn reader(&self) -> nfs::io::Reader {
+        nfs::io::Reader::new(self.file.clone(), self.client.clone())
+    }
+
+    /// Sets the user metadata, saving a new version of the container
+    pub fn update_metadata(&mut self, metadata: Option<String>) -> Result<(), String> {
+        let user_metadata = try!(nfs::rest::validate_metadata(metadata));
+        let mut directory = try!(self.get_container());
+        let file = try!(self.get_current_file(&directory));
+        let mut file_helper = nfs::helper::FileHelper::new(self.client.clone());
+        try!(file_helper.update_metadata(file, &mut directory, user_metadata));
+        self.file = try!(self.get_current_file(&directory));
+        Ok(())
+    }
+
+    /// Opens a Writer over the content. Closing the Writer saves a new version of the container;
+    /// this Blob keeps describing the content as it was.
+    pub fn open_writer(&self, mode: WriteMode) -> Result<nfs::io::Writer, String> {
+        let directory = try!(self.get_container());
+        let mut file = try!(self.get_current_file(&directory));
+        if mode == WriteMode::Overwrite {
+            let mut metadata = file.get_metadata();
+            metadata.set_size(0);
+            file.set_metadata(metadata);
+            file.set_content(nfs::file::FileContent::Inline(Vec::new()));
+        }
+        let mut file_helper = nfs::helper::FileHelper::new(self.client.clone());
+        file_helper.update(file, directory)
+    }
+
+    /// The latest version of the container the blob is in
+    fn get_container(&self) -> Result<nfs::directory_listing::DirectoryListing, String> {
+        let (directory_id, parent_id) = match self.container {
+            Some(ref ids) => ids.clone(),
+            None => return Err("Blob is read-only".to_string()),
+        };
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
+        directory_helper.get(directory_id, parent_id)
+    }
+
+    /// Changes apply to the blob as it is now in the container, which may have moved on since
+    /// this Blob was listed
+    fn get_current_file(&self, directory: &nfs::directory_listing::DirectoryListing) -> Result<nfs::file::File, String> {
+        match directory.find_file(&self.get_name()) {
+            Some(file) => Ok(file.clone()),
+            None => Err("File not found".to_string()),
+        }
+    }
 }
 
 impl nfs::traits::FileWrapper for Blob {
@@ -58,7 +141,58 @@ impl nfs::traits::FileWrapper for Blob {
         file: nfs::file::File) -> Blob {
         Blob {
             client: client,
-            file: file
+            file: file,
+            container: None
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_dummy_client() -> ::client::Client {
+        let keyword = "Spandan".to_string();
+        let password = "Sharma".as_bytes();
+        let pin = 1234u32;
+
+        ::client::Client::create_account(&keyword,
+                                         pin,
+                                         &password,
+                                         ::client::non_networking_test_framework::get_new_data_store()).ok().unwrap()
+    }
+
+    #[test]
+    fn update_metadata_and_content() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let root_id = ::nfs::helper::DirectoryHelper::new(client.clone()).create(parent_id.clone(), "Root".to_string(), Vec::new()).ok().unwrap();
+        let mut container = ::nfs::rest::Container::authorise(client.clone(), root_id.0, parent_id.0).ok().unwrap();
+        assert!(container.transaction(|tx| tx.create_blob("a.txt".to_string(), None, b"hello world")).is_ok());
+
+        let mut blob = container.get_blob("a.txt".to_string(), None).ok().unwrap();
+        assert!(blob.update_metadata(Some("".to_string())).is_err());
+        assert!(blob.update_metadata(Some("greeting".to_string())).is_ok());
+        assert_eq!(blob.get_user_metadata(), Some("greeting".to_string().into_bytes()));
+
+        let mut writer = blob.open_writer(WriteMode::Modify).ok().unwrap();
+        writer.write(b"HELLO", 0);
+        assert!(writer.close().is_ok());
+
+        let container = ::nfs::rest::Container::authorise(client.clone(), root_id.0, parent_id.0).ok().unwrap();
+        let blob = container.get_blob("a.txt".to_string(), None).ok().unwrap();
+        assert_eq!(blob.get_user_metadata(), Some("greeting".to_string().into_bytes()));
+        assert_eq!(blob.reader().read(0, blob.get_size()).ok().unwrap(), b"HELLO world".to_vec());
+
+        let mut writer = blob.open_writer(WriteMode::Overwrite).ok().unwrap();
+        writer.write(b"bye", 0);
+        assert!(writer.close().is_ok());
+        let container = ::nfs::rest::Container::authorise(client.clone(), root_id.0, parent_id.0).ok().unwrap();
+        let blob = container.get_blob("a.txt".to_string(), None).ok().unwrap();
+        assert_eq!(blob.reader().read(0, blob.get_size()).ok().unwrap(), b"bye".to_vec());
+
+        // Blobs not listed from a container have nowhere to save to
+        let detached = <Blob as ::nfs::traits::FileWrapper>::convert_from_file(client, ::nfs::traits::FileWrapper::convert_to_file(&blob));
+        assert!(detached.open_writer(WriteMode::Modify).is_err());
+    }
+}
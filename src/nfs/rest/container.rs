@@ -24,7 +24,12 @@ use nfs::traits::DirectoryInfoWrapper;
 
 pub struct Container {
     client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
-    directory_listing: nfs::directory_listing::DirectoryListing
+    directory_listing: nfs::directory_listing::DirectoryListing,
+    // Version to open for each directory id, set when browsing a snapshot
+    pinned_versions: Option<::std::sync::Arc<::std::collections::BTreeMap<routing::NameType, routing::NameType>>>,
+    read_only: bool,
+    // Confined to its subtree: the Client is not handed out
+    sandboxed: bool
 }
 
 impl Container {
@@ -38,18 +43,92 @@ impl Container {
         }
         Ok(Container {
             client: client,
-            directory_listing: result.unwrap()
+            directory_listing: result.unwrap(),
+            pinned_versions: None,
+            read_only: false,
+            sandboxed: false
         })
     }
 
+    /// Opens the root directory of the user, creating it and recording it in the session packet
+    /// on first use
+    pub fn user_root(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> Result<Container, String> {
+        let owner = client.lock().unwrap().get_owner();
+        let existing = client.lock().unwrap().get_user_root_directory_id();
+        let root_id = match existing {
+            Some(root_id) => root_id,
+            None => {
+                let mut directory_helper = nfs::helper::DirectoryHelper::new(client.clone());
+                let root_id = try!(directory_helper.create(owner.clone(), "Root".to_string(), Vec::new()));
+                let result = client.lock().unwrap().set_user_root_directory_id(root_id.clone());
+                try!(result.map_err(|err| err.to_string()));
+                root_id
+            },
+        };
+        Container::authorise(client, root_id.0, owner.0)
+    }
+
+    /// Opens one of the well-known containers of the account, provisioning them first with
+    /// nfs::rest::provision_well_known if that was not done on logging in
+    pub fn well_known(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>, well_known: nfs::rest::WellKnown) -> Result<Container, String> {
+        let provisioned = client.lock().unwrap().get_well_known_directory_id(well_known.get_name()).is_some();
+        if !provisioned {
+            try!(nfs::rest::provision_well_known(client.clone()));
+        }
+        let (dir_id, root_id) = {
+            let client = client.lock().unwrap();
+            match (client.get_well_known_directory_id(well_known.get_name()), client.get_user_root_directory_id()) {
+                (Some(dir_id), Some(root_id)) => (dir_id, root_id),
+                _ => return Err(format!("{} is not provisioned", well_known.get_name())),
+            }
+        };
+        Container::authorise(client, dir_id.0, root_id.0)
+    }
+
+    /// A read-only Container in which every container, this one included, is opened at the
+    /// version recorded for its id. Used to browse snapshots.
+    pub fn pinned(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+                  directory_listing: nfs::directory_listing::DirectoryListing,
+                  pinned_versions: ::std::sync::Arc<::std::collections::BTreeMap<routing::NameType, routing::NameType>>) -> Container {
+        Container {
+            client: client,
+            directory_listing: directory_listing,
+            pinned_versions: Some(pinned_versions),
+            read_only: false,
+            sandboxed: false
+        }
+    }
+
+    /// Restricts the Container, and every container opened through it, to its own subtree,
+    /// optionally read-only. Used to hand containers to apps.
+    pub fn into_sandboxed(mut self, read_only: bool) -> Container {
+        self.read_only = self.read_only || read_only;
+        self.sandboxed = true;
+        self
+    }
+
+    /// Whether the Container is a read-only view, such as a snapshot
+    pub fn is_read_only(&self) -> bool {
+        self.read_only || self.pinned_versions.is_some()
+    }
+
+    /// The Client the Container works through. Sandboxed containers do not give it out.
+    pub fn get_client(&self) -> Result<::std::sync::Arc<::std::sync::Mutex<client::Client>>, String> {
+        if self.sandboxed {
+            return Err("Container is sandboxed".to_string());
+        }
+        Ok(self.client.clone())
+    }
+
     pub fn get_id(&self) -> [u8;64] {
         self.directory_listing.get_id().0
     }
 
+    /// The user metadata as text, with any bytes which are not UTF-8 replaced
     pub fn get_metadata(&self) -> Option<String> {
         let metadata = self.directory_listing.get_metadata().get_user_metadata();
         match metadata {
-            Some(data) => Some(String::from_utf8(data).unwrap()),
+            Some(data) => Some(String::from_utf8_lossy(&data[..]).into_owned()),
             None => None
         }
     }
@@ -67,7 +146,13 @@ impl Container {
     }
 
     pub fn get_blobs(&self) -> Vec<nfs::rest::Blob> {
-        self.directory_listing.get_files().iter().map(|x| nfs::rest::Blob::convert_from_file(self.client.clone(), x.clone())).collect()
+        self.directory_listing.get_files().map(|x| self.to_blob(x.clone())).collect()
+    }
+
+    /// Lists the blobs satisfying the query, sorted and paged as it asks
+    pub fn query_blobs(&self, query: &nfs::query::Query) -> Result<nfs::query::Page<nfs::rest::Blob>, String> {
+        let page = try!(self.directory_listing.query_files(query));
+        Ok(page.map(|file| self.to_blob(file)))
     }
 
     pub fn get_blob(&self, name: String, version: Option<[u8;64]>) -> Result<nfs::rest::Blob, String> {
@@ -84,17 +169,23 @@ impl Container {
         } else {
             directory_listing = self.directory_listing.clone();
         }
-        match directory_listing.get_files().iter().find(|file| file.get_name() == name) {
-            Some(file) => Ok(nfs::rest::Blob::convert_from_file(self.client.clone(), file.clone())),
+        match directory_listing.find_file(&name) {
+            Some(file) => Ok(self.to_blob(file.clone())),
             None => Err("File not found".to_string())
         }
     }
     
 
+    /// Creates a container inside this one. Fails if one of that name already exists, rather than
+    /// replacing it and losing what it holds.
     pub fn create(&mut self, name: String, metadata: Option<String>) -> Result<(), String> {
-        match self.validate_metadata(metadata) {
+        try!(self.check_writable());
+        if self.has_container(&name) {
+            return Err(format!("Container {} already exists", name));
+        }
+        match nfs::rest::validate_metadata(metadata) {
             Ok(user_metadata) => {
-                let parent_dir_id = self.directory_listing.get_parent_dir_id();
+                let parent_dir_id = self.directory_listing.get_id();
                 let mut dir_id;
 
                 // Create directory
@@ -120,16 +211,14 @@ impl Container {
                         return Err(result.unwrap_err());
                     }
 
-                    let mut sub_dirs = self.directory_listing.get_sub_directories();
                     created_directory = result.unwrap();
-                    sub_dirs.push(created_directory.get_info());
-                    self.directory_listing.set_sub_directories(sub_dirs);
+                    let _ = self.directory_listing.upsert_sub_directory(created_directory.get_info());
                 }
 
                 // Update the Container
                 {
                     let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
-                    let result = directory_helper.update(created_directory);
+                    let result = directory_helper.update_listing(&mut self.directory_listing);
                     if result.is_err() {
                         return Err("Failed to create Conatiner".to_string());
                     }
@@ -141,18 +230,33 @@ impl Container {
         }
     }
 
+    pub fn has_container(&self, name: &str) -> bool {
+        self.directory_listing.find_sub_directory(name).is_some()
+    }
+
+    pub fn has_blob(&self, name: &str) -> bool {
+        self.directory_listing.find_file(name).is_some()
+    }
+
     pub fn get_containers(&self) -> Vec<nfs::rest::ContainerInfo> {
-        self.directory_listing.get_sub_directories().iter().map(|info| {
+        self.directory_listing.get_sub_directories().map(|info| {
                 nfs::rest::ContainerInfo::convert_from_directory_info(info.clone())
             }).collect()
     }
 
+    /// Lists the containers satisfying the query, sorted and paged as it asks
+    pub fn query_containers(&self, query: &nfs::query::Query) -> Result<nfs::query::Page<nfs::rest::ContainerInfo>, String> {
+        let page = try!(self.directory_listing.query_sub_directories(query));
+        Ok(page.map(|info| nfs::rest::ContainerInfo::convert_from_directory_info(info)))
+    }
+
     pub fn update_metadata(&mut self, metadata: Option<String>) -> Result<(), String>{
-        match self.validate_metadata(metadata) {
+        try!(self.check_writable());
+        match nfs::rest::validate_metadata(metadata) {
             Ok(user_metadata) => {
                 self.directory_listing.set_user_metadata(user_metadata);
                 let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
-                match directory_helper.update(self.directory_listing.clone()) {
+                match directory_helper.update_listing(&mut self.directory_listing) {
                     Ok(_) => Ok(()),
                     Err(msg) => Err(msg),
                 }
@@ -162,14 +266,22 @@ impl Container {
     }
 
     pub fn get_container(&mut self, name: String, version: Option<[u8; 64]>) -> Result<Container, String> {
-        let sub_dirs = self.directory_listing.get_sub_directories();
-        let dir_info = sub_dirs.iter().find(|&entry| entry.get_name() == name);
-        if dir_info.is_none() {
-            return Err("Container not found".to_string());
-        }
-        let dir_id = dir_info.unwrap().get_id();
+        let dir_id = match self.directory_listing.find_sub_directory(&name) {
+            Some(dir_info) => dir_info.get_id(),
+            None => return Err("Container not found".to_string()),
+        };
         let parent_id = self.directory_listing.get_id();
 
+        let mut version = version;
+        if let Some(ref pinned_versions) = self.pinned_versions {
+            if version.is_none() {
+                match pinned_versions.get(&dir_id) {
+                    Some(pinned_version) => version = Some(pinned_version.0),
+                    None => return Err("Container not found in snapshot".to_string()),
+                }
+            }
+        }
+
         let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
         let mut result;
         if version.is_some() {
@@ -181,7 +293,13 @@ impl Container {
         if result.is_err() {
             return Err(result.unwrap_err());
         }
-        Ok(Container::convert_from_directory_listing(self.client.clone(), result.unwrap()))
+        let mut container = match self.pinned_versions {
+            Some(ref pinned_versions) => Container::pinned(self.client.clone(), result.unwrap(), pinned_versions.clone()),
+            None => Container::convert_from_directory_listing(self.client.clone(), result.unwrap()),
+        };
+        container.read_only = self.read_only;
+        container.sandboxed = self.sandboxed;
+        Ok(container)
     }
 
     pub fn get_versions(&mut self) -> Result<Vec<[u8;64]>, String> {
@@ -194,23 +312,57 @@ impl Container {
         }
     }
 
+    /// Versions of the container in which the blob was modified, oldest first. Pass one to
+    /// get_blob to read the blob as it was then.
+    pub fn get_blob_versions(&self, name: String) -> Result<Vec<[u8;64]>, String> {
+        let file = match self.directory_listing.find_file(&name) {
+            Some(file) => file.clone(),
+            None => return Err("File not found".to_string()),
+        };
+        let mut file_helper = nfs::helper::FileHelper::new(self.client.clone());
+        let versions = try!(file_helper.get_versions(self.directory_listing.get_id(), self.directory_listing.get_parent_dir_id(), file));
+        Ok(versions.iter().map(|v| v.0).collect())
+    }
+
+    pub fn get_retention_policy(&self) -> Option<nfs::retention::RetentionPolicy> {
+        self.directory_listing.get_retention_policy()
+    }
+
+    /// Sets which versions of the container prune_versions keeps
+    pub fn set_retention_policy(&mut self, retention_policy: Option<nfs::retention::RetentionPolicy>) -> Result<(), String> {
+        try!(self.check_writable());
+        self.directory_listing.set_retention_policy(retention_policy);
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
+        directory_helper.update_listing(&mut self.directory_listing)
+    }
+
+    /// Drops the versions of the container which its retention policy does not keep
+    pub fn prune_versions(&mut self) -> Result<nfs::retention::PruneReport, String> {
+        try!(self.check_writable());
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
+        directory_helper.prune_versions(self.directory_listing.get_id(), self.directory_listing.get_parent_dir_id())
+    }
+
+    /// Moves the named container to the trash of the account
     pub fn delete_container(&mut self, name: String) -> Result<(), String> {
-        let mut sub_dirs = self.directory_listing.get_sub_directories();
-        let find_result = sub_dirs.binary_search_by(|info| info.get_name().cmp(&name));
-        if find_result.is_err() {
-            return Err("Container not found".to_string());
-        }
-        sub_dirs.remove(find_result.unwrap());
-        self.directory_listing.set_sub_directories(sub_dirs);
+        try!(self.check_writable());
+        let info = match self.directory_listing.find_sub_directory(&name) {
+            Some(info) => info.clone(),
+            None => return Err("Container not found".to_string()),
+        };
+        let mut trash = try!(nfs::rest::Trash::open(self.client.clone()));
+        try!(trash.trash_container(&self.directory_listing, info));
+        let _ = self.directory_listing.remove_sub_directory(&name);
         let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
-        match directory_helper.update(self.directory_listing.clone()) {
+        match directory_helper.update_listing(&mut self.directory_listing) {
             Ok(_) => Ok(()),
             Err(msg) => Err(msg)
         }
     }
 
     pub fn create_blob(&mut self, name: String, metadata: Option<String>, size: u64) -> Result<nfs::io::Writer, String> {
-        match self.validate_metadata(metadata) {
+        try!(self.check_writable());
+        match nfs::rest::validate_metadata(metadata) {
             Ok(user_metadata) => {
                 let mut file_helper = nfs::helper::FileHelper::new(self.client.clone());
                 file_helper.create(name, size, user_metadata, self.directory_listing.clone())
@@ -219,33 +371,58 @@ impl Container {
         }
     }
 
+    /// Moves the named blob to the trash of the account
     pub fn delete_blob(&mut self, name: String) -> Result<(), String> {
-        let mut sub_dirs = self.directory_listing.get_sub_directories();
-        match sub_dirs.iter().position(|file| file.get_name() == name) {
-            Some(pos) => {
-                sub_dirs.remove(pos);
-                self.directory_listing.set_sub_directories(sub_dirs);
-                let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
-                match directory_helper.update(self.directory_listing.clone()) {
-                    Ok(_) => Ok(()),
-                    Err(msg) => Err(msg)
-                }
-            },
-            None => Err("File not found".to_string())
+        try!(self.check_writable());
+        let file = match self.directory_listing.find_file(&name) {
+            Some(file) => file.clone(),
+            None => return Err("File not found".to_string()),
+        };
+        let mut trash = try!(nfs::rest::Trash::open(self.client.clone()));
+        try!(trash.trash_blob(&self.directory_listing, file));
+        let _ = self.directory_listing.remove_file(&name);
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
+        match directory_helper.update_listing(&mut self.directory_listing) {
+            Ok(_) => Ok(()),
+            Err(msg) => Err(msg)
         }
     }
 
-    fn validate_metadata(&self, metadata: Option<String>) -> Result<Vec<u8>, String> {
-        match metadata {
-            Some(data) => {
-                if data.len() == 0 {
-                    Err("Metadata cannot be empty".to_string())
-                } else {
-                    Ok(data.into_bytes())
-                }
-            },
-            None => Ok(Vec::new()),
+    /// Writes the tree below the container to the output as a tar archive
+    pub fn export_archive<W: ::std::io::Write>(&self, output: &mut W) -> Result<(), String> {
+        nfs::archive::export(self.client.clone(), &self.directory_listing, output)
+    }
+
+    /// Reads a tar archive into the container. Returns the number of blobs imported.
+    pub fn import_archive<R: ::std::io::Read>(&mut self, input: &mut R) -> Result<usize, String> {
+        try!(self.check_writable());
+        nfs::archive::import(self.client.clone(), &mut self.directory_listing, input)
+    }
+
+    /// Runs the operations against a Transaction over this container and commits their changes
+    /// as a single version. If an operation or the commit fails the container is left as it was.
+    pub fn transaction<F>(&mut self, operations: F) -> Result<(), String> where F: FnOnce(&mut nfs::rest::Transaction) -> Result<(), String> {
+        try!(self.check_writable());
+        let mut transaction = nfs::rest::Transaction::new(self.client.clone(), self.directory_listing.clone());
+        try!(operations(&mut transaction));
+        self.directory_listing = try!(transaction.commit());
+        Ok(())
+    }
+
+    /// Blobs of a read-only container are read-only too
+    fn to_blob(&self, file: nfs::file::File) -> nfs::rest::Blob {
+        if self.is_read_only() {
+            nfs::rest::Blob::convert_from_file(self.client.clone(), file)
+        } else {
+            nfs::rest::Blob::from_container(self.client.clone(), file, &self.directory_listing)
+        }
+    }
+
+    fn check_writable(&self) -> Result<(), String> {
+        if self.is_read_only() {
+            return Err("Container is read-only".to_string());
         }
+        Ok(())
     }
 }
 
@@ -258,7 +435,10 @@ impl nfs::traits::DirectoryListingWrapper for Container {
     fn convert_from_directory_listing(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>, directory_listing: nfs::directory_listing::DirectoryListing) -> Container {
         Container {
             client: client,
-            directory_listing: directory_listing
+            directory_listing: directory_listing,
+            pinned_versions: None,
+            read_only: false,
+            sandboxed: false
         }
     }
 }
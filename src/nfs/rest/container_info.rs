@@ -27,10 +27,11 @@ impl ContainerInfo {
         self.info.get_metadata().get_name()
     }
 
+    /// The user metadata as text, with any bytes which are not UTF-8 replaced
     pub fn get_metadata(&self) -> Option<String> {
         let metadata = self.info.get_metadata().get_user_metadata();
         match metadata {
-            Some(data) => Some(String::from_utf8(data).unwrap()),
+            Some(data) => Some(String::from_utf8_lossy(&data[..]).into_owned()),
             None => None
         }
     }
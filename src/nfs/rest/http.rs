@@ -0,0 +1,353 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use std::io::{BufRead, Read, Write};
+
+/// Upper limit on the size of a request body accepted by the server
+pub static MAX_BODY_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// HTTP request as parsed off the wire
+#[allow(dead_code)]
+pub struct Request {
+    pub method: String,
+    pub path: Vec<String>,
+    pub query: ::std::collections::BTreeMap<String, String>,
+    pub headers: ::std::collections::BTreeMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+#[allow(dead_code)]
+impl Request {
+    /// Reads a single request. Header names are lower-cased and path segments are percent-decoded.
+    pub fn read<R: Read>(stream: R) -> Result<Request, String> {
+        let mut reader = ::std::io::BufReader::new(stream);
+
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).is_err() {
+            return Err("Failed to read request".to_string());
+        }
+        let parts: Vec<&str> = request_line.trim().split(' ').collect();
+        if parts.len() != 3 || !parts[2].starts_with("HTTP/1.") {
+            return Err("Malformed request line".to_string());
+        }
+
+        let (raw_path, raw_query) = match parts[1].find('?') {
+            Some(pos) => (&parts[1][..pos], &parts[1][pos + 1..]),
+            None => (parts[1], ""),
+        };
+
+        let mut path = Vec::new();
+        for segment in raw_path.split('/').filter(|segment| !segment.is_empty()) {
+            path.push(try!(percent_decode(segment)));
+        }
+
+        let mut query = ::std::collections::BTreeMap::new();
+        for pair in raw_query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = match pair.find('=') {
+                Some(pos) => (&pair[..pos], &pair[pos + 1..]),
+                None => (pair, ""),
+            };
+            let _ = query.insert(try!(query_decode(key)), try!(query_decode(value)));
+        }
+
+        let mut headers = ::std::collections::BTreeMap::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).is_err() {
+                return Err("Failed to read request headers".to_string());
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            match line.find(':') {
+                Some(pos) => {
+                    let _ = headers.insert(line[..pos].trim().to_lowercase(), line[pos + 1..].trim().to_string());
+                },
+                None => return Err("Malformed header".to_string()),
+            }
+        }
+
+        let content_length = match headers.get("content-length") {
+            Some(value) => match value.parse::<u64>() {
+                Ok(length) if length <= MAX_BODY_SIZE => length,
+                _ => return Err("Invalid Content-Length".to_string()),
+            },
+            None => 0,
+        };
+
+        // Grown as the body arrives, so that a large Content-Length alone costs nothing
+        let mut body = Vec::new();
+        if reader.take(content_length).read_to_end(&mut body).is_err() || body.len() as u64 != content_length {
+            return Err("Truncated request body".to_string());
+        }
+
+        Ok(Request {
+            method: parts[0].to_string(),
+            path: path,
+            query: query,
+            headers: headers,
+            body: body,
+        })
+    }
+
+    pub fn get_header(&self, name: &str) -> Option<&String> {
+        self.headers.get(&name.to_lowercase())
+    }
+}
+
+/// Size of the blocks in which streamed bodies are read and sent
+pub static STREAM_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// HTTP response to be written back to the client
+#[allow(dead_code)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    stream: Option<(Box<Read>, u64)>,
+}
+
+#[allow(dead_code)]
+impl Response {
+    pub fn new(status: u16) -> Response {
+        Response {
+            status: status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            stream: None,
+        }
+    }
+
+    pub fn with_body(status: u16, content_type: &str, body: Vec<u8>) -> Response {
+        let mut response = Response::new(status);
+        response.add_header("Content-Type", content_type);
+        response.body = body;
+        response
+    }
+
+    pub fn error(status: u16, message: String) -> Response {
+        Response::with_body(status, "text/plain; charset=utf-8", message.into_bytes())
+    }
+
+    pub fn add_header(&mut self, name: &str, value: &str) {
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+
+    /// Sends `length` bytes from `body` instead of the in-memory body. The body is read and written
+    /// in blocks of STREAM_BLOCK_SIZE so that it never has to be held in memory as a whole.
+    pub fn set_stream(&mut self, body: Box<Read>, length: u64) {
+        self.stream = Some((body, length));
+    }
+
+    /// Writes the response. When `include_body` is false (HEAD requests) only the head is sent.
+    pub fn write_to<W: Write>(mut self, stream: &mut W, include_body: bool) -> Result<(), String> {
+        let content_length = match self.stream {
+            Some((_, length)) => length,
+            None => self.body.len() as u64,
+        };
+
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason_phrase(self.status));
+        for &(ref name, ref value) in self.headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if !self.headers.iter().any(|&(ref name, _)| name.to_lowercase() == "content-length") {
+            head.push_str(&format!("Content-Length: {}\r\n", content_length));
+        }
+        head.push_str("Connection: close\r\n\r\n");
+
+        if stream.write_all(head.as_bytes()).is_err() {
+            return Err("Failed to write response".to_string());
+        }
+        if include_body {
+            match self.stream.take() {
+                Some((body, length)) => {
+                    let mut body = ::std::io::BufReader::with_capacity(STREAM_BLOCK_SIZE, body).take(length);
+                    match ::std::io::copy(&mut body, stream) {
+                        Ok(written) if written == length => (),
+                        _ => return Err("Failed to stream response".to_string()),
+                    }
+                },
+                None => if stream.write_all(&self.body[..]).is_err() {
+                    return Err("Failed to write response".to_string());
+                },
+            }
+        }
+        match stream.flush() {
+            Ok(_) => Ok(()),
+            Err(_) => Err("Failed to write response".to_string()),
+        }
+    }
+}
+
+pub fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        206 => "Partial Content",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        416 => "Range Not Satisfiable",
+        500 => "Internal Server Error",
+        _ => "Unknown",
+    }
+}
+
+/// Interprets a `Range` header against content of `size` bytes. Returns the inclusive span to be
+/// served, `Ok(None)` if the header is to be ignored (unsupported unit, several ranges or bad syntax)
+/// and `Err(())` if the range cannot be satisfied.
+pub fn parse_range(value: &str, size: u64) -> Result<Option<(u64, u64)>, ()> {
+    let value = value.trim();
+    if !value.starts_with("bytes=") || value.contains(',') {
+        return Ok(None);
+    }
+    let spec = &value["bytes=".len()..];
+    let pos = match spec.find('-') {
+        Some(pos) => pos,
+        None => return Ok(None),
+    };
+    let (first, last) = (spec[..pos].trim(), spec[pos + 1..].trim());
+
+    if first.is_empty() {
+        let suffix = match last.parse::<u64>() {
+            Ok(suffix) => suffix,
+            Err(_) => return Ok(None),
+        };
+        if suffix == 0 || size == 0 {
+            return Err(());
+        }
+        return Ok(Some((size.saturating_sub(suffix), size - 1)));
+    }
+
+    let first = match first.parse::<u64>() {
+        Ok(first) => first,
+        Err(_) => return Ok(None),
+    };
+    let last = if last.is_empty() {
+        size.saturating_sub(1)
+    } else {
+        match last.parse::<u64>() {
+            Ok(last) if last >= first => ::std::cmp::min(last, size.saturating_sub(1)),
+            _ => return Ok(None),
+        }
+    };
+    if first >= size {
+        return Err(());
+    }
+    Ok(Some((first, last)))
+}
+
+/// Decodes a path segment. '+' stands for itself here, unlike in a query string.
+pub fn percent_decode(input: &str) -> Result<String, String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' => {
+                if index + 2 >= bytes.len() {
+                    return Err("Malformed percent-encoding".to_string());
+                }
+                match (hex_value(bytes[index + 1]), hex_value(bytes[index + 2])) {
+                    (Some(high), Some(low)) => decoded.push(high * 16 + low),
+                    _ => return Err("Malformed percent-encoding".to_string()),
+                }
+                index += 3;
+            },
+            byte => {
+                decoded.push(byte);
+                index += 1;
+            },
+        }
+    }
+    match String::from_utf8(decoded) {
+        Ok(decoded) => Ok(decoded),
+        Err(_) => Err("Path is not valid UTF-8".to_string()),
+    }
+}
+
+/// Decodes a key or value of the query string, where '+' stands for a space
+pub fn query_decode(input: &str) -> Result<String, String> {
+    percent_decode(&input.replace("+", " "))
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'...b'9' => Some(byte - b'0'),
+        b'a'...b'f' => Some(byte - b'a' + 10),
+        b'A'...b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_request() {
+        let raw = "PUT /blobs/My%20Docs/a+b.txt?version=ab&x=c+d%2B HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nX-Safe-Metadata: meta\r\n\r\nhello";
+        let request = Request::read(raw.as_bytes()).ok().unwrap();
+
+        assert_eq!(request.method, "PUT".to_string());
+        assert_eq!(request.path, vec!["blobs".to_string(), "My Docs".to_string(), "a+b.txt".to_string()]);
+        assert_eq!(request.query.get("version"), Some(&"ab".to_string()));
+        assert_eq!(request.query.get("x"), Some(&"c d+".to_string()));
+        assert_eq!(request.get_header("x-safe-metadata"), Some(&"meta".to_string()));
+        assert_eq!(request.body, "hello".to_string().into_bytes());
+    }
+
+    #[test]
+    fn range_parsing() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Ok(Some((0, 99))));
+        assert_eq!(parse_range("bytes=900-", 1000), Ok(Some((900, 999))));
+        assert_eq!(parse_range("bytes=990-2000", 1000), Ok(Some((990, 999))));
+        assert_eq!(parse_range("bytes=-100", 1000), Ok(Some((900, 999))));
+        assert_eq!(parse_range("bytes=-2000", 1000), Ok(Some((0, 999))));
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(()));
+        assert_eq!(parse_range("bytes=-0", 1000), Err(()));
+        assert_eq!(parse_range("bytes=0-", 0), Err(()));
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), Ok(None));
+        assert_eq!(parse_range("bytes=5-1", 1000), Ok(None));
+        assert_eq!(parse_range("items=0-1", 1000), Ok(None));
+    }
+
+    #[test]
+    fn streamed_response() {
+        let mut response = Response::new(206);
+        response.set_stream(Box::new(::std::io::Cursor::new(vec![7u8; 10])), 4);
+        let mut written = Vec::new();
+        assert!(response.write_to(&mut written, true).is_ok());
+
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+        assert!(written.contains("Content-Length: 4\r\n"));
+        assert!(written.ends_with("\r\n\r\n\u{7}\u{7}\u{7}\u{7}"));
+    }
+
+    #[test]
+    fn reject_malformed_requests() {
+        assert!(Request::read("GET /\r\n\r\n".as_bytes()).is_err());
+        assert!(Request::read("GET /%4 HTTP/1.1\r\n\r\n".as_bytes()).is_err());
+        assert!(Request::read("PUT / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort".as_bytes()).is_err());
+    }
+}
@@ -15,10 +15,36 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
+mod app_client;
 mod container;
 mod blob;
 mod container_info;
+mod trash;
+mod transaction;
+mod well_known;
+mod http;
+mod server;
 
+pub use self::app_client::*;
 pub use self::container::*;
 pub use self::blob::*;
 pub use self::container_info::*;
+pub use self::trash::*;
+pub use self::transaction::*;
+pub use self::well_known::*;
+pub use self::server::*;
+
+/// User metadata as stored from the metadata given to the API: none is empty, but an empty
+/// string is refused
+fn validate_metadata(metadata: Option<String>) -> Result<Vec<u8>, String> {
+    match metadata {
+        Some(data) => {
+            if data.len() == 0 {
+                Err("Metadata cannot be empty".to_string())
+            } else {
+                Ok(data.into_bytes())
+            }
+        },
+        None => Ok(Vec::new()),
+    }
+}
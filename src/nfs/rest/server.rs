@@ -0,0 +1,635 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+use nfs;
+use routing;
+use client;
+use self_encryption;
+use rustc_serialize::json;
+use rustc_serialize::hex::{FromHex, ToHex};
+use nfs::traits::FileWrapper;
+use nfs::traits::DirectoryListingWrapper;
+use super::http;
+use super::http::{Request, Response};
+
+/// Serves the Container and Blob API of a logged in Client over HTTP.
+///
+///  - `GET|HEAD /containers/{path}` lists a container as JSON, filtered, sorted and paged by the
+///    query parameters described in `get_query`
+///  - `PUT /containers/{path}` creates a container, `DELETE` removes it
+///  - `GET /versions/{path}` lists the versions of a container
+///  - `GET|HEAD /blobs/{path}/{name}` streams the content of a blob, honouring `Range` and `If-None-Match`
+///  - `PUT /blobs/{path}/{name}` creates or overwrites a blob, `DELETE` removes it
+///
+/// `{path}` is relative to the root container the server was bound with. `GET` requests accept a
+/// `version` query parameter (hex) selecting a version of the container the path ends in, or of
+/// the container holding the blob. User metadata travels in the `X-Safe-Metadata` header.
+///
+/// Callers are not authenticated, so the server is only reachable over loopback, and requests
+/// whose `Host` is not a loopback name are refused so that web pages cannot reach it through DNS
+/// rebinding.
+pub struct Server {
+    handler: ::std::sync::Arc<Handler>,
+    listener: ::std::net::TcpListener,
+}
+
+/// Seconds a connection may take to send a request or receive the response
+const CONNECTION_TIMEOUT_SECS: u64 = 30;
+
+/// Serves the requests of the connections, each on its own thread
+struct Handler {
+    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+    root_id: routing::NameType,
+    root_parent_id: routing::NameType,
+}
+
+impl Server {
+    /// Binds the server to a loopback address. The server does not authenticate its callers, so
+    /// binding to any other interface is refused.
+    pub fn bind(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+                root: &nfs::rest::Container,
+                address: &str) -> Result<Server, String> {
+        let listener = match ::std::net::TcpListener::bind(address) {
+            Ok(listener) => listener,
+            Err(_) => return Err(format!("Failed to bind to {}", address)),
+        };
+
+        let is_loopback = match listener.local_addr() {
+            Ok(::std::net::SocketAddr::V4(address)) => address.ip().octets()[0] == 127,
+            Ok(::std::net::SocketAddr::V6(address)) => address.ip().segments() == [0, 0, 0, 0, 0, 0, 0, 1],
+            Err(_) => false,
+        };
+        if !is_loopback {
+            return Err("Server can only be bound to a loopback address".to_string());
+        }
+
+        let root_listing = root.convert_to_directory_listing();
+        Ok(Server {
+            handler: ::std::sync::Arc::new(Handler {
+                client: client,
+                root_id: root_listing.get_id(),
+                root_parent_id: root_listing.get_parent_dir_id(),
+            }),
+            listener: listener,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<::std::net::SocketAddr, String> {
+        match self.listener.local_addr() {
+            Ok(address) => Ok(address),
+            Err(_) => Err("Failed to get local address".to_string()),
+        }
+    }
+
+    /// Accepts connections until the listener fails, serving each on its own thread
+    pub fn serve(&self) {
+        for stream in self.listener.incoming() {
+            if let Ok(stream) = stream {
+                let handler = self.handler.clone();
+                let _ = ::std::thread::spawn(move || handler.handle_connection(stream));
+            }
+        }
+    }
+}
+
+impl Handler {
+    fn handle_connection(&self, mut stream: ::std::net::TcpStream) -> Result<(), String> {
+        let timeout = Some(::std::time::Duration::from_secs(CONNECTION_TIMEOUT_SECS));
+        if stream.set_read_timeout(timeout).is_err() || stream.set_write_timeout(timeout).is_err() {
+            return Err("Failed to set the connection timeouts".to_string());
+        }
+        let request = match Request::read(&stream) {
+            Ok(request) => request,
+            Err(message) => return Response::error(400, message).write_to(&mut stream, true),
+        };
+
+        let response = if Handler::is_loopback_host(&request) {
+            self.dispatch(&request)
+        } else {
+            Response::error(403, "Host must be a loopback name".to_string())
+        };
+        response.write_to(&mut stream, request.method != "HEAD")
+    }
+
+    /// Whether the Host of the request names the loopback interface, as every request sent
+    /// to the server by a local app does
+    fn is_loopback_host(request: &Request) -> bool {
+        let host = match request.get_header("Host") {
+            Some(host) => host.to_lowercase(),
+            None => return false,
+        };
+        let name = if host.starts_with('[') {
+            match host.find(']') {
+                Some(end) => host[1..end].to_string(),
+                None => return false,
+            }
+        } else {
+            host.split(':').next().unwrap_or("").to_string()
+        };
+        match name.parse::<::std::net::IpAddr>() {
+            Ok(::std::net::IpAddr::V4(address)) => address.octets()[0] == 127,
+            Ok(::std::net::IpAddr::V6(address)) => address.segments() == [0, 0, 0, 0, 0, 0, 0, 1],
+            Err(_) => name == "localhost",
+        }
+    }
+
+    fn dispatch(&self, request: &Request) -> Response {
+        let resource = match request.path.first() {
+            Some(resource) => resource.clone(),
+            None => return Response::error(404, "Not found".to_string()),
+        };
+
+        let result = match (&request.method[..], &resource[..]) {
+            ("GET", "containers") | ("HEAD", "containers") => self.get_container(request),
+            ("PUT", "containers") => self.create_container(request),
+            ("DELETE", "containers") => self.delete_container(request),
+            ("GET", "versions") => self.get_versions(request),
+            ("GET", "blobs") | ("HEAD", "blobs") => self.get_blob(request),
+            ("PUT", "blobs") => self.put_blob(request),
+            ("DELETE", "blobs") => self.delete_blob(request),
+            (_, "containers") | (_, "versions") | (_, "blobs") => Err(Response::error(405, "Method not allowed".to_string())),
+            _ => Err(Response::error(404, "Not found".to_string())),
+        };
+
+        match result {
+            Ok(response) => response,
+            Err(response) => response,
+        }
+    }
+
+    fn get_container(&self, request: &Request) -> Result<Response, Response> {
+        let version = try!(Handler::get_version(request));
+        let container = try!(self.resolve(&request.path[1..], version));
+
+        let mut containers_query = try!(Handler::get_query(request));
+        let mut blobs_query = containers_query.clone();
+        containers_query.set_cursor(request.query.get("containers_cursor").map(|cursor| cursor.clone()));
+        blobs_query.set_cursor(request.query.get("blobs_cursor").map(|cursor| cursor.clone()));
+
+        let containers = match container.query_containers(&containers_query) {
+            Ok(page) => page,
+            Err(message) => return Err(Response::error(400, message)),
+        };
+        let blobs = match container.query_blobs(&blobs_query) {
+            Ok(page) => page,
+            Err(message) => return Err(Response::error(400, message)),
+        };
+
+        let body = Handler::container_to_json(&container, containers, blobs).to_string().into_bytes();
+        Ok(Response::with_body(200, "application/json", body))
+    }
+
+    fn create_container(&self, request: &Request) -> Result<Response, Response> {
+        let (parent_path, name) = try!(Handler::split_name(&request.path));
+        let mut parent = try!(self.resolve(parent_path, None));
+        match parent.create(name.clone(), Handler::get_metadata(request)) {
+            Ok(_) => Ok(Response::new(201)),
+            Err(message) => Err(Response::error(if parent.has_container(&name) { 409 } else { 400 }, message)),
+        }
+    }
+
+    fn delete_container(&self, request: &Request) -> Result<Response, Response> {
+        let (parent_path, name) = try!(Handler::split_name(&request.path));
+        let mut parent = try!(self.resolve(parent_path, None));
+        match parent.delete_container(name) {
+            Ok(_) => Ok(Response::new(204)),
+            Err(message) => Err(Response::error(404, message)),
+        }
+    }
+
+    fn get_versions(&self, request: &Request) -> Result<Response, Response> {
+        let mut container = try!(self.resolve(&request.path[1..], None));
+        match container.get_versions() {
+            Ok(versions) => {
+                let versions = json::Json::Array(versions.iter().map(|version| json::Json::String((&version[..]).to_hex())).collect());
+                Ok(Response::with_body(200, "application/json", versions.to_string().into_bytes()))
+            },
+            Err(message) => Err(Response::error(500, message)),
+        }
+    }
+
+    fn get_blob(&self, request: &Request) -> Result<Response, Response> {
+        use std::io::Seek;
+
+        let (container_path, name) = try!(Handler::split_name(&request.path));
+        let container = try!(self.resolve(container_path, None));
+        let version = try!(Handler::get_version(request));
+        let blob = match container.get_blob(name, version) {
+            Ok(blob) => blob,
+            Err(message) => return Err(Response::error(404, message)),
+        };
+
+        let etag = blob.get_etag();
+        if request.get_header("If-None-Match").map_or(false, |tags| tags.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*")) {
+            let mut response = Response::new(304);
+            response.add_header("ETag", &etag);
+            return Ok(response);
+        }
+
+        let mut reader = blob.reader();
+        let size = reader.size();
+        let range = match request.get_header("Range") {
+            Some(value) => match http::parse_range(value, size) {
+                Ok(range) => range,
+                Err(_) => {
+                    let mut response = Response::error(416, "Range not satisfiable".to_string());
+                    response.add_header("Content-Range", &format!("bytes */{}", size));
+                    return Err(response);
+                },
+            },
+            None => None,
+        };
+
+        let mut response = match range {
+            Some((first, last)) => {
+                let mut response = Response::new(206);
+                response.add_header("Content-Range", &format!("bytes {}-{}/{}", first, last, size));
+                response
+            },
+            None => Response::new(200),
+        };
+        let (first, length) = match range {
+            Some((first, last)) => (first, last - first + 1),
+            None => (0, size),
+        };
+        if reader.seek(::std::io::SeekFrom::Start(first)).is_err() {
+            return Err(Response::error(500, "Failed to seek".to_string()));
+        }
+
+        response.add_header("Content-Type", "application/octet-stream");
+        response.add_header("Accept-Ranges", "bytes");
+        response.add_header("ETag", &etag);
+        Handler::add_blob_headers(&mut response, &blob);
+        if request.method != "HEAD" {
+            response.set_stream(Box::new(reader), length);
+        } else {
+            response.add_header("Content-Length", &format!("{}", length));
+        }
+        Ok(response)
+    }
+
+    fn put_blob(&self, request: &Request) -> Result<Response, Response> {
+        let (container_path, name) = try!(Handler::split_name(&request.path));
+        let mut container = try!(self.resolve(container_path, None));
+        let metadata = Handler::get_metadata(request);
+        let size = request.body.len() as u64;
+
+        let (status, writer) = match container.get_blob(name.clone(), None) {
+            Ok(blob) => {
+                // Overwrite from an empty datamap so that shorter content does not keep the old tail
+                let mut file_metadata = blob.convert_to_file().get_metadata();
+                file_metadata.set_size(size);
+                if metadata.is_some() {
+                    match nfs::rest::validate_metadata(metadata) {
+                        Ok(user_metadata) => file_metadata.set_user_metadata(user_metadata),
+                        Err(message) => return Err(Response::error(400, message)),
+                    }
+                }
+                let file = nfs::file::File::new(file_metadata, self_encryption::datamap::DataMap::None);
+                let mut file_helper = nfs::helper::FileHelper::new(self.client.clone());
+                (200, file_helper.update(file, container.convert_to_directory_listing()))
+            },
+            Err(_) => (201, container.create_blob(name, metadata, size)),
+        };
+
+        let mut writer = match writer {
+            Ok(writer) => writer,
+            Err(message) => return Err(Response::error(400, message)),
+        };
+        writer.write(&request.body[..], 0);
+        match writer.close() {
+            Ok(_) => Ok(Response::new(status)),
+            Err(message) => Err(Response::error(500, message)),
+        }
+    }
+
+    fn delete_blob(&self, request: &Request) -> Result<Response, Response> {
+        let (container_path, name) = try!(Handler::split_name(&request.path));
+        let mut container = try!(self.resolve(container_path, None));
+        match container.delete_blob(name) {
+            Ok(_) => Ok(Response::new(204)),
+            Err(message) => Err(Response::error(404, message)),
+        }
+    }
+
+    /// Walks from the root container down the given path. The version, if any, applies to the last container.
+    fn resolve(&self, path: &[String], version: Option<[u8; 64]>) -> Result<nfs::rest::Container, Response> {
+        let mut container = match nfs::rest::Container::authorise(self.client.clone(), self.root_id.0, self.root_parent_id.0) {
+            Ok(container) => container,
+            Err(message) => return Err(Response::error(500, message)),
+        };
+
+        if path.is_empty() && version.is_some() {
+            let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
+            return match directory_helper.get_by_version(self.root_id.clone(), self.root_parent_id.clone(), routing::NameType(version.unwrap())) {
+                Ok(listing) => Ok(nfs::rest::Container::convert_from_directory_listing(self.client.clone(), listing)),
+                Err(message) => Err(Response::error(404, message)),
+            };
+        }
+
+        for (index, name) in path.iter().enumerate() {
+            let container_version = if index + 1 == path.len() { version } else { None };
+            container = match container.get_container(name.clone(), container_version) {
+                Ok(container) => container,
+                Err(message) => return Err(Response::error(404, message)),
+            };
+        }
+        Ok(container)
+    }
+
+    fn split_name(path: &Vec<String>) -> Result<(&[String], String), Response> {
+        if path.len() < 2 {
+            return Err(Response::error(400, "Missing name".to_string()));
+        }
+        Ok((&path[1..path.len() - 1], path[path.len() - 1].clone()))
+    }
+
+    fn get_version(request: &Request) -> Result<Option<[u8; 64]>, Response> {
+        let hex = match request.query.get("version") {
+            Some(hex) => hex,
+            None => return Ok(None),
+        };
+        match hex.from_hex() {
+            Ok(ref bytes) if bytes.len() == 64 => {
+                let mut version = [0u8; 64];
+                for (index, byte) in bytes.iter().enumerate() {
+                    version[index] = *byte;
+                }
+                Ok(Some(version))
+            },
+            _ => Err(Response::error(400, "Invalid version".to_string())),
+        }
+    }
+
+    /// Builds the listing query shared by the containers and the blobs of a listing from the
+    /// `offset`, `limit`, `sort`, `order`, `prefix`, `glob`, `modified_after`, `min_size`,
+    /// `max_size` and `metadata` query parameters
+    fn get_query(request: &Request) -> Result<nfs::query::Query, Response> {
+        let mut query = nfs::query::Query::new();
+        let parameters = &request.query;
+        let bad_request = |name: &str| Response::error(400, format!("Invalid {}", name));
+
+        if let Some(offset) = parameters.get("offset") {
+            match offset.parse::<usize>() {
+                Ok(offset) => query.set_offset(offset),
+                Err(_) => return Err(bad_request("offset")),
+            }
+        }
+        if let Some(limit) = parameters.get("limit") {
+            match limit.parse::<usize>() {
+                Ok(limit) => query.set_limit(Some(limit)),
+                Err(_) => return Err(bad_request("limit")),
+            }
+        }
+
+        let sort_by = match parameters.get("sort").map(|sort| &sort[..]) {
+            None | Some("name") => nfs::query::SortBy::Name,
+            Some("size") => nfs::query::SortBy::Size,
+            Some("created") => nfs::query::SortBy::CreatedTime,
+            Some("modified") => nfs::query::SortBy::ModifiedTime,
+            Some(_) => return Err(bad_request("sort")),
+        };
+        let sort_order = match parameters.get("order").map(|order| &order[..]) {
+            None | Some("asc") => nfs::query::SortOrder::Ascending,
+            Some("desc") => nfs::query::SortOrder::Descending,
+            Some(_) => return Err(bad_request("order")),
+        };
+        query.set_sort(sort_by, sort_order);
+
+        if let Some(prefix) = parameters.get("prefix") {
+            query.add_filter(nfs::query::Filter::NamePrefix(prefix.clone()));
+        }
+        if let Some(pattern) = parameters.get("glob") {
+            query.add_filter(nfs::query::Filter::NameGlob(pattern.clone()));
+        }
+        if let Some(modified_after) = parameters.get("modified_after") {
+            match ::time::strptime(modified_after, "%Y-%m-%dT%H:%M:%SZ") {
+                Ok(tm) => query.add_filter(nfs::query::Filter::ModifiedAfter(tm)),
+                Err(_) => return Err(bad_request("modified_after")),
+            }
+        }
+        if parameters.contains_key("min_size") || parameters.contains_key("max_size") {
+            let min_size = parameters.get("min_size").map_or(Ok(0), |size| size.parse::<u64>());
+            let max_size = parameters.get("max_size").map_or(Ok(::std::u64::MAX), |size| size.parse::<u64>());
+            match (min_size, max_size) {
+                (Ok(min_size), Ok(max_size)) => query.add_filter(nfs::query::Filter::SizeRange(min_size, max_size)),
+                _ => return Err(bad_request("size range")),
+            }
+        }
+        if let Some(metadata) = parameters.get("metadata") {
+            query.add_filter(nfs::query::Filter::UserMetadataContains(metadata.clone().into_bytes()));
+        }
+
+        Ok(query)
+    }
+
+    fn get_metadata(request: &Request) -> Option<String> {
+        request.get_header("X-Safe-Metadata").map(|metadata| metadata.clone())
+    }
+
+    fn add_blob_headers(response: &mut Response, blob: &nfs::rest::Blob) {
+        response.add_header("Last-Modified", &format!("{}", blob.get_modified_time().rfc822()));
+        response.add_header("X-Safe-Created", &format!("{}", blob.get_created_time().rfc3339()));
+        response.add_header("X-Safe-Modified", &format!("{}", blob.get_modified_time().rfc3339()));
+        if let Some(user_metadata) = blob.get_user_metadata() {
+            let metadata: String = String::from_utf8_lossy(&user_metadata[..]).chars().filter(|c| !c.is_control()).collect();
+            response.add_header("X-Safe-Metadata", &metadata);
+        }
+    }
+
+    fn container_to_json(container: &nfs::rest::Container,
+                         containers: nfs::query::Page<nfs::rest::ContainerInfo>,
+                         blobs: nfs::query::Page<nfs::rest::Blob>) -> json::Json {
+        let mut object = ::std::collections::BTreeMap::new();
+        let _ = object.insert("name".to_string(), json::Json::String(container.get_name()));
+        let _ = object.insert("id".to_string(), json::Json::String((&container.get_id()[..]).to_hex()));
+        let _ = object.insert("metadata".to_string(), Handler::optional_string(container.get_metadata()));
+        let _ = object.insert("created".to_string(), json::Json::String(format!("{}", container.get_created_time().rfc3339())));
+        let _ = object.insert("modified".to_string(), json::Json::String(format!("{}", container.get_modified_time().rfc3339())));
+
+        let _ = object.insert("containers_total".to_string(), json::Json::U64(containers.total as u64));
+        let _ = object.insert("containers_next".to_string(), Handler::optional_string(containers.next_cursor.clone()));
+        let _ = object.insert("blobs_total".to_string(), json::Json::U64(blobs.total as u64));
+        let _ = object.insert("blobs_next".to_string(), Handler::optional_string(blobs.next_cursor.clone()));
+
+        let containers = containers.items.iter().map(|info| {
+            let mut entry = ::std::collections::BTreeMap::new();
+            let _ = entry.insert("name".to_string(), json::Json::String(info.get_name()));
+            let _ = entry.insert("metadata".to_string(), Handler::optional_string(info.get_metadata()));
+            let _ = entry.insert("created".to_string(), json::Json::String(format!("{}", info.get_created_time().rfc3339())));
+            json::Json::Object(entry)
+        }).collect();
+        let _ = object.insert("containers".to_string(), json::Json::Array(containers));
+
+        let blobs = blobs.items.iter().map(|blob| {
+            let mut entry = ::std::collections::BTreeMap::new();
+            let _ = entry.insert("name".to_string(), json::Json::String(blob.get_name()));
+            let _ = entry.insert("size".to_string(), json::Json::U64(blob.get_size()));
+            let _ = entry.insert("metadata".to_string(),
+                                 Handler::optional_string(blob.get_user_metadata().map(|data| String::from_utf8_lossy(&data[..]).into_owned())));
+            let _ = entry.insert("created".to_string(), json::Json::String(format!("{}", blob.get_created_time().rfc3339())));
+            let _ = entry.insert("modified".to_string(), json::Json::String(format!("{}", blob.get_modified_time().rfc3339())));
+            json::Json::Object(entry)
+        }).collect();
+        let _ = object.insert("blobs".to_string(), json::Json::Array(blobs));
+
+        json::Json::Object(object)
+    }
+
+    fn optional_string(value: Option<String>) -> json::Json {
+        match value {
+            Some(value) => json::Json::String(value),
+            None => json::Json::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use rustc_serialize::json;
+    use rustc_serialize::hex::ToHex;
+
+    fn get_dummy_client() -> ::client::Client {
+        let keyword = "Spandan".to_string();
+        let password = "Sharma".as_bytes();
+        let pin = 1234u32;
+
+        ::client::Client::create_account(&keyword,
+                                         pin,
+                                         &password,
+                                         ::client::non_networking_test_framework::get_new_data_store()).ok().unwrap()
+    }
+
+    fn send(address: ::std::net::SocketAddr, method: &str, path: &str, headers: &str, body: &[u8]) -> (u16, String, Vec<u8>) {
+        let mut stream = ::std::net::TcpStream::connect(address).unwrap();
+        let head = format!("{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n{}\r\n", method, path, body.len(), headers);
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(body).unwrap();
+
+        let mut raw = Vec::new();
+        let _ = stream.read_to_end(&mut raw).unwrap();
+        let split = (0..raw.len() - 3).find(|&index| &raw[index..index + 4] == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(raw[..split].to_vec()).unwrap();
+        let status = head[9..12].parse::<u16>().unwrap();
+        (status, head, raw[split + 4..].to_vec())
+    }
+
+    #[test]
+    fn rest_over_loopback() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let mut dir_helper = ::nfs::helper::DirectoryHelper::new(client.clone());
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let root_id = dir_helper.create(parent_id.clone(), "Root".to_string(), Vec::new()).ok().unwrap();
+        let root = ::nfs::rest::Container::authorise(client.clone(), root_id.0, parent_id.0).ok().unwrap();
+
+        assert!(Server::bind(client.clone(), &root, "0.0.0.0:0").is_err());
+
+        let server = Server::bind(client.clone(), &root, "127.0.0.1:0").ok().unwrap();
+        let address = server.local_addr().ok().unwrap();
+        let _ = ::std::thread::spawn(move || server.serve());
+
+        // A connection which sends nothing does not hold up the others
+        let _idle = ::std::net::TcpStream::connect(address).unwrap();
+
+        // A page reaching the server through DNS rebinding still names its own host
+        let mut stream = ::std::net::TcpStream::connect(address).unwrap();
+        stream.write_all(b"GET /containers HTTP/1.1\r\nHost: attacker.example:8080\r\n\r\n").unwrap();
+        let mut raw = Vec::new();
+        let _ = stream.read_to_end(&mut raw).unwrap();
+        assert!(raw.starts_with(b"HTTP/1.1 403"));
+
+        assert_eq!(send(address, "PUT", "/containers/My%20Docs", "X-Safe-Metadata: docs\r\n", &[]).0, 201);
+        assert_eq!(send(address, "PUT", "/containers/My%20Docs", "", &[]).0, 409);
+
+        let (status, _, body) = send(address, "GET", "/containers", "", &[]);
+        assert_eq!(status, 200);
+        let listing = json::Json::from_str(&String::from_utf8(body).unwrap()).unwrap();
+        let containers = listing.find("containers").unwrap().as_array().unwrap();
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0].find("name").unwrap().as_string(), Some("My Docs"));
+        assert_eq!(containers[0].find("metadata").unwrap().as_string(), Some("docs"));
+
+        assert_eq!(send(address, "PUT", "/blobs/My%20Docs/a.txt", "X-Safe-Metadata: text\r\n", b"hello world").0, 201);
+        assert_eq!(send(address, "PUT", "/blobs/My%20Docs/b.jpg", "", b"jpeg").0, 201);
+
+        let (status, _, body) = send(address, "GET", "/containers/My%20Docs?glob=*.jpg", "", &[]);
+        assert_eq!(status, 200);
+        let listing = json::Json::from_str(&String::from_utf8(body).unwrap()).unwrap();
+        assert_eq!(listing.find("blobs_total").unwrap().as_u64(), Some(1));
+        assert_eq!(listing.find("blobs").unwrap().as_array().unwrap()[0].find("name").unwrap().as_string(), Some("b.jpg"));
+
+        let (_, _, body) = send(address, "GET", "/containers/My%20Docs?limit=1&sort=size&order=desc", "", &[]);
+        let listing = json::Json::from_str(&String::from_utf8(body).unwrap()).unwrap();
+        assert_eq!(listing.find("blobs").unwrap().as_array().unwrap()[0].find("name").unwrap().as_string(), Some("a.txt"));
+        let cursor = listing.find("blobs_next").unwrap().as_string().unwrap().to_string();
+        let (_, _, body) = send(address, "GET", &format!("/containers/My%20Docs?limit=1&sort=size&order=desc&blobs_cursor={}", cursor), "", &[]);
+        let listing = json::Json::from_str(&String::from_utf8(body).unwrap()).unwrap();
+        assert_eq!(listing.find("blobs").unwrap().as_array().unwrap()[0].find("name").unwrap().as_string(), Some("b.jpg"));
+        assert!(listing.find("blobs_next").unwrap().is_null());
+        assert_eq!(send(address, "GET", "/containers/My%20Docs?sort=colour", "", &[]).0, 400);
+
+        assert_eq!(send(address, "DELETE", "/blobs/My%20Docs/b.jpg", "", &[]).0, 204);
+        let (status, head, body) = send(address, "GET", "/blobs/My%20Docs/a.txt", "", &[]);
+        assert_eq!(status, 200);
+        assert_eq!(body, b"hello world".to_vec());
+        assert!(head.contains("X-Safe-Metadata: text"));
+
+        let (status, head, body) = send(address, "HEAD", "/blobs/My%20Docs/a.txt", "", &[]);
+        assert_eq!(status, 200);
+        assert!(head.contains("Content-Length: 11"));
+        assert!(body.is_empty());
+
+        let (status, head, body) = send(address, "GET", "/blobs/My%20Docs/a.txt", "Range: bytes=6-\r\n", &[]);
+        assert_eq!(status, 206);
+        assert_eq!(body, b"world".to_vec());
+        assert!(head.contains("Content-Range: bytes 6-10/11"));
+        assert_eq!(send(address, "GET", "/blobs/My%20Docs/a.txt", "Range: bytes=-3\r\n", &[]).2, b"rld".to_vec());
+        assert_eq!(send(address, "GET", "/blobs/My%20Docs/a.txt", "Range: bytes=20-\r\n", &[]).0, 416);
+
+        let etag = head.lines().find(|line| line.starts_with("ETag: ")).unwrap()["ETag: ".len()..].to_string();
+        assert_eq!(send(address, "GET", "/blobs/My%20Docs/a.txt", &format!("If-None-Match: {}\r\n", etag), &[]).0, 304);
+
+        assert_eq!(send(address, "PUT", "/blobs/My%20Docs/a.txt", "X-Safe-Metadata: \r\n", b"bye").0, 400);
+        assert_eq!(send(address, "PUT", "/blobs/My%20Docs/a.txt", "", b"bye").0, 200);
+        assert_eq!(send(address, "GET", "/blobs/My%20Docs/a.txt", &format!("If-None-Match: {}\r\n", etag), &[]).0, 200);
+        assert_eq!(send(address, "GET", "/blobs/My%20Docs/a.txt", "", &[]).2, b"bye".to_vec());
+
+        let (status, _, body) = send(address, "GET", "/versions/My%20Docs", "", &[]);
+        assert_eq!(status, 200);
+        let versions = json::Json::from_str(&String::from_utf8(body).unwrap()).unwrap();
+        let versions = versions.as_array().unwrap();
+        // Created, a.txt and b.jpg stored, b.jpg deleted and a.txt replaced
+        assert_eq!(versions.len(), 5);
+
+        let first_version = versions[0].as_string().unwrap();
+        assert_eq!(send(address, "GET", &format!("/blobs/My%20Docs/a.txt?version={}", first_version), "", &[]).0, 404);
+        let second_version = versions[1].as_string().unwrap();
+        assert_eq!(send(address, "GET", &format!("/blobs/My%20Docs/a.txt?version={}", second_version), "", &[]).2, b"hello world".to_vec());
+        let latest_version = versions[4].as_string().unwrap();
+        assert_eq!(send(address, "GET", &format!("/blobs/My%20Docs/a.txt?version={}", latest_version), "", &[]).2, b"bye".to_vec());
+        assert_eq!(send(address, "GET", "/containers/My%20Docs?version=zz", "", &[]).0, 400);
+        assert_eq!(send(address, "GET", &format!("/containers?version={}", (&root_id.0[..]).to_hex()), "", &[]).0, 404);
+
+        assert_eq!(send(address, "DELETE", "/blobs/My%20Docs/a.txt", "", &[]).0, 204);
+        assert_eq!(send(address, "GET", "/blobs/My%20Docs/a.txt", "", &[]).0, 404);
+        assert_eq!(send(address, "DELETE", "/containers/My%20Docs", "", &[]).0, 204);
+        assert_eq!(send(address, "GET", "/containers/My%20Docs", "", &[]).0, 404);
+        assert_eq!(send(address, "POST", "/containers", "", &[]).0, 405);
+        assert_eq!(send(address, "GET", "/unknown", "", &[]).0, 404);
+    }
+}
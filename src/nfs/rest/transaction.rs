@@ -0,0 +1,305 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+use nfs;
+use routing;
+use client;
+
+/// Changes to a container staged against an in-memory copy of its listing. Nothing is visible
+/// in the container until the Transaction is committed, which saves the listing as a single
+/// new version. Blob content is stored as it is staged, but is only referenced by the
+/// container once the commit succeeds. New containers are only stored by the commit.
+pub struct Transaction {
+    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+    directory_listing: nfs::directory_listing::DirectoryListing,
+    trashed_blobs: Vec<nfs::file::File>,
+    trashed_containers: Vec<nfs::directory_info::DirectoryInfo>,
+    // Containers whose own listing has to carry a new name
+    renamed_containers: Vec<(routing::NameType, String)>,
+    // Containers staged by create_container, which do not exist until the commit
+    created_containers: Vec<routing::NameType>,
+}
+
+impl Transaction {
+    /// Starts a Transaction over a copy of the listing
+    pub fn new(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+               directory_listing: nfs::directory_listing::DirectoryListing) -> Transaction {
+        Transaction {
+            client: client,
+            directory_listing: directory_listing,
+            trashed_blobs: Vec::new(),
+            trashed_containers: Vec::new(),
+            renamed_containers: Vec::new(),
+            created_containers: Vec::new(),
+        }
+    }
+
+    /// The listing with the staged changes applied
+    pub fn get_directory_listing(&self) -> &nfs::directory_listing::DirectoryListing {
+        &self.directory_listing
+    }
+
+    pub fn create_blob(&mut self, name: String, metadata: Option<String>, data: &[u8]) -> Result<(), String> {
+        let user_metadata = try!(nfs::rest::validate_metadata(metadata));
+        if self.directory_listing.find_file(&name).is_some() {
+            return Err("File already exists".to_string());
+        }
+        let mut metadata = nfs::metadata::Metadata::new(name, user_metadata);
+        metadata.set_size(data.len() as u64);
+        let file = nfs::file::File::new(metadata, ::self_encryption::datamap::DataMap::None);
+        self.write_blob(file, data)
+    }
+
+    /// Replaces the content of the blob
+    pub fn update_blob(&mut self, name: String, data: &[u8]) -> Result<(), String> {
+        let mut file = match self.directory_listing.find_file(&name) {
+            Some(file) => file.clone(),
+            None => return Err("File not found".to_string()),
+        };
+        let mut metadata = file.get_metadata();
+        metadata.set_size(data.len() as u64);
+        file.set_metadata(metadata);
+        file.set_content(nfs::file::FileContent::DataMap(::self_encryption::datamap::DataMap::None));
+        self.write_blob(file, data)
+    }
+
+    pub fn update_blob_metadata(&mut self, name: String, metadata: Option<String>) -> Result<(), String> {
+        let user_metadata = try!(nfs::rest::validate_metadata(metadata));
+        let file = match self.directory_listing.find_file_mut(&name) {
+            Some(file) => file,
+            None => return Err("File not found".to_string()),
+        };
+        let mut metadata = file.get_metadata();
+        metadata.set_user_metadata(user_metadata);
+        file.set_metadata(metadata);
+        Ok(())
+    }
+
+    pub fn rename_blob(&mut self, name: String, new_name: String) -> Result<(), String> {
+        if self.directory_listing.find_file(&new_name).is_some() {
+            return Err(format!("{} already exists", new_name));
+        }
+        let mut file = match self.directory_listing.remove_file(&name) {
+            Some(file) => file,
+            None => return Err("File not found".to_string()),
+        };
+        let mut metadata = file.get_metadata();
+        metadata.set_name(new_name);
+        file.set_metadata(metadata);
+        let _ = self.directory_listing.upsert_file(file);
+        Ok(())
+    }
+
+    /// Removes the blob from the container. It is moved to the trash when the Transaction commits.
+    pub fn delete_blob(&mut self, name: String) -> Result<(), String> {
+        match self.directory_listing.remove_file(&name) {
+            Some(file) => {
+                self.trashed_blobs.push(file);
+                Ok(())
+            },
+            None => Err("File not found".to_string()),
+        }
+    }
+
+    pub fn create_container(&mut self, name: String, metadata: Option<String>) -> Result<(), String> {
+        let user_metadata = try!(nfs::rest::validate_metadata(metadata));
+        if self.directory_listing.find_sub_directory(&name).is_some() {
+            return Err(format!("{} already exists", name));
+        }
+        let info = nfs::directory_info::DirectoryInfo::new(self.directory_listing.get_id(), nfs::metadata::Metadata::new(name, user_metadata));
+        self.created_containers.push(info.get_id());
+        let _ = self.directory_listing.upsert_sub_directory(info);
+        Ok(())
+    }
+
+    pub fn rename_container(&mut self, name: String, new_name: String) -> Result<(), String> {
+        if self.directory_listing.find_sub_directory(&new_name).is_some() {
+            return Err(format!("{} already exists", new_name));
+        }
+        let mut info = match self.directory_listing.remove_sub_directory(&name) {
+            Some(info) => info,
+            None => return Err("Container not found".to_string()),
+        };
+        info.get_mut_metadata().set_name(new_name.clone());
+        if !self.created_containers.contains(&info.get_id()) {
+            self.renamed_containers.push((info.get_id(), new_name));
+        }
+        let _ = self.directory_listing.upsert_sub_directory(info);
+        Ok(())
+    }
+
+    /// Removes the container from its parent. It is moved to the trash when the Transaction commits.
+    pub fn delete_container(&mut self, name: String) -> Result<(), String> {
+        match self.directory_listing.remove_sub_directory(&name) {
+            Some(info) => {
+                match self.created_containers.iter().position(|id| *id == info.get_id()) {
+                    Some(index) => { let _ = self.created_containers.remove(index); },
+                    None => self.trashed_containers.push(info),
+                }
+                Ok(())
+            },
+            None => Err("Container not found".to_string()),
+        }
+    }
+
+    /// Sets the user metadata of the container itself
+    pub fn update_metadata(&mut self, metadata: Option<String>) -> Result<(), String> {
+        let user_metadata = try!(nfs::rest::validate_metadata(metadata));
+        self.directory_listing.set_user_metadata(user_metadata);
+        Ok(())
+    }
+
+    /// Applies the staged changes, saving the container as one new version. Returns the
+    /// committed listing.
+    pub fn commit(self) -> Result<nfs::directory_listing::DirectoryListing, String> {
+        let Transaction { client, mut directory_listing, trashed_blobs, trashed_containers, renamed_containers, created_containers } = self;
+        let parent_dir_id = directory_listing.get_id();
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(client.clone());
+
+        // A commit failing after this leaves the new containers stored but referenced by nothing
+        for dir_id in created_containers.iter() {
+            let info = directory_listing.get_sub_directories().find(|info| info.get_id() == *dir_id).cloned();
+            if let Some(info) = info {
+                let _ = try!(directory_helper.create_from_info(info));
+            }
+        }
+
+        // Listings given their new name are given back the old one if a later step fails, so a
+        // failed commit leaves every container under the name it had
+        let mut renamed = Vec::new();
+        let result = Transaction::rename_listings(&mut directory_helper, &parent_dir_id, &renamed_containers, &mut renamed)
+            .and_then(|()| Transaction::move_to_trash(&client, &directory_listing, trashed_blobs, trashed_containers));
+        let result = result.and_then(|()| directory_helper.update_listing(&mut directory_listing));
+        if let Err(error) = result {
+            for (mut listing, old_name) in renamed.into_iter() {
+                listing.set_name(old_name);
+                let _ = directory_helper.update(listing);
+            }
+            return Err(error);
+        }
+        Ok(directory_listing)
+    }
+
+    /// Saves the new name in the listing of each renamed container, recording the listings
+    /// saved and the names they had
+    fn rename_listings(directory_helper: &mut nfs::helper::DirectoryHelper, parent_dir_id: &routing::NameType,
+                       renamed_containers: &[(routing::NameType, String)],
+                       renamed: &mut Vec<(nfs::directory_listing::DirectoryListing, String)>) -> Result<(), String> {
+        for &(ref dir_id, ref new_name) in renamed_containers.iter() {
+            let mut listing = try!(directory_helper.get(dir_id.clone(), parent_dir_id.clone()));
+            let old_name = listing.get_name();
+            if old_name != *new_name {
+                listing.set_name(new_name.clone());
+                try!(directory_helper.update_listing(&mut listing));
+                renamed.push((listing, old_name));
+            }
+        }
+        Ok(())
+    }
+
+    // Deleted entries reach the trash before they leave the container, so a failed commit
+    // can leave an entry in both places but never in neither
+    fn move_to_trash(client: &::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+                     directory_listing: &nfs::directory_listing::DirectoryListing,
+                     trashed_blobs: Vec<nfs::file::File>,
+                     trashed_containers: Vec<nfs::directory_info::DirectoryInfo>) -> Result<(), String> {
+        if trashed_blobs.is_empty() && trashed_containers.is_empty() {
+            return Ok(());
+        }
+        let mut trash = try!(nfs::rest::Trash::open(client.clone()));
+        for file in trashed_blobs.into_iter() {
+            try!(trash.trash_blob(directory_listing, file));
+        }
+        for info in trashed_containers.into_iter() {
+            try!(trash.trash_container(directory_listing, info));
+        }
+        Ok(())
+    }
+
+    fn write_blob(&mut self, file: nfs::file::File, data: &[u8]) -> Result<(), String> {
+        let directory = nfs::directory_listing::DirectoryListing::from_info(self.directory_listing.get_info());
+        let mut writer = try!(nfs::io::Writer::new(directory, file, self.client.clone()));
+        writer.write(data, 0);
+        let _ = self.directory_listing.upsert_file(try!(writer.finish()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    fn get_dummy_client() -> ::client::Client {
+        let keyword = "Spandan".to_string();
+        let password = "Sharma".as_bytes();
+        let pin = 1234u32;
+
+        ::client::Client::create_account(&keyword,
+                                         pin,
+                                         &password,
+                                         ::client::non_networking_test_framework::get_new_data_store()).ok().unwrap()
+    }
+
+    #[test]
+    fn commit_and_roll_back() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let root_id = ::nfs::helper::DirectoryHelper::new(client.clone()).create(parent_id.clone(), "Root".to_string(), Vec::new()).ok().unwrap();
+        let mut container = ::nfs::rest::Container::authorise(client.clone(), root_id.0, parent_id.0).ok().unwrap();
+        let versions = container.get_versions().ok().unwrap().len();
+
+        assert!(container.transaction(|tx| {
+            for i in 0..10 {
+                try!(tx.create_blob(format!("{}.txt", i), None, format!("blob {}", i).as_bytes()));
+            }
+            try!(tx.create_container("Docs".to_string(), None));
+            try!(tx.rename_blob("0.txt".to_string(), "zero.txt".to_string()));
+            try!(tx.update_blob_metadata("1.txt".to_string(), Some("first".to_string())));
+            tx.delete_blob("2.txt".to_string())
+        }).is_ok());
+        assert_eq!(container.get_versions().ok().unwrap().len(), versions + 1);
+        assert_eq!(container.get_blobs().len(), 9);
+        assert!(container.get_blob("zero.txt".to_string(), None).is_ok());
+        assert!(container.get_blob("2.txt".to_string(), None).is_err());
+        assert_eq!(container.get_blob("1.txt".to_string(), None).ok().unwrap().get_user_metadata(), Some("first".to_string().into_bytes()));
+        assert_eq!(::nfs::rest::Trash::open(client.clone()).ok().unwrap().get_entries().len(), 1);
+
+        let mut saved = ::nfs::rest::Container::authorise(client.clone(), root_id.0, parent_id.0).ok().unwrap();
+        assert_eq!(saved.get_blobs().len(), 9);
+        assert!(saved.transaction(|tx| tx.rename_container("Docs".to_string(), "Papers".to_string())).is_ok());
+        assert_eq!(saved.get_container("Papers".to_string(), None).ok().unwrap().get_name(), "Papers".to_string());
+
+        // A failing step leaves both the container and the network untouched
+        let versions = saved.get_versions().ok().unwrap().len();
+        let mut staged = None;
+        assert!(saved.transaction(|tx| {
+            try!(tx.delete_blob("3.txt".to_string()));
+            try!(tx.create_container("Scratch".to_string(), None));
+            staged = tx.get_directory_listing().find_sub_directory("Scratch").map(|info| info.get_id());
+            tx.delete_blob("missing.txt".to_string())
+        }).is_err());
+        assert_eq!(saved.get_blobs().len(), 9);
+        assert_eq!(saved.get_versions().ok().unwrap().len(), versions);
+        let mut directory_helper = ::nfs::helper::DirectoryHelper::new(client.clone());
+        assert!(directory_helper.find(staged.unwrap(), root_id.clone()).ok().unwrap().is_none());
+
+        // A container created and renamed in one Transaction is stored once, under its final name
+        assert!(saved.transaction(|tx| {
+            try!(tx.create_container("Draft".to_string(), None));
+            tx.rename_container("Draft".to_string(), "Final".to_string())
+        }).is_ok());
+        assert_eq!(saved.get_container("Final".to_string(), None).ok().unwrap().get_name(), "Final".to_string());
+        assert_eq!(::nfs::rest::Trash::open(client).ok().unwrap().get_entries().len(), 1);
+    }
+}
@@ -0,0 +1,318 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+use nfs;
+use routing;
+use time;
+use client;
+
+/// Entries are purged once they have been in the trash this long, unless the policy is changed
+pub const DEFAULT_TRASH_MAX_AGE_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// Where a trashed entry came from and when it was deleted
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, Clone, Debug)]
+pub struct TrashEntry {
+    name: String,
+    original_name: String,
+    container_id: routing::NameType,
+    container_parent_id: routing::NameType,
+    is_container: bool,
+    deleted_sec: i64,
+    deleted_nsec: i32,
+}
+
+impl TrashEntry {
+    /// Name of the entry within the trash, used to restore it
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn get_original_name(&self) -> String {
+        self.original_name.clone()
+    }
+
+    /// Id of the container the entry was deleted from
+    pub fn get_container_id(&self) -> [u8; 64] {
+        self.container_id.0
+    }
+
+    /// Whether the entry is a container rather than a blob
+    pub fn is_container(&self) -> bool {
+        self.is_container
+    }
+
+    pub fn get_deleted_time(&self) -> time::Tm {
+        time::at_utc(time::Timespec { sec: self.deleted_sec, nsec: self.deleted_nsec })
+    }
+}
+
+/// Index of the trash, kept in the user metadata of the trash directory
+#[derive(RustcEncodable, RustcDecodable, Clone)]
+struct TrashIndex {
+    max_age_secs: i64,
+    entries: ::std::collections::BTreeMap<String, TrashEntry>,
+}
+
+/// The per-account trash. Deleted blobs and containers are moved here, keeping their content,
+/// until they are restored or purged.
+pub struct Trash {
+    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+    directory_listing: nfs::directory_listing::DirectoryListing,
+    index: TrashIndex,
+}
+
+impl Trash {
+    /// Opens the trash of the account, creating it on first use. Failing to fetch an existing
+    /// trash is an error, so that it is never replaced by an empty one.
+    pub fn open(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> Result<Trash, String> {
+        let owner = client.lock().unwrap().get_owner();
+        let trash_id = Trash::trash_id(&owner);
+
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(client.clone());
+        let directory_listing = match try!(directory_helper.find(trash_id.clone(), owner.clone())) {
+            Some(directory_listing) => directory_listing,
+            None => {
+                let index = TrashIndex { max_age_secs: DEFAULT_TRASH_MAX_AGE_SECS, entries: ::std::collections::BTreeMap::new() };
+                let _ = try!(directory_helper.create_with_id(trash_id.clone(), owner.clone(), "Trash".to_string(), nfs::utils::serialise(index)));
+                try!(directory_helper.get(trash_id, owner))
+            },
+        };
+
+        let index = match directory_listing.get_user_metadata() {
+            Some(data) => try!(nfs::utils::try_deserialise(data)),
+            None => return Err("Trash index missing".to_string()),
+        };
+        Ok(Trash {
+            client: client,
+            directory_listing: directory_listing,
+            index: index,
+        })
+    }
+
+    /// Entries in the trash, oldest first
+    pub fn get_entries(&self) -> Vec<TrashEntry> {
+        let mut entries: Vec<TrashEntry> = self.index.entries.values().cloned().collect();
+        entries.sort_by(|a, b| (a.deleted_sec, a.deleted_nsec).cmp(&(b.deleted_sec, b.deleted_nsec)));
+        entries
+    }
+
+    /// How long entries stay in the trash before purge_expired drops them
+    pub fn get_max_age(&self) -> time::Duration {
+        time::Duration::seconds(self.index.max_age_secs)
+    }
+
+    pub fn set_max_age(&mut self, max_age: time::Duration) -> Result<(), String> {
+        self.index.max_age_secs = max_age.num_seconds();
+        self.save()
+    }
+
+    /// Moves a blob deleted from a container into the trash
+    pub fn trash_blob(&mut self, container: &nfs::directory_listing::DirectoryListing, file: nfs::file::File) -> Result<(), String> {
+        let entry = Trash::new_entry(container, file.get_name(), false);
+        let mut metadata = file.get_metadata();
+        metadata.set_name(entry.get_name());
+        let mut trashed = file;
+        trashed.set_metadata(metadata);
+
+        let _ = self.directory_listing.upsert_file(trashed);
+        let _ = self.index.entries.insert(entry.get_name(), entry);
+        self.purge_and_save()
+    }
+
+    /// Moves a container deleted from its parent into the trash. The content of the container
+    /// is left in place, only the reference to it moves.
+    pub fn trash_container(&mut self, container: &nfs::directory_listing::DirectoryListing, info: nfs::directory_info::DirectoryInfo) -> Result<(), String> {
+        let entry = Trash::new_entry(container, info.get_name(), true);
+        let mut trashed = info;
+        trashed.get_mut_metadata().set_name(entry.get_name());
+
+        let _ = self.directory_listing.upsert_sub_directory(trashed);
+        let _ = self.index.entries.insert(entry.get_name(), entry);
+        self.purge_and_save()
+    }
+
+    /// Puts an entry back into the container it was deleted from, under its original name.
+    /// Fails if that container is gone or already has an entry of that name.
+    pub fn restore_from_trash(&mut self, name: String) -> Result<(), String> {
+        let entry = match self.index.entries.get(&name) {
+            Some(entry) => entry.clone(),
+            None => return Err("Entry not found in trash".to_string()),
+        };
+
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
+        let mut container = try!(directory_helper.get(entry.container_id.clone(), entry.container_parent_id.clone()));
+        if container.find_file(&entry.original_name).is_some() || container.find_sub_directory(&entry.original_name).is_some() {
+            return Err(format!("{} already exists", entry.original_name));
+        }
+
+        if entry.is_container {
+            let mut info = match self.directory_listing.remove_sub_directory(&name) {
+                Some(info) => info,
+                None => return Err("Trashed container missing".to_string()),
+            };
+            info.get_mut_metadata().set_name(entry.get_original_name());
+            let _ = container.upsert_sub_directory(info);
+        } else {
+            let mut file = match self.directory_listing.remove_file(&name) {
+                Some(file) => file,
+                None => return Err("Trashed blob missing".to_string()),
+            };
+            let mut metadata = file.get_metadata();
+            metadata.set_name(entry.get_original_name());
+            file.set_metadata(metadata);
+            let _ = container.upsert_file(file);
+        }
+
+        try!(directory_helper.update(container));
+        let _ = self.index.entries.remove(&name);
+        self.save()
+    }
+
+    /// Drops every entry in the trash
+    pub fn empty_trash(&mut self) -> Result<(), String> {
+        let names: Vec<String> = self.index.entries.keys().cloned().collect();
+        for name in names.iter() {
+            self.drop_entry(name);
+        }
+        self.save()
+    }
+
+    /// Drops the entries which have been in the trash longer than the maximum age. Returns the
+    /// number of entries dropped.
+    pub fn purge_expired(&mut self) -> Result<usize, String> {
+        let cutoff = time::now_utc() - self.get_max_age();
+        self.purge_older_than(cutoff)
+    }
+
+    /// Drops the entries deleted before the cutoff. Returns the number of entries dropped.
+    pub fn purge_older_than(&mut self, cutoff: time::Tm) -> Result<usize, String> {
+        let count = self.drop_older_than(cutoff);
+        if count > 0 {
+            try!(self.save());
+        }
+        Ok(count)
+    }
+
+    fn drop_older_than(&mut self, cutoff: time::Tm) -> usize {
+        let cutoff = cutoff.to_timespec();
+        let expired: Vec<String> = self.index.entries.values()
+                                                     .filter(|entry| (entry.deleted_sec, entry.deleted_nsec) < (cutoff.sec, cutoff.nsec))
+                                                     .map(|entry| entry.get_name())
+                                                     .collect();
+        for name in expired.iter() {
+            self.drop_entry(name);
+        }
+        expired.len()
+    }
+
+    fn drop_entry(&mut self, name: &String) {
+        let _ = self.directory_listing.remove_file(name);
+        let _ = self.directory_listing.remove_sub_directory(name);
+        let _ = self.index.entries.remove(name);
+    }
+
+    fn purge_and_save(&mut self) -> Result<(), String> {
+        let cutoff = time::now_utc() - self.get_max_age();
+        let _ = self.drop_older_than(cutoff);
+        self.save()
+    }
+
+    fn save(&mut self) -> Result<(), String> {
+        self.directory_listing.set_user_metadata(nfs::utils::serialise(self.index.clone()));
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
+        directory_helper.update_listing(&mut self.directory_listing)
+    }
+
+    fn new_entry(container: &nfs::directory_listing::DirectoryListing, original_name: String, is_container: bool) -> TrashEntry {
+        let deleted_time = time::now_utc().to_timespec();
+        TrashEntry {
+            name: format!("{}.{:09}.{}", deleted_time.sec, deleted_time.nsec, original_name),
+            original_name: original_name,
+            container_id: container.get_id(),
+            container_parent_id: container.get_parent_dir_id(),
+            is_container: is_container,
+            deleted_sec: deleted_time.sec,
+            deleted_nsec: deleted_time.nsec,
+        }
+    }
+
+    fn trash_id(owner: &routing::NameType) -> routing::NameType {
+        let mut seed = owner.0.to_vec();
+        seed.extend(b"trash".iter().cloned());
+        routing::NameType(::sodiumoxide::crypto::hash::sha512::hash(&seed[..]).0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nfs::traits::DirectoryListingWrapper;
+
+    fn get_dummy_client() -> ::client::Client {
+        let keyword = "Spandan".to_string();
+        let password = "Sharma".as_bytes();
+        let pin = 1234u32;
+
+        ::client::Client::create_account(&keyword,
+                                         pin,
+                                         &password,
+                                         ::client::non_networking_test_framework::get_new_data_store()).ok().unwrap()
+    }
+
+    #[test]
+    fn delete_restore_and_purge() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let root_id = ::nfs::helper::DirectoryHelper::new(client.clone()).create(parent_id.clone(), "Root".to_string(), Vec::new()).ok().unwrap();
+        let mut container = ::nfs::rest::Container::authorise(client.clone(), root_id.0, parent_id.0).ok().unwrap();
+
+        let writer = container.create_blob("a.txt".to_string(), None, 0).ok().unwrap();
+        assert!(writer.close().is_ok());
+        container = ::nfs::rest::Container::authorise(client.clone(), root_id.0, parent_id.0).ok().unwrap();
+        assert!(container.create("Docs".to_string(), None).is_ok());
+
+        assert!(container.delete_blob("a.txt".to_string()).is_ok());
+        assert!(container.delete_container("Docs".to_string()).is_ok());
+        assert!(container.get_blobs().is_empty());
+        assert!(container.get_containers().is_empty());
+
+        let mut trash = Trash::open(client.clone()).ok().unwrap();
+        let entries = trash.get_entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].get_original_name(), "a.txt".to_string());
+        assert!(!entries[0].is_container());
+        assert_eq!(entries[0].get_container_id(), root_id.0);
+        assert!(entries[1].is_container());
+
+        assert!(trash.restore_from_trash(entries[0].get_name()).is_ok());
+        assert!(trash.restore_from_trash(entries[0].get_name()).is_err());
+        container = ::nfs::rest::Container::authorise(client.clone(), root_id.0, parent_id.0).ok().unwrap();
+        assert!(container.get_blob("a.txt".to_string(), None).is_ok());
+        assert_eq!(container.convert_to_directory_listing().get_files().len(), 1);
+
+        // Nothing is old enough for the default policy, everything is before a cutoff in the future
+        assert_eq!(trash.purge_expired().ok().unwrap(), 0);
+        let cutoff = ::time::now_utc() + ::time::Duration::seconds(1);
+        assert_eq!(trash.purge_older_than(cutoff).ok().unwrap(), 1);
+        assert!(Trash::open(client.clone()).ok().unwrap().get_entries().is_empty());
+
+        assert!(container.delete_blob("a.txt".to_string()).is_ok());
+        let mut trash = Trash::open(client.clone()).ok().unwrap();
+        assert_eq!(trash.get_entries().len(), 1);
+        assert!(trash.empty_trash().is_ok());
+        assert!(Trash::open(client).ok().unwrap().get_entries().is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+use nfs;
+use routing;
+use client;
+use nfs::traits::DirectoryListingWrapper;
+
+/// The standard containers every account has under its root directory
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, RustcEncodable, RustcDecodable)]
+pub enum WellKnown {
+    Documents,
+    Pictures,
+    Music,
+    Videos,
+    Downloads,
+    Public,
+}
+
+impl WellKnown {
+    pub fn all() -> Vec<WellKnown> {
+        vec![WellKnown::Documents, WellKnown::Pictures, WellKnown::Music, WellKnown::Videos, WellKnown::Downloads, WellKnown::Public]
+    }
+
+    /// The well-known container of that name
+    pub fn from_name(name: &str) -> Option<WellKnown> {
+        WellKnown::all().into_iter().find(|well_known| well_known.get_name() == name)
+    }
+
+    /// Name of the container under the root directory
+    pub fn get_name(&self) -> &'static str {
+        match *self {
+            WellKnown::Documents => "Documents",
+            WellKnown::Pictures => "Pictures",
+            WellKnown::Music => "Music",
+            WellKnown::Videos => "Videos",
+            WellKnown::Downloads => "Downloads",
+            WellKnown::Public => "Public",
+        }
+    }
+}
+
+/// Creates the root directory of the user and the well-known containers under it where they
+/// do not exist yet, recording them in the session packet. A container of the same name the
+/// user already made is adopted rather than duplicated. Does nothing once provisioned.
+/// Container::well_known calls it when the account is not provisioned yet, so every account is
+/// provisioned on first use, older accounts included; the binaries and the ffi also call it on
+/// logging in.
+pub fn provision_well_known(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> Result<(), String> {
+    let missing: Vec<WellKnown> = {
+        let client = client.lock().unwrap();
+        WellKnown::all().into_iter().filter(|well_known| client.get_well_known_directory_id(well_known.get_name()).is_none()).collect()
+    };
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let mut root = try!(nfs::rest::Container::user_root(client.clone()));
+    let to_create: Vec<WellKnown> = {
+        let existing = root.convert_to_directory_listing();
+        missing.iter().filter(|well_known| existing.find_sub_directory(well_known.get_name()).is_none()).cloned().collect()
+    };
+    if !to_create.is_empty() {
+        try!(root.transaction(|tx| {
+            for well_known in to_create.iter() {
+                try!(tx.create_container(well_known.get_name().to_string(), None));
+            }
+            Ok(())
+        }));
+    }
+
+    let listing = root.convert_to_directory_listing();
+    let mut dir_ids = ::std::collections::BTreeMap::<String, routing::NameType>::new();
+    for well_known in missing.iter() {
+        match listing.find_sub_directory(well_known.get_name()) {
+            Some(info) => { let _ = dir_ids.insert(well_known.get_name().to_string(), info.get_id()); },
+            None => return Err(format!("Failed to create {}", well_known.get_name())),
+        }
+    }
+    let result = client.lock().unwrap().set_well_known_directory_ids(dir_ids);
+    result.map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn provisioned_once_and_recorded() {
+        let keyword = "Spandan".to_string();
+        let password = "Sharma".as_bytes();
+        let pin = 1234u32;
+        let data_store = ::client::non_networking_test_framework::get_new_data_store();
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(::client::Client::create_account(&keyword, pin, &password, data_store.clone()).ok().unwrap()));
+        assert!(client.lock().unwrap().get_well_known_directory_id("Documents").is_none());
+
+        // Opening one provisions them all
+        let documents = ::nfs::rest::Container::well_known(client.clone(), WellKnown::Documents).ok().unwrap();
+        assert_eq!(documents.get_name(), "Documents".to_string());
+        let pictures = ::nfs::rest::Container::well_known(client.clone(), WellKnown::Pictures).ok().unwrap();
+        assert!(pictures.get_id() != documents.get_id());
+        assert!(provision_well_known(client.clone()).is_ok());
+        assert_eq!(::nfs::rest::Container::well_known(client.clone(), WellKnown::Documents).ok().unwrap().get_id(), documents.get_id());
+
+        let root = ::nfs::rest::Container::user_root(client.clone()).ok().unwrap();
+        assert_eq!(root.get_containers().len(), WellKnown::all().len());
+
+        // Another session finds the same containers through the session packet
+        let other = ::client::Client::log_in(&keyword, pin, &password, data_store).ok().unwrap();
+        assert_eq!(other.get_user_root_directory_id().map(|id| id.0.to_vec()), Some(root.get_id().to_vec()));
+        assert_eq!(other.get_well_known_directory_id("Documents").map(|id| id.0.to_vec()), Some(documents.get_id().to_vec()));
+    }
+}
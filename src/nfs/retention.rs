@@ -0,0 +1,161 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+use routing;
+use time;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+const SECONDS_PER_WEEK: i64 = 7 * SECONDS_PER_DAY;
+
+/// Which versions of a directory to keep when pruning. A version is kept if any of the rules
+/// keeps it, and the latest version is always kept. A policy with no rules keeps everything.
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+pub struct RetentionPolicy {
+    keep_last: Option<usize>,
+    keep_within_secs: Option<i64>,
+    keep_daily: Option<usize>,
+    keep_weekly: Option<usize>,
+}
+
+impl RetentionPolicy {
+    pub fn new() -> RetentionPolicy {
+        RetentionPolicy {
+            keep_last: None,
+            keep_within_secs: None,
+            keep_daily: None,
+            keep_weekly: None,
+        }
+    }
+
+    /// Keep the most recent `count` versions
+    pub fn set_keep_last(&mut self, count: Option<usize>) {
+        self.keep_last = count;
+    }
+
+    /// Keep every version younger than `age`
+    pub fn set_keep_within(&mut self, age: Option<time::Duration>) {
+        self.keep_within_secs = age.map(|age| age.num_seconds());
+    }
+
+    /// Keep the latest version of each of the `count` most recent days having versions
+    pub fn set_keep_daily(&mut self, count: Option<usize>) {
+        self.keep_daily = count;
+    }
+
+    /// Keep the latest version of each of the `count` most recent weeks having versions
+    pub fn set_keep_weekly(&mut self, count: Option<usize>) {
+        self.keep_weekly = count;
+    }
+
+    fn keeps_everything(&self) -> bool {
+        self.keep_last.is_none() && self.keep_within_secs.is_none() && self.keep_daily.is_none() && self.keep_weekly.is_none()
+    }
+
+    /// Indices of the versions to keep, given the time each was saved, oldest first
+    pub fn select(&self, saved_times: &[time::Tm], now: time::Tm) -> ::std::collections::BTreeSet<usize> {
+        let mut keep = ::std::collections::BTreeSet::new();
+        if saved_times.is_empty() {
+            return keep;
+        }
+        if self.keeps_everything() {
+            return (0..saved_times.len()).collect();
+        }
+
+        let _ = keep.insert(saved_times.len() - 1);
+        if let Some(count) = self.keep_last {
+            keep.extend(saved_times.len().saturating_sub(count)..saved_times.len());
+        }
+        if let Some(age_secs) = self.keep_within_secs {
+            let cutoff = now.to_timespec().sec - age_secs;
+            keep.extend((0..saved_times.len()).filter(|&index| saved_times[index].to_timespec().sec >= cutoff));
+        }
+        if let Some(count) = self.keep_daily {
+            keep.extend(RetentionPolicy::latest_per_period(saved_times, SECONDS_PER_DAY, count));
+        }
+        if let Some(count) = self.keep_weekly {
+            keep.extend(RetentionPolicy::latest_per_period(saved_times, SECONDS_PER_WEEK, count));
+        }
+        keep
+    }
+
+    /// The latest version in each of the `count` most recent periods having versions
+    fn latest_per_period(saved_times: &[time::Tm], period_secs: i64, count: usize) -> Vec<usize> {
+        let mut selected = Vec::new();
+        let mut last_period = None;
+        for index in (0..saved_times.len()).rev() {
+            if selected.len() == count {
+                break;
+            }
+            let period = RetentionPolicy::period_of(saved_times[index].to_timespec().sec, period_secs);
+            if last_period != Some(period) {
+                selected.push(index);
+                last_period = Some(period);
+            }
+        }
+        selected
+    }
+
+    fn period_of(sec: i64, period_secs: i64) -> i64 {
+        if sec >= 0 { sec / period_secs } else { (sec - period_secs + 1) / period_secs }
+    }
+}
+
+/// Outcome of pruning the versions of a directory
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PruneReport {
+    /// Versions left in the version list, oldest first
+    pub kept: Vec<routing::NameType>,
+    /// Versions dropped from the version list
+    pub removed: Vec<routing::NameType>,
+    /// ImmutableData no longer referenced by any kept version, including listing shards
+    pub unreferenced: Vec<routing::NameType>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn times(hours: &[i64]) -> Vec<::time::Tm> {
+        hours.iter().map(|hour| ::time::at_utc(::time::Timespec { sec: hour * 60 * 60, nsec: 0 })).collect()
+    }
+
+    #[test]
+    fn select() {
+        // Two versions a day over four days
+        let saved_times = times(&[1, 13, 25, 37, 49, 61, 73, 85]);
+        let now = saved_times[7];
+
+        let mut policy = RetentionPolicy::new();
+        assert_eq!(policy.select(&saved_times[..], now).len(), 8);
+
+        policy.set_keep_last(Some(2));
+        assert_eq!(policy.select(&saved_times[..], now).into_iter().collect::<Vec<usize>>(), vec![6, 7]);
+
+        let mut policy = RetentionPolicy::new();
+        policy.set_keep_within(Some(::time::Duration::hours(30)));
+        assert_eq!(policy.select(&saved_times[..], now).into_iter().collect::<Vec<usize>>(), vec![5, 6, 7]);
+
+        let mut policy = RetentionPolicy::new();
+        policy.set_keep_daily(Some(3));
+        assert_eq!(policy.select(&saved_times[..], now).into_iter().collect::<Vec<usize>>(), vec![3, 5, 7]);
+
+        let mut policy = RetentionPolicy::new();
+        policy.set_keep_weekly(Some(1));
+        assert_eq!(policy.select(&saved_times[..], now).into_iter().collect::<Vec<usize>>(), vec![7]);
+
+        assert!(policy.select(&[], now).is_empty());
+    }
+}
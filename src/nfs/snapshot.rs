@@ -0,0 +1,310 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+use nfs;
+use routing;
+use routing::sendable::Sendable;
+use maidsafe_types;
+use maidsafe_types::TypeTag;
+use client;
+use time;
+use nfs::traits::DirectoryListingWrapper;
+
+/// Version of every directory of a tree at the time a snapshot was taken. Stored encrypted as
+/// ImmutableData.
+#[derive(RustcEncodable, RustcDecodable, Clone)]
+struct Manifest {
+    name: String,
+    created_sec: i64,
+    created_nsec: i32,
+    root_id: routing::NameType,
+    root_parent_id: routing::NameType,
+    // (directory id, parent directory id, version)
+    directories: Vec<(routing::NameType, routing::NameType, routing::NameType)>,
+}
+
+/// Describes a snapshot
+#[derive(Clone, Debug)]
+pub struct SnapshotInfo {
+    name: String,
+    created_time: time::Tm,
+    root_id: [u8; 64],
+    directory_count: usize,
+}
+
+impl SnapshotInfo {
+    fn from_manifest(manifest: &Manifest) -> SnapshotInfo {
+        SnapshotInfo {
+            name: manifest.name.clone(),
+            created_time: time::at_utc(time::Timespec { sec: manifest.created_sec, nsec: manifest.created_nsec }),
+            root_id: manifest.root_id.0,
+            directory_count: manifest.directories.len(),
+        }
+    }
+
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn get_created_time(&self) -> time::Tm {
+        self.created_time
+    }
+
+    /// Id of the container the snapshot was taken of
+    pub fn get_root_id(&self) -> [u8; 64] {
+        self.root_id
+    }
+
+    /// Number of containers recorded, the root included
+    pub fn get_directory_count(&self) -> usize {
+        self.directory_count
+    }
+}
+
+/// Records the current version of the container and of every container below it under the
+/// given name, which must be unique within the account. prune_versions keeps the versions
+/// recorded, so the snapshot can always be browsed and rolled back to.
+pub fn create(root: &nfs::rest::Container, name: &str) -> Result<SnapshotInfo, String> {
+    let client = try!(root.get_client());
+    let root_listing = root.convert_to_directory_listing();
+    if try!(load_manifests(&client)).iter().any(|&(_, ref manifest)| manifest.name == name) {
+        return Err(format!("Snapshot {} already exists", name));
+    }
+
+    let mut directory_helper = nfs::helper::DirectoryHelper::new(client.clone());
+    let mut directories = Vec::new();
+    let mut pending = vec![(root_listing.get_id(), root_listing.get_parent_dir_id())];
+    while let Some((id, parent_id)) = pending.pop() {
+        let version = match try!(directory_helper.get_versions(id.clone())).last() {
+            Some(version) => version.clone(),
+            None => return Err("Directory has no versions".to_string()),
+        };
+        let listing = try!(directory_helper.get_by_version(id.clone(), parent_id.clone(), version.clone()));
+        pending.extend(listing.get_sub_directories().map(|info| (info.get_id(), id.clone())));
+        directories.push((id, parent_id, version));
+    }
+
+    let created_time = time::now_utc().to_timespec();
+    let manifest = Manifest {
+        name: name.to_string(),
+        created_sec: created_time.sec,
+        created_nsec: created_time.nsec,
+        root_id: root_listing.get_id(),
+        root_parent_id: root_listing.get_parent_dir_id(),
+        directories: directories,
+    };
+
+    let encrypted = match client.lock().unwrap().hybrid_encrypt(&nfs::utils::serialise(manifest.clone())[..], None) {
+        Ok(encrypted) => encrypted,
+        Err(_) => return Err("Encryption failed".to_string()),
+    };
+    let immutable_data = maidsafe_types::ImmutableData::new(encrypted);
+    try!(nfs::utils::network_put(&client, immutable_data.clone()));
+
+    let index_name = index_id(&client);
+    let structured_data_type_id: maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
+    // Only a missing index is started afresh: one which could not be fetched would be overwritten
+    let index = match try!(nfs::utils::network_get(&client, structured_data_type_id.type_tag(), index_name.clone())) {
+        Some(data) => {
+            let mut index: maidsafe_types::StructuredData = nfs::utils::deserialise(data);
+            let mut manifests = index.value();
+            manifests.push(immutable_data.name());
+            index.set_value(manifests);
+            index
+        },
+        None => {
+            let owner = client.lock().unwrap().get_owner();
+            maidsafe_types::StructuredData::new(index_name, owner, vec![immutable_data.name()])
+        },
+    };
+    try!(nfs::utils::network_put(&client, index));
+
+    Ok(SnapshotInfo::from_manifest(&manifest))
+}
+
+/// Snapshots of the account, oldest first
+pub fn list(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> Result<Vec<SnapshotInfo>, String> {
+    Ok(try!(load_manifests(&client)).iter().map(|&(_, ref manifest)| SnapshotInfo::from_manifest(manifest)).collect())
+}
+
+/// Opens the root of a snapshot as a read-only Container. Containers reached from it are opened
+/// at the versions recorded in the snapshot.
+pub fn browse(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>, name: &str) -> Result<nfs::rest::Container, String> {
+    let manifest = try!(find_manifest(&client, name));
+    let pinned_versions: ::std::collections::BTreeMap<routing::NameType, routing::NameType> =
+        manifest.directories.iter().map(|&(ref id, _, ref version)| (id.clone(), version.clone())).collect();
+
+    let mut directory_helper = nfs::helper::DirectoryHelper::new(client.clone());
+    let root_version = match pinned_versions.get(&manifest.root_id) {
+        Some(version) => version.clone(),
+        None => return Err("Snapshot manifest misses its root".to_string()),
+    };
+    let root_listing = try!(directory_helper.get_by_version(manifest.root_id.clone(), manifest.root_parent_id.clone(), root_version));
+    Ok(nfs::rest::Container::pinned(client, root_listing, ::std::sync::Arc::new(pinned_versions)))
+}
+
+/// Brings every container recorded in the snapshot back to its recorded version, by storing
+/// that version again as the latest one. Containers created since are no longer referenced.
+pub fn rollback(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>, name: &str) -> Result<(), String> {
+    let manifest = try!(find_manifest(&client, name));
+    let mut directory_helper = nfs::helper::DirectoryHelper::new(client.clone());
+    for &(ref id, ref parent_id, ref version) in manifest.directories.iter() {
+        let versions = try!(directory_helper.get_versions(id.clone()));
+        if versions.last() == Some(version) {
+            continue;
+        }
+        let listing = try!(directory_helper.get_by_version(id.clone(), parent_id.clone(), version.clone()));
+        try!(directory_helper.update(listing));
+    }
+    Ok(())
+}
+
+/// Versions of the directory recorded by any snapshot of the account
+pub fn pinned_versions(client: &::std::sync::Arc<::std::sync::Mutex<client::Client>>, directory_id: &routing::NameType) -> Result<Vec<routing::NameType>, String> {
+    Ok(try!(load_manifests(client)).into_iter().flat_map(|(_, manifest)| manifest.directories.into_iter())
+        .filter(|&(ref id, _, _)| id == directory_id)
+        .map(|(_, _, version)| version)
+        .collect())
+}
+
+fn find_manifest(client: &::std::sync::Arc<::std::sync::Mutex<client::Client>>, name: &str) -> Result<Manifest, String> {
+    match try!(load_manifests(client)).into_iter().find(|&(_, ref manifest)| manifest.name == name) {
+        Some((_, manifest)) => Ok(manifest),
+        None => Err(format!("Snapshot {} not found", name)),
+    }
+}
+
+fn load_manifests(client: &::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> Result<Vec<(routing::NameType, Manifest)>, String> {
+    let structured_data_type_id: maidsafe_types::data::StructuredDataTypeTag = unsafe { ::std::mem::uninitialized() };
+    let index: maidsafe_types::StructuredData = match try!(nfs::utils::network_get(client, structured_data_type_id.type_tag(), index_id(client))) {
+        Some(data) => nfs::utils::deserialise(data),
+        // No snapshot taken yet
+        None => return Ok(Vec::new()),
+    };
+
+    let mut manifests = Vec::new();
+    for manifest_name in index.value() {
+        let immutable_data_type_id: maidsafe_types::data::ImmutableDataTypeTag = unsafe { ::std::mem::uninitialized() };
+        let immutable_data: maidsafe_types::ImmutableData = match try!(nfs::utils::network_get(client, immutable_data_type_id.type_tag(), manifest_name.clone())) {
+            Some(data) => nfs::utils::deserialise(data),
+            None => return Err("Snapshot manifest missing".to_string()),
+        };
+        let decrypted = match client.lock().unwrap().hybrid_decrypt(&immutable_data.value()[..], None) {
+            Some(decrypted) => decrypted,
+            None => return Err("Failed to decrypt".to_string()),
+        };
+        manifests.push((manifest_name, nfs::utils::deserialise(decrypted)));
+    }
+    Ok(manifests)
+}
+
+/// Id of the StructuredData listing the snapshot manifests of the account
+fn index_id(client: &::std::sync::Arc<::std::sync::Mutex<client::Client>>) -> routing::NameType {
+    let mut seed = client.lock().unwrap().get_owner().0.to_vec();
+    seed.extend(b"snapshots".iter().cloned());
+    routing::NameType(::sodiumoxide::crypto::hash::sha512::hash(&seed[..]).0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_dummy_client() -> ::client::Client {
+        let keyword = "Spandan".to_string();
+        let password = "Sharma".as_bytes();
+        let pin = 1234u32;
+
+        ::client::Client::create_account(&keyword,
+                                         pin,
+                                         &password,
+                                         ::client::non_networking_test_framework::get_new_data_store()).ok().unwrap()
+    }
+
+    #[test]
+    fn create_browse_and_rollback() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let root_id = ::nfs::helper::DirectoryHelper::new(client.clone()).create(parent_id.clone(), "Root".to_string(), Vec::new()).ok().unwrap();
+        let authorise = || ::nfs::rest::Container::authorise(client.clone(), root_id.0, parent_id.0).ok().unwrap();
+
+        let mut root = authorise();
+        assert!(root.create("Docs".to_string(), None).is_ok());
+        assert!(root.create_blob("a.txt".to_string(), None, 0).ok().unwrap().close().is_ok());
+
+        let snapshot = create(&authorise(), "before-migration").ok().unwrap();
+        assert_eq!(snapshot.get_directory_count(), 2);
+        assert!(create(&authorise(), "before-migration").is_err());
+
+        // Change the tree after the snapshot
+        let mut root = authorise();
+        assert!(root.delete_blob("a.txt".to_string()).is_ok());
+        let mut docs = root.get_container("Docs".to_string(), None).ok().unwrap();
+        assert!(docs.create_blob("b.txt".to_string(), None, 0).ok().unwrap().close().is_ok());
+
+        let snapshots = list(client.clone()).ok().unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].get_name(), "before-migration".to_string());
+        assert_eq!(snapshots[0].get_root_id(), root_id.0);
+
+        let mut browsed = browse(client.clone(), "before-migration").ok().unwrap();
+        assert!(browsed.is_read_only());
+        assert!(browsed.get_blob("a.txt".to_string(), None).is_ok());
+        assert!(browsed.create_blob("c.txt".to_string(), None, 0).is_err());
+        let browsed_docs = browsed.get_container("Docs".to_string(), None).ok().unwrap();
+        assert!(browsed_docs.is_read_only());
+        assert!(browsed_docs.get_blobs().is_empty());
+        assert!(browse(client.clone(), "missing").is_err());
+
+        assert!(rollback(client.clone(), "before-migration").is_ok());
+        let mut root = authorise();
+        assert!(root.get_blob("a.txt".to_string(), None).is_ok());
+        assert!(root.get_container("Docs".to_string(), None).ok().unwrap().get_blobs().is_empty());
+    }
+
+    #[test]
+    fn pruning_keeps_snapshot_versions() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let root_id = ::nfs::helper::DirectoryHelper::new(client.clone()).create(parent_id.clone(), "Root".to_string(), Vec::new()).ok().unwrap();
+        let authorise = || ::nfs::rest::Container::authorise(client.clone(), root_id.0, parent_id.0).ok().unwrap();
+
+        let mut root = authorise();
+        assert!(root.create_blob("a.txt".to_string(), None, 0).ok().unwrap().close().is_ok());
+        assert!(create(&authorise(), "before-prune").is_ok());
+        let pinned = ::nfs::helper::DirectoryHelper::new(client.clone()).get_versions(root_id.clone()).ok().unwrap().last().unwrap().clone();
+
+        let mut root = authorise();
+        assert!(root.delete_blob("a.txt".to_string()).is_ok());
+        assert!(root.create_blob("b.txt".to_string(), None, 0).ok().unwrap().close().is_ok());
+        let mut policy = ::nfs::retention::RetentionPolicy::new();
+        policy.set_keep_last(Some(1));
+        let mut root = authorise();
+        assert!(root.set_retention_policy(Some(policy)).is_ok());
+
+        let report = root.prune_versions().ok().unwrap();
+        assert!(!report.removed.is_empty());
+        assert!(report.kept.contains(&pinned));
+        assert!(!report.removed.contains(&pinned));
+        assert!(!report.unreferenced.contains(&pinned));
+
+        let browsed = browse(client.clone(), "before-prune").ok().unwrap();
+        assert!(browsed.get_blob("a.txt".to_string(), None).is_ok());
+        assert!(rollback(client.clone(), "before-prune").is_ok());
+        let root = authorise();
+        assert!(root.get_blob("a.txt".to_string(), None).is_ok());
+        assert!(root.get_blob("b.txt".to_string(), None).is_err());
+    }
+}
@@ -0,0 +1,819 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use nfs;
+use routing;
+use client;
+use super::state::{DirectoryState, FileState, LocalStamp, SyncState, STATE_FILE_NAME};
+
+/// Appended to the name of a file being downloaded until it is complete
+static PART_SUFFIX: &'static str = ".safe_sync_part";
+
+/// Direction in which changes are propagated
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SyncMode {
+    /// The remote directory is made to mirror the local folder
+    Upload,
+    /// The local folder is made to mirror the remote directory
+    Download,
+    /// Changes on either side are propagated to the other. Conflicting changes are kept side by side
+    TwoWay,
+}
+
+/// Outcome of a sync run. Paths are relative to the synchronised root.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SyncReport {
+    pub pushed: Vec<String>,
+    pub pulled: Vec<String>,
+    pub deleted_local: Vec<String>,
+    pub deleted_remote: Vec<String>,
+    pub conflicts: Vec<String>,
+    pub unchanged_directories: usize,
+}
+
+impl SyncReport {
+    fn new() -> SyncReport {
+        SyncReport {
+            pushed: Vec::new(),
+            pulled: Vec::new(),
+            deleted_local: Vec::new(),
+            deleted_remote: Vec::new(),
+            conflicts: Vec::new(),
+            unchanged_directories: 0,
+        }
+    }
+}
+
+/// Keeps a local folder and a network directory in step
+pub struct SyncEngine {
+    client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+    local_root: ::std::path::PathBuf,
+    directory_id: routing::NameType,
+    parent_directory_id: routing::NameType,
+    mode: SyncMode,
+}
+
+/// What a local folder holds, files by hash
+struct LocalScan {
+    files: ::std::collections::BTreeMap<String, Vec<u8>>,
+    stamps: ::std::collections::BTreeMap<String, LocalStamp>,
+    directories: ::std::collections::BTreeSet<String>,
+}
+
+/// Remote changes made by a pass over one directory, staged in a Transaction so that they are
+/// committed as a single version. They are only recorded in the sync state once committed, so
+/// that changes which failed to reach the network are made again by the next pass.
+struct Changes {
+    transaction: nfs::rest::Transaction,
+    // Names of the files pushed with the hash of the content pushed
+    pushed: Vec<(String, Vec<u8>)>,
+    deleted_files: Vec<String>,
+    created_directories: Vec<String>,
+    deleted_directories: Vec<String>,
+}
+
+impl Changes {
+    fn new(transaction: nfs::rest::Transaction) -> Changes {
+        Changes {
+            transaction: transaction,
+            pushed: Vec::new(),
+            deleted_files: Vec::new(),
+            created_directories: Vec::new(),
+            deleted_directories: Vec::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pushed.is_empty() && self.deleted_files.is_empty() && self.created_directories.is_empty() && self.deleted_directories.is_empty()
+    }
+}
+
+enum Action {
+    Nothing,
+    Push,
+    Pull,
+    DeleteLocal,
+    DeleteRemote,
+    Conflict,
+    Forget,
+}
+
+impl SyncEngine {
+    /// Create a new SyncEngine for the local folder and the directory identified by directory_id
+    pub fn new(client: ::std::sync::Arc<::std::sync::Mutex<client::Client>>,
+               local_root: ::std::path::PathBuf,
+               directory_id: routing::NameType,
+               parent_directory_id: routing::NameType,
+               mode: SyncMode) -> SyncEngine {
+        SyncEngine {
+            client: client,
+            local_root: local_root,
+            directory_id: directory_id,
+            parent_directory_id: parent_directory_id,
+            mode: mode,
+        }
+    }
+
+    /// Performs one sync pass. Only directories whose remote version or local content changed since
+    /// the previous pass are fetched and compared. Sub-directories have StructuredData of their
+    /// own, so a change below one does not show in its parent's version and each is still looked
+    /// at, but an untouched one costs a version lookup and a read of the local folder: files whose
+    /// size and modification time are as recorded are not hashed again. The changes a pass makes to
+    /// a remote directory are saved as one new version of it, with deleted entries moved to the
+    /// trash.
+    pub fn run(&mut self) -> Result<SyncReport, String> {
+        if ::std::fs::create_dir_all(&self.local_root).is_err() {
+            return Err("Failed to create local folder".to_string());
+        }
+
+        let mut state = try!(SyncState::load(&self.local_root, self.directory_id.clone(), self.parent_directory_id.clone()));
+        let mut report = SyncReport::new();
+        let local_root = self.local_root.clone();
+
+        let result = self.sync_directory(&local_root, "", state.get_mut_root(), &mut report);
+        // Whatever was synchronised before a failure is still recorded so that the next pass resumes
+        try!(state.save(&self.local_root));
+        try!(result);
+
+        Ok(report)
+    }
+
+    fn sync_directory(&self,
+                      local_path: &::std::path::Path,
+                      relative_path: &str,
+                      state: &mut DirectoryState,
+                      report: &mut SyncReport) -> Result<(), String> {
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
+        let latest_version = try!(directory_helper.get_versions(state.get_id())).last().map(|version| version.clone());
+        let scan = try!(self.scan_local(local_path, relative_path.is_empty(), state));
+        let local_files = &scan.files;
+        let local_directories = &scan.directories;
+
+        let remote_changed = latest_version != state.get_version();
+        let local_changed = local_files.len() != state.get_files().len() ||
+                            local_directories.len() != state.get_sub_directories().len() ||
+                            local_files.iter().any(|(name, hash)| state.get_files().get(name).map_or(true, |file_state| file_state.get_hash() != hash)) ||
+                            local_directories.iter().any(|name| !state.get_sub_directories().contains_key(name));
+
+        if !remote_changed && !local_changed {
+            report.unchanged_directories += 1;
+            SyncEngine::record_stamps(state, &scan);
+            return self.sync_children(local_path, relative_path, state, report);
+        }
+
+        let listing = try!(self.get_listing(state));
+        let read_version = listing.get_version();
+        let mut changes = Changes::new(nfs::rest::Transaction::new(self.client.clone(), listing));
+        let result = self.sync_files(local_path, relative_path, local_files, state, &mut changes, report)
+                         .and_then(|()| self.sync_sub_directories(local_path, relative_path, local_directories, state, &mut changes, report));
+        // Whatever was changed before a failure is still committed so that the next pass resumes
+        let committed = self.commit(state, changes, read_version);
+        try!(result);
+        let version = try!(committed);
+        SyncEngine::record_stamps(state, &scan);
+        state.set_version(version);
+
+        self.sync_children(local_path, relative_path, state, report)
+    }
+
+    fn sync_children(&self,
+                     local_path: &::std::path::Path,
+                     relative_path: &str,
+                     state: &mut DirectoryState,
+                     report: &mut SyncReport) -> Result<(), String> {
+        let names: Vec<String> = state.get_sub_directories().keys().cloned().collect();
+        for name in names {
+            let sub_state = state.get_mut_sub_directories().get_mut(&name).unwrap();
+            try!(self.sync_directory(&local_path.join(&name), &SyncEngine::join(relative_path, &name), sub_state, report));
+        }
+        Ok(())
+    }
+
+    /// Commits the changes and records them in the state. Returns the version to record for the
+    /// directory: the committed one, unless another client stored a version since the listing was
+    /// read, in which case its changes are yet to be synchronised and the version read is kept.
+    fn commit(&self, state: &mut DirectoryState, changes: Changes, read_version: Option<routing::NameType>) -> Result<Option<routing::NameType>, String> {
+        if changes.is_empty() {
+            return Ok(read_version);
+        }
+        let Changes { transaction, pushed, deleted_files, created_directories, deleted_directories } = changes;
+        let listing = try!(transaction.commit());
+
+        for (name, hash) in pushed.into_iter() {
+            let file_state = match listing.find_file(&name) {
+                Some(file) => FileState::new(hash, file.get_metadata().get_size(), file.get_metadata().get_modified_time()),
+                None => return Err(format!("{} missing after upload", name)),
+            };
+            let _ = state.get_mut_files().insert(name, file_state);
+        }
+        for name in deleted_files.iter() {
+            let _ = state.get_mut_files().remove(name);
+        }
+        let state_id = state.get_id();
+        for name in created_directories.into_iter() {
+            let created_id = match listing.find_sub_directory(&name) {
+                Some(info) => info.get_id(),
+                None => return Err(format!("{} missing after creating it", name)),
+            };
+            let _ = state.get_mut_sub_directories().insert(name, DirectoryState::new(created_id, state_id.clone()));
+        }
+        for name in deleted_directories.iter() {
+            let _ = state.get_mut_sub_directories().remove(name);
+        }
+
+        let committed = listing.get_version();
+        let versions = try!(nfs::helper::DirectoryHelper::new(self.client.clone()).get_versions(state.get_id()));
+        let previous = versions.iter().position(|version| Some(version) == committed.as_ref())
+                                      .and_then(|index| if index > 0 { versions.get(index - 1) } else { None });
+        if read_version.is_some() && previous == read_version.as_ref() {
+            Ok(committed)
+        } else {
+            Ok(read_version)
+        }
+    }
+
+    fn sync_files(&self,
+                  local_path: &::std::path::Path,
+                  relative_path: &str,
+                  local_files: &::std::collections::BTreeMap<String, Vec<u8>>,
+                  state: &mut DirectoryState,
+                  changes: &mut Changes,
+                  report: &mut SyncReport) -> Result<(), String> {
+        let remote_files: ::std::collections::BTreeMap<String, nfs::file::File> = changes.transaction.get_directory_listing().get_files()
+                                                                                          .map(|file| (file.get_name(), file.clone()))
+                                                                                          .collect();
+
+        let mut names: ::std::collections::BTreeSet<String> = local_files.keys().cloned().collect();
+        names.extend(remote_files.keys().cloned());
+        names.extend(state.get_files().keys().cloned());
+
+        for name in names {
+            let local_hash = local_files.get(&name);
+            let remote_file = remote_files.get(&name);
+            let recorded = state.get_files().get(&name).cloned();
+            let file_path = local_path.join(&name);
+            let display_path = SyncEngine::join(relative_path, &name);
+
+            let local_modified = match (local_hash, recorded.as_ref()) {
+                (Some(hash), Some(file_state)) => hash != file_state.get_hash(),
+                (None, None) => false,
+                _ => true,
+            };
+            let remote_modified = match (remote_file, recorded.as_ref()) {
+                (Some(file), Some(file_state)) => !file_state.matches_remote(file),
+                (None, None) => false,
+                _ => true,
+            };
+
+            let action = match (local_hash.is_some(), remote_file.is_some()) {
+                (false, false) => Action::Forget,
+                (true, false) => match self.mode {
+                    SyncMode::Upload => Action::Push,
+                    SyncMode::Download => Action::DeleteLocal,
+                    SyncMode::TwoWay => if recorded.is_some() && !local_modified { Action::DeleteLocal } else { Action::Push },
+                },
+                (false, true) => match self.mode {
+                    SyncMode::Upload => Action::DeleteRemote,
+                    SyncMode::Download => Action::Pull,
+                    SyncMode::TwoWay => if recorded.is_some() && !remote_modified { Action::DeleteRemote } else { Action::Pull },
+                },
+                (true, true) => match self.mode {
+                    _ if !local_modified && !remote_modified => Action::Nothing,
+                    SyncMode::Upload => Action::Push,
+                    SyncMode::Download => Action::Pull,
+                    SyncMode::TwoWay => match (local_modified, remote_modified) {
+                        (true, true) => Action::Conflict,
+                        (true, false) => Action::Push,
+                        _ => Action::Pull,
+                    },
+                },
+            };
+
+            match action {
+                Action::Nothing => (),
+                Action::Forget => {
+                    let _ = state.get_mut_files().remove(&name);
+                },
+                Action::Push => {
+                    let content = try!(SyncEngine::read_local(&file_path));
+                    try!(SyncEngine::push(changes, &name, content));
+                    report.pushed.push(display_path);
+                },
+                Action::Pull => {
+                    let file = remote_file.unwrap().clone();
+                    let content = try!(self.pull(file.clone()));
+                    try!(SyncEngine::write_local(&file_path, &content[..]));
+                    let mut file_state = FileState::new(SyncEngine::hash(&content[..]),
+                                                        file.get_metadata().get_size(),
+                                                        file.get_metadata().get_modified_time());
+                    file_state.set_local_stamp(SyncEngine::stamp_of(&file_path));
+                    let _ = state.get_mut_files().insert(name.clone(), file_state);
+                    report.pulled.push(display_path);
+                },
+                Action::DeleteLocal => {
+                    if ::std::fs::remove_file(&file_path).is_err() {
+                        return Err(format!("Failed to delete {}", display_path));
+                    }
+                    let _ = state.get_mut_files().remove(&name);
+                    report.deleted_local.push(display_path);
+                },
+                Action::DeleteRemote => {
+                    try!(changes.transaction.delete_blob(name.clone()));
+                    changes.deleted_files.push(name.clone());
+                    report.deleted_remote.push(display_path);
+                },
+                Action::Conflict => {
+                    let remote_content = try!(self.pull(remote_file.unwrap().clone()));
+                    let local_content = try!(SyncEngine::read_local(&file_path));
+                    if SyncEngine::hash(&remote_content[..]) == *local_hash.unwrap() {
+                        // Both sides made the same change
+                        let file = remote_file.unwrap();
+                        let _ = state.get_mut_files().insert(name.clone(), FileState::new(local_hash.unwrap().clone(),
+                                                                                          file.get_metadata().get_size(),
+                                                                                          file.get_metadata().get_modified_time()));
+                        continue;
+                    }
+
+                    // The local copy keeps the original name and the remote one is kept next to it on both sides
+                    let conflict_name = SyncEngine::conflict_name(&name);
+                    try!(SyncEngine::write_local(&local_path.join(&conflict_name), &remote_content[..]));
+                    try!(SyncEngine::push(changes, &conflict_name, remote_content));
+                    try!(SyncEngine::push(changes, &name, local_content));
+                    report.conflicts.push(SyncEngine::join(relative_path, &conflict_name));
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn sync_sub_directories(&self,
+                            local_path: &::std::path::Path,
+                            relative_path: &str,
+                            local_directories: &::std::collections::BTreeSet<String>,
+                            state: &mut DirectoryState,
+                            changes: &mut Changes,
+                            report: &mut SyncReport) -> Result<(), String> {
+        let remote_directories: ::std::collections::BTreeMap<String, nfs::directory_info::DirectoryInfo> = changes.transaction
+                                                                                                                  .get_directory_listing()
+                                                                                                                  .get_sub_directories()
+                                                                                                                  .map(|info| (info.get_name(), info.clone()))
+                                                                                                                  .collect();
+
+        let mut names: ::std::collections::BTreeSet<String> = local_directories.clone();
+        names.extend(remote_directories.keys().cloned());
+        names.extend(state.get_sub_directories().keys().cloned());
+
+        for name in names {
+            let local_exists = local_directories.contains(&name);
+            let remote_info = remote_directories.get(&name);
+            let recorded = state.get_sub_directories().contains_key(&name);
+            let directory_path = local_path.join(&name);
+            let display_path = SyncEngine::join(relative_path, &name);
+
+            let state_id = state.get_id();
+
+            match (local_exists, remote_info) {
+                (false, None) => {
+                    let _ = state.get_mut_sub_directories().remove(&name);
+                },
+                (true, None) => {
+                    let delete_local = match self.mode {
+                        SyncMode::Upload => false,
+                        SyncMode::Download => true,
+                        SyncMode::TwoWay => recorded,
+                    };
+                    if delete_local {
+                        let unchanged = self.mode != SyncMode::TwoWay ||
+                                        try!(self.local_matches(&directory_path, &state.get_sub_directories()[&name]));
+                        if unchanged {
+                            if ::std::fs::remove_dir_all(&directory_path).is_err() {
+                                return Err(format!("Failed to delete {}", display_path));
+                            }
+                            let _ = state.get_mut_sub_directories().remove(&name);
+                            report.deleted_local.push(display_path);
+                            continue;
+                        }
+                        // Changed here since it was deleted remotely, so it is sent back rather than lost
+                        report.conflicts.push(display_path.clone());
+                    }
+                    try!(changes.transaction.create_container(name.clone(), None));
+                    changes.created_directories.push(name);
+                },
+                (false, Some(info)) => {
+                    let delete_remote = match self.mode {
+                        SyncMode::Upload => true,
+                        SyncMode::Download => false,
+                        SyncMode::TwoWay => recorded,
+                    };
+                    if delete_remote {
+                        let unchanged = self.mode != SyncMode::TwoWay || {
+                            let recorded_state = &state.get_sub_directories()[&name];
+                            recorded_state.get_id() == info.get_id() && try!(self.remote_matches(recorded_state))
+                        };
+                        if unchanged {
+                            try!(changes.transaction.delete_container(name.clone()));
+                            changes.deleted_directories.push(name);
+                            report.deleted_remote.push(display_path);
+                            continue;
+                        }
+                        // Changed remotely since it was deleted here, so it is fetched again rather than lost
+                        report.conflicts.push(display_path.clone());
+                    }
+                    if ::std::fs::create_dir_all(&directory_path).is_err() {
+                        return Err(format!("Failed to create {}", display_path));
+                    }
+                    let _ = state.get_mut_sub_directories().insert(name.clone(), DirectoryState::new(info.get_id(), state_id));
+                },
+                (true, Some(info)) => {
+                    let stale = state.get_sub_directories().get(&name).map_or(true, |sub_state| sub_state.get_id() != info.get_id());
+                    if stale {
+                        let _ = state.get_mut_sub_directories().insert(name.clone(), DirectoryState::new(info.get_id(), state_id));
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_listing(&self, state: &DirectoryState) -> Result<nfs::directory_listing::DirectoryListing, String> {
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
+        directory_helper.get(state.get_id(), state.get_parent_id())
+    }
+
+    /// Stages the content as the new content of the file, creating it if the directory has none
+    fn push(changes: &mut Changes, name: &String, content: Vec<u8>) -> Result<(), String> {
+        let hash = SyncEngine::hash(&content[..]);
+        if changes.transaction.get_directory_listing().find_file(name).is_some() {
+            try!(changes.transaction.update_blob(name.clone(), &content[..]));
+        } else {
+            try!(changes.transaction.create_blob(name.clone(), None, &content[..]));
+        }
+        changes.pushed.push((name.clone(), hash));
+        Ok(())
+    }
+
+    fn pull(&self, file: nfs::file::File) -> Result<Vec<u8>, String> {
+        let mut file_helper = nfs::helper::FileHelper::new(self.client.clone());
+        let mut reader = file_helper.read(file);
+        let size = reader.size();
+        match reader.read(0, size) {
+            Ok(content) => Ok(content),
+            Err(error) => Err(error.to_string()),
+        }
+    }
+
+    /// Lists the local folder, hashing only the files which changed since their recorded stamp
+    fn scan_local(&self, local_path: &::std::path::Path, is_root: bool, state: &DirectoryState) -> Result<LocalScan, String> {
+        let mut scan = LocalScan {
+            files: ::std::collections::BTreeMap::new(),
+            stamps: ::std::collections::BTreeMap::new(),
+            directories: ::std::collections::BTreeSet::new(),
+        };
+
+        let entries = match ::std::fs::read_dir(local_path) {
+            Ok(entries) => entries,
+            Err(ref error) if error.kind() == ::std::io::ErrorKind::NotFound => return Ok(scan),
+            Err(_) => return Err(format!("Failed to read {}", local_path.display())),
+        };
+
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(_) => return Err(format!("Failed to read {}", local_path.display())),
+            };
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            if is_root && name.starts_with(STATE_FILE_NAME) {
+                continue;
+            }
+
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => return Err(format!("Failed to stat {}", name)),
+            };
+            if metadata.is_dir() {
+                let _ = scan.directories.insert(name);
+            } else if metadata.is_file() && !name.ends_with(PART_SUFFIX) {
+                let stamp = SyncEngine::stamp(&metadata);
+                let recorded_hash = match (stamp.as_ref(), state.get_files().get(&name)) {
+                    (Some(stamp), Some(file_state)) if file_state.get_local_stamp() == Some(stamp) => Some(file_state.get_hash().clone()),
+                    _ => None,
+                };
+                let hash = match recorded_hash {
+                    Some(hash) => hash,
+                    None => SyncEngine::hash(&try!(SyncEngine::read_local(&entry.path()))[..]),
+                };
+                if let Some(stamp) = stamp {
+                    let _ = scan.stamps.insert(name.clone(), stamp);
+                }
+                let _ = scan.files.insert(name, hash);
+            }
+        }
+
+        Ok(scan)
+    }
+
+    /// Records the stamps of the scanned files which still have the hash recorded for them
+    fn record_stamps(state: &mut DirectoryState, scan: &LocalScan) {
+        for (name, stamp) in scan.stamps.iter() {
+            if let Some(file_state) = state.get_mut_files().get_mut(name) {
+                if scan.files.get(name) == Some(file_state.get_hash()) {
+                    file_state.set_local_stamp(Some(stamp.clone()));
+                }
+            }
+        }
+    }
+
+    /// True if the local folder holds just what was recorded at the last sync
+    fn local_matches(&self, local_path: &::std::path::Path, state: &DirectoryState) -> Result<bool, String> {
+        let scan = try!(self.scan_local(local_path, false, state));
+        if scan.files.len() != state.get_files().len() ||
+           scan.files.iter().any(|(name, hash)| state.get_files().get(name).map_or(true, |file_state| file_state.get_hash() != hash)) ||
+           scan.directories.len() != state.get_sub_directories().len() ||
+           scan.directories.iter().any(|name| !state.get_sub_directories().contains_key(name)) {
+            return Ok(false);
+        }
+        for (name, sub_state) in state.get_sub_directories().iter() {
+            if !try!(self.local_matches(&local_path.join(name), sub_state)) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// True if the remote directory is still at the version recorded at the last sync, and so are
+    /// those below it
+    fn remote_matches(&self, state: &DirectoryState) -> Result<bool, String> {
+        let mut directory_helper = nfs::helper::DirectoryHelper::new(self.client.clone());
+        if try!(directory_helper.get_versions(state.get_id())).last().cloned() != state.get_version() {
+            return Ok(false);
+        }
+        for sub_state in state.get_sub_directories().values() {
+            if !try!(self.remote_matches(sub_state)) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    #[cfg(unix)]
+    fn stamp(metadata: &::std::fs::Metadata) -> Option<LocalStamp> {
+        use std::os::unix::fs::MetadataExt;
+
+        Some(LocalStamp {
+            size: metadata.len(),
+            modified_sec: metadata.mtime(),
+            modified_nsec: metadata.mtime_nsec(),
+        })
+    }
+
+    // Without a modification time to go by every file is hashed on every pass
+    #[cfg(not(unix))]
+    fn stamp(_: &::std::fs::Metadata) -> Option<LocalStamp> {
+        None
+    }
+
+    fn stamp_of(path: &::std::path::Path) -> Option<LocalStamp> {
+        match ::std::fs::metadata(path) {
+            Ok(metadata) => SyncEngine::stamp(&metadata),
+            Err(_) => None,
+        }
+    }
+
+    fn read_local(path: &::std::path::Path) -> Result<Vec<u8>, String> {
+        use std::io::Read;
+
+        let mut content = Vec::new();
+        match ::std::fs::File::open(path) {
+            Ok(mut file) => match file.read_to_end(&mut content) {
+                Ok(_) => Ok(content),
+                Err(_) => Err(format!("Failed to read {}", path.display())),
+            },
+            Err(_) => Err(format!("Failed to open {}", path.display())),
+        }
+    }
+
+    fn write_local(path: &::std::path::Path, content: &[u8]) -> Result<(), String> {
+        use std::io::Write;
+
+        // Appended rather than replacing the extension, so that "a.txt" and "a.md" do not share it
+        let temp_path = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => path.with_file_name(format!("{}{}", name, PART_SUFFIX)),
+            None => return Err(format!("Invalid path {}", path.display())),
+        };
+        {
+            let mut file = match ::std::fs::File::create(&temp_path) {
+                Ok(file) => file,
+                Err(_) => return Err(format!("Failed to create {}", temp_path.display())),
+            };
+            if file.write_all(content).is_err() {
+                return Err(format!("Failed to write {}", temp_path.display()));
+            }
+        }
+        match ::std::fs::rename(&temp_path, path) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(format!("Failed to write {}", path.display())),
+        }
+    }
+
+    fn hash(content: &[u8]) -> Vec<u8> {
+        use crypto::digest::Digest;
+
+        let mut hasher = ::crypto::sha2::Sha256::new();
+        hasher.input(content);
+        let mut digest = vec![0u8; hasher.output_bytes()];
+        hasher.result(&mut digest);
+        digest
+    }
+
+    fn conflict_name(name: &String) -> String {
+        let suffix = match ::time::strftime("%Y-%m-%d %H%M%S", &::time::now_utc()) {
+            Ok(timestamp) => format!(" (conflicted copy {})", timestamp),
+            Err(_) => " (conflicted copy)".to_string(),
+        };
+        match name.rfind('.') {
+            Some(pos) if pos > 0 => format!("{}{}{}", &name[..pos], suffix, &name[pos..]),
+            _ => format!("{}{}", name, suffix),
+        }
+    }
+
+    fn join(relative_path: &str, name: &String) -> String {
+        if relative_path.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", relative_path, name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    fn get_dummy_client() -> ::client::Client {
+        let keyword = "Spandan".to_string();
+        let password = "Sharma".as_bytes();
+        let pin = 1234u32;
+
+        ::client::Client::create_account(&keyword,
+                                         pin,
+                                         &password,
+                                         ::client::non_networking_test_framework::get_new_data_store()).ok().unwrap()
+    }
+
+    fn write_file(path: &::std::path::Path, content: &[u8]) {
+        let mut file = ::std::fs::File::create(path).unwrap();
+        file.write_all(content).unwrap();
+    }
+
+    fn read_file(path: &::std::path::Path) -> Vec<u8> {
+        use std::io::Read;
+        let mut content = Vec::new();
+        let _ = ::std::fs::File::open(path).unwrap().read_to_end(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn two_way_sync() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let mut dir_helper = ::nfs::helper::DirectoryHelper::new(client.clone());
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let dir_id = dir_helper.create(parent_id.clone(), "Synced".to_string(), Vec::new()).ok().unwrap();
+
+        let first_root = ::std::env::temp_dir().join(format!("sync_first_{}", ::rand::random::<u64>()));
+        let second_root = ::std::env::temp_dir().join(format!("sync_second_{}", ::rand::random::<u64>()));
+        ::std::fs::create_dir_all(&first_root.join("docs")).unwrap();
+        write_file(&first_root.join("a.txt"), &[1u8; 20]);
+        write_file(&first_root.join("docs").join("b.txt"), &[2u8; 30]);
+
+        let mut first = SyncEngine::new(client.clone(), first_root.clone(), dir_id.clone(), parent_id.clone(), SyncMode::TwoWay);
+        let mut second = SyncEngine::new(client.clone(), second_root.clone(), dir_id.clone(), parent_id.clone(), SyncMode::TwoWay);
+
+        let report = first.run().ok().unwrap();
+        assert_eq!(report.pushed, vec!["a.txt".to_string(), "docs/b.txt".to_string()]);
+
+        let report = second.run().ok().unwrap();
+        assert_eq!(report.pulled, vec!["a.txt".to_string(), "docs/b.txt".to_string()]);
+        assert_eq!(read_file(&second_root.join("docs").join("b.txt")), vec![2u8; 30]);
+
+        // Nothing changed - the second pass is incremental
+        let report = second.run().ok().unwrap();
+        assert!(report.pushed.is_empty() && report.pulled.is_empty());
+        assert_eq!(report.unchanged_directories, 2);
+
+        // Both sides change the same file
+        write_file(&first_root.join("a.txt"), &[3u8; 10]);
+        write_file(&second_root.join("a.txt"), &[4u8; 10]);
+        let _ = first.run().ok().unwrap();
+        let report = second.run().ok().unwrap();
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(read_file(&second_root.join("a.txt")), vec![4u8; 10]);
+        assert_eq!(read_file(&second_root.join(&report.conflicts[0])), vec![3u8; 10]);
+
+        // Deletion propagates
+        ::std::fs::remove_file(&second_root.join("docs").join("b.txt")).unwrap();
+        let report = second.run().ok().unwrap();
+        assert_eq!(report.deleted_remote, vec!["docs/b.txt".to_string()]);
+        let report = first.run().ok().unwrap();
+        assert_eq!(report.deleted_local, vec!["docs/b.txt".to_string()]);
+
+        let _ = ::std::fs::remove_dir_all(&first_root);
+        let _ = ::std::fs::remove_dir_all(&second_root);
+    }
+
+    #[test]
+    fn pass_saves_one_version_per_directory() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let mut dir_helper = ::nfs::helper::DirectoryHelper::new(client.clone());
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let dir_id = dir_helper.create(parent_id.clone(), "Synced".to_string(), Vec::new()).ok().unwrap();
+
+        let root = ::std::env::temp_dir().join(format!("sync_versions_{}", ::rand::random::<u64>()));
+        ::std::fs::create_dir_all(&root.join("docs")).unwrap();
+        for name in vec!["a.txt", "b.txt", "c.txt"] {
+            write_file(&root.join(name), name.as_bytes());
+        }
+        let mut engine = SyncEngine::new(client.clone(), root.clone(), dir_id.clone(), parent_id.clone(), SyncMode::TwoWay);
+        assert_eq!(engine.run().ok().unwrap().pushed.len(), 3);
+        // The three files and the docs directory were added in one version
+        assert_eq!(dir_helper.get_versions(dir_id.clone()).ok().unwrap().len(), 2);
+
+        ::std::fs::remove_file(&root.join("a.txt")).unwrap();
+        ::std::fs::remove_file(&root.join("b.txt")).unwrap();
+        write_file(&root.join("c.txt"), &[5u8; 10]);
+        let report = engine.run().ok().unwrap();
+        assert_eq!(report.deleted_remote, vec!["a.txt".to_string(), "b.txt".to_string()]);
+        assert_eq!(dir_helper.get_versions(dir_id.clone()).ok().unwrap().len(), 3);
+        // Deleted files can be restored from the trash
+        assert_eq!(::nfs::rest::Trash::open(client.clone()).ok().unwrap().get_entries().len(), 2);
+
+        // Nothing changed, so nothing is saved
+        assert_eq!(engine.run().ok().unwrap().unchanged_directories, 2);
+        assert_eq!(dir_helper.get_versions(dir_id).ok().unwrap().len(), 3);
+
+        let _ = ::std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn deleting_changed_directory_keeps_it() {
+        let client = ::std::sync::Arc::new(::std::sync::Mutex::new(get_dummy_client()));
+        let mut dir_helper = ::nfs::helper::DirectoryHelper::new(client.clone());
+        let parent_id = ::routing::NameType::new([8u8; 64]);
+        let dir_id = dir_helper.create(parent_id.clone(), "Synced".to_string(), Vec::new()).ok().unwrap();
+
+        let first_root = ::std::env::temp_dir().join(format!("sync_first_{}", ::rand::random::<u64>()));
+        let second_root = ::std::env::temp_dir().join(format!("sync_second_{}", ::rand::random::<u64>()));
+        ::std::fs::create_dir_all(&first_root.join("docs")).unwrap();
+        write_file(&first_root.join("docs").join("b.txt"), &[2u8; 30]);
+        // Left behind by an interrupted download
+        write_file(&first_root.join("a.txt.safe_sync_part"), &[1u8; 20]);
+
+        let mut first = SyncEngine::new(client.clone(), first_root.clone(), dir_id.clone(), parent_id.clone(), SyncMode::TwoWay);
+        let mut second = SyncEngine::new(client.clone(), second_root.clone(), dir_id.clone(), parent_id.clone(), SyncMode::TwoWay);
+        assert_eq!(first.run().ok().unwrap().pushed, vec!["docs/b.txt".to_string()]);
+        let _ = second.run().ok().unwrap();
+
+        // Deleted on the second side while a file is added to it on the first
+        ::std::fs::remove_dir_all(&second_root.join("docs")).unwrap();
+        write_file(&first_root.join("docs").join("c.txt"), &[3u8; 10]);
+        assert_eq!(second.run().ok().unwrap().deleted_remote, vec!["docs".to_string()]);
+
+        let report = first.run().ok().unwrap();
+        assert_eq!(report.conflicts, vec!["docs".to_string()]);
+        assert!(report.deleted_local.is_empty());
+        assert_eq!(read_file(&first_root.join("docs").join("c.txt")), vec![3u8; 10]);
+
+        let report = second.run().ok().unwrap();
+        assert_eq!(report.pulled, vec!["docs/b.txt".to_string(), "docs/c.txt".to_string()]);
+
+        // Deleted on the first side while the second changes a file in it
+        ::std::fs::remove_dir_all(&first_root.join("docs")).unwrap();
+        write_file(&second_root.join("docs").join("b.txt"), &[4u8; 30]);
+        let _ = second.run().ok().unwrap();
+        let report = first.run().ok().unwrap();
+        assert_eq!(report.conflicts, vec!["docs".to_string()]);
+        assert_eq!(read_file(&first_root.join("docs").join("b.txt")), vec![4u8; 30]);
+
+        let _ = ::std::fs::remove_dir_all(&first_root);
+        let _ = ::std::fs::remove_dir_all(&second_root);
+    }
+}
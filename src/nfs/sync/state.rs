@@ -0,0 +1,240 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use cbor;
+use routing;
+
+/// Name of the state database kept at the root of every synchronised local folder
+pub static STATE_FILE_NAME: &'static str = ".safe_sync_state";
+
+/// Size and modification time of a local file, which tell whether it needs hashing again
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, Clone, Debug)]
+pub struct LocalStamp {
+    pub size: u64,
+    pub modified_sec: i64,
+    pub modified_nsec: i64,
+}
+
+/// Snapshot of a file as it was when last synchronised
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, Clone, Debug)]
+pub struct FileState {
+    hash: Vec<u8>,
+    size: u64,
+    remote_modified_sec: i64,
+    remote_modified_nsec: i32,
+    local_stamp: Option<LocalStamp>,
+}
+
+#[allow(dead_code)]
+impl FileState {
+    pub fn new(hash: Vec<u8>, size: u64, remote_modified: ::time::Tm) -> FileState {
+        let timespec = remote_modified.to_timespec();
+        FileState {
+            hash: hash,
+            size: size,
+            remote_modified_sec: timespec.sec,
+            remote_modified_nsec: timespec.nsec,
+            local_stamp: None,
+        }
+    }
+
+    pub fn get_hash(&self) -> &Vec<u8> {
+        &self.hash
+    }
+
+    /// Stamp of the local file when it last had the recorded hash
+    pub fn get_local_stamp(&self) -> Option<&LocalStamp> {
+        self.local_stamp.as_ref()
+    }
+
+    pub fn set_local_stamp(&mut self, local_stamp: Option<LocalStamp>) {
+        self.local_stamp = local_stamp;
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    /// True if the remote file still has the size and modification time recorded at the last sync
+    pub fn matches_remote(&self, file: &::nfs::file::File) -> bool {
+        let metadata = file.get_metadata();
+        let timespec = metadata.get_modified_time().to_timespec();
+        metadata.get_size() == self.size &&
+        timespec.sec == self.remote_modified_sec &&
+        timespec.nsec == self.remote_modified_nsec
+    }
+}
+
+/// Snapshot of a directory as it was when last synchronised
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, Clone, Debug)]
+pub struct DirectoryState {
+    id: routing::NameType,
+    parent_id: routing::NameType,
+    version: Option<routing::NameType>,
+    files: ::std::collections::BTreeMap<String, FileState>,
+    sub_directories: ::std::collections::BTreeMap<String, DirectoryState>,
+}
+
+#[allow(dead_code)]
+impl DirectoryState {
+    pub fn new(id: routing::NameType, parent_id: routing::NameType) -> DirectoryState {
+        DirectoryState {
+            id: id,
+            parent_id: parent_id,
+            version: None,
+            files: ::std::collections::BTreeMap::new(),
+            sub_directories: ::std::collections::BTreeMap::new(),
+        }
+    }
+
+    pub fn get_id(&self) -> routing::NameType {
+        self.id.clone()
+    }
+
+    pub fn get_parent_id(&self) -> routing::NameType {
+        self.parent_id.clone()
+    }
+
+    pub fn get_version(&self) -> Option<routing::NameType> {
+        self.version.clone()
+    }
+
+    pub fn set_version(&mut self, version: Option<routing::NameType>) {
+        self.version = version;
+    }
+
+    pub fn get_files(&self) -> &::std::collections::BTreeMap<String, FileState> {
+        &self.files
+    }
+
+    pub fn get_mut_files(&mut self) -> &mut ::std::collections::BTreeMap<String, FileState> {
+        &mut self.files
+    }
+
+    pub fn get_sub_directories(&self) -> &::std::collections::BTreeMap<String, DirectoryState> {
+        &self.sub_directories
+    }
+
+    pub fn get_mut_sub_directories(&mut self) -> &mut ::std::collections::BTreeMap<String, DirectoryState> {
+        &mut self.sub_directories
+    }
+}
+
+/// Local state database of a synchronised folder
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Eq, Clone, Debug)]
+pub struct SyncState {
+    root: DirectoryState,
+}
+
+#[allow(dead_code)]
+impl SyncState {
+    pub fn new(root_id: routing::NameType, root_parent_id: routing::NameType) -> SyncState {
+        SyncState {
+            root: DirectoryState::new(root_id, root_parent_id),
+        }
+    }
+
+    /// Loads the state database of the local folder. A fresh state is returned if none exists yet
+    /// or if the existing one was recorded against a different remote directory.
+    pub fn load(local_root: &::std::path::Path,
+                root_id: routing::NameType,
+                root_parent_id: routing::NameType) -> Result<SyncState, String> {
+        use std::io::Read;
+
+        let mut file = match ::std::fs::File::open(&local_root.join(STATE_FILE_NAME)) {
+            Ok(file) => file,
+            Err(ref error) if error.kind() == ::std::io::ErrorKind::NotFound => return Ok(SyncState::new(root_id, root_parent_id)),
+            Err(_) => return Err("Failed to open sync state".to_string()),
+        };
+
+        let mut contents = Vec::new();
+        if file.read_to_end(&mut contents).is_err() {
+            return Err("Failed to read sync state".to_string());
+        }
+
+        let mut decoder = cbor::Decoder::from_bytes(contents);
+        let state: SyncState = match decoder.decode().next() {
+            Some(Ok(state)) => state,
+            _ => return Err("Sync state is corrupted".to_string()),
+        };
+
+        if state.root.get_id() != root_id {
+            return Ok(SyncState::new(root_id, root_parent_id));
+        }
+        Ok(state)
+    }
+
+    /// Writes the state database next to the synchronised content. The new state is written to a
+    /// temporary file first and renamed over the old one so that a crash never leaves it torn.
+    pub fn save(&self, local_root: &::std::path::Path) -> Result<(), String> {
+        use std::io::Write;
+
+        let temp_path = local_root.join(format!("{}.tmp", STATE_FILE_NAME));
+        {
+            let mut file = match ::std::fs::File::create(&temp_path) {
+                Ok(file) => file,
+                Err(_) => return Err("Failed to create sync state".to_string()),
+            };
+            if file.write_all(&::nfs::utils::serialise(self.clone())[..]).is_err() || file.sync_all().is_err() {
+                return Err("Failed to write sync state".to_string());
+            }
+        }
+
+        match ::std::fs::rename(&temp_path, &local_root.join(STATE_FILE_NAME)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err("Failed to save sync state".to_string()),
+        }
+    }
+
+    pub fn get_root(&self) -> &DirectoryState {
+        &self.root
+    }
+
+    pub fn get_mut_root(&mut self) -> &mut DirectoryState {
+        &mut self.root
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let local_root = ::std::env::temp_dir().join(format!("sync_state_{}", ::rand::random::<u64>()));
+        ::std::fs::create_dir_all(&local_root).unwrap();
+
+        let root_id = ::routing::NameType::new([1u8; 64]);
+        let parent_id = ::routing::NameType::new([2u8; 64]);
+
+        let mut state = SyncState::new(root_id.clone(), parent_id.clone());
+        state.get_mut_root().set_version(Some(::routing::NameType::new([3u8; 64])));
+        state.get_mut_root().get_mut_files().insert("hello.txt".to_string(), FileState::new(vec![4u8; 32], 20, ::time::now_utc()));
+        state.get_mut_root().get_mut_sub_directories().insert("sub".to_string(), DirectoryState::new(::routing::NameType::new([5u8; 64]), root_id.clone()));
+
+        assert!(state.save(&local_root).is_ok());
+        assert_eq!(SyncState::load(&local_root, root_id, parent_id.clone()).ok().unwrap(), state);
+
+        // State recorded against some other remote directory is discarded
+        let other_id = ::routing::NameType::new([6u8; 64]);
+        let fresh_state = SyncState::load(&local_root, other_id.clone(), parent_id).ok().unwrap();
+        assert!(fresh_state.get_root().get_version().is_none());
+        assert_eq!(fresh_state.get_root().get_id(), other_id);
+
+        let _ = ::std::fs::remove_dir_all(&local_root);
+    }
+}
@@ -15,6 +15,9 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 use cbor;
+use client;
+use routing;
+use routing::sendable::Sendable;
 use rustc_serialize::{Decodable, Encodable};
 
 #[allow(unused_must_use)]
@@ -28,3 +31,42 @@ pub fn deserialise<T>(data: Vec<u8>) -> T where T : Decodable {
     let mut d = cbor::Decoder::from_bytes(data);
     d.decode().next().unwrap().unwrap()
 }
+
+/// Like deserialise, but reports data which does not decode rather than panicking
+pub fn try_deserialise<T>(data: Vec<u8>) -> Result<T, String> where T : Decodable {
+    let mut d = cbor::Decoder::from_bytes(data);
+    match d.decode().next() {
+        Some(Ok(value)) => Ok(value),
+        _ => Err("Failed to decode".to_string()),
+    }
+}
+
+/// Fetches data from the network. None if nothing is stored under the name; any other failure,
+/// one which outlasted the retries included, is an error, so that data which could not be
+/// fetched is never taken for data which does not exist.
+pub fn network_get(client: &::std::sync::Arc<::std::sync::Mutex<client::Client>>, tag_id: u64, name: routing::NameType) -> Result<Option<Vec<u8>>, String> {
+    let get_result = client.lock().unwrap().get(tag_id, name);
+    if get_result.is_err() {
+        return Err("Network IO Error".to_string());
+    }
+
+    match get_result.ok().unwrap().get() {
+        Ok(data) => Ok(Some(data)),
+        Err(routing::error::ResponseError::NoData) => Ok(None),
+        Err(_) => Err("Failed to fetch data".to_string()),
+    }
+}
+
+/// Stores data on the network, failing with nfs::OUT_OF_SPACE if the account has no space left
+pub fn network_put<T>(client: &::std::sync::Arc<::std::sync::Mutex<client::Client>>, sendable: T) -> Result<Vec<u8>, String> where T: Sendable {
+    let put_result = client.lock().unwrap().put(sendable);
+    if put_result.is_err() {
+        return Err("Network IO Error".to_string());
+    }
+
+    match put_result.ok().unwrap().get() {
+        Ok(data) => Ok(data),
+        Err(ref error) if client::is_out_of_space(error) => Err(::nfs::OUT_OF_SPACE.to_string()),
+        Err(_) => Err("Failed to store data".to_string()),
+    }
+}
@@ -0,0 +1,79 @@
+/*
+ * Exercises the C interface against the mock network.
+ *
+ * cc -Iffi tests/c/ffi_test.c -Ltarget/debug -lmaidsafe_client -o target/ffi_test
+ * LD_LIBRARY_PATH=target/debug target/ffi_test
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "maidsafe_client.h"
+
+#define CHECK(call, expected)                                                          \
+    do {                                                                               \
+        int32_t code = (call);                                                         \
+        if (code != (expected)) {                                                      \
+            fprintf(stderr, "%s:%d: %s returned %d, expected %d (%s)\n", __FILE__,     \
+                    __LINE__, #call, code, (expected), safe_last_error());             \
+            exit(1);                                                                   \
+        }                                                                              \
+    } while (0)
+
+int main(void) {
+    SafeDataStore* data_store = safe_data_store_new();
+    SafeClient* client = NULL;
+    SafeContainer* root = NULL;
+    SafeContainer* photos = NULL;
+    SafeNameList* names = NULL;
+    const char content[] = "not really a cat";
+    uint8_t* data = NULL;
+    size_t length = 0;
+
+    CHECK(safe_create_account("Spandan", 1234, "Sharma", data_store, &client), SAFE_OK);
+    safe_client_free(client);
+    CHECK(safe_log_in("Spandan", 1234, "Wrong", data_store, &client), SAFE_ERR_LOGIN_FAILED);
+    CHECK(safe_log_in("Spandan", 1234, "Sharma", data_store, &client), SAFE_OK);
+
+    CHECK(safe_container_user_root(client, &root), SAFE_OK);
+    CHECK(safe_container_create(root, "photos", "{\"shared\":false}"), SAFE_OK);
+    CHECK(safe_container_create(root, "photos", NULL), SAFE_ERR_ALREADY_EXISTS);
+
+    CHECK(safe_container_list_containers(root, &names), SAFE_OK);
+    if (safe_name_list_len(names) != 1 || strcmp(safe_name_list_get(names, 0), "photos") != 0) {
+        fprintf(stderr, "unexpected container list\n");
+        return 1;
+    }
+    safe_name_list_free(names);
+
+    CHECK(safe_container_get(root, "photos", &photos), SAFE_OK);
+    CHECK(safe_blob_write(photos, "cat.jpg", (const uint8_t*)content, strlen(content), NULL), SAFE_OK);
+    CHECK(safe_container_list_blobs(photos, &names), SAFE_OK);
+    if (safe_name_list_len(names) != 1 || strcmp(safe_name_list_get(names, 0), "cat.jpg") != 0) {
+        fprintf(stderr, "unexpected blob list\n");
+        return 1;
+    }
+    safe_name_list_free(names);
+
+    CHECK(safe_blob_read(photos, "cat.jpg", &data, &length), SAFE_OK);
+    if (length != strlen(content) || memcmp(data, content, length) != 0) {
+        fprintf(stderr, "blob content differs\n");
+        return 1;
+    }
+    safe_buffer_free(data, length);
+
+    CHECK(safe_blob_delete(photos, "cat.jpg"), SAFE_OK);
+    CHECK(safe_blob_read(photos, "cat.jpg", &data, &length), SAFE_ERR_NOT_FOUND);
+    safe_container_free(photos);
+
+    CHECK(safe_container_delete(root, "photos"), SAFE_OK);
+    CHECK(safe_container_get(root, "photos", &photos), SAFE_ERR_NOT_FOUND);
+    CHECK(safe_container_get(root, NULL, &photos), SAFE_ERR_NULL_POINTER);
+
+    safe_container_free(root);
+    safe_client_free(client);
+    safe_data_store_free(data_store);
+    printf("ffi_test passed\n");
+    return 0;
+}